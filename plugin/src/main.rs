@@ -0,0 +1,285 @@
+//! The theme list as a zellij plugin pane: type to filter, Enter applies
+//! the selected theme and closes the pane.
+//!
+//! WASI gives the plugin no network, so it lists what the selector cached,
+//! plus the local theme files and the config's own `themes` block, and
+//! cannot download a theme from a custom source. Everything else, from
+//! the filter to writing the config, is the library's, as the selector
+//! uses it.
+//!
+//! zellij mounts the directory it was started in at `/host`, which is
+//! taken to be the home directory. The plugin's configuration can point
+//! it elsewhere:
+//!
+//! ```kdl
+//! plugin location="file:~/.config/zellij/plugins/zellij-theme-plugin.wasm" {
+//!     config_dir "/host/.config/zellij"
+//!     cache "/host/.cache/zellij-theme-plugin/theme_cache.json"
+//!     key "theme"
+//!     default_theme "default"
+//! }
+//! ```
+//!
+//! `default_theme` is listed even though no file defines it, as zellij
+//! builds it in; an empty one lists none.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zellij_theme_selector::cache::{self, CACHE_FILE, OLD_CACHE_FILE};
+use zellij_theme_selector::config::{self, DEFAULT_THEME_KEY};
+use zellij_theme_selector::history::{self, HISTORY_FILE};
+use zellij_theme_selector::local;
+use zellij_theme_selector::matcher;
+use zellij_theme_selector::rollback::{self, ROLLBACK_FILE};
+use zellij_theme_selector::tags::{self, Tags, TAGS_FILE};
+use zellij_theme_selector::theme::{self, Origin, Theme};
+use zellij_tile::prelude::*;
+
+/// Where zellij's config directory is when `/host` is the home directory.
+const DEFAULT_CONFIG_DIR: &str = "/host/.config/zellij";
+/// Where the selector keeps its cache when `/host` is the home directory.
+const DEFAULT_CACHE_DIR: &str = "/host/.cache/zellij-theme-plugin";
+
+const HELP: &str = "Type to filter, ↑↓ to move, Enter to apply, Esc to close";
+
+#[derive(Default)]
+struct ThemePlugin {
+    config_path: PathBuf,
+    theme_dir: PathBuf,
+    cache_path: PathBuf,
+    history_path: PathBuf,
+    rollback_path: PathBuf,
+    /// The config node holding the theme name, as `--key` sets it.
+    key: String,
+    /// Listed without a file, like the selector's `default_theme` setting.
+    default_theme: Option<String>,
+    themes: Vec<Theme>,
+    tags: Tags,
+    /// Indices into `themes` of those matching `filter`, best first.
+    visible: Vec<usize>,
+    /// Index into `visible`.
+    selected: usize,
+    filter: String,
+    /// The theme the config names, if any.
+    current: Option<String>,
+    /// Shown instead of the key hints, e.g. why a theme was not applied.
+    message: Option<String>,
+    /// Whether zellij lets the plugin close its own pane.
+    can_close: bool,
+}
+
+register_plugin!(ThemePlugin);
+
+impl ZellijPlugin for ThemePlugin {
+    fn load(&mut self, configuration: BTreeMap<String, String>) {
+        request_permission(&[PermissionType::ChangeApplicationState]);
+        subscribe(&[EventType::Key, EventType::PermissionRequestResult]);
+
+        let config_dir = configuration
+            .get("config_dir")
+            .map_or_else(|| PathBuf::from(DEFAULT_CONFIG_DIR), PathBuf::from);
+        self.config_path = config_dir.join("config.kdl");
+        self.theme_dir = config_dir.join("themes");
+        self.history_path = config_dir.join(HISTORY_FILE);
+        self.rollback_path = config_dir.join(ROLLBACK_FILE);
+        self.cache_path = match configuration.get("cache") {
+            Some(path) => PathBuf::from(path),
+            None => [Path::new(DEFAULT_CACHE_DIR).join(CACHE_FILE), config_dir.join(OLD_CACHE_FILE)]
+                .into_iter()
+                .find(|path| path.is_file())
+                .unwrap_or_else(|| Path::new(DEFAULT_CACHE_DIR).join(CACHE_FILE)),
+        };
+        self.key = DEFAULT_THEME_KEY.to_string();
+        if let Some(key) = configuration.get("key") {
+            match config::theme_key_problem(key) {
+                Some(problem) => {
+                    self.message = Some(format!("Ignoring key '{}': {}", key, problem));
+                }
+                None => self.key = key.clone(),
+            }
+        }
+        self.default_theme = match configuration.get("default_theme") {
+            Some(name) => Some(name.clone()).filter(|name| !name.is_empty()),
+            None => Some("default".to_string()),
+        };
+        self.tags = tags::load(&config_dir.join(TAGS_FILE)).unwrap_or_default();
+        self.list_themes();
+    }
+
+    fn update(&mut self, event: Event) -> bool {
+        match event {
+            Event::PermissionRequestResult(status) => {
+                self.can_close = status == PermissionStatus::Granted;
+                false
+            }
+            Event::Key(key) => {
+                self.handle_key(key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&mut self, rows: usize, cols: usize) {
+        let current = self.current.as_deref().unwrap_or("none");
+        let title = format!("Themes: {} listed, current {}", self.visible.len(), current);
+        println!("{}", fit(&title, cols));
+        println!("{}", fit(&format!("> {}", self.filter), cols));
+        let height = rows.saturating_sub(3);
+        // Scrolled just far enough to show the selected theme
+        let first = (self.selected + 1).saturating_sub(height);
+        for (row, &index) in self.visible.iter().enumerate().skip(first).take(height) {
+            let theme = &self.themes[index];
+            let marker = if self.current.as_deref() == Some(theme.name.as_str()) {
+                '*'
+            } else {
+                ' '
+            };
+            let line = fit(&format!("{} {}  {}", marker, theme.name, theme.location()), cols);
+            if row == self.selected {
+                println!("\u{1b}[7m{}\u{1b}[0m", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+        for _ in self.visible.len().saturating_sub(first).min(height)..height {
+            println!();
+        }
+        print!("{}", fit(self.message.as_deref().unwrap_or(HELP), cols));
+    }
+}
+
+impl ThemePlugin {
+    /// Lists the cached themes together with the local files and the
+    /// config's `themes` block, which win on name clashes as they do in
+    /// the selector, and the default theme unless one of them has its name.
+    fn list_themes(&mut self) {
+        let mut themes = Vec::new();
+        match cache::read(&self.cache_path) {
+            Some(cache) => themes.extend(cache.themes),
+            None => {
+                self.message = Some(format!(
+                    "No theme cache at {}; run zellij-theme-selector once to list zellij's themes",
+                    self.cache_path.display()
+                ));
+            }
+        }
+        themes.extend(local::themes(&self.theme_dir));
+        themes.extend(config_themes(&self.config_path));
+        let mut themes = theme::merge_themes(themes);
+        if let Some(name) = &self.default_theme {
+            if !themes.iter().any(|theme| &theme.name == name) {
+                themes.push(Theme::new(name.as_str()));
+            }
+        }
+        self.themes = themes;
+        self.current = fs::read_to_string(&self.config_path)
+            .ok()
+            .and_then(|content| content.parse().ok())
+            .and_then(|doc| config::theme_reference(&doc, &self.key));
+        self.refilter();
+        if let Some(current) = &self.current {
+            let position = self.visible.iter().position(|&i| &self.themes[i].name == current);
+            self.selected = position.unwrap_or_default();
+        }
+    }
+
+    fn refilter(&mut self) {
+        let ranked = matcher::rank(&self.filter, self.themes.iter().enumerate(), &self.tags);
+        self.visible = ranked.into_iter().map(|ranked| ranked.index).collect();
+        self.selected = 0;
+    }
+
+    fn handle_key(&mut self, key: Key) {
+        match key {
+            Key::Up | Key::Ctrl('p') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = (self.selected + 1).min(self.visible.len().saturating_sub(1));
+            }
+            Key::Char('\n') => self.apply(),
+            Key::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.refilter();
+            }
+            Key::Esc | Key::Ctrl('c') => self.close(),
+            Key::Backspace => {
+                self.filter.pop();
+                self.refilter();
+            }
+            Key::Char(c) if !c.is_control() => {
+                self.filter.push(c);
+                self.refilter();
+            }
+            _ => {}
+        }
+    }
+
+    /// Points the config at the selected theme and closes the pane. The
+    /// history and undo stack are updated as the selector would, so the
+    /// selector can take the change back. The active theme is left alone,
+    /// so the config is not rewritten for nothing.
+    fn apply(&mut self) {
+        let Some(theme) = self.visible.get(self.selected).map(|&i| &self.themes[i]) else {
+            return;
+        };
+        let name = theme.name.clone();
+        if theme.origin == Origin::Custom {
+            self.message = Some(format!(
+                "{} is not installed; apply it once with zellij-theme-selector to download it",
+                name
+            ));
+            return;
+        }
+        if self.current.as_deref() == Some(name.as_str()) {
+            self.message = Some(format!("{} is already the active theme", name));
+            self.close();
+            return;
+        }
+        if let Err(e) = config::set_theme_reference_in(&self.config_path, Some(&name), &self.key) {
+            self.message = Some(format!("Could not write {}: {}", self.config_path.display(), e));
+            return;
+        }
+        if let Err(e) = history::record(&self.history_path, &name) {
+            eprintln!("could not record {} in the history: {}", name, e);
+        }
+        let previous = self.current.replace(name.clone());
+        let pushed = rollback::update(&self.rollback_path, |stack| {
+            stack.push(previous.as_deref(), Some(&name));
+        });
+        if let Err(e) = pushed {
+            eprintln!("could not update the undo stack: {}", e);
+        }
+        self.message = Some(format!("Applied {}", name));
+        self.close();
+    }
+
+    fn close(&mut self) {
+        if self.can_close {
+            close_plugin_pane(get_plugin_ids().plugin_id);
+        } else {
+            let note = "the plugin may not close its pane without permission to change zellij";
+            self.message = Some(match self.message.take() {
+                Some(message) => format!("{}; {}", message, note),
+                None => format!("Not closing: {}", note),
+            });
+        }
+    }
+}
+
+/// The themes defined in the `themes` block of the config at `path`.
+fn config_themes(path: &Path) -> Vec<Theme> {
+    let Some(doc) = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.parse::<kdl::KdlDocument>().ok())
+    else {
+        return Vec::new();
+    };
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    config::defined_themes(&doc, &file)
+}
+
+/// `text` cut to `cols` characters, so a long line does not wrap.
+fn fit(text: &str, cols: usize) -> String {
+    text.chars().take(cols).collect()
+}
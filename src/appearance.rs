@@ -0,0 +1,166 @@
+//! Following the desktop's light/dark preference, for `watch-appearance`.
+//!
+//! On Linux the preference is the `color-scheme` setting of the XDG desktop
+//! portal, read once and then followed through its `SettingChanged` signal.
+//! Both go through `gdbus`, which comes with GLib, the same way the
+//! clipboard goes through `wl-copy` and friends. macOS has no such signal to
+//! wait on from the command line, so `defaults read -g AppleInterfaceStyle`
+//! is polled instead.
+
+use crate::theme::Appearance;
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// How long the preference has to stay put before it counts, so flipping
+/// back and forth quickly only switches the theme once.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// How often macOS is asked for its appearance.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+const PORTAL: &[&str] = &[
+    "--session",
+    "--dest",
+    "org.freedesktop.portal.Desktop",
+    "--object-path",
+    "/org/freedesktop/portal/desktop",
+];
+
+/// Starts following the desktop's preference in the background. The
+/// current one is sent first, then every change; the channel closes if the
+/// preference can no longer be followed, e.g. because `gdbus` exited.
+pub async fn watch() -> io::Result<UnboundedReceiver<Appearance>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if cfg!(target_os = "macos") {
+        let current = macos_appearance().await?;
+        let _ = sender.send(current);
+        tokio::spawn(poll_macos(current, sender));
+    } else if cfg!(target_os = "linux") {
+        let _ = sender.send(portal_appearance().await?);
+        let mut monitor = Command::new("gdbus")
+            .arg("monitor")
+            .args(PORTAL)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(gdbus_error)?;
+        let stdout = monitor.stdout.take().expect("stdout is piped");
+        tokio::spawn(async move {
+            // Holding on to the child keeps it alive until the task ends
+            let _monitor = monitor;
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let changed = line.contains("SettingChanged")
+                    && line.contains("'org.freedesktop.appearance', 'color-scheme'");
+                if let Some(appearance) = changed.then(|| color_scheme(&line)).flatten() {
+                    log::debug!("the portal reports a {} color scheme", appearance.label());
+                    if sender.send(appearance).is_err() {
+                        break;
+                    }
+                }
+            }
+            log::warn!("gdbus monitor stopped");
+        });
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "following the desktop appearance only works on Linux and macOS",
+        ));
+    }
+    Ok(receiver)
+}
+
+/// Waits for the next preference that then stays unchanged for
+/// [`DEBOUNCE`], and returns it. `None` once the watcher has stopped.
+pub async fn next_settled(changes: &mut UnboundedReceiver<Appearance>) -> Option<Appearance> {
+    let mut latest = changes.recv().await?;
+    loop {
+        match tokio::time::timeout(DEBOUNCE, changes.recv()).await {
+            Ok(Some(appearance)) => latest = appearance,
+            Ok(None) | Err(_) => return Some(latest),
+        }
+    }
+}
+
+/// The portal's `color-scheme` right now.
+async fn portal_appearance() -> io::Result<Appearance> {
+    let output = Command::new("gdbus")
+        .arg("call")
+        .args(PORTAL)
+        .args([
+            "--method",
+            "org.freedesktop.portal.Settings.Read",
+            "org.freedesktop.appearance",
+            "color-scheme",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(gdbus_error)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "could not read the color scheme from the desktop portal: {}",
+            stderr.trim()
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    color_scheme(&stdout).ok_or_else(|| {
+        io::Error::other(format!("unexpected answer from the desktop portal: {}", stdout.trim()))
+    })
+}
+
+/// The appearance in a `color-scheme` value as `gdbus` prints it, e.g.
+/// `(<<uint32 1>>,)`: 1 prefers dark, 2 light, and 0 has no preference,
+/// which is taken as light.
+fn color_scheme(text: &str) -> Option<Appearance> {
+    let (_, rest) = text.split_once("uint32 ")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    match digits.parse::<u32>().ok()? {
+        1 => Some(Appearance::Dark),
+        0 | 2 => Some(Appearance::Light),
+        _ => None,
+    }
+}
+
+fn gdbus_error(e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("could not run gdbus, which comes with GLib: {}", e))
+}
+
+/// Light unless `AppleInterfaceStyle` says `Dark`; the key is missing, and
+/// `defaults` fails, in light mode.
+async fn macos_appearance() -> io::Result<Appearance> {
+    let output = Command::new("defaults")
+        .args(["read", "-g", "AppleInterfaceStyle"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await?;
+    let dark = output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "Dark";
+    Ok(if dark { Appearance::Dark } else { Appearance::Light })
+}
+
+async fn poll_macos(mut current: Appearance, sender: UnboundedSender<Appearance>) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        match macos_appearance().await {
+            Ok(appearance) if appearance != current => {
+                current = appearance;
+                if sender.send(appearance).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("could not run defaults: {}", e);
+                return;
+            }
+        }
+    }
+}
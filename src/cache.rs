@@ -0,0 +1,51 @@
+//! The theme cache: the last listing of the remote themes, kept so the
+//! selector starts without a fetch. The zellij plugin, which has no
+//! network, lists the themes from it instead.
+
+use crate::theme::Theme;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The cache's name in `Settings::cache_dir`.
+pub const CACHE_FILE: &str = "theme_cache.json";
+/// The cache's name next to `config.kdl`, where older versions kept it and
+/// where it stays without a cache directory.
+pub const OLD_CACHE_FILE: &str = ".theme_cache.json";
+
+#[derive(Deserialize, Serialize)]
+pub struct CacheData {
+    pub themes: Vec<Theme>,
+    /// When the listing was made, in seconds since the epoch.
+    pub timestamp: u64,
+    /// The ref the themes were listed at; caches from before refs were
+    /// used have none.
+    #[serde(default)]
+    pub git_ref: Option<String>,
+}
+
+/// Reads the cache at `path` however old it is; `None` if there is none or
+/// it does not parse. Older versions cached the `default` entry that is now
+/// added when listing, without a source; it is dropped so that turning the
+/// default theme off applies to their caches too.
+pub fn read(path: &Path) -> Option<CacheData> {
+    let content = fs::read_to_string(path).ok()?;
+    let mut cache: CacheData = serde_json::from_str(&content).ok()?;
+    cache.themes.retain(|theme| theme.source.is_some() || theme.name != "default");
+    Some(cache)
+}
+
+/// Seconds since the epoch, the clock cache timestamps are kept in.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// How old a cache written at `timestamp` is at `now`, or `None` when it is
+/// dated after `now`, as after the clock was set back. Such a cache cannot
+/// be told apart from a stale one, so it is treated as one.
+pub fn age(timestamp: u64, now: u64) -> Option<u64> {
+    now.checked_sub(timestamp)
+}
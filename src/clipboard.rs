@@ -0,0 +1,78 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// How text ended up on the clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    /// A platform clipboard tool such as `pbcopy` or `wl-copy`.
+    Tool(&'static str),
+    /// An OSC 52 escape sequence, handled by the terminal itself. This also
+    /// works over SSH, but only if the terminal supports it.
+    Osc52,
+}
+
+impl Mechanism {
+    pub fn label(self) -> &'static str {
+        match self {
+            Mechanism::Tool(tool) => tool,
+            Mechanism::Osc52 => "OSC 52",
+        }
+    }
+}
+
+/// Clipboard tools worth trying in this session, in order of preference.
+fn available_tools() -> Vec<(&'static str, &'static [&'static str])> {
+    let mut tools: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(("pbcopy", &[]));
+    }
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+    if env::var_os("DISPLAY").is_some() {
+        tools.push(("xclip", &["-selection", "clipboard"]));
+        tools.push(("xsel", &["--clipboard", "--input"]));
+    }
+    tools
+}
+
+fn copy_with_tool(tool: &str, args: &[&str], text: &str) -> io::Result<()> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed", tool)))
+    }
+}
+
+/// Writes the OSC 52 "set clipboard" sequence to the terminal.
+fn copy_with_osc52(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()
+}
+
+/// Copies `text` to the system clipboard, preferring a local clipboard
+/// tool and falling back to OSC 52 in headless sessions. Over SSH a tool
+/// would fill the remote machine's clipboard, so OSC 52 goes first there.
+pub fn copy(text: &str) -> io::Result<Mechanism> {
+    if env::var_os("SSH_CONNECTION").is_some() || env::var_os("SSH_TTY").is_some() {
+        return copy_with_osc52(text).map(|_| Mechanism::Osc52);
+    }
+    for (tool, args) in available_tools() {
+        if copy_with_tool(tool, args, text).is_ok() {
+            return Ok(Mechanism::Tool(tool));
+        }
+    }
+    copy_with_osc52(text).map(|_| Mechanism::Osc52)
+}
@@ -0,0 +1,397 @@
+use serde::{Deserialize, Serialize};
+
+/// WCAG 2.x minimum contrast for normal text at level AA.
+pub const AA_RATIO: f64 = 4.5;
+/// WCAG 2.x minimum contrast for normal text at level AAA.
+pub const AAA_RATIO: f64 = 7.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parses `#rrggbb`, `rrggbb` or the short `#rgb` form.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().trim_start_matches('#');
+        if !hex.is_ascii() {
+            return None;
+        }
+        match hex.len() {
+            6 => Some(Self::new(
+                u8::from_str_radix(&hex[0..2], 16).ok()?,
+                u8::from_str_radix(&hex[2..4], 16).ok()?,
+                u8::from_str_radix(&hex[4..6], 16).ok()?,
+            )),
+            3 => {
+                let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
+                Some(Self::new(digit(0)?, digit(1)?, digit(2)?))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Resolves an index into the standard xterm 256-color palette.
+    pub fn from_ansi256(index: u8) -> Self {
+        const BASE: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (128, 0, 0),
+            (0, 128, 0),
+            (128, 128, 0),
+            (0, 0, 128),
+            (128, 0, 128),
+            (0, 128, 128),
+            (192, 192, 192),
+            (128, 128, 128),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (0, 0, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+        match index {
+            0..=15 => {
+                let (r, g, b) = BASE[index as usize];
+                Self::new(r, g, b)
+            }
+            16..=231 => {
+                let i = index - 16;
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                Self::new(level(i / 36), level((i / 6) % 6), level(i % 6))
+            }
+            232..=255 => {
+                let v = 8 + (index - 232) * 10;
+                Self::new(v, v, v)
+            }
+        }
+    }
+
+    /// The closest color in the xterm 256-color palette, for terminals
+    /// without 24-bit color. Only the 6×6×6 cube and the gray ramp are
+    /// considered; the first 16 entries differ between terminal themes.
+    pub fn to_ansi256(self) -> u8 {
+        // Cube levels are 0, 95, 135, 175, 215, 255
+        let level = |v: u8| -> u8 {
+            if v < 48 {
+                0
+            } else if v < 115 {
+                1
+            } else {
+                ((v - 35) / 40).min(5)
+            }
+        };
+        let (r, g, b) = (level(self.r), level(self.g), level(self.b));
+        let cube = 16 + 36 * r + 6 * g + b;
+
+        // Gray ramp levels are 8, 18, ..., 238
+        let average = (self.r as u16 + self.g as u16 + self.b as u16) / 3;
+        let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+        let distance = |index: u8| {
+            let other = Self::from_ansi256(index);
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(self.r, other.r) + d(self.g, other.g) + d(self.b, other.b)
+        };
+        if distance(gray) < distance(cube) {
+            gray
+        } else {
+            cube
+        }
+    }
+
+    /// Relative luminance as defined by WCAG 2.x, in the range `0.0..=1.0`.
+    pub fn relative_luminance(self) -> f64 {
+        0.2126 * linearize(self.r) + 0.7152 * linearize(self.g) + 0.0722 * linearize(self.b)
+    }
+
+    /// Whether dark text reads better on this color than light text, i.e.
+    /// it contrasts more with black than with white.
+    pub fn is_light(self) -> bool {
+        contrast_ratio(self, Rgb::new(0, 0, 0)) > contrast_ratio(self, Rgb::new(255, 255, 255))
+    }
+}
+
+/// Hue in degrees (`0.0..360.0`), saturation and lightness in `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsl {
+    pub h: f64,
+    pub s: f64,
+    pub l: f64,
+}
+
+impl From<Rgb> for Hsl {
+    fn from(rgb: Rgb) -> Self {
+        let (r, g, b) = (rgb.r as f64 / 255.0, rgb.g as f64 / 255.0, rgb.b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return Hsl { h: 0.0, s: 0.0, l };
+        }
+        let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        Hsl { h, s, l }
+    }
+}
+
+impl From<Hsl> for Rgb {
+    fn from(hsl: Hsl) -> Self {
+        let h = hsl.h.rem_euclid(360.0);
+        let s = hsl.s.clamp(0.0, 1.0);
+        let l = hsl.l.clamp(0.0, 1.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+        let m = l - c / 2.0;
+        let (r, g, b) = match (h / 60.0) as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgb::new(channel(r), channel(g), channel(b))
+    }
+}
+
+/// Shortest distance between two hues, in degrees.
+pub fn hue_distance(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.0);
+    d.min(360.0 - d)
+}
+
+/// OKLCH: perceptual lightness (`0.0..=1.0`), chroma and hue in degrees.
+/// Equal steps in `l` look like equal changes in brightness, which makes
+/// it the right space for darkening or shifting a whole palette.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Oklch {
+    pub l: f64,
+    pub c: f64,
+    pub h: f64,
+}
+
+impl From<Rgb> for Oklch {
+    fn from(rgb: Rgb) -> Self {
+        let (r, g, b) = (linearize(rgb.r), linearize(rgb.g), linearize(rgb.b));
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        let lightness = 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s;
+        let a = 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s;
+        let b = 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s;
+        Oklch {
+            l: lightness,
+            c: a.hypot(b),
+            h: b.atan2(a).to_degrees().rem_euclid(360.0),
+        }
+    }
+}
+
+impl Oklch {
+    /// The same color as OKLab coordinates `[l, a, b]`.
+    fn to_lab(self) -> [f64; 3] {
+        let h = self.h.to_radians();
+        [self.l, self.c * h.cos(), self.c * h.sin()]
+    }
+
+    /// Linear sRGB channels, possibly outside `0.0..=1.0` when the color
+    /// is out of gamut.
+    fn to_linear_srgb(self) -> [f64; 3] {
+        let (a, b) = (
+            self.c * self.h.to_radians().cos(),
+            self.c * self.h.to_radians().sin(),
+        );
+        let l = (self.l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m = (self.l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s = (self.l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        [
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        ]
+    }
+
+    /// Converts back to sRGB. Colors outside the sRGB gamut keep their
+    /// lightness and hue and lose chroma until they fit.
+    pub fn to_rgb(self) -> Rgb {
+        let l = self.l.clamp(0.0, 1.0);
+        let in_gamut = |c: f64| {
+            Oklch { l, c, h: self.h }
+                .to_linear_srgb()
+                .iter()
+                .all(|v| (-1e-4..=1.0 + 1e-4).contains(v))
+        };
+        let mut chroma = self.c.max(0.0);
+        if !in_gamut(chroma) {
+            let (mut low, mut high) = (0.0, chroma);
+            for _ in 0..24 {
+                let mid = (low + high) / 2.0;
+                if in_gamut(mid) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            chroma = low;
+        }
+        let [r, g, b] = Oklch { l, c: chroma, h: self.h }.to_linear_srgb();
+        Rgb::new(delinearize(r), delinearize(g), delinearize(b))
+    }
+}
+
+/// Converts an 8-bit sRGB channel to linear light.
+fn linearize(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Inverse of [`linearize`], clamping to the 8-bit range.
+fn delinearize(value: f64) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let c = if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// How different two colors look: their distance in OKLab, scaled by 100.
+/// Below about 2 the difference is hard to see; black and white are 100
+/// apart.
+pub fn delta_e(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (Oklch::from(a).to_lab(), Oklch::from(b).to_lab());
+    let [dl, da, db] = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    (dl * dl + da * da + db * db).sqrt() * 100.0
+}
+
+/// WCAG contrast ratio between two colors, from 1.0 (identical) to 21.0.
+/// The argument order does not matter.
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f64 {
+    let (la, lb) = (a.relative_luminance(), b.relative_luminance());
+    let (lighter, darker) = if la >= lb { (la, lb) } else { (lb, la) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContrastLevel {
+    Fail,
+    Aa,
+    Aaa,
+}
+
+impl ContrastLevel {
+    pub fn from_ratio(ratio: f64) -> Self {
+        if ratio >= AAA_RATIO {
+            ContrastLevel::Aaa
+        } else if ratio >= AA_RATIO {
+            ContrastLevel::Aa
+        } else {
+            ContrastLevel::Fail
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ContrastLevel::Fail => "fail",
+            ContrastLevel::Aa => "AA",
+            ContrastLevel::Aaa => "AAA",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgb = Rgb::new(0, 0, 0);
+    const WHITE: Rgb = Rgb::new(255, 255, 255);
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn black_on_white_is_21_to_1_either_way() {
+        assert_close(contrast_ratio(BLACK, WHITE), 21.0);
+        assert_close(contrast_ratio(WHITE, BLACK), 21.0);
+    }
+
+    #[test]
+    fn identical_colors_are_1_to_1() {
+        for color in [BLACK, WHITE, Rgb::new(0x88, 0xc0, 0xd0)] {
+            assert_close(contrast_ratio(color, color), 1.0);
+        }
+    }
+
+    #[test]
+    fn linearization_switches_to_the_power_curve_above_0_03928() {
+        // 10/255 is just below WCAG's 0.03928, 11/255 just above it.
+        assert_close(linearize(10), 10.0 / 255.0 / 12.92);
+        assert_close(linearize(11), ((11.0 / 255.0 + 0.055) / 1.055_f64).powf(2.4));
+        assert_close(linearize(0), 0.0);
+        assert_close(linearize(255), 1.0);
+    }
+
+    #[test]
+    fn black_and_white_are_100_apart() {
+        assert!((delta_e(BLACK, WHITE) - 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn delta_e_is_zero_for_identical_colors_and_symmetric() {
+        let (a, b) = (Rgb::new(0x2e, 0x34, 0x40), Rgb::new(0x88, 0xc0, 0xd0));
+        assert_close(delta_e(a, a), 0.0);
+        assert_close(delta_e(a, b), delta_e(b, a));
+    }
+
+    #[test]
+    fn a_one_step_difference_is_hard_to_see_and_a_hue_change_is_not() {
+        let gray = Rgb::new(0x80, 0x80, 0x80);
+        assert!(delta_e(gray, Rgb::new(0x81, 0x80, 0x80)) < 2.0);
+        assert!(delta_e(Rgb::new(255, 0, 0), Rgb::new(0, 0, 255)) > 20.0);
+    }
+
+    #[test]
+    fn palette_colors_outside_the_first_16_quantize_to_themselves() {
+        for index in 16..=255 {
+            let color = Rgb::from_ansi256(index);
+            assert_eq!(Rgb::from_ansi256(color.to_ansi256()), color, "index {}", index);
+        }
+    }
+
+    #[test]
+    fn colors_quantize_to_the_nearest_cube_or_gray_entry() {
+        assert_eq!(BLACK.to_ansi256(), 16);
+        assert_eq!(WHITE.to_ansi256(), 231);
+        assert_eq!(Rgb::new(255, 0, 0).to_ansi256(), 196);
+        assert_eq!(Rgb::new(0x80, 0x80, 0x80).to_ansi256(), 244);
+        // Nord's background: closer to the gray ramp than to any cube color.
+        assert_eq!(Rgb::new(0x2e, 0x34, 0x40).to_ansi256(), 237);
+        assert_eq!(Rgb::new(0x88, 0xc0, 0xd0).to_ansi256(), 110);
+    }
+}
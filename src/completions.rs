@@ -0,0 +1,162 @@
+//! Shell completion scripts. The static part comes from clap_complete;
+//! arguments whose value name is `THEME` are completed with theme names by
+//! running `<bin> list --cached`, which never touches the network.
+
+use clap::Command;
+use clap_complete::Shell;
+use std::io::{self, Write};
+
+/// The value name that marks an argument as taking a theme name.
+pub const THEME_VALUE_NAME: &str = "THEME";
+
+/// A subcommand with a theme name argument, and its options that take a
+/// value, so the word after them is not mistaken for the theme.
+struct ThemeArg {
+    subcommand: String,
+    id: String,
+    value_options: Vec<String>,
+}
+
+pub fn write(shell: Shell, command: &mut Command, out: &mut impl Write) -> io::Result<()> {
+    command.build();
+    let bin = command.get_name().to_string();
+    let theme_args = theme_args(command);
+    let global_value_options = value_options(command);
+
+    let mut script = Vec::new();
+    clap_complete::generate(shell, command, &bin, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    let script = match shell {
+        Shell::Bash => bash(&bin, script, &theme_args, &global_value_options),
+        Shell::Zsh => zsh(&bin, script, &theme_args),
+        Shell::Fish => fish(&bin, script, &theme_args),
+        _ => script,
+    };
+    out.write_all(script.as_bytes())
+}
+
+fn theme_args(command: &Command) -> Vec<ThemeArg> {
+    command
+        .get_subcommands()
+        .filter_map(|subcommand| {
+            let arg = subcommand.get_positionals().find(|arg| {
+                arg.get_value_names()
+                    .is_some_and(|names| names == [THEME_VALUE_NAME])
+            })?;
+            Some(ThemeArg {
+                subcommand: subcommand.get_name().to_string(),
+                id: arg.get_id().to_string(),
+                value_options: value_options(subcommand),
+            })
+        })
+        .collect()
+}
+
+/// The `--long` and `-s` spellings of every option that takes a value.
+fn value_options(command: &Command) -> Vec<String> {
+    command
+        .get_opts()
+        .flat_map(|arg| {
+            let long = arg.get_long().map(|long| format!("--{}", long));
+            let short = arg.get_short().map(|short| format!("-{}", short));
+            long.into_iter().chain(short)
+        })
+        .collect()
+}
+
+/// Wraps the generated function: after a subcommand that takes a theme,
+/// a word that is neither an option nor an option's value is a theme name.
+fn bash(bin: &str, script: String, theme_args: &[ThemeArg], globals: &[String]) -> String {
+    let function = format!("_{}", bin);
+    let cases: String = theme_args
+        .iter()
+        .map(|arg| {
+            let values = if arg.value_options.is_empty() {
+                String::from("\"\"")
+            } else {
+                arg.value_options.join("|")
+            };
+            format!(
+                r#"            {subcommand})
+                case "${{prev}}" in
+                    {values}) ;;
+                    *) themes=1 ;;
+                esac
+                ;;
+"#,
+                subcommand = arg.subcommand
+            )
+        })
+        .collect();
+    let globals = if globals.is_empty() {
+        String::from("\"\"")
+    } else {
+        globals.join("|")
+    };
+    let wrapper = format!(
+        r#"
+{function}_themes() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local i subcommand="" themes=0
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in
+            {globals}) ((i++)) ;;
+            -*) ;;
+            *) subcommand="${{COMP_WORDS[i]}}"; break ;;
+        esac
+    done
+    if [[ ${{cur}} != -* ]]; then
+        case "${{subcommand}}" in
+{cases}        esac
+    fi
+    if [[ ${{themes}} -eq 1 ]]; then
+        COMPREPLY=( $(compgen -W "$({bin} list --cached 2>/dev/null)" -- "${{cur}}") )
+        return 0
+    fi
+    {function} "$@"
+}}
+
+complete -F {function}_themes -o bashdefault -o default {bin}
+"#
+    );
+    script + &wrapper
+}
+
+/// Gives each theme argument a completion action listing the themes.
+fn zsh(bin: &str, script: String, theme_args: &[ThemeArg]) -> String {
+    let action = format!("_{}_themes", bin);
+    let mut lines: Vec<String> = script
+        .lines()
+        .map(|line| {
+            let is_theme_arg = theme_args
+                .iter()
+                .any(|arg| line.starts_with(&format!("':{} -- ", arg.id)));
+            match line.strip_suffix(":' \\") {
+                Some(spec) if is_theme_arg => format!("{}:{}' \\", spec, action),
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+    let function = format!(
+        r#"{action}() {{
+    local themes; themes=(${{(f)"$({bin} list --cached 2>/dev/null)"}})
+    compadd -a themes
+}}"#
+    );
+    // After the `#compdef` line, ahead of the call at the bottom
+    lines.insert(1.min(lines.len()), function);
+    lines.join("\n") + "\n"
+}
+
+fn fish(bin: &str, script: String, theme_args: &[ThemeArg]) -> String {
+    if theme_args.is_empty() {
+        return script;
+    }
+    let subcommands: Vec<&str> = theme_args.iter().map(|arg| arg.subcommand.as_str()).collect();
+    let subcommands = subcommands.join(" ");
+    script
+        + &format!(
+            r#"complete -c {bin} -n "__fish_seen_subcommand_from {subcommands}" -f -a "({bin} list --cached 2>/dev/null)"
+"#
+        )
+}
@@ -0,0 +1,377 @@
+//! Editing the zellij config: the node naming the active theme, and
+//! writing the file back without losing what else is in it. Shared by the
+//! selector and the zellij plugin, so nothing here touches the network.
+
+use crate::lock::{self, FileLock};
+use crate::theme::{definition_name, Origin, Theme, ThemeColors};
+use kdl::{KdlDocument, KdlNode};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The config node that names the active theme, as zellij reads it.
+pub const DEFAULT_THEME_KEY: &str = "theme";
+
+/// Points the `key` node of the config at `path` at `name`, or removes it
+/// for `None`, leaving the rest of the file as it was. A config that does
+/// not exist yet is created.
+pub fn set_theme_reference_in(path: &Path, name: Option<&str>, key: &str) -> io::Result<()> {
+    // Held until the new file is in place, so another instance cannot read
+    // the old one meanwhile and write its own change over this one
+    let _lock = FileLock::acquire(path)?;
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    // A byte order mark is put back as found
+    let (bom, text) = match content.strip_prefix('\u{feff}') {
+        Some(text) => ("\u{feff}", text),
+        None => ("", content.as_str()),
+    };
+    let mut doc: KdlDocument = text.parse().map_err(io::Error::other)?;
+    set_theme_node(&mut doc, key, name);
+    write_if_changed(path, &content, &format!("{}{}", bom, doc))
+}
+
+/// Points the top-level `theme "name"` setting of `doc` at `theme`, adding
+/// it at the end if there is none. A `theme { ... }` block defining a theme
+/// is left alone. Only the document changes; reading and writing the
+/// config is up to the caller.
+pub fn apply_theme_to_doc(doc: &mut KdlDocument, theme: &str) {
+    set_theme_node(doc, DEFAULT_THEME_KEY, Some(theme));
+}
+
+/// [`apply_theme_to_doc`] for the `key` node, or removing every such node
+/// for `None`. An added node ends like most of the document's lines, so a
+/// config with `\r\n` line endings keeps them.
+pub fn set_theme_node(doc: &mut KdlDocument, key: &str, name: Option<&str>) {
+    let Some(selected_theme) = name else {
+        doc.nodes_mut().retain(|node| !is_theme_reference(node, key));
+        return;
+    };
+
+    // Update the `theme "name"` reference, leaving any `themes { ... }`
+    // block and theme definitions that happen to be called `theme` alone
+    let mut found = false;
+    for theme_node in doc.nodes_mut().iter_mut().filter(|node| is_theme_reference(node, key)) {
+        // Clear existing values and entries
+        theme_node.clear_entries();
+        // Add the new theme value
+        theme_node.push(selected_theme);
+        found = true;
+    }
+    if found {
+        return;
+    }
+    let newline = dominant_newline(&doc.to_string());
+    // Make sure the new node starts on its own line
+    if let Some(last) = doc.nodes_mut().last_mut() {
+        let trailing = last.trailing().unwrap_or_default();
+        if !trailing.ends_with('\n') {
+            last.set_trailing(format!("{}{}", trailing, newline));
+        }
+    }
+    // Create a new theme node with the value
+    let mut node = KdlNode::new(key);
+    node.push(selected_theme);
+    node.set_trailing(newline);
+    doc.nodes_mut().push(node);
+}
+
+/// The themes defined in the `themes` block of the config `doc`, with their
+/// colors, marked as coming from the config file named `file`.
+pub fn defined_themes(doc: &KdlDocument, file: &str) -> Vec<Theme> {
+    doc.nodes()
+        .iter()
+        .filter(|node| node.name().value() == "themes")
+        .filter_map(KdlNode::children)
+        .flat_map(KdlDocument::nodes)
+        .map(|node| {
+            let mut theme = Theme::with_colors(definition_name(node), ThemeColors::from_node(node));
+            theme.origin = Origin::Config;
+            theme.file = Some(file.to_string());
+            theme
+        })
+        .collect()
+}
+
+/// The theme the `key` node of `doc` names, if any. With several such
+/// nodes the last wins, as it does for zellij.
+pub fn theme_reference(doc: &KdlDocument, key: &str) -> Option<String> {
+    doc.nodes()
+        .iter()
+        .filter(|node| is_theme_reference(node, key))
+        .filter_map(|node| node.get(0)?.value().as_string())
+        .next_back()
+        .map(str::to_string)
+}
+
+/// `\r\n` if most lines of `text` end that way, otherwise `\n`.
+fn dominant_newline(text: &str) -> &'static str {
+    let crlf = text.matches("\r\n").count();
+    if crlf * 2 > text.matches('\n').count() {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Whether the file at `path` sets a theme; an unreadable or invalid
+/// file is an error rather than `false`, so it is reported.
+pub fn has_theme_reference(path: &Path) -> io::Result<bool> {
+    let doc: KdlDocument = fs::read_to_string(path)?.parse().map_err(io::Error::other)?;
+    Ok(doc.nodes().iter().any(|node| is_theme_reference(node, DEFAULT_THEME_KEY)))
+}
+
+/// Replaces the file at `path` with `new`, unless that is what it already
+/// holds (`old`), so selecting the current theme does not touch the file.
+pub(crate) fn write_if_changed(path: &Path, old: &str, new: &str) -> io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    write_atomically(path, new.as_bytes())
+}
+
+/// Writes `contents` to a temporary file and renames it over `path`, so a
+/// crash never leaves a half-written file. A symlink is followed and its
+/// target replaced, keeping the link intact, and the permissions of the
+/// file being replaced are kept.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e),
+    };
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("{} is not a file", target.display())))?;
+    // Next to the target, since a rename cannot cross file systems
+    let temp = target.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        lock::process_id().unwrap_or_default()
+    ));
+    let result = fs::write(&temp, contents).and_then(|()| {
+        if let Ok(metadata) = fs::metadata(&target) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        fs::rename(&temp, &target)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Why `key` cannot name the node holding the theme, if it cannot: it has
+/// to be a KDL identifier that is written without quotes.
+pub fn theme_key_problem(key: &str) -> Option<&'static str> {
+    if key.is_empty() {
+        return Some("it is empty");
+    }
+    if key == "themes" {
+        return Some("`themes` is the block the themes are defined in");
+    }
+    if matches!(key, "true" | "false" | "null") {
+        return Some("it is a KDL keyword");
+    }
+    // Whatever KDL reads back as a single node called `key` is fine
+    let doc = format!("{} \"x\"\n", key).parse::<KdlDocument>();
+    match doc {
+        Ok(doc) if doc.nodes().len() == 1 && doc.nodes()[0].name().value() == key => None,
+        _ => Some("it is not a KDL identifier that can be written without quotes"),
+    }
+}
+
+/// Whether the `themes` block of the config in `content` defines `name`.
+pub fn defines_theme(content: &str, name: &str) -> bool {
+    let Ok(doc) = content.parse::<KdlDocument>() else {
+        return false;
+    };
+    doc.nodes()
+        .iter()
+        .filter(|node| node.name().value() == "themes")
+        .filter_map(KdlNode::children)
+        .any(|themes| themes.get(name).is_some())
+}
+
+/// Whether `node` is the top-level `theme "name"` setting, or the same
+/// under another `key`, rather than a definition block that happens to be
+/// called `theme`.
+pub(crate) fn is_theme_reference(node: &KdlNode, key: &str) -> bool {
+    node.name().value() == key && node.children().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(config: &str, theme: &str) -> String {
+        let mut doc: KdlDocument = config.parse().unwrap();
+        apply_theme_to_doc(&mut doc, theme);
+        doc.to_string()
+    }
+
+    #[test]
+    fn a_theme_node_is_added_when_there_is_none() {
+        assert_eq!(
+            applied("default_layout \"compact\"\n", "nord"),
+            "default_layout \"compact\"\ntheme \"nord\"\n"
+        );
+        assert_eq!(applied("", "nord"), "theme \"nord\"\n");
+    }
+
+    #[test]
+    fn an_existing_theme_node_is_replaced_with_the_name_alone() {
+        let config = "// mine\ntheme \"dracula\" \"extra\" key=1 // was\nmouse_mode true\n";
+        assert_eq!(
+            applied(config, "nord"),
+            "// mine\ntheme \"nord\" // was\nmouse_mode true\n"
+        );
+    }
+
+    #[test]
+    fn a_theme_node_with_children_is_left_alone() {
+        let config = "theme {\n    fg 1 2 3\n}\n";
+        let result = applied(config, "nord");
+        assert!(result.starts_with(config));
+        let doc: KdlDocument = result.parse().unwrap();
+        assert_eq!(theme_reference(&doc, DEFAULT_THEME_KEY).as_deref(), Some("nord"));
+        assert_eq!(doc.nodes().len(), 2);
+    }
+
+    #[test]
+    fn awkward_theme_names_read_back_unchanged() {
+        let names = ["my theme", "say \"hi\"", "thème ✨", "1984", "null", "-1", "back\\slash"];
+        for name in names {
+            let mut doc: KdlDocument = "mouse_mode true\n".parse().unwrap();
+            set_theme_node(&mut doc, DEFAULT_THEME_KEY, Some(name));
+            let written = doc.to_string();
+            let read: KdlDocument = written.parse().unwrap();
+            let reference = theme_reference(&read, DEFAULT_THEME_KEY);
+            assert_eq!(reference.as_deref(), Some(name), "{}", written);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_symlinked_config_stays_a_symlink_and_its_target_changes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("dotfiles/config.kdl");
+        fs::create_dir(target.parent().unwrap()).unwrap();
+        fs::write(&target, "theme \"dracula\"\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        let link = dir.path().join("config.kdl");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        set_theme_reference_in(&link, Some("nord"), DEFAULT_THEME_KEY).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), target);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "theme \"nord\"\n");
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+        // Nothing is left behind next to either the link or its target
+        let entries = |dir: &Path| fs::read_dir(dir).unwrap().count();
+        assert_eq!(entries(dir.path()), 2);
+        assert_eq!(entries(target.parent().unwrap()), 1);
+    }
+
+    #[test]
+    fn setting_the_theme_already_set_leaves_the_file_untouched() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = dir.path().join("config.kdl");
+        fs::write(&config, "theme \"nord\" // mine\n").unwrap();
+        let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        fs::File::options().write(true).open(&config).unwrap().set_modified(past).unwrap();
+
+        set_theme_reference_in(&config, Some("nord"), DEFAULT_THEME_KEY).unwrap();
+
+        assert_eq!(fs::metadata(&config).unwrap().modified().unwrap(), past);
+        assert_eq!(fs::read_to_string(&config).unwrap(), "theme \"nord\" // mine\n");
+    }
+
+    /// The config `content` after [`set_theme_reference_in`] selects `name`.
+    fn rewritten(content: &str, name: &str) -> String {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = dir.path().join("config.kdl");
+        fs::write(&config, content).unwrap();
+        set_theme_reference_in(&config, Some(name), DEFAULT_THEME_KEY).unwrap();
+        fs::read_to_string(&config).unwrap()
+    }
+
+    #[test]
+    fn only_the_theme_line_changes_whatever_the_line_endings() {
+        for (prefix, newline) in [("", "\n"), ("", "\r\n"), ("\u{feff}", "\r\n")] {
+            let lines = |theme: &str| {
+                let lines = ["// mine", theme, "themes {", "    nord { fg 1 2 3; }", "}", ""];
+                format!("{}{}", prefix, lines.join(newline))
+            };
+            let config = lines("theme \"dracula\"");
+            assert_eq!(rewritten(&config, "nord"), lines("theme \"nord\""), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn an_added_theme_line_ends_like_the_others() {
+        for (prefix, newline) in [("", "\n"), ("", "\r\n"), ("\u{feff}", "\r\n")] {
+            let config = format!("{}// mine{}mouse_mode true{}", prefix, newline, newline);
+            let expected = format!("{}theme \"nord\"{}", config, newline);
+            assert_eq!(rewritten(&config, "nord"), expected, "{:?}", config);
+        }
+        // Without a newline at the end, the last line gets the dominant one
+        let config = "// mine\r\nmouse_mode true";
+        let expected = "// mine\r\nmouse_mode true\r\ntheme \"nord\"\r\n";
+        assert_eq!(rewritten(config, "nord"), expected);
+    }
+
+    #[test]
+    fn only_a_scalar_theme_node_is_a_theme_reference() {
+        let doc: KdlDocument =
+            "themes {\n    theme { fg 1 2 3; }\n}\ntheme { fg 1 2 3; }\ntheme \"nord\"\n"
+                .parse()
+                .unwrap();
+        let references: Vec<bool> =
+            doc.nodes().iter().map(|node| is_theme_reference(node, DEFAULT_THEME_KEY)).collect();
+        assert_eq!(references, [false, false, true]);
+    }
+
+    #[test]
+    fn the_reference_next_to_a_themes_block_is_updated_and_the_block_left_alone() {
+        let block = "themes {\n    theme {\n        fg 1 2 3\n    }\n}\n";
+        let config = format!("{}theme \"theme\"\n", block);
+        assert_eq!(applied(&config, "nord"), format!("{}theme \"nord\"\n", block));
+    }
+
+    #[test]
+    fn a_reference_is_added_after_a_themes_block_without_one() {
+        let block = "themes {\n    nord {\n        fg 1 2 3\n    }\n}\n";
+        assert_eq!(applied(block, "nord"), format!("{}theme \"nord\"\n", block));
+    }
+
+    #[test]
+    fn a_config_with_an_inline_theme_only_changes_the_selector() {
+        let themes = concat!(
+            "themes {\n",
+            "    mytheme {\n",
+            "        fg 216 222 233\n",
+            "        bg 46 52 64\n",
+            "    }\n",
+            "}\n"
+        );
+        let config = format!("{}theme \"mytheme\" // picked\nmouse_mode false\n", themes);
+        let expected = format!("{}theme \"nord\" // picked\nmouse_mode false\n", themes);
+        assert_eq!(rewritten(&config, "nord"), expected);
+        assert_eq!(rewritten(&expected, "mytheme"), config);
+
+        let defined = defined_themes(&config.parse().unwrap(), "config.kdl");
+        assert_eq!(defined.len(), 1);
+        assert_eq!(defined[0].name, "mytheme");
+        assert_eq!(defined[0].origin, Origin::Config);
+        assert_eq!(defined[0].file.as_deref(), Some("config.kdl"));
+        let colors = defined[0].colors.as_ref().unwrap();
+        assert_eq!(colors.bg, Some(crate::color::Rgb::new(46, 52, 64)));
+    }
+}
@@ -1,178 +1,2673 @@
+use chrono::{DateTime, Local, TimeZone};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io;
-use std::env;
-use reqwest;
-use std::time::{Duration, SystemTime};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use serde_json::Value;
-use kdl::{KdlDocument, KdlNode};
+use kdl::KdlDocument;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinSet;
+use walkdir::WalkDir;
+use crate::cache::{self, CacheData, CACHE_FILE, OLD_CACHE_FILE};
+use crate::config::{
+    defined_themes, defines_theme, has_theme_reference, set_theme_reference_in, theme_reference,
+    write_atomically, DEFAULT_THEME_KEY,
+};
+use crate::history::{self, HistoryEntry, HISTORY_FILE};
+use crate::hook::{self, HookFailure};
+use crate::http;
+use crate::live::LiveSwitcher;
+use crate::local;
+use crate::lock::FileLock;
+use crate::rollback::{self, Rollback, ROLLBACK_FILE};
+use crate::settings::Settings;
+use crate::tags::{self, Tags, TAGS_FILE, TAG_MANIFESTS};
+use crate::theme::{extract_themes, merge_themes, Origin, Theme, ThemeColors, ThemeSource};
 
-const GITHUB_API_URL: &str = "https://api.github.com/repos/zellij-org/zellij/contents/zellij-utils/assets/themes";
-const CACHE_DURATION: Duration = Duration::from_secs(3600); // 1 hour
+pub const GITHUB_API_URL: &str = "https://api.github.com/repos/zellij-org/zellij/contents/zellij-utils/assets/themes";
+/// Where `GITHUB_API_URL` and other GitHub API URLs start; a GitHub
+/// Enterprise instance has its own, e.g. `https://ghe.example.com/api/v3`.
+pub const GITHUB_API_BASE: &str = "https://api.github.com";
+/// Where `GITHUB_API_URL` finds the themes in the zellij repository.
+const THEMES_PATH: &str = "zellij-utils/assets/themes";
+/// Where older zellij releases kept them.
+const OLD_THEMES_PATH: &str = "example/themes";
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(3600); // 1 hour
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// How many theme files are downloaded at the same time.
+const DOWNLOAD_CONCURRENCY: usize = 8;
+/// A fetch that failed files and came up with fewer themes than this share
+/// of the cached list does not replace it, unless it was forced.
+pub const SHRINK_LIMIT: f64 = 0.5;
 
+#[derive(Clone)]
 pub struct ThemeData {
     config_path: PathBuf,
+    /// Further configs kept in sync with `config_path`, from `--config`.
+    extra_config_paths: Vec<PathBuf>,
     theme_dir: PathBuf,
+    /// Layouts whose `theme` nodes follow the config, if enabled.
+    layouts_dir: Option<PathBuf>,
     cache_path: PathBuf,
+    history_path: PathBuf,
+    rollback_path: PathBuf,
+    tags_path: PathBuf,
+    api_url: String,
+    /// The API the token is sent to, `GITHUB_API_BASE` unless overridden.
+    api_base: String,
+    /// The branch, tag or commit to list, from `--ref`.
+    git_ref: Option<String>,
+    /// The theme listed without a file; see [`Self::default_theme`].
+    default_theme: Option<String>,
+    list_default_theme: bool,
+    /// The release tag of the installed zellij, looked up once when needed.
+    detected_ref: Arc<OnceLock<Option<String>>>,
+    cache_ttl: Duration,
+    request_timeout: Duration,
+    github_token: Option<String>,
+    /// The proxy given with `--proxy`, which replaces the ones in the
+    /// environment.
+    proxy: Option<String>,
+    /// A PEM file of extra certificates to trust, from
+    /// `ZELLIJ_THEME_CA_BUNDLE`.
+    ca_bundle: Option<PathBuf>,
+    post_apply_hook: Option<String>,
+    /// Where hook scripts run after applying a theme are looked for.
+    hooks_dir: Option<PathBuf>,
+    /// The config node naming the active theme, `theme` unless `--key`
+    /// says otherwise.
+    theme_key: String,
+    /// Built on the first request and shared by clones, so runs served from
+    /// the cache never set up TLS.
+    client: Arc<OnceLock<reqwest::Client>>,
+}
+
+/// The result of theme discovery. Files that could not be downloaded or
+/// parsed are reported in `failures` instead of silently thinning the list.
+pub struct FetchOutcome {
+    pub themes: Vec<Theme>,
+    pub failures: Vec<FetchFailure>,
+    /// How the fetched list differs from the cache it replaced; `None` when
+    /// the themes came from the cache or there was no cache before.
+    pub changes: Option<ListChanges>,
+    /// The fetch lost so many themes to `failures` that the cached list was
+    /// used instead, and left in place; see [`SHRINK_LIMIT`].
+    pub kept_cache: bool,
+}
+
+/// Themes a fetch found that the replaced cache did not have, and the
+/// other way around, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListChanges {
+    pub added: Vec<String>,
+    /// Left out are themes from files that failed to download this time,
+    /// as they are most likely still there.
+    pub removed: Vec<String>,
+}
+
+impl ListChanges {
+    fn between(old: &[Theme], new: &[Theme], failures: &[FetchFailure]) -> Self {
+        let failed = |theme: &Theme| {
+            let file = theme.source.as_ref().map(|source| source.file.as_str());
+            failures.iter().any(|failure| Some(failure.file.as_str()) == file)
+        };
+        let names = |themes: &[Theme]| -> HashSet<String> {
+            themes.iter().map(|theme| theme.name.clone()).collect()
+        };
+        let (old_names, new_names) = (names(old), names(new));
+        let mut added: Vec<String> = new_names.difference(&old_names).cloned().collect();
+        let mut removed: Vec<String> = old
+            .iter()
+            .filter(|theme| !new_names.contains(&theme.name) && !failed(theme))
+            .map(|theme| theme.name.clone())
+            .collect();
+        added.sort();
+        removed.sort();
+        removed.dedup();
+        Self { added, removed }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Progress of a fetch started with [`ThemeData::stream_themes`].
+pub enum FetchEvent {
+    /// Themes as soon as they are parsed: one remote file at a time, or
+    /// everything at once when read from the cache or the local directory.
+    /// They are not yet deduplicated against each other.
+    Themes(Vec<Theme>),
+    /// `done` of the `total` remote theme files have been processed.
+    Progress { done: usize, total: usize },
+}
+
+/// A theme file that was skipped, with the reason why.
+pub struct FetchFailure {
+    pub file: String,
+    pub error: String,
+}
+
+/// The GitHub API requests left until the hourly quota resets.
+pub struct RateLimit {
+    pub remaining: u64,
+    pub reset: Option<DateTime<Local>>,
+}
+
+/// Theme files this tool has downloaded into `theme_dir`, by file name.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct InstallManifest {
+    files: HashMap<String, InstalledFile>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
-struct CacheData {
-    themes: Vec<String>,
-    timestamp: u64,
+struct InstalledFile {
+    /// SHA of the version that was installed, as listed by the source.
+    sha: String,
+    /// SHA-256 of the file as written, to notice later local edits.
+    content_hash: String,
+    /// The listing the file was installed from; `None` in manifests written
+    /// before it was recorded.
+    #[serde(default)]
+    source: Option<InstallSource>,
 }
 
-impl ThemeData {
-    pub fn new() -> io::Result<Self> {
-        let config_path = Self::get_config_path()?;
-        let theme_dir = config_path.parent().unwrap().join("themes");
-        let cache_path = config_path.parent().unwrap().join(".theme_cache.json");
-        
-        Ok(Self {
+/// A listing theme files are installed from: `api_url` at a ref.
+#[derive(Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct InstallSource {
+    api_url: String,
+    git_ref: Option<String>,
+}
+
+/// What installing a theme's file into `theme_dir` came to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Install {
+    /// Nothing had to be written: the theme is built into zellij, its file
+    /// was put there by hand, or the installed copy is current.
+    Skipped,
+    Installed(PathBuf),
+    /// A newer version is available but the installed file has been edited
+    /// since it was downloaded, so it was left alone.
+    Modified(PathBuf),
+    /// Zellij would not find the theme, so the config was left alone; see
+    /// [`ThemeData::resolves`].
+    Unresolved,
+    /// Every config already names the theme, so nothing was written and
+    /// neither the history nor the undo stack changed.
+    AlreadyApplied,
+}
+
+/// What happened to one layout file's `theme` node, as returned by
+/// [`ThemeData::update_layouts`].
+pub struct LayoutUpdate {
+    /// Path below the layouts directory.
+    pub file: String,
+    pub result: io::Result<()>,
+}
+
+/// How an installed theme file compares with the version its source lists
+/// now, as reported by [`ThemeData::check_installed`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct InstalledStatus {
+    /// File name in `theme_dir`.
+    pub file: String,
+    pub upstream: Upstream,
+    /// The file was edited here since it was installed.
+    pub modified: bool,
+}
+
+/// What [`ThemeData::download_all`] did with each file the source lists.
+#[derive(Default)]
+pub struct DownloadReport {
+    pub downloaded: Vec<String>,
+    /// Files already installed at the listed version, put there by hand, or
+    /// edited since they were installed.
+    pub skipped: Vec<String>,
+    pub failed: Vec<FetchFailure>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Upstream {
+    UpToDate,
+    /// The source has a newer version than the one installed.
+    Outdated,
+    /// The source no longer lists the file.
+    Unlisted,
+    /// The listing has no SHA for the file and downloading it failed.
+    Unknown(String),
+}
+
+impl Upstream {
+    pub fn label(&self) -> String {
+        match self {
+            Upstream::UpToDate => String::from("up to date"),
+            Upstream::Outdated => String::from("outdated"),
+            Upstream::Unlisted => String::from("no longer listed by the source"),
+            Upstream::Unknown(error) => format!("unknown: {}", error),
+        }
+    }
+}
+
+/// Builds a [`ThemeData`] from explicit paths; see [`ThemeData::builder`].
+/// Only the config path is required. The theme directory, cache and
+/// history default to their usual names next to the config file.
+#[derive(Clone, Debug, Default)]
+pub struct ThemeDataBuilder {
+    config_path: Option<PathBuf>,
+    extra_config_paths: Vec<PathBuf>,
+    theme_dir: Option<PathBuf>,
+    layouts_dir: Option<PathBuf>,
+    cache_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+    rollback_path: Option<PathBuf>,
+    tags_path: Option<PathBuf>,
+    api_url: Option<String>,
+    api_base: Option<String>,
+    git_ref: Option<String>,
+    default_theme: Option<String>,
+    no_default_theme: bool,
+    cache_ttl: Option<Duration>,
+    request_timeout: Option<Duration>,
+    github_token: Option<String>,
+    proxy: Option<String>,
+    ca_bundle: Option<PathBuf>,
+    post_apply_hook: Option<String>,
+    hooks_dir: Option<PathBuf>,
+    theme_key: Option<String>,
+}
+
+impl ThemeDataBuilder {
+    /// The `config.kdl` that applying a theme writes.
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Another config kept in sync with the first, like a second `--config`.
+    pub fn extra_config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_config_paths.push(path.into());
+        self
+    }
+
+    pub fn theme_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.theme_dir = Some(dir.into());
+        self
+    }
+
+    /// Layout files to keep in step with the config; none by default.
+    pub fn layouts_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.layouts_dir = Some(dir.into());
+        self
+    }
+
+    pub fn cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
+
+    pub fn history_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history_path = Some(path.into());
+        self
+    }
+
+    /// The undo stack; see [`rollback`](crate::rollback).
+    pub fn rollback_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.rollback_path = Some(path.into());
+        self
+    }
+
+    pub fn tags_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tags_path = Some(path.into());
+        self
+    }
+
+    pub fn api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = Some(api_url.into());
+        self
+    }
+
+    /// The GitHub API host `api_url` is on, for GitHub Enterprise.
+    pub fn api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = Some(api_base.into());
+        self
+    }
+
+    /// The branch, tag or commit to list the themes at instead of the
+    /// installed zellij's release tag.
+    pub fn git_ref(mut self, git_ref: impl Into<String>) -> Self {
+        self.git_ref = Some(git_ref.into());
+        self
+    }
+
+    /// The theme to list even though no file defines it, instead of
+    /// zellij's built-in `default`.
+    pub fn default_theme(mut self, name: impl Into<String>) -> Self {
+        self.default_theme = Some(name.into());
+        self
+    }
+
+    /// Lists only the themes that files define, without a default one.
+    pub fn no_default_theme(mut self) -> Self {
+        self.no_default_theme = true;
+        self
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn github_token(mut self, token: impl Into<String>) -> Self {
+        self.github_token = Some(token.into());
+        self
+    }
+
+    /// Sends every request through `url` instead of the proxies in the
+    /// environment, except to the hosts in `NO_PROXY`.
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Trusts the certificates in the PEM file at `path` as well as the
+    /// system's, e.g. those of a proxy that inspects TLS.
+    pub fn ca_bundle(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_bundle = Some(path.into());
+        self
+    }
+
+    pub fn post_apply_hook(mut self, command: impl Into<String>) -> Self {
+        self.post_apply_hook = Some(command.into());
+        self
+    }
+
+    /// The `hooks.d` directory whose executables run after applying.
+    pub fn hooks_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.hooks_dir = Some(dir.into());
+        self
+    }
+
+    /// The config node to read and write the theme name in instead of
+    /// `theme`, e.g. `ui_theme`. It should pass
+    /// [`theme_key_problem`](crate::config::theme_key_problem).
+    pub fn theme_key(mut self, key: impl Into<String>) -> Self {
+        self.theme_key = Some(key.into());
+        self
+    }
+
+    /// Fails only when no config path was given.
+    pub fn build(self) -> io::Result<ThemeData> {
+        let config_path = self.config_path.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no config path was given")
+        })?;
+        let dir = config_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        Ok(ThemeData {
+            theme_dir: self.theme_dir.unwrap_or_else(|| dir.join("themes")),
+            layouts_dir: self.layouts_dir,
+            cache_path: self.cache_path.unwrap_or_else(|| dir.join(OLD_CACHE_FILE)),
+            history_path: self.history_path.unwrap_or_else(|| dir.join(HISTORY_FILE)),
+            rollback_path: self.rollback_path.unwrap_or_else(|| dir.join(ROLLBACK_FILE)),
+            tags_path: self.tags_path.unwrap_or_else(|| dir.join(TAGS_FILE)),
             config_path,
-            theme_dir,
-            cache_path,
+            extra_config_paths: self.extra_config_paths,
+            api_url: self.api_url.unwrap_or_else(|| GITHUB_API_URL.to_string()),
+            api_base: self.api_base.unwrap_or_else(|| GITHUB_API_BASE.to_string()),
+            git_ref: self.git_ref,
+            default_theme: self.default_theme,
+            list_default_theme: !self.no_default_theme,
+            detected_ref: Arc::default(),
+            cache_ttl: self.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL),
+            request_timeout: self.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT),
+            github_token: self.github_token,
+            proxy: self.proxy,
+            ca_bundle: self.ca_bundle,
+            post_apply_hook: self.post_apply_hook,
+            hooks_dir: self.hooks_dir,
+            theme_key: self.theme_key.unwrap_or_else(|| DEFAULT_THEME_KEY.to_string()),
+            client: Arc::default(),
         })
     }
+}
 
-    fn get_config_path() -> io::Result<PathBuf> {
-        if let Ok(dir) = env::var("ZELLIJ_CONFIG_DIR") {
-            Ok(PathBuf::from(dir).join("config.kdl"))
-        } else {
-            let home = env::var("HOME").expect("HOME environment variable not set");
-            Ok(PathBuf::from(home).join(".config/zellij/config.kdl"))
+impl ThemeData {
+    /// Paths and options from `settings`, which take them from the
+    /// environment, the settings file and `config.toml`: `config.kdl`,
+    /// `themes/`, the history and the tags live in the zellij config
+    /// directory, the cache in `settings.cache_dir`. A cache an older
+    /// version left next to the config is moved there first.
+    pub fn new(settings: &Settings) -> io::Result<Self> {
+        let config_dir = settings.zellij_config_dir.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "HOME is not set, so the zellij config directory is unknown; set \
+                 ZELLIJ_CONFIG_DIR to it",
+            )
+        })?;
+
+        let (config_path, extra_config_paths) = match settings.config_files.split_first() {
+            Some((first, rest)) => (first.clone(), rest.to_vec()),
+            None => (config_dir.join("config.kdl"), Vec::new()),
+        };
+
+        let old_cache_path = config_dir.join(OLD_CACHE_FILE);
+        let cache_path = match &settings.cache_dir {
+            Some(dir) => migrate_cache(old_cache_path, dir.join(CACHE_FILE)),
+            None => old_cache_path,
+        };
+        let mut builder = Self::builder()
+            .config_path(config_path)
+            .theme_dir(config_dir.join("themes"))
+            .cache_path(cache_path)
+            .history_path(config_dir.join(HISTORY_FILE))
+            .rollback_path(config_dir.join(ROLLBACK_FILE))
+            .tags_path(config_dir.join(TAGS_FILE))
+            .api_url(settings.api_url.clone())
+            .api_base(settings.api_base.clone())
+            .cache_ttl(settings.cache_ttl)
+            .request_timeout(settings.request_timeout)
+            .theme_key(settings.theme_key.clone());
+        for path in extra_config_paths {
+            builder = builder.extra_config_path(path);
+        }
+        if settings.update_layouts {
+            builder = builder.layouts_dir(config_dir.join("layouts"));
+        }
+        if let Some(token) = &settings.github_token {
+            builder = builder.github_token(token.clone());
+        }
+        if let Some(proxy) = &settings.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(path) = &settings.ca_bundle {
+            builder = builder.ca_bundle(path.clone());
+        }
+        if let Some(git_ref) = &settings.git_ref {
+            builder = builder.git_ref(git_ref.clone());
+        }
+        if let Some(name) = &settings.default_theme {
+            builder = builder.default_theme(name.clone());
+        }
+        if !settings.list_default_theme {
+            builder = builder.no_default_theme();
+        }
+        if let Some(command) = &settings.post_apply_hook {
+            builder = builder.post_apply_hook(command.clone());
         }
+        if let Some(dir) = &settings.hooks_dir {
+            builder = builder.hooks_dir(dir.clone());
+        }
+        builder.build()
     }
 
-    fn read_cache(&self) -> Option<CacheData> {
-        if let Ok(content) = fs::read_to_string(&self.cache_path) {
-            if let Ok(cache) = serde_json::from_str::<CacheData>(&content) {
-                let now = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                
-                if now - cache.timestamp < CACHE_DURATION.as_secs() {
-                    return Some(cache);
-                }
-            }
+    /// Starts a `ThemeData` with explicit paths instead of ones derived
+    /// from the environment, e.g. to keep tests inside a temporary
+    /// directory.
+    pub fn builder() -> ThemeDataBuilder {
+        ThemeDataBuilder::default()
+    }
+
+    /// Points theme discovery at another GitHub contents API listing, e.g. a
+    /// local mock server in tests.
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    /// Sends `token` with GitHub API requests, e.g. one typed in on the
+    /// welcome screen for this run only.
+    pub fn with_github_token(mut self, token: impl Into<String>) -> Self {
+        self.github_token = Some(token.into());
+        self
+    }
+
+    pub fn theme_dir(&self) -> &Path {
+        &self.theme_dir
+    }
+
+    pub fn cache_path(&self) -> &Path {
+        &self.cache_path
+    }
+
+    pub fn cache_ttl(&self) -> Duration {
+        self.cache_ttl
+    }
+
+    /// A client whose requests give up after the configured timeout, so a
+    /// hanging server cannot stall the selector, going through the proxy
+    /// and trusting the CA bundle if any; see [`http::client_builder`]. It
+    /// is created on first use and reused after that.
+    pub fn http_client(&self) -> io::Result<reqwest::Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client.clone());
         }
-        None
+        let client = http::client_builder(
+            self.request_timeout,
+            self.proxy.as_deref(),
+            self.ca_bundle.as_deref(),
+        )?
+        .build()
+        .map_err(io::Error::other)?;
+        Ok(self.client.get_or_init(|| client).clone())
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    pub fn api_base(&self) -> &str {
+        &self.api_base
+    }
+
+    pub fn has_github_token(&self) -> bool {
+        self.github_token.is_some()
+    }
+
+    /// Whether a request to `url` goes through a proxy, and which, for
+    /// error messages; see [`http::route`].
+    pub fn route(&self, url: &str) -> String {
+        http::route(self.proxy.as_deref(), url)
+    }
+
+    /// The ref the theme listing is asked for at: the one given with
+    /// `--ref`, else the release tag of the installed zellij (e.g.
+    /// `v0.40.1`) when listing zellij's own themes. `None` lists the
+    /// default branch.
+    pub fn listing_ref(&self) -> Option<String> {
+        if self.git_ref.is_some() {
+            return self.git_ref.clone();
+        }
+        if self.is_custom_source() {
+            return None;
+        }
+        self.detected_ref
+            .get_or_init(|| {
+                let version = LiveSwitcher::default().version()?;
+                log::debug!("installed zellij is {}", version);
+                Some(format!("v{}", version))
+            })
+            .clone()
+    }
+
+    /// The `rate_limit` endpoint on `api_base`, which answers without
+    /// counting against the quota, or `None` when the themes are not
+    /// listed through that API.
+    pub fn rate_limit_url(&self) -> Option<String> {
+        let on_api = self
+            .api_url
+            .strip_prefix(self.api_base.as_str())
+            .is_some_and(|path| path.starts_with('/'));
+        on_api.then(|| format!("{}/rate_limit", self.api_base))
+    }
+
+    /// What is left of the GitHub API quota, asked from
+    /// [`rate_limit_url`](Self::rate_limit_url). `Ok(None)` when the themes
+    /// are not listed through that API or it has no rate limit.
+    pub async fn rate_limit(&self) -> io::Result<Option<RateLimit>> {
+        let Some(url) = self.rate_limit_url() else {
+            return Ok(None);
+        };
+        let client = self.http_client()?;
+        log::debug!("GET {}", url);
+        let response = self.api_request(&client, &url).send().await.map_err(io::Error::other)?;
+        let remaining = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        Ok(remaining.map(|remaining| RateLimit {
+            remaining,
+            reset: rate_limit_reset(&response),
+        }))
+    }
+
+    /// A GET request with the User-Agent GitHub insists on. The token is
+    /// only added for the GitHub API itself, or the GitHub Enterprise API
+    /// in `api_base`, and never sent to another host.
+    pub fn api_request(&self, client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+        let request = client.get(url).header("User-Agent", "zellij-theme-plugin");
+        let is_api = url
+            .strip_prefix(self.api_base.as_str())
+            .is_some_and(|path| path.starts_with('/'));
+        match &self.github_token {
+            Some(token) if is_api => request.bearer_auth(token),
+            _ => request,
+        }
+    }
+
+    /// Reads the cache regardless of its age, returning how many themes it
+    /// holds and how old it is at `now`, in seconds since the epoch. The age
+    /// is `None` when the cache is dated after `now`, which
+    /// [`cache::age`] treats as stale. `Ok(None)` means there is no cache
+    /// yet.
+    pub fn inspect_cache(&self, now: u64) -> io::Result<Option<(usize, Option<Duration>)>> {
+        let content = match fs::read_to_string(&self.cache_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let cache: CacheData = serde_json::from_str(&content)?;
+        let age = cache::age(cache.timestamp, now).map(Duration::from_secs);
+        Ok(Some((cache.themes.len(), age)))
+    }
+
+    /// Reads the cache if it is for the current ref and younger than the
+    /// TTL at `now`, in seconds since the epoch.
+    fn read_cache(&self, now: u64) -> Option<CacheData> {
+        let path = self.cache_path.display();
+        let Some(cache) = self.read_cache_any_age() else {
+            log::debug!("cache miss: {} is missing or unreadable", path);
+            return None;
+        };
+        let git_ref = self.listing_ref();
+        let Some(age) = cache::age(cache.timestamp, now) else {
+            let ahead = cache.timestamp - now;
+            log::debug!("cache miss: {} is dated {}s in the future", path, ahead);
+            return None;
+        };
+        if cache.git_ref != git_ref {
+            log::debug!(
+                "cache miss: {} was listed at {}, not {}",
+                path,
+                cache.git_ref.as_deref().unwrap_or("the default branch"),
+                git_ref.as_deref().unwrap_or("the default branch")
+            );
+            None
+        } else if age < self.cache_ttl.as_secs() {
+            log::debug!("cache hit: {} is {}s old", path, age);
+            Some(cache)
+        } else {
+            let ttl = self.cache_ttl.as_secs();
+            log::debug!("cache miss: {} is {}s old, past the {}s TTL", path, age, ttl);
+            None
+        }
+    }
+
+    fn read_cache_any_age(&self) -> Option<CacheData> {
+        cache::read(&self.cache_path)
     }
 
-    fn write_cache(&self, themes: &[String]) -> io::Result<()> {
+    /// Replaces the cache with `themes`, dated `now`.
+    fn write_cache(&self, themes: &[Theme], now: u64) -> io::Result<()> {
         let cache = CacheData {
             themes: themes.to_vec(),
-            timestamp: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            timestamp: now,
+            git_ref: self.listing_ref(),
         };
-        
-        let content = serde_json::to_string(&cache)?;
-        fs::write(&self.cache_path, content)?;
-        Ok(())
+        let _lock = self.lock_cache()?;
+        self.store_cache(&cache)
+    }
+
+    /// Takes the lock on the cache for a read-modify-write, creating its
+    /// directory if needed.
+    fn lock_cache(&self) -> io::Result<FileLock> {
+        if let Some(dir) = self.cache_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        FileLock::acquire(&self.cache_path)
+    }
+
+    /// Writes the cache; the caller holds [`lock_cache`](Self::lock_cache).
+    fn store_cache(&self, cache: &CacheData) -> io::Result<()> {
+        let content = serde_json::to_string(cache)?;
+        write_atomically(&self.cache_path, content.as_bytes())
+    }
+
+    /// Swaps the cached themes from remote `file` for `themes`, keeping the
+    /// cache's age so the rest of it expires as it would have.
+    fn update_cached_file(&self, file: &str, themes: &[Theme]) -> io::Result<()> {
+        if !self.cache_path.is_file() {
+            return Ok(());
+        }
+        let _lock = self.lock_cache()?;
+        let Some(mut cache) = self.read_cache_any_age() else {
+            return Ok(());
+        };
+        cache.themes.retain(|theme| {
+            theme.source.as_ref().is_none_or(|source| source.file != file)
+        });
+        cache.themes.extend(themes.iter().cloned());
+        cache.themes = merge_themes(cache.themes);
+        self.store_cache(&cache)
+    }
+
+    /// The text at `url`. A request that fails to get an answer names the
+    /// proxy it went through, `proxy` being the one from `--proxy`.
+    async fn download_theme_file(
+        client: &reqwest::Client,
+        url: &str,
+        proxy: Option<&str>,
+    ) -> Result<String, String> {
+        log::debug!("GET {}", url);
+        let response = client.get(url).send().await.map_err(|e| {
+            let error = format!("{} (connecting {})", e, http::route(proxy, url));
+            log::warn!("GET {} failed: {}", url, error);
+            error
+        })?;
+        log::debug!("{} answered {}", url, response.status());
+        let response = response.error_for_status().map_err(|e| e.to_string())?;
+        response.text().await.map_err(|e| e.to_string())
+    }
+
+    /// Lists the themes available to zellij: the upstream themes (cached)
+    /// merged with the theme files in `theme_dir`, which win on name clashes.
+    pub async fn fetch_themes(&self, force_refresh: bool) -> io::Result<FetchOutcome> {
+        self.collect_themes(force_refresh, None).await
+    }
+
+    /// Like [`fetch_themes`](Self::fetch_themes), but also sends each batch
+    /// of themes over `events` as soon as it is parsed, so a list can fill
+    /// in while the remaining files download. The returned outcome is the
+    /// final, deduplicated list.
+    pub async fn stream_themes(
+        &self,
+        force_refresh: bool,
+        events: UnboundedSender<FetchEvent>,
+    ) -> io::Result<FetchOutcome> {
+        self.collect_themes(force_refresh, Some(&events)).await
+    }
+
+    /// Like [`fetch_themes`](Self::fetch_themes), but never touches the
+    /// network: the cache is used however old it is, and without one only
+    /// the local themes and the default theme are listed. Fast enough for
+    /// shell completion.
+    pub fn cached_themes(&self) -> FetchOutcome {
+        let themes = self.read_cache_any_age().map(|cache| cache.themes).unwrap_or_default();
+        let mut outcome = FetchOutcome {
+            themes,
+            failures: Vec::new(),
+            changes: None,
+            kept_cache: false,
+        };
+        self.add_local_themes(&mut outcome, None);
+        self.add_default_theme(&mut outcome, None);
+        outcome
+    }
+
+    async fn collect_themes(
+        &self,
+        force_refresh: bool,
+        events: Option<&UnboundedSender<FetchEvent>>,
+    ) -> io::Result<FetchOutcome> {
+        let mut outcome = self.fetch_remote_themes(force_refresh, events).await?;
+        self.add_local_themes(&mut outcome, events);
+        self.add_default_theme(&mut outcome, events);
+        Ok(outcome)
+    }
+
+    /// The theme listed even when no file defines it: the `default_theme`
+    /// setting, else zellij's built-in `default`, which a custom source does
+    /// not have. `None` with `--no-default-theme`.
+    pub fn default_theme(&self) -> Option<&str> {
+        if !self.list_default_theme {
+            return None;
+        }
+        match &self.default_theme {
+            Some(name) => Some(name),
+            None if self.is_custom_source() => None,
+            None => Some("default"),
+        }
+    }
+
+    /// Appends [`Self::default_theme`] to the list unless a theme of that
+    /// name is already in it.
+    fn add_default_theme(
+        &self,
+        outcome: &mut FetchOutcome,
+        events: Option<&UnboundedSender<FetchEvent>>,
+    ) {
+        let Some(name) = self.default_theme() else {
+            return;
+        };
+        if outcome.themes.iter().any(|theme| theme.name == name) {
+            return;
+        }
+        emit(events, || FetchEvent::Themes(vec![Theme::new(name)]));
+        outcome.themes.push(Theme::new(name));
+    }
+
+    /// Merges the themes in `theme_dir` into a list of remote ones.
+    fn add_local_themes(
+        &self,
+        outcome: &mut FetchOutcome,
+        events: Option<&UnboundedSender<FetchEvent>>,
+    ) {
+        // Files installed from a source that still lists them are shown as
+        // that source's themes, so newer versions can be installed over them
+        let manifest = self.read_manifest();
+        let installed: HashSet<&str> = outcome
+            .themes
+            .iter()
+            .filter_map(|theme| theme.source.as_ref())
+            .map(|source| source.file.as_str())
+            .filter(|file| manifest.files.contains_key(*file))
+            .collect();
+        let (mut local, failures) = self.scan_local_themes(&installed);
+        match self.config_themes() {
+            Ok(defined) => local.extend(defined),
+            Err(e) => {
+                log::warn!("could not read the themes in {}: {}", self.config_path.display(), e)
+            }
+        }
+        emit(events, || FetchEvent::Themes(local.clone()));
+        outcome.themes.extend(local);
+        outcome.themes = merge_themes(std::mem::take(&mut outcome.themes));
+        outcome.failures.extend(failures);
+    }
+
+    /// Parses every `*.kdl` file in `theme_dir` and its subdirectories.
+    /// These are read on each run rather than cached so new files show up
+    /// immediately.
+    pub fn local_themes(&self) -> (Vec<Theme>, Vec<FetchFailure>) {
+        self.scan_local_themes(&HashSet::new())
     }
 
-    fn extract_themes_from_kdl(content: &str) -> Vec<String> {
-        if let Ok(doc) = content.parse::<KdlDocument>() {
-            // Look for the themes node
-            if let Some(themes_node) = doc.get("themes") {
-                // Get the children of the themes node
-                if let Some(children) = themes_node.children() {
-                    // Each direct child node of the themes node is a theme
-                    return children
-                        .nodes()
-                        .iter()
-                        .map(|node| node.name().to_string())
-                        .collect();
+    fn scan_local_themes(&self, skip_files: &HashSet<&str>) -> (Vec<Theme>, Vec<FetchFailure>) {
+        let mut themes = Vec::new();
+        let mut failures = Vec::new();
+        if !self.theme_dir.is_dir() {
+            return (themes, failures);
+        }
+        // Sorted so that duplicate names resolve the same way every time.
+        // Following symlinks, walkdir reports a link back to an ancestor as
+        // an error instead of descending into it again.
+        let entries = WalkDir::new(&self.theme_dir)
+            .follow_links(true)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()));
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(&self.theme_dir);
+                    log::warn!("could not scan {}: {}", path.display(), e);
+                    failures.push(FetchFailure {
+                        file: path.display().to_string(),
+                        error: e.to_string(),
+                    });
+                    continue;
                 }
+            };
+            let path = entry.path();
+            if !entry.file_type().is_file() || path.extension().is_none_or(|ext| ext != "kdl") {
+                continue;
+            }
+            if skip_files.contains(self.local_file_name(path).as_str()) {
+                continue;
+            }
+            let (found, error) = match fs::read_to_string(path) {
+                Ok(text) => extract_leniently(&text, &file_stem(path)),
+                Err(e) => (Vec::new(), Some(e.to_string())),
+            };
+            log::trace!("{}: {} themes", path.display(), found.len());
+            themes.extend(self.as_local(found, path));
+            if let Some(error) = error {
+                log::warn!("could not parse {}: {}", path.display(), error);
+                failures.push(FetchFailure {
+                    file: path.display().to_string(),
+                    error,
+                });
             }
         }
-        Vec::new()
+        (themes, failures)
     }
 
-    pub async fn fetch_themes(force_refresh: bool) -> io::Result<Vec<String>> {
-        let instance = Self::new()?;
-        
+    /// Parses one local theme file, e.g. again after it was edited.
+    pub fn read_theme_file(&self, path: &Path) -> Result<Vec<Theme>, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let themes = extract_themes(&text, &file_stem(path))?;
+        Ok(self.as_local(themes, path))
+    }
+
+    /// Marks `themes` as read from the local file at `path`.
+    fn as_local(&self, themes: Vec<Theme>, path: &Path) -> Vec<Theme> {
+        local::mark_local(themes, &self.local_file_name(path))
+    }
+
+    /// The themes defined in the `themes` block of `config.kdl`, with their
+    /// colors. Like local files they are read on each run, never cached.
+    pub fn config_themes(&self) -> Result<Vec<Theme>, String> {
+        let content = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let doc = content.parse::<KdlDocument>().map_err(|e| e.to_string())?;
+        Ok(defined_themes(&doc, &self.config_file_name()))
+    }
+
+    /// `config.kdl`, or whatever the config file is called, as shown next
+    /// to the themes it defines.
+    pub fn config_file_name(&self) -> String {
+        self.config_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// How a local theme file is named in the list: its path below
+    /// `theme_dir` with `/` separators, e.g. `catppuccin/latte.kdl`.
+    pub fn local_file_name(&self, path: &Path) -> String {
+        local::file_name(&self.theme_dir, path)
+    }
+
+    /// Fetches and parses the one file `theme` came from again, bypassing
+    /// the cache, and updates the cache with the result. A local theme's
+    /// file, or the config for a theme defined there, is simply read again.
+    /// Returns every theme the file defines.
+    pub async fn refetch_theme(&self, theme: &Theme) -> io::Result<Vec<Theme>> {
+        if theme.origin == Origin::Config {
+            return self.config_themes().map_err(io::Error::other);
+        }
+        if theme.origin == Origin::Local {
+            let path = self.theme_file(theme).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("the file of '{}' is gone", theme.name),
+                )
+            })?;
+            return self.read_theme_file(&path).map_err(io::Error::other);
+        }
+        let source = theme.source.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' is built into zellij and has no file to fetch", theme.name),
+            )
+        })?;
+        let client = self.http_client()?;
+        let text = Self::download_theme_file(&client, &source.download_url, self.proxy.as_deref())
+            .await
+            .map_err(io::Error::other)?;
+        let found = extract_themes(&text, source.file.trim_end_matches(".kdl"))
+            .map_err(io::Error::other)?;
+        let found = from_source(found, source, theme.origin);
+        if let Err(e) = self.update_cached_file(&source.file, &found) {
+            log::warn!("could not write {}: {}", self.cache_path.display(), e);
+        }
+        Ok(found)
+    }
+
+    /// The file in `theme_dir` that defines `theme`, if it has one there:
+    /// its own file for a local theme, or the installed copy of a remote one.
+    pub fn theme_file(&self, theme: &Theme) -> Option<PathBuf> {
+        // Local files may sit in subdirectories, installed copies do not
+        let (file, safe) = match theme.origin {
+            Origin::Local => {
+                let file = theme.file.as_deref()?;
+                (file, file.split('/').all(is_plain_file_name))
+            }
+            _ => {
+                let file = theme.source.as_ref()?.file.as_str();
+                (file, is_plain_file_name(file))
+            }
+        };
+        let path = self.theme_dir.join(file);
+        (safe && path.is_file()).then_some(path)
+    }
+
+    /// Whether `theme` is installed as a file in `theme_dir`: every local
+    /// theme is, a remote one once its file has been downloaded there.
+    pub fn is_installed(&self, theme: &Theme) -> bool {
+        match theme.origin {
+            Origin::Local => true,
+            Origin::Config => false,
+            Origin::Custom | Origin::Upstream => self.theme_file(theme).is_some(),
+        }
+    }
+
+    /// Removes a theme file from `theme_dir`, and its entry in the install
+    /// manifest if it was installed from a source. Refuses a path that
+    /// resolves to somewhere outside `theme_dir`, e.g. through a linked
+    /// subdirectory; a linked file is unlinked and its target left alone.
+    pub fn delete_theme_file(&self, path: &Path) -> io::Result<()> {
+        let theme_dir = fs::canonicalize(&self.theme_dir)?;
+        let parent = match path.parent() {
+            Some(parent) => fs::canonicalize(parent)?,
+            None => PathBuf::new(),
+        };
+        if !parent.starts_with(&theme_dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("it is in {}, outside the theme directory", parent.display()),
+            ));
+        }
+        if fs::symlink_metadata(path)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+        fs::remove_file(path).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => io::Error::new(
+                e.kind(),
+                format!("no permission to delete it; is {} writable?", parent.display()),
+            ),
+            _ => e,
+        })?;
+        log::info!("deleted {}", path.display());
+        let file = self.local_file_name(path);
+        self.update_manifest(|manifest| manifest.files.remove(&file).is_some())
+    }
+
+    /// Puts a copy of a remote `theme` into `theme_dir` so it can be edited:
+    /// the original file for a custom source, otherwise its colors written
+    /// out as `<name>.kdl`. Returns the path of the new file.
+    pub async fn install_for_editing(&self, theme: &Theme) -> io::Result<PathBuf> {
+        if let Install::Installed(path) = self.install_theme(theme, false).await? {
+            return Ok(path);
+        }
+        let colors = theme.colors.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no colors are known for '{}'", theme.name),
+            )
+        })?;
+        let path = self.theme_dir.join(format!("{}.kdl", theme.name));
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        self.save_theme(&theme.name, colors)
+    }
+
+    /// Installs a theme definition pasted from elsewhere, in any form a
+    /// theme file may take, as `<name>.kdl` in `theme_dir`. The snippet must
+    /// parse and define exactly one theme with colors; `name` renames it,
+    /// and is required when the colors are not wrapped in a named node. An
+    /// existing file is only replaced with `overwrite`.
+    pub fn import_theme(
+        &self,
+        snippet: &str,
+        name: Option<&str>,
+        overwrite: bool,
+    ) -> io::Result<Theme> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut themes = extract_themes(snippet, name.unwrap_or_default())
+            .map_err(|e| invalid(format!("the snippet is not valid KDL: {}", e)))?;
+        let theme = match themes.len() {
+            0 => return Err(invalid(String::from("the snippet does not define a theme"))),
+            1 => themes.remove(0),
+            _ => {
+                let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+                return Err(invalid(format!(
+                    "the snippet defines {} themes ({}); keep just one",
+                    names.len(),
+                    names.join(", ")
+                )));
+            }
+        };
+        let name = name.unwrap_or(&theme.name);
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the snippet does not name its theme",
+            ));
+        }
+        let colors = theme
+            .colors
+            .as_ref()
+            .ok_or_else(|| invalid(format!("'{}' in the snippet has no colors", theme.name)))?;
+        let path = self.theme_dir.join(format!("{}.kdl", name));
+        if path.exists() && !overwrite {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        let path = self.save_theme(name, colors)?;
+        self.read_theme_file(&path)
+            .map_err(invalid)?
+            .into_iter()
+            .find(|theme| theme.name == name)
+            .ok_or_else(|| invalid(format!("{} did not read back", path.display())))
+    }
+
+    /// Writes `colors` as a theme file named after the theme into
+    /// `theme_dir`, returning the path written.
+    pub fn save_theme(&self, name: &str, colors: &ThemeColors) -> io::Result<PathBuf> {
+        if let Some(problem) = theme_name_problem(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a valid theme name: {}", name.escape_debug(), problem),
+            ));
+        }
+        self.ensure_theme_dir()?;
+        let path = self.theme_dir.join(format!("{}.kdl", name));
+        fs::write(&path, colors.to_kdl(name))?;
+        Ok(path)
+    }
+
+    async fn fetch_remote_themes(
+        &self,
+        force_refresh: bool,
+        events: Option<&UnboundedSender<FetchEvent>>,
+    ) -> io::Result<FetchOutcome> {
         // Try to read from cache first unless force refresh is requested
         if !force_refresh {
-            if let Some(cache) = instance.read_cache() {
-                return Ok(cache.themes);
+            if let Some(cache) = self.read_cache(cache::unix_now()) {
+                emit(events, || FetchEvent::Themes(cache.themes.clone()));
+                return Ok(FetchOutcome {
+                    themes: cache.themes,
+                    failures: Vec::new(),
+                    changes: None,
+                    kept_cache: false,
+                });
             }
         }
         
         // Fetch from GitHub
-        let client = reqwest::Client::new();
-        let response = client
-            .get(GITHUB_API_URL)
-            .header("User-Agent", "zellij-theme-plugin")
-            .send()
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-            
-        let files: Vec<Value> = response
-            .json()
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let client = self.http_client()?;
+        let (files, manifest) = self.fetch_source_listing(&client).await?;
+        let manifest = match &manifest {
+            Some(manifest) => self.fetch_tag_manifest(&client, manifest).await,
+            None => BTreeMap::new(),
+        };
             
+        let origin = if self.is_custom_source() {
+            Origin::Custom
+        } else {
+            Origin::Upstream
+        };
+        let total = files.len();
+        emit(events, || FetchEvent::Progress { done: 0, total });
+
+        // Download a few files at a time. Each result goes into the slot of
+        // its file, so the merged list does not depend on which finished
+        // first; only the streamed batches arrive in completion order
+        let mut results: Vec<Option<(Vec<Theme>, Option<String>)>> = vec![None; total];
+        let mut queue = files.iter().cloned().enumerate();
+        let mut downloads = JoinSet::new();
+        let start = |downloads: &mut JoinSet<_>, (index, source): (usize, ThemeSource)| {
+            let (client, proxy) = (client.clone(), self.proxy.clone());
+            downloads.spawn(async move {
+                let url = &source.download_url;
+                let text = Self::download_theme_file(&client, url, proxy.as_deref()).await;
+                (index, text)
+            });
+        };
+        for next in queue.by_ref().take(DOWNLOAD_CONCURRENCY) {
+            start(&mut downloads, next);
+        }
+        let mut done = 0;
+        while let Some(joined) = downloads.join_next().await {
+            let (index, text) = joined.map_err(io::Error::other)?;
+            if let Some(next) = queue.next() {
+                start(&mut downloads, next);
+            }
+            let source = &files[index];
+            // Parse the KDL file, remembering why it failed if it did
+            let (found, error) = match text {
+                Ok(text) => extract_leniently(&text, source.file.trim_end_matches(".kdl")),
+                Err(error) => (Vec::new(), Some(error)),
+            };
+            if let Some(error) = &error {
+                log::warn!("could not load {}: {}", source.file, error);
+            }
+            log::trace!("{}: {} themes", source.file, found.len());
+            let mut found = from_source(found, source, origin);
+            for theme in &mut found {
+                theme.source_tags = manifest.get(&theme.name).cloned().unwrap_or_default();
+            }
+            if !found.is_empty() {
+                emit(events, || FetchEvent::Themes(found.clone()));
+            }
+            results[index] = Some((found, error));
+            done += 1;
+            emit(events, || FetchEvent::Progress { done, total });
+        }
+
         let mut themes = Vec::new();
-        
-        // Process each file
-        for file in files {
-            if let Some(name) = file["name"].as_str() {
-                if name.ends_with(".kdl") {
-                    // Get the raw content URL
-                    if let Some(download_url) = file["download_url"].as_str() {
-                        // Download and parse the KDL file
-                        if let Ok(content) = client.get(download_url).send().await {
-                            if let Ok(text) = content.text().await {
-                                // Parse the KDL file and extract theme names
-                                themes.extend(Self::extract_themes_from_kdl(&text));
-                            }
-                        }
-                    }
-                }
+        let mut failures = Vec::new();
+        for (source, (found, error)) in files.into_iter().zip(results.into_iter().flatten()) {
+            themes.extend(found);
+            if let Some(error) = error {
+                failures.push(FetchFailure {
+                    file: source.file,
+                    error,
+                });
             }
         }
-            
-        // Add default theme and sort
-        themes.push("default".to_string());
-        themes.sort();
+
+        // Keep one entry per name. The default theme is added after the
+        // local themes rather than cached, so changing it takes effect at once
+        let themes = merge_themes(themes);
         
-        // Cache the results
-        instance.write_cache(&themes)?;
+        // A flaky connection can fail most downloads at once. Caching what
+        // is left would hide the missing themes until the cache expires, so
+        // the old list stays unless the user asked for this fetch
+        let mut previous = self.read_cache_any_age();
+        if !force_refresh && !failures.is_empty() {
+            let shrunk = previous.take_if(|cache| {
+                cache.git_ref == self.listing_ref()
+                    && (themes.len() as f64) < cache.themes.len() as f64 * SHRINK_LIMIT
+            });
+            if let Some(cache) = shrunk {
+                log::warn!(
+                    "keeping the cached {} themes: {} files failed and only {} themes loaded",
+                    cache.themes.len(),
+                    failures.len(),
+                    themes.len()
+                );
+                return Ok(FetchOutcome {
+                    themes: cache.themes,
+                    failures,
+                    changes: None,
+                    kept_cache: true,
+                });
+            }
+        }
+
+        // Cache the results. Without a writable config dir the themes are
+        // still good for this run; they are just fetched again next time
+        if let Err(e) = self.write_cache(&themes, cache::unix_now()) {
+            log::warn!("could not write {}: {}", self.cache_path.display(), e);
+        }
+        let changes = previous.map(|cache| ListChanges::between(&cache.themes, &themes, &failures));
+        if let Some(changes) = changes.as_ref().filter(|changes| !changes.is_empty()) {
+            log::info!(
+                "since the last fetch: added {:?}, removed {:?}",
+                changes.added,
+                changes.removed
+            );
+        }
         
-        Ok(themes)
+        Ok(FetchOutcome {
+            themes,
+            failures,
+            changes,
+            kept_cache: false,
+        })
     }
 
-    pub fn ensure_theme_dir(&self) -> io::Result<()> {
+    /// The `*.kdl` files in the `api_url` listing, at [`Self::listing_ref`].
+    /// A release whose tag has no theme directory is tried at the old
+    /// location next, and a detected tag that is not there at all falls
+    /// back to the default branch; a ref given with `--ref` does not.
+    async fn fetch_listing(&self, client: &reqwest::Client) -> io::Result<Vec<ThemeSource>> {
+        Ok(self.fetch_source_listing(client).await?.0)
+    }
+
+    /// Like [`Self::fetch_listing`], also returning the source's tag
+    /// manifest if it lists one of [`TAG_MANIFESTS`].
+    async fn fetch_source_listing(
+        &self,
+        client: &reqwest::Client,
+    ) -> io::Result<(Vec<ThemeSource>, Option<ThemeSource>)> {
+        let urls = self.listing_urls();
+        let mut response = None;
+        for (index, url) in urls.iter().enumerate() {
+            log::debug!("GET {}", url);
+            let answer = self.api_request(client, url).send().await.map_err(|e| {
+                let error = format!("{} (connecting {})", e, self.route(url));
+                log::warn!("GET {} failed: {}", url, error);
+                io::Error::other(error)
+            })?;
+            log::debug!("{} answered {}", url, answer.status());
+            if answer.status() == reqwest::StatusCode::NOT_FOUND && index + 1 < urls.len() {
+                log::info!("{} was not found, trying {}", url, urls[index + 1]);
+                continue;
+            }
+            if !answer.status().is_success() {
+                return Err(listing_error(url, &answer));
+            }
+            response = Some(answer);
+            break;
+        }
+        let response = response.ok_or_else(|| io::Error::other("no theme listing to fetch"))?;
+        let files: Vec<Value> = response.json().await.map_err(io::Error::other)?;
+        let (manifests, files): (Vec<ThemeSource>, Vec<ThemeSource>) = files
+            .iter()
+            .filter_map(|file| {
+                Some(ThemeSource {
+                    file: file["name"].as_str()?.to_string(),
+                    download_url: file["download_url"].as_str()?.to_string(),
+                    sha: file["sha"].as_str().unwrap_or_default().to_string(),
+                })
+            })
+            .partition(|source| TAG_MANIFESTS.contains(&source.file.as_str()));
+        let manifest = TAG_MANIFESTS
+            .iter()
+            .find_map(|name| manifests.iter().find(|source| source.file == *name))
+            .cloned();
+        let files = files.into_iter().filter(|source| source.file.ends_with(".kdl")).collect();
+        Ok((files, manifest))
+    }
+
+    /// The tags `manifest` gives each theme, by name. A manifest that
+    /// cannot be read only costs the tags, so the failure is logged and no
+    /// tags are returned.
+    async fn fetch_tag_manifest(
+        &self,
+        client: &reqwest::Client,
+        manifest: &ThemeSource,
+    ) -> BTreeMap<String, Vec<String>> {
+        let url = &manifest.download_url;
+        let parsed = Self::download_theme_file(client, url, self.proxy.as_deref())
+            .await
+            .and_then(|content| tags::parse_manifest(&manifest.file, &content));
+        match parsed {
+            Ok(tags) => {
+                log::debug!("{} tags {} themes", manifest.file, tags.len());
+                tags
+            }
+            Err(e) => {
+                log::warn!("ignoring the tag manifest {}: {}", manifest.file, e);
+                BTreeMap::new()
+            }
+        }
+    }
+
+    pub fn ensure_theme_dir(&self) -> io::Result<()> {
         if !self.theme_dir.exists() {
             fs::create_dir_all(&self.theme_dir)?;
-            println!("Created theme directory at: {}", self.theme_dir.display());
+            log::info!("created theme directory {}", self.theme_dir.display());
         }
         Ok(())
     }
 
-    pub fn update_config(&self, selected_theme: &str) -> io::Result<()> {
+    /// The `config.kdl` that applying a theme edits, and that the current
+    /// theme is read from.
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// Every config applying a theme writes: [`config_path`](Self::config_path)
+    /// first, then any others given with `--config`.
+    pub fn config_paths(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.config_path.as_path())
+            .chain(self.extra_config_paths.iter().map(PathBuf::as_path))
+    }
+
+    /// Whether `api_url` lists something other than zellij's own themes,
+    /// which a GitHub Enterprise mirror of the repository still lists. Those
+    /// themes are not built into zellij, so their files have to be present
+    /// in `theme_dir` before they can be used.
+    fn is_custom_source(&self) -> bool {
+        let path = self.api_url.strip_prefix(self.api_base.as_str());
+        path != GITHUB_API_URL.strip_prefix(GITHUB_API_BASE)
+    }
+
+    /// The listings [`Self::fetch_listing`] tries, in order.
+    fn listing_urls(&self) -> Vec<String> {
+        let Some(git_ref) = self.listing_ref() else {
+            return vec![self.api_url.clone()];
+        };
+        let mut urls = vec![with_ref(&self.api_url, &git_ref)];
+        if let Some(repo) = self.api_url.strip_suffix(THEMES_PATH) {
+            urls.push(with_ref(&format!("{}{}", repo, OLD_THEMES_PATH), &git_ref));
+        }
+        if self.git_ref.is_none() {
+            urls.push(self.api_url.clone());
+        }
+        urls
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.theme_dir.join(".installed.json")
+    }
+
+    /// The listing files are installed from now, recorded with each file so
+    /// that [`stale_files`](Self::stale_files) only judges its own.
+    fn install_source(&self) -> InstallSource {
+        InstallSource {
+            api_url: self.api_url.clone(),
+            git_ref: self.listing_ref(),
+        }
+    }
+
+    fn read_manifest(&self) -> InstallManifest {
+        fs::read_to_string(self.manifest_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Reads the manifest again under its lock and writes it back if
+    /// `change` says it changed it, so installs by two instances at once do
+    /// not lose each other's entries.
+    fn update_manifest(&self, change: impl FnOnce(&mut InstallManifest) -> bool) -> io::Result<()> {
+        let path = self.manifest_path();
+        let _lock = FileLock::acquire(&path)?;
+        let mut manifest = self.read_manifest();
+        if change(&mut manifest) {
+            write_atomically(&path, serde_json::to_string(&manifest)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Downloads the file defining `theme` into `theme_dir` when it comes
+    /// from a custom source. Nothing is downloaded when the installed copy
+    /// is already the listed version, or when a file of the same name was
+    /// put there by hand. An installed file with local edits is only
+    /// replaced if `overwrite` is set.
+    pub async fn install_theme(&self, theme: &Theme, overwrite: bool) -> io::Result<Install> {
+        let Some(source) = theme.source.as_ref().filter(|_| self.is_custom_source()) else {
+            return Ok(Install::Skipped);
+        };
+        if !is_plain_file_name(&source.file) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing to install '{}' outside the theme directory", source.file),
+            ));
+        }
+        let path = self.theme_dir.join(&source.file);
+        let manifest = self.read_manifest();
+        if let Ok(existing) = fs::read(&path) {
+            match manifest.files.get(&source.file) {
+                Some(installed) if installed.sha == source.sha && !installed.sha.is_empty() => {
+                    return Ok(Install::Skipped)
+                }
+                Some(installed) if installed.content_hash != content_hash(&existing) && !overwrite => {
+                    return Ok(Install::Modified(path))
+                }
+                Some(_) => {}
+                None => return Ok(Install::Skipped),
+            }
+        }
+
+        let client = self.http_client()?;
+        let url = &source.download_url;
+        let content = Self::download_theme_file(&client, url, self.proxy.as_deref())
+            .await
+            .map_err(io::Error::other)?;
+        self.ensure_theme_dir()?;
+        fs::write(&path, &content)?;
+        let installed = InstalledFile {
+            sha: source.sha.clone(),
+            content_hash: content_hash(content.as_bytes()),
+            source: Some(self.install_source()),
+        };
+        self.update_manifest(|manifest| {
+            manifest.files.insert(source.file.clone(), installed);
+            true
+        })?;
+        log::info!("installed {} to {}", source.file, path.display());
+        Ok(Install::Installed(path))
+    }
+
+    /// Compares every theme file installed from a source with what the
+    /// source lists now. Files with a SHA in both the listing and the
+    /// install record are compared by SHA; the others are downloaded and
+    /// compared with the content that was installed. Files placed in
+    /// `theme_dir` by hand have no known source and are left out.
+    pub async fn check_installed(&self) -> io::Result<Vec<InstalledStatus>> {
+        let manifest = self.read_manifest();
+        let mut files: Vec<(&String, &InstalledFile)> = manifest
+            .files
+            .iter()
+            .filter(|(file, _)| self.theme_dir.join(file).is_file())
+            .collect();
+        if files.is_empty() {
+            return Ok(Vec::new());
+        }
+        files.sort_by_key(|(file, _)| *file);
+
+        let client = self.http_client()?;
+        let listing = self.fetch_listing(&client).await?;
+        let mut statuses = Vec::new();
+        for (file, installed) in files {
+            let modified = fs::read(self.theme_dir.join(file))
+                .is_ok_and(|content| content_hash(&content) != installed.content_hash);
+            let upstream = match listing.iter().find(|source| &source.file == file) {
+                None => Upstream::Unlisted,
+                Some(source) if !source.sha.is_empty() && !installed.sha.is_empty() => {
+                    if source.sha == installed.sha {
+                        Upstream::UpToDate
+                    } else {
+                        Upstream::Outdated
+                    }
+                }
+                Some(source) => {
+                    let url = &source.download_url;
+                    let proxy = self.proxy.as_deref();
+                    let latest = Self::download_theme_file(&client, url, proxy).await;
+                    match latest.map(|content| content_hash(content.as_bytes())) {
+                        Ok(hash) if hash == installed.content_hash => Upstream::UpToDate,
+                        Ok(_) => Upstream::Outdated,
+                        Err(error) => Upstream::Unknown(error),
+                    }
+                }
+            };
+            statuses.push(InstalledStatus {
+                file: file.clone(),
+                upstream,
+                modified,
+            });
+        }
+        Ok(statuses)
+    }
+
+    /// Theme files installed from the current source that it no longer
+    /// lists. Files put in `theme_dir` by hand, files edited since they were
+    /// installed and files from another `api_url` or ref are never
+    /// included, so these can go without losing work.
+    pub async fn stale_files(&self) -> io::Result<Vec<PathBuf>> {
+        let manifest = self.read_manifest();
+        let current = self.install_source();
+        let mut installed: Vec<(&String, &InstalledFile)> = manifest
+            .files
+            .iter()
+            .filter(|(_, installed)| installed.source.as_ref() == Some(&current))
+            .filter(|(file, _)| self.theme_dir.join(file).is_file())
+            .collect();
+        if installed.is_empty() {
+            return Ok(Vec::new());
+        }
+        installed.sort_by_key(|(file, _)| *file);
+
+        let client = self.http_client()?;
+        let listing = self.fetch_listing(&client).await?;
+        if listing.is_empty() {
+            // More likely a wrong ref or URL than every theme being gone
+            return Err(io::Error::other("the source lists no theme files"));
+        }
+        let listed: HashSet<&str> = listing.iter().map(|source| source.file.as_str()).collect();
+        Ok(installed
+            .into_iter()
+            .filter(|(file, _)| !listed.contains(file.as_str()))
+            .map(|(file, installed)| (self.theme_dir.join(file), installed))
+            .filter(|(path, installed)| {
+                fs::read(path).is_ok_and(|content| content_hash(&content) == installed.content_hash)
+            })
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Downloads every file the source lists into `theme_dir`, a few at a
+    /// time, so the themes are at hand offline. Unlike
+    /// [`install_theme`](Self::install_theme) this includes zellij's own
+    /// files. Files already installed at the listed version are skipped, and
+    /// so are files put there by hand or edited since they were installed,
+    /// unless `overwrite` is set for the latter.
+    pub async fn download_all(&self, overwrite: bool) -> io::Result<DownloadReport> {
+        let client = self.http_client()?;
+        let listing = self.fetch_listing(&client).await?;
+        let mut manifest = self.read_manifest();
+        let current_source = Some(self.install_source());
+        // The files whose entries changed, to be merged into the manifest as
+        // it is by then, once the downloads are done
+        let mut touched = Vec::new();
+        let mut report = DownloadReport::default();
+        let mut wanted = Vec::new();
+        for source in listing {
+            if !is_plain_file_name(&source.file) {
+                report.failed.push(FetchFailure {
+                    error: "refusing to write outside the theme directory".to_string(),
+                    file: source.file,
+                });
+                continue;
+            }
+            if let Ok(existing) = fs::read(self.theme_dir.join(&source.file)) {
+                let current = match manifest.files.get(&source.file) {
+                    Some(installed) if installed.content_hash != content_hash(&existing) => {
+                        !overwrite
+                    }
+                    Some(installed) => installed.sha == source.sha && !installed.sha.is_empty(),
+                    None => true,
+                };
+                if current {
+                    // Listed here, so it counts as this source's from now on,
+                    // even if an older manifest did not say where it came from
+                    match manifest.files.get_mut(&source.file) {
+                        Some(installed) if installed.source != current_source => {
+                            installed.source.clone_from(&current_source);
+                            touched.push(source.file.clone());
+                        }
+                        _ => {}
+                    }
+                    report.skipped.push(source.file);
+                    continue;
+                }
+            }
+            wanted.push(source);
+        }
+        let any_wanted = !wanted.is_empty();
+        if any_wanted {
+            self.ensure_theme_dir()?;
+        }
+
+        let mut queue = wanted.into_iter();
+        let mut downloads = JoinSet::new();
+        let start = |downloads: &mut JoinSet<_>, source: ThemeSource| {
+            let (client, proxy) = (client.clone(), self.proxy.clone());
+            downloads.spawn(async move {
+                let url = &source.download_url;
+                let text = Self::download_theme_file(&client, url, proxy.as_deref()).await;
+                (source, text)
+            });
+        };
+        for next in queue.by_ref().take(DOWNLOAD_CONCURRENCY) {
+            start(&mut downloads, next);
+        }
+        while let Some(joined) = downloads.join_next().await {
+            let (source, text) = joined.map_err(io::Error::other)?;
+            if let Some(next) = queue.next() {
+                start(&mut downloads, next);
+            }
+            // Without a SHA in the listing the file has to be downloaded to
+            // tell whether it changed
+            let path = self.theme_dir.join(&source.file);
+            let unchanged = |content: &str| {
+                fs::read(&path).is_ok_and(|existing| existing == content.as_bytes())
+            };
+            if text.as_deref().is_ok_and(unchanged) {
+                if let Some(installed) = manifest.files.get_mut(&source.file) {
+                    installed.sha = source.sha;
+                    installed.source.clone_from(&current_source);
+                    touched.push(source.file.clone());
+                }
+                report.skipped.push(source.file);
+                continue;
+            }
+            let written = text.and_then(|content| {
+                fs::write(&path, &content)
+                    .map(|()| content_hash(content.as_bytes()))
+                    .map_err(|e| e.to_string())
+            });
+            match written {
+                Ok(hash) => {
+                    manifest.files.insert(
+                        source.file.clone(),
+                        InstalledFile {
+                            sha: source.sha,
+                            content_hash: hash,
+                            source: current_source.clone(),
+                        },
+                    );
+                    touched.push(source.file.clone());
+                    report.downloaded.push(source.file);
+                }
+                Err(error) => {
+                    log::warn!("could not download {}: {}", source.file, error);
+                    report.failed.push(FetchFailure {
+                        file: source.file,
+                        error,
+                    });
+                }
+            }
+        }
+        if !touched.is_empty() {
+            self.update_manifest(|stored| {
+                for file in touched {
+                    if let Some(installed) = manifest.files.remove(&file) {
+                        stored.files.insert(file, installed);
+                    }
+                }
+                true
+            })?;
+        }
+        if any_wanted {
+            log::info!(
+                "downloaded {} theme files to {}",
+                report.downloaded.len(),
+                self.theme_dir.display()
+            );
+        }
+        report.downloaded.sort();
+        report.skipped.sort();
+        report.failed.sort_by(|a, b| a.file.cmp(&b.file));
+        Ok(report)
+    }
+
+    /// Makes `theme` the active theme in the zellij config, installing its
+    /// file first if zellij would not otherwise find it. Without `force`
+    /// the config is left as it was when that would overwrite local edits,
+    /// returning [`Install::Modified`], or when zellij would still not find
+    /// the theme, returning [`Install::Unresolved`]. A theme that is already
+    /// active is left alone too, returning [`Install::AlreadyApplied`], so
+    /// the config is not rewritten for zellij to reload; `force` applies it
+    /// again anyway, installing its file if that is out of date.
+    pub async fn update_config(&self, theme: &Theme, force: bool) -> io::Result<Install> {
+        if !force && self.is_applied(theme)? {
+            log::info!("not applying {}: it is already the active theme", theme.name);
+            return Ok(Install::AlreadyApplied);
+        }
+        let previous = self.current_theme_if_any()?;
+        let install = self.preview_theme(theme, force).await?;
+        if let Install::Modified(_) | Install::Unresolved = install {
+            return Ok(install);
+        }
+        history::record(&self.history_path, &theme.name)?;
+        self.push_rollback(previous.as_deref(), Some(&theme.name))?;
+        Ok(install)
+    }
+
+    /// Like [`update_config`](Self::update_config), but the theme is not
+    /// added to the history until [`keep_preview`](Self::keep_preview) is
+    /// called, so [`revert_preview`](Self::revert_preview) leaves no trace.
+    pub async fn preview_theme(&self, theme: &Theme, force: bool) -> io::Result<Install> {
+        let install = self.install_theme(theme, force).await?;
+        if let Install::Modified(_) = install {
+            return Ok(install);
+        }
+        if !force && !self.resolves(theme) {
+            log::info!("not applying {}: zellij would not find it", theme.name);
+            return Ok(Install::Unresolved);
+        }
+        self.set_theme_reference(Some(&theme.name))?;
+        Ok(install)
+    }
+
+    /// Whether zellij finds `theme` once the config names it: it is one of
+    /// zellij's own themes, a file in `theme_dir` defines it, or the
+    /// config's `themes` block does. Themes listed from zellij's
+    /// repository are taken to be built in, as [`install_theme`] does.
+    ///
+    /// [`install_theme`]: Self::install_theme
+    pub fn resolves(&self, theme: &Theme) -> bool {
+        let built_in = theme.origin == Origin::Upstream && theme.source.is_some();
+        built_in
+            || theme.name == "default"
+            || self.theme_file(theme).is_some()
+            || self.local_themes().0.iter().any(|local| local.name == theme.name)
+            || fs::read_to_string(&self.config_path)
+                .is_ok_and(|content| defines_theme(&content, &theme.name))
+    }
+
+    /// Runs the post-apply hook, then the scripts in `hooks.d`, once
+    /// `theme` has been written to the config. Every hook runs even if an
+    /// earlier one failed; the failures are returned and noted in the
+    /// history.
+    pub async fn run_hooks(&self, theme: &Theme) -> Vec<HookFailure> {
+        let mut failures = Vec::new();
+        if let Some(command) = self.post_apply_hook.as_deref() {
+            if let Err(e) = hook::run(command, &theme.name).await {
+                failures.push(HookFailure {
+                    hook: "post-apply hook".to_string(),
+                    error: e.to_string(),
+                });
+            }
+        }
+        let scripts = self.hooks_dir.as_deref().map(hook::scripts).unwrap_or_default();
+        let env = hook::theme_env(theme);
+        for script in scripts {
+            log::debug!("running hook {}", script.display());
+            if let Err(e) = hook::run_script(&script, &theme.name, &env).await {
+                let file = script.file_name().unwrap_or_default().to_string_lossy();
+                failures.push(HookFailure {
+                    hook: format!("hook {}", file),
+                    error: e.to_string(),
+                });
+            }
+        }
+        if !failures.is_empty() {
+            let notes: Vec<String> = failures
+                .iter()
+                .map(|failure| format!("{}: {}", failure.hook, failure.error))
+                .collect();
+            for note in &notes {
+                log::warn!("hook failed for {}: {}", theme.name, note);
+            }
+            if let Err(e) = history::record_hook_failures(&self.history_path, &notes) {
+                log::warn!("could not note the hook failures in the history: {}", e);
+            }
+        }
+        failures
+    }
+
+    /// Keeps a previewed theme, `previous` being the one set before it.
+    pub fn keep_preview(&self, name: &str, previous: Option<&str>) -> io::Result<()> {
+        history::record(&self.history_path, name)?;
+        self.push_rollback(previous, Some(name))
+    }
+
+    /// Puts back the theme that was set before a preview, as returned by
+    /// [`current_theme`](Self::current_theme); `None` removes the setting.
+    pub fn revert_preview(&self, previous: Option<&str>) -> io::Result<()> {
+        self.set_theme_reference(previous)
+    }
+
+    /// The theme `config.kdl` currently selects, if any.
+    pub fn current_theme(&self) -> io::Result<Option<String>> {
         let content = fs::read_to_string(&self.config_path)?;
-        let mut doc: KdlDocument = content.parse().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-        // Update or add theme node
-        if let Some(theme_node) = doc.get_mut("theme") {
-            // Clear existing values and entries
-            theme_node.clear_entries();
-            // Add the new theme value
-            theme_node.push(selected_theme);
+        let doc: KdlDocument = content.parse().map_err(io::Error::other)?;
+        Ok(theme_reference(&doc, &self.theme_key))
+    }
+
+    /// Removes the `theme` setting from every config, so zellij falls back
+    /// to its built-in default rather than to a user theme that happens to
+    /// be called `default`. Returns the theme that was set, or `None` when
+    /// there was none and nothing had to change.
+    pub fn reset_theme(&self) -> io::Result<Option<String>> {
+        let previous = self.current_theme()?;
+        self.set_theme_reference(None)?;
+        if previous.is_some() {
+            self.push_rollback(previous.as_deref(), None)?;
+        }
+        Ok(previous)
+    }
+
+    /// The undo stack, oldest entry first.
+    pub fn rollback(&self) -> io::Result<Rollback> {
+        rollback::load(&self.rollback_path)
+    }
+
+    /// Puts back the theme set before the last change, or none if there
+    /// was none, and returns it; `None` when there is nothing to undo. The
+    /// theme goes into the history like an applied one, but the files
+    /// are not installed again.
+    pub fn undo(&self) -> io::Result<Option<Option<String>>> {
+        self.step_rollback(Rollback::back)
+    }
+
+    /// Sets the theme the last [`undo`](Self::undo) went back from again,
+    /// like `undo` does; `None` when nothing was undone since the last
+    /// change.
+    pub fn redo(&self) -> io::Result<Option<Option<String>>> {
+        self.step_rollback(Rollback::forward)
+    }
+
+    fn step_rollback(
+        &self,
+        step: fn(&mut Rollback) -> Option<Option<String>>,
+    ) -> io::Result<Option<Option<String>>> {
+        // The config is only written once the step is known to exist; a
+        // failed write leaves the position where it was
+        let target = rollback::update(&self.rollback_path, |stack| -> io::Result<_> {
+            let mut moved = stack.clone();
+            let Some(entry) = step(&mut moved) else {
+                return Ok(None);
+            };
+            self.set_theme_reference(entry.as_deref())?;
+            *stack = moved;
+            Ok(Some(entry))
+        })??;
+        if let Some(Some(name)) = &target {
+            history::record(&self.history_path, name)?;
+        }
+        Ok(target)
+    }
+
+    /// Notes a change from `previous` to `current` on the undo stack.
+    fn push_rollback(&self, previous: Option<&str>, current: Option<&str>) -> io::Result<()> {
+        rollback::update(&self.rollback_path, |stack| stack.push(previous, current))
+    }
+
+    /// Whether every config names `theme` and zellij finds it, so applying
+    /// it would change nothing. Names are compared as parsed, so quoting
+    /// does not matter: `"theme" r"nord"` names `nord` like `theme "nord"`.
+    fn is_applied(&self, theme: &Theme) -> io::Result<bool> {
+        for path in self.config_paths() {
+            let content = match fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            let doc: KdlDocument = content.parse().map_err(io::Error::other)?;
+            if theme_reference(&doc, &self.theme_key).as_deref() != Some(theme.name.as_str()) {
+                return Ok(false);
+            }
+        }
+        Ok(self.resolves(theme))
+    }
+
+    /// [`current_theme`](Self::current_theme), with a config that does not
+    /// exist yet setting none.
+    fn current_theme_if_any(&self) -> io::Result<Option<String>> {
+        match self.current_theme() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Points the `theme` node of every layout file that has one at `name`,
+    /// or removes it for `None`, when layouts are to be kept in step.
+    /// Layouts without a `theme` node are never touched. Each file is
+    /// handled on its own; only the ones that had a node are returned.
+    pub fn update_layouts(&self, name: Option<&str>) -> Vec<LayoutUpdate> {
+        let Some(dir) = self.layouts_dir.as_ref().filter(|dir| dir.is_dir()) else {
+            return Vec::new();
+        };
+        let entries = WalkDir::new(dir)
+            .follow_links(true)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "kdl"));
+        let mut updates = Vec::new();
+        for entry in entries {
+            let path = entry.path();
+            let result = match has_theme_reference(path) {
+                Ok(false) => continue,
+                Ok(true) => set_theme_reference_in(path, name, DEFAULT_THEME_KEY),
+                Err(e) => Err(e),
+            };
+            let file = path.strip_prefix(dir).unwrap_or(path);
+            updates.push(LayoutUpdate {
+                file: file.display().to_string(),
+                result,
+            });
+        }
+        updates
+    }
+
+    /// Points the `theme "name"` setting at `name` in every config, or
+    /// removes it for `None`. Each file is written on its own, so one that
+    /// cannot be updated does not keep the others from changing; the error
+    /// then says which were updated and why the rest failed.
+    fn set_theme_reference(&self, name: Option<&str>) -> io::Result<()> {
+        if self.extra_config_paths.is_empty() {
+            return log_config_write(&self.config_path, name, &self.theme_key);
+        }
+        let mut updated = Vec::new();
+        let mut failed = Vec::new();
+        for path in self.config_paths() {
+            match log_config_write(path, name, &self.theme_key) {
+                Ok(()) => updated.push(path.display().to_string()),
+                Err(e) => failed.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        if failed.is_empty() {
+            return Ok(());
+        }
+        let updated = if updated.is_empty() {
+            String::from("none")
         } else {
-            // Create a new theme node with the value
-            let mut node = KdlNode::new("theme");
-            node.push(selected_theme);
-            doc.nodes_mut().push(node);
+            updated.join(", ")
+        };
+        Err(io::Error::other(format!(
+            "updated {}; failed {}",
+            updated,
+            failed.join("; ")
+        )))
+    }
+
+    /// Themes applied so far, oldest first.
+    pub fn history(&self) -> io::Result<Vec<HistoryEntry>> {
+        history::load(&self.history_path)
+    }
+
+    pub fn tags(&self) -> io::Result<Tags> {
+        tags::load(&self.tags_path)
+    }
+
+    /// Replaces the tags of `theme` in the file, leaving the others as they
+    /// are on disk, and returns all of them.
+    pub fn set_tags(&self, theme: &str, new: BTreeSet<String>) -> io::Result<Tags> {
+        let mut all = self.tags()?;
+        all.set(theme, new);
+        self.save_tags(&all)?;
+        Ok(all)
+    }
+
+    pub fn save_tags(&self, tags: &Tags) -> io::Result<()> {
+        tags::save(&self.tags_path, tags)
+    }
+}
+
+/// Moves a cache an older version kept at `old` to `new`, so upgrading does
+/// not cost a full fetch. Returns where the cache is to be used from, which
+/// stays `old` if it could not be moved.
+fn migrate_cache(old: PathBuf, new: PathBuf) -> PathBuf {
+    if new.exists() || !old.is_file() {
+        return new;
+    }
+    let moved = new
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        // A rename cannot cross file systems, and the cache dir is often on
+        // another one
+        .and_then(|()| {
+            fs::rename(&old, &new)
+                .or_else(|_| fs::copy(&old, &new).and_then(|_| fs::remove_file(&old)))
+        });
+    match moved {
+        Ok(()) => {
+            log::info!("moved the cache from {} to {}", old.display(), new.display());
+            new
         }
+        Err(e) => {
+            log::warn!("could not move the cache to {}: {}", new.display(), e);
+            old
+        }
+    }
+}
 
-        // Write updated document back to file
-        fs::write(&self.config_path, doc.to_string())?;
-        Ok(())
+/// [`set_theme_reference_in`], logging the outcome.
+fn log_config_write(path: &Path, name: Option<&str>, key: &str) -> io::Result<()> {
+    let result = set_theme_reference_in(path, name, key);
+    match (&result, name) {
+        (Ok(()), Some(name)) => log::info!("set theme {} in {}", name, path.display()),
+        (Ok(()), None) => log::info!("removed the theme setting from {}", path.display()),
+        (Err(e), _) => log::warn!("could not write {}: {}", path.display(), e),
+    }
+    result
+}
+
+/// `url` asking for its content at `git_ref`.
+fn with_ref(url: &str, git_ref: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}ref={}", url, separator, git_ref)
+}
+
+/// Describes a failed request for the theme listing, saying when GitHub's
+/// rate limit resets if that is why it failed.
+fn listing_error(url: &str, response: &reqwest::Response) -> io::Error {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    if header("x-ratelimit-remaining") != Some("0") {
+        return io::Error::other(format!("{} answered {}", url, response.status()));
+    }
+    io::Error::other(match rate_limit_reset(response) {
+        Some(reset) => format!("GitHub API rate limit exceeded; it resets at {}", reset.format("%H:%M")),
+        None => String::from("GitHub API rate limit exceeded"),
+    })
+}
+
+/// When the rate limit in GitHub's headers on `response` resets.
+fn rate_limit_reset(response: &reqwest::Response) -> Option<DateTime<Local>> {
+    response
+        .headers()
+        .get("x-ratelimit-reset")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .and_then(|secs| Local.timestamp_opt(secs, 0).single())
+}
+
+/// Sends an event if anyone is listening. A receiver that has gone away
+/// just means nobody wants the progress any more.
+fn emit(events: Option<&UnboundedSender<FetchEvent>>, event: impl FnOnce() -> FetchEvent) {
+    if let Some(events) = events {
+        let _ = events.send(event());
+    }
+}
+
+fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Marks themes parsed from a remote file with where they came from.
+fn from_source(themes: Vec<Theme>, source: &ThemeSource, origin: Origin) -> Vec<Theme> {
+    themes
+        .into_iter()
+        .map(|mut theme| {
+            theme.source = Some(source.clone());
+            theme.origin = origin;
+            theme.file = Some(source.file.clone());
+            theme
+        })
+        .collect()
+}
+
+/// Whether `name` is a dot file or directory, such as a `.git` checkout.
+pub(crate) fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// Why `name` cannot be a theme written to `<name>.kdl`, if it cannot. Any
+/// other name is written quoted where needed and reads back unchanged.
+fn theme_name_problem(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("it is empty")
+    } else if name.contains(['/', '\\']) {
+        Some("it is also the file name, which cannot contain '/' or '\\'")
+    } else if name.starts_with('.') {
+        Some("it is also the file name, which would be hidden")
+    } else if name.chars().any(char::is_control) {
+        Some("it contains a control character such as a newline")
+    } else if name.trim() != name {
+        Some("it starts or ends with a space")
+    } else {
+        None
+    }
+}
+
+/// Whether `name` can be used as a file name directly inside a directory.
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// The themes in a theme file and, if it is not valid KDL, why not. Such a
+/// file still lists the themes [`scan_theme_names`] finds in it, by name
+/// only, so one syntax error does not make them all disappear.
+fn extract_leniently(content: &str, file_stem: &str) -> (Vec<Theme>, Option<String>) {
+    match extract_themes(content, file_stem) {
+        Ok(themes) => (themes, None),
+        Err(error) => {
+            let names = scan_theme_names(content);
+            if !names.is_empty() {
+                log::warn!(
+                    "{} is not valid KDL; listing its themes without colors: {}",
+                    file_stem,
+                    names.join(", ")
+                );
+            }
+            (names.into_iter().map(Theme::new).collect(), Some(error))
+        }
+    }
+}
+
+/// The names of the themes a file that does not parse seems to define:
+/// the `name {` and `theme "name" {` lines directly inside a `themes`
+/// block. Only line structure and braces are looked at, so a name is found
+/// as long as the error is not in its own line.
+fn scan_theme_names(content: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut depth = 0usize;
+    let mut in_themes = false;
+    for line in content.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if let Some(head) = line.strip_suffix('{').map(str::trim_end) {
+            let name = head.strip_prefix("theme ").unwrap_or(head).trim().trim_matches('"');
+            match depth {
+                0 => in_themes = head == "themes",
+                1 if in_themes && !name.is_empty() && !name.contains(char::is_whitespace) => {
+                    names.push(name.to_string())
+                }
+                _ => {}
+            }
+        }
+        depth = (depth + line.matches('{').count()).saturating_sub(line.matches('}').count());
+    }
+    names
+}
+
+/// The file name of `path` without its extension, e.g. `nord` for
+/// `themes/nord.kdl`.
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const NORD: &str = "\
+themes {
+    nord {
+        fg 216 222 233
+        bg 46 52 64
+    }
+}
+";
+
+    /// A `ThemeData` keeping everything in `dir`, pointed at a listing
+    /// nothing answers so a test never reaches the network by mistake.
+    fn theme_data(dir: &Path) -> ThemeData {
+        ThemeData::builder()
+            .config_path(dir.join("config.kdl"))
+            .api_url("http://127.0.0.1:9/repos/o/r/contents/themes")
+            .request_timeout(Duration::from_secs(2))
+            .build()
+            .unwrap()
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[tokio::test]
+    async fn applying_the_active_theme_leaves_the_config_untouched() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("config.kdl");
+        write(&config, "theme \"nord\"\n");
+        write(&dir.path().join("themes/nord.kdl"), NORD);
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&config).unwrap().set_modified(past).unwrap();
+
+        let data = theme_data(dir.path());
+        let install = data.update_config(&Theme::new("nord"), false).await.unwrap();
+
+        assert!(matches!(install, Install::AlreadyApplied));
+        assert_eq!(fs::metadata(&config).unwrap().modified().unwrap(), past);
+        assert!(!data.history_path.exists());
+    }
+
+    #[test]
+    fn the_rate_limit_is_asked_from_the_api_the_themes_are_listed_through() {
+        let dir = TempDir::new().unwrap();
+        let data = |api_url: &str| {
+            ThemeData::builder()
+                .config_path(dir.path().join("config.kdl"))
+                .api_url(api_url)
+                .api_base("https://ghe.example.com/api/v3")
+                .build()
+                .unwrap()
+        };
+
+        let enterprise = data("https://ghe.example.com/api/v3/repos/o/r/contents/themes");
+        assert_eq!(
+            enterprise.rate_limit_url().as_deref(),
+            Some("https://ghe.example.com/api/v3/rate_limit")
+        );
+        let elsewhere = data("https://themes.example.com/repos/o/r/contents/themes");
+        assert_eq!(elsewhere.rate_limit_url(), None);
+    }
+
+    const NOW: u64 = 1_700_000_000;
+
+    /// A `ThemeData` in `dir` with an hour's TTL and a cache of one theme
+    /// written at `timestamp`.
+    fn cached_at(dir: &Path, timestamp: u64) -> ThemeData {
+        let data = ThemeData::builder()
+            .config_path(dir.join("config.kdl"))
+            .api_url("http://127.0.0.1:9/repos/o/r/contents/themes")
+            .cache_ttl(Duration::from_secs(3600))
+            .build()
+            .unwrap();
+        data.write_cache(&[Theme::new("nord")], timestamp).unwrap();
+        data
+    }
+
+    #[test]
+    fn a_fresh_cache_is_used() {
+        let dir = TempDir::new().unwrap();
+        let data = cached_at(dir.path(), NOW - 60);
+        assert!(data.read_cache(NOW).is_some());
+        let age = Some(Duration::from_secs(60));
+        assert_eq!(data.inspect_cache(NOW).unwrap(), Some((1, age)));
+    }
+
+    #[test]
+    fn a_stale_cache_is_refetched() {
+        let dir = TempDir::new().unwrap();
+        let data = cached_at(dir.path(), NOW - 7200);
+        assert!(data.read_cache(NOW).is_none());
+        let age = Some(Duration::from_secs(7200));
+        assert_eq!(data.inspect_cache(NOW).unwrap(), Some((1, age)));
+    }
+
+    #[test]
+    fn a_cache_dated_in_the_future_is_stale() {
+        let dir = TempDir::new().unwrap();
+        let data = cached_at(dir.path(), NOW + 60);
+        assert!(data.read_cache(NOW).is_none());
+        assert_eq!(data.inspect_cache(NOW).unwrap(), Some((1, None)));
+    }
+
+    #[test]
+    fn there_is_nothing_to_inspect_without_a_cache() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(theme_data(dir.path()).inspect_cache(NOW).unwrap(), None);
     }
-} 
\ No newline at end of file
+
+    /// Serves the routes `routes` gives for the server's base URL, each a
+    /// path with the status and body to answer it with, on a local port;
+    /// anything else is a 404. Returns the base URL, e.g.
+    /// `http://127.0.0.1:41234`.
+    async fn serve(routes: impl FnOnce(&str) -> Vec<(String, u16, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = routes(&base);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).into_owned();
+                let path = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let (status, body) = routes
+                    .iter()
+                    .find(|(route, _, _)| *route == path)
+                    .map_or((404, String::new()), |(_, status, body)| (*status, body.clone()));
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        base
+    }
+
+    const LISTING_PATH: &str = "/repos/o/r/contents/themes";
+
+    /// A contents API listing of `files` whose download URLs point at
+    /// `/raw/<file>` on `base`.
+    fn listing(base: &str, files: &[&str]) -> (String, u16, String) {
+        let entries: Vec<Value> = files
+            .iter()
+            .map(|file| {
+                serde_json::json!({
+                    "name": file,
+                    "download_url": format!("{}/raw/{}", base, file),
+                    "sha": format!("sha-{}", file),
+                })
+            })
+            .collect();
+        (LISTING_PATH.to_string(), 200, Value::Array(entries).to_string())
+    }
+
+    /// A `ThemeData` in `dir` listing the themes `base` serves.
+    fn served(dir: &Path, base: &str) -> ThemeData {
+        ThemeData::builder()
+            .config_path(dir.join("config.kdl"))
+            .api_url(format!("{}{}", base, LISTING_PATH))
+            .api_base(base)
+            .request_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn prune_only_judges_files_from_the_current_source() {
+        let base = serve(|base| vec![listing(base, &["kept.kdl"])]).await;
+        let dir = TempDir::new().unwrap();
+        let data = served(dir.path(), &base);
+        let current = data.install_source();
+        let elsewhere = InstallSource {
+            api_url: "https://api.github.com/repos/someone/else/contents/themes".to_string(),
+            git_ref: None,
+        };
+        let at_ref = InstallSource {
+            git_ref: Some("v1".to_string()),
+            ..current.clone()
+        };
+        let mut manifest = InstallManifest::default();
+        for (file, source) in [
+            ("kept.kdl", Some(current.clone())),
+            ("gone.kdl", Some(current)),
+            ("other.kdl", Some(elsewhere)),
+            ("tagged.kdl", Some(at_ref)),
+            ("unrecorded.kdl", None),
+        ] {
+            write(&dir.path().join("themes").join(file), file);
+            let installed = InstalledFile {
+                sha: format!("sha-{}", file),
+                content_hash: content_hash(file.as_bytes()),
+                source,
+            };
+            manifest.files.insert(file.to_string(), installed);
+        }
+        fs::write(data.manifest_path(), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let stale = data.stale_files().await.unwrap();
+
+        assert_eq!(stale, vec![dir.path().join("themes/gone.kdl")]);
+    }
+
+    #[tokio::test]
+    async fn download_all_records_the_source_of_files_it_finds_current() {
+        let base = serve(|base| vec![listing(base, &["a.kdl"])]).await;
+        let dir = TempDir::new().unwrap();
+        let data = served(dir.path(), &base);
+        write(&dir.path().join("themes/a.kdl"), "a");
+        let mut manifest = InstallManifest::default();
+        let installed = InstalledFile {
+            sha: "sha-a.kdl".to_string(),
+            content_hash: content_hash(b"a"),
+            source: None,
+        };
+        manifest.files.insert("a.kdl".to_string(), installed);
+        fs::write(data.manifest_path(), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let report = data.download_all(false).await.unwrap();
+
+        assert_eq!(report.skipped, vec!["a.kdl".to_string()]);
+        let source = data.read_manifest().files["a.kdl"].source.clone();
+        assert!(source == Some(data.install_source()));
+    }
+
+    #[test]
+    fn the_cache_reads_back_what_was_written() {
+        let dir = TempDir::new().unwrap();
+        let data = ThemeData::builder()
+            .config_path(dir.path().join("config.kdl"))
+            .cache_path(dir.path().join("cache/themes.json"))
+            .build()
+            .unwrap();
+        let themes = extract_themes(NORD, "nord").unwrap();
+
+        data.write_cache(&themes, NOW).unwrap();
+
+        let cache = data.read_cache(NOW).unwrap();
+        assert_eq!(cache.themes, themes);
+        assert_eq!(cache.timestamp, NOW);
+        assert!(dir.path().join("cache/themes.json").is_file());
+    }
+
+    #[tokio::test]
+    async fn applying_a_theme_to_a_fresh_config_adds_the_setting() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("config.kdl");
+        let data = theme_data(dir.path());
+
+        let install = data.update_config(&Theme::new("default"), false).await.unwrap();
+
+        assert!(matches!(install, Install::Skipped));
+        assert_eq!(fs::read_to_string(&config).unwrap(), "theme \"default\"\n");
+        assert_eq!(data.history().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn applying_a_theme_to_an_existing_config_only_changes_the_setting() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("config.kdl");
+        write(&config, "// mine\ntheme \"dracula\"\nmouse_mode false\n");
+        write(&dir.path().join("themes/nord.kdl"), NORD);
+        let data = theme_data(dir.path());
+        let nord = data.local_themes().0.remove(0);
+
+        data.update_config(&nord, false).await.unwrap();
+
+        let expected = "// mine\ntheme \"nord\"\nmouse_mode false\n";
+        assert_eq!(fs::read_to_string(&config).unwrap(), expected);
+        assert_eq!(data.undo().unwrap(), Some(Some("dracula".to_string())));
+        let restored = "// mine\ntheme \"dracula\"\nmouse_mode false\n";
+        assert_eq!(fs::read_to_string(&config).unwrap(), restored);
+    }
+
+    #[test]
+    fn themes_are_read_from_the_theme_dir_given() {
+        let dir = TempDir::new().unwrap();
+        let elsewhere = dir.path().join("elsewhere");
+        write(&elsewhere.join("nord.kdl"), NORD);
+        write(&elsewhere.join("notes.txt"), NORD);
+        write(&dir.path().join("themes/ignored.kdl"), NORD);
+        let data = ThemeData::builder()
+            .config_path(dir.path().join("config.kdl"))
+            .theme_dir(&elsewhere)
+            .build()
+            .unwrap();
+
+        let (themes, failures) = data.local_themes();
+
+        assert!(failures.is_empty());
+        let files: Vec<_> = themes.iter().map(|theme| theme.file.as_deref()).collect();
+        assert_eq!(files, [Some("nord.kdl")]);
+        assert_eq!(themes[0].name, "nord");
+        assert_eq!(themes[0].origin, Origin::Local);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_read_only_cache_directory_only_costs_the_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = serve(|base| {
+            let file = ("/raw/nord.kdl".to_string(), 200, NORD.to_string());
+            vec![listing(base, &["nord.kdl"]), file]
+        })
+        .await;
+        let dir = TempDir::new().unwrap();
+        let cache_dir = dir.path().join("cache");
+        fs::create_dir(&cache_dir).unwrap();
+        fs::set_permissions(&cache_dir, fs::Permissions::from_mode(0o555)).unwrap();
+        if fs::write(cache_dir.join("probe"), "").is_ok() {
+            // Running as root, so the directory is writable after all
+            return;
+        }
+        let data = ThemeData::builder()
+            .config_path(dir.path().join("config.kdl"))
+            .cache_path(cache_dir.join("themes.json"))
+            .api_url(format!("{}{}", base, LISTING_PATH))
+            .api_base(&base)
+            .build()
+            .unwrap();
+
+        let outcome = data.fetch_themes(false).await;
+
+        fs::set_permissions(&cache_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let names: Vec<String> = outcome.unwrap().themes.into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["nord"]);
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn themes_in_nested_directories_are_found() {
+        let dir = TempDir::new().unwrap();
+        let themes_dir = dir.path().join("themes");
+        let theme = |name: &str| NORD.replace("nord", name);
+        write(&themes_dir.join("catppuccin/latte.kdl"), &theme("latte"));
+        write(&themes_dir.join("catppuccin/dark/mocha.kdl"), &theme("mocha"));
+        write(&themes_dir.join("nord.kdl"), NORD);
+        write(&themes_dir.join(".git/old.kdl"), &theme("old"));
+        write(&themes_dir.join("catppuccin/README.md"), "");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&themes_dir, themes_dir.join("catppuccin/loop")).unwrap();
+
+        let (themes, failures) = theme_data(dir.path()).local_themes();
+
+        let found: Vec<(&str, Option<&str>)> =
+            themes.iter().map(|theme| (theme.name.as_str(), theme.file.as_deref())).collect();
+        assert_eq!(
+            found,
+            [
+                ("mocha", Some("catppuccin/dark/mocha.kdl")),
+                ("latte", Some("catppuccin/latte.kdl")),
+                ("nord", Some("nord.kdl")),
+            ]
+        );
+        // The link back up is reported rather than followed around
+        assert_eq!(failures.len(), usize::from(cfg!(unix)));
+    }
+
+    const BROKEN: &str = "\
+themes {
+    dracula {
+        fg 248 248 242
+        bg 40 42 54 \"unterminated
+    }
+    gruvbox {
+        fg 235 219 178
+    }
+}
+";
+
+    #[test]
+    fn a_broken_file_keeps_its_theme_names_and_reports_why() {
+        let (themes, error) = extract_leniently(BROKEN, "broken");
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["dracula", "gruvbox"]);
+        assert!(themes.iter().all(|theme| theme.colors.is_none()));
+        assert!(error.is_some());
+    }
+
+    #[test]
+    fn a_broken_file_does_not_hide_the_themes_of_the_others() {
+        let dir = TempDir::new().unwrap();
+        write(&dir.path().join("themes/broken.kdl"), BROKEN);
+        write(&dir.path().join("themes/nord.kdl"), NORD);
+
+        let (themes, failures) = theme_data(dir.path()).local_themes();
+
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["dracula", "gruvbox", "nord"]);
+        assert!(themes[2].colors.is_some());
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].file.ends_with("broken.kdl"), "{}", failures[0].file);
+    }
+
+    /// Serves a listing of `missing.kdl` and `nord.kdl`, of which only
+    /// `nord.kdl` can be downloaded.
+    async fn serve_with_a_missing_file() -> String {
+        serve(|base| {
+            let nord = ("/raw/nord.kdl".to_string(), 200, NORD.to_string());
+            vec![listing(base, &["missing.kdl", "nord.kdl"]), nord]
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn a_file_that_fails_to_download_does_not_take_the_others_with_it() {
+        let base = serve_with_a_missing_file().await;
+        let dir = TempDir::new().unwrap();
+        let data = served(dir.path(), &base);
+
+        let outcome = data.fetch_themes(false).await.unwrap();
+
+        let names: Vec<&str> = outcome.themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["nord"]);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].file, "missing.kdl");
+        assert!(outcome.failures[0].error.contains("404"), "{}", outcome.failures[0].error);
+        assert_eq!(data.read_cache_any_age().unwrap().themes, outcome.themes);
+    }
+
+    #[tokio::test]
+    async fn failures_do_not_shrink_the_cached_list_unless_forced() {
+        let base = serve_with_a_missing_file().await;
+        let dir = TempDir::new().unwrap();
+        let data = served(dir.path(), &base);
+        let cached: Vec<Theme> = ["a", "b", "c", "nord"].into_iter().map(Theme::new).collect();
+        // Dated at the epoch, so it is stale and the listing is fetched
+        data.write_cache(&cached, 0).unwrap();
+
+        let outcome = data.fetch_themes(false).await.unwrap();
+        assert!(outcome.kept_cache);
+        assert_eq!(outcome.themes, cached);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(data.read_cache_any_age().unwrap().themes, cached);
+
+        let outcome = data.fetch_themes(true).await.unwrap();
+        assert!(!outcome.kept_cache);
+        assert_eq!(outcome.themes.len(), 1);
+        assert_eq!(data.read_cache_any_age().unwrap().themes, outcome.themes);
+    }
+
+    #[test]
+    fn an_unset_home_is_an_error_rather_than_a_panic() {
+        // Changing the environment of this process would race the other
+        // tests, so the test runs again in a child without one
+        if std::env::var_os("ZELLIJ_THEME_TEST_CHILD").is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "data::tests::an_unset_home_is_an_error_rather_than_a_panic"])
+                .env_clear()
+                .env("ZELLIJ_THEME_TEST_CHILD", "1")
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(output.status.success(), "{}", stdout);
+            assert!(stdout.contains("1 passed"), "{}", stdout);
+            return;
+        }
+
+        let (settings, _) = Settings::load();
+        assert_eq!(settings.zellij_config_dir, None);
+        assert_eq!(settings.path(), None);
+        let error = ThemeData::new(&settings).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("ZELLIJ_CONFIG_DIR"), "{}", error);
+    }
+}
@@ -0,0 +1,311 @@
+//! `doctor`: checks the environment the selector depends on and explains
+//! what to do about anything that looks wrong.
+
+use crate::cache;
+use crate::data::{self, ThemeData, GITHUB_API_URL};
+use crate::logging;
+use crate::settings::Settings;
+use kdl::KdlDocument;
+use reqwest::StatusCode;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        }
+    }
+
+    /// Process exit code for a run whose worst result is `self`.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Pass => 0,
+            Status::Warn => 1,
+            Status::Fail => 2,
+        }
+    }
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Runs every check in order. Only the network check is asynchronous.
+pub async fn run_checks(settings: &Settings) -> Vec<Check> {
+    let theme_data = match ThemeData::new(settings) {
+        Ok(theme_data) => theme_data,
+        Err(e) => {
+            return vec![Check::fail(
+                "config path",
+                e.to_string(),
+                "set ZELLIJ_CONFIG_DIR to the directory containing config.kdl",
+            )]
+        }
+    };
+    let mut checks: Vec<Check> = theme_data
+        .config_paths()
+        .map(|path| check_config(path, settings))
+        .collect();
+    checks.extend([
+        check_theme_dir(&theme_data),
+        check_cache(&theme_data),
+        check_network(&theme_data).await,
+        check_zellij_binary(),
+        check_session(),
+        check_log(),
+    ]);
+    checks
+}
+
+fn check_config(path: &Path, settings: &Settings) -> Check {
+    const NAME: &str = "config";
+    let origin = settings.zellij_config_dir_origin.label();
+    match fs::read_to_string(path) {
+        Ok(content) => match content.parse::<KdlDocument>() {
+            Ok(_) => Check::pass(NAME, format!("{} (from {})", path.display(), origin)),
+            Err(e) => Check::fail(
+                NAME,
+                format!("{} does not parse: {}", path.display(), e),
+                "fix the syntax error; zellij will not load this file either",
+            ),
+        },
+        Err(e) => Check::fail(
+            NAME,
+            format!("{} (from {}): {}", path.display(), origin, e),
+            "run `zellij setup --dump-config > config.kdl` there, or point ZELLIJ_CONFIG_DIR elsewhere",
+        ),
+    }
+}
+
+fn check_theme_dir(theme_data: &ThemeData) -> Check {
+    const NAME: &str = "theme directory";
+    let dir = theme_data.theme_dir();
+    if !dir.is_dir() {
+        return Check::warn(
+            NAME,
+            format!("{} does not exist", dir.display()),
+            "it is created on the first run; only needed for local themes",
+        );
+    }
+    // Counted the way they are scanned: nested too, dot directories not
+    let count = WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !data::is_hidden(entry.file_name()))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "kdl"))
+        .count();
+    // Installing, generating and editing themes all write here
+    let probe = dir.join(format!(".doctor.{}.tmp", std::process::id()));
+    if let Err(e) = fs::write(&probe, b"") {
+        return Check::fail(
+            NAME,
+            format!("{} ({} theme files) is not writable: {}", dir.display(), count, e),
+            "fix its permissions; themes cannot be installed or generated until then",
+        );
+    }
+    let _ = fs::remove_file(&probe);
+    Check::pass(NAME, format!("{} ({} theme files, writable)", dir.display(), count))
+}
+
+fn check_cache(theme_data: &ThemeData) -> Check {
+    const NAME: &str = "theme cache";
+    let path = theme_data.cache_path();
+    match theme_data.inspect_cache(cache::unix_now()) {
+        Ok(None) => Check::pass(NAME, format!("{} not created yet", path.display())),
+        Ok(Some((themes, None))) => Check::warn(
+            NAME,
+            format!("{}: {} themes, dated in the future", path.display(), themes),
+            "the clock was set back; it is refreshed on the next run",
+        ),
+        Ok(Some((themes, Some(age)))) if age > theme_data.cache_ttl() => Check::warn(
+            NAME,
+            format!("{}: {} themes, {} old", path.display(), themes, format_age(age)),
+            "stale; it is refreshed on the next run",
+        ),
+        Ok(Some((themes, Some(age)))) => Check::pass(
+            NAME,
+            format!("{}: {} themes, {} old", path.display(), themes, format_age(age)),
+        ),
+        Err(e) => Check::warn(
+            NAME,
+            format!("{} is unreadable: {}", path.display(), e),
+            "run with --force-refresh to rebuild it",
+        ),
+    }
+}
+
+async fn check_network(theme_data: &ThemeData) -> Check {
+    const NAME: &str = "network";
+    // GitHub reports the remaining quota without spending any of it here
+    let rate_limit_url = theme_data.rate_limit_url();
+    let url = rate_limit_url.as_deref().unwrap_or(theme_data.api_url());
+    let client = match theme_data.http_client() {
+        Ok(client) => client,
+        Err(e) => {
+            return Check::fail(
+                NAME,
+                e.to_string(),
+                "check ZELLIJ_THEME_CA_BUNDLE and the TLS setup of this system",
+            )
+        }
+    };
+    let response = match theme_data.api_request(&client, url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Check::fail(
+                NAME,
+                format!("{} is unreachable {}: {}", url, theme_data.route(url), e),
+                "check your connection or proxy; cached themes still work offline",
+            )
+        }
+    };
+    let remaining = response
+        .headers()
+        .get("x-ratelimit-remaining")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let token = if theme_data.has_github_token() {
+        "authenticated with GITHUB_TOKEN"
+    } else {
+        "anonymous"
+    };
+    match (response.status(), remaining) {
+        (StatusCode::UNAUTHORIZED, _) if theme_data.has_github_token() => Check::fail(
+            NAME,
+            format!("{} rejected GITHUB_TOKEN", url),
+            "the token is invalid or expired; create a new one or unset GITHUB_TOKEN",
+        ),
+        (_, Some(0)) => Check::warn(
+            NAME,
+            format!("GitHub API rate limit exhausted ({})", token),
+            if theme_data.has_github_token() {
+                "wait for the hourly reset; cached themes are used meanwhile"
+            } else {
+                "wait for the hourly reset, or set GITHUB_TOKEN for a higher limit"
+            },
+        ),
+        (status, Some(remaining)) if status.is_success() => Check::pass(
+            NAME,
+            format!("{} reachable, {} API requests left ({})", url, remaining, token),
+        ),
+        (status, _) if status.is_success() => {
+            let source = if theme_data.api_url() == GITHUB_API_URL {
+                "GitHub API"
+            } else {
+                url
+            };
+            Check::pass(NAME, format!("{} reachable", source))
+        }
+        (status, _) => Check::fail(
+            NAME,
+            format!("{} answered {}", url, status),
+            "check api_url and api_base in the settings file",
+        ),
+    }
+}
+
+fn check_zellij_binary() -> Check {
+    const NAME: &str = "zellij binary";
+    match Command::new("zellij").arg("--version").output() {
+        Ok(output) if output.status.success() => Check::pass(
+            NAME,
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ),
+        Ok(output) => Check::warn(
+            NAME,
+            format!("`zellij --version` exited with {}", output.status),
+            "reinstall zellij",
+        ),
+        Err(_) => Check::warn(
+            NAME,
+            "zellij is not on PATH",
+            "install zellij or add it to PATH; themes can still be written to the config",
+        ),
+    }
+}
+
+fn check_session() -> Check {
+    const NAME: &str = "zellij session";
+    match env::var("ZELLIJ_SESSION_NAME") {
+        Ok(session) => Check::pass(NAME, format!("running inside session '{}'", session)),
+        Err(_) if env::var_os("ZELLIJ").is_some() => Check::pass(NAME, "running inside a session"),
+        Err(_) => Check::warn(
+            NAME,
+            "not running inside a zellij session",
+            "theme changes take effect the next time zellij starts",
+        ),
+    }
+}
+
+fn check_log() -> Check {
+    const NAME: &str = "log";
+    match logging::active_path() {
+        Some(path) => Check::pass(
+            NAME,
+            format!("{} (level {})", path.display(), log::max_level()),
+        ),
+        None => Check::warn(
+            NAME,
+            "not writing a log",
+            "set XDG_STATE_HOME or HOME, or pass --log-file with a writable path",
+        ),
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+/// The editor to run: `$VISUAL`, then `$EDITOR`, then `vi`. It may carry
+/// arguments, e.g. `code --wait`.
+pub fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .map(|command| command.trim().to_string())
+        .find(|command| !command.is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Opens `path` in the editor and waits for it to exit. The caller has to
+/// hand the terminal over first.
+pub fn edit(path: &Path) -> io::Result<ExitStatus> {
+    let command = editor_command();
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    Command::new(program).args(words).arg(path).status()
+}
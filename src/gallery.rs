@@ -0,0 +1,168 @@
+//! `export-gallery`: every known theme as a card on one static HTML page,
+//! e.g. for a team wiki.
+//!
+//! The page is self-contained: the CSS is inline and there is no script.
+//! Nothing in it depends on when or where it was made, and themes are
+//! sorted by name within each source, so a copy kept in git only changes
+//! when a theme does.
+
+use crate::color::Rgb;
+use crate::theme::{Appearance, Origin, Theme};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; background: #f4f4f4; color: #222; }
+h2 { margin-top: 2em; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; }
+.card { width: 16em; border: 1px solid #ccc; border-radius: 6px; background: #fff; \
+padding: 0.75em; }
+.name { font-weight: bold; }
+.badge { float: right; font-size: 0.8em; padding: 0 0.5em; border-radius: 3px; }
+.dark { background: #333; color: #eee; }
+.light { background: #eee; color: #333; border: 1px solid #ccc; }
+.swatches { display: flex; margin: 0.5em 0; }
+.swatches span { flex: 1; height: 1.5em; }
+.sample { font-family: monospace; padding: 0.5em; border-radius: 3px; }
+.missing { color: #888; font-style: italic; padding: 0.5em 0; }
+.file { color: #888; font-size: 0.8em; margin-top: 0.5em; }
+";
+
+/// The page for `themes`, grouped by where they came from in the order
+/// definitions win: the config, local files, a custom source, zellij.
+pub fn render(themes: &[Theme]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Zellij themes</title>\n");
+    html.push_str(&format!("<style>\n{}</style>\n</head>\n<body>\n", STYLE));
+    html.push_str(&format!("<h1>Zellij themes ({})</h1>\n", themes.len()));
+    for origin in [Origin::Config, Origin::Local, Origin::Custom, Origin::Upstream] {
+        let mut group: Vec<&Theme> =
+            themes.iter().filter(|theme| theme.origin == origin).collect();
+        if group.is_empty() {
+            continue;
+        }
+        group.sort_by(|a, b| a.name.cmp(&b.name));
+        html.push_str(&format!("<h2>{} ({})</h2>\n", heading(origin), group.len()));
+        html.push_str("<div class=\"cards\">\n");
+        for theme in group {
+            card(&mut html, theme);
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn heading(origin: Origin) -> &'static str {
+    match origin {
+        Origin::Config => "Defined in config.kdl",
+        Origin::Local => "Local theme files",
+        Origin::Custom => "Custom source",
+        Origin::Upstream => "Zellij",
+    }
+}
+
+/// One theme: name and appearance, a strip of its colors and sample text,
+/// or a placeholder when its colors are unknown.
+fn card(html: &mut String, theme: &Theme) {
+    html.push_str("<div class=\"card\">\n");
+    let appearance = match theme.appearance {
+        Appearance::Unknown => String::new(),
+        appearance => format!("<span class=\"badge {0}\">{0}</span>", appearance.label()),
+    };
+    html.push_str(&format!(
+        "<div><span class=\"name\">{}</span>{}</div>\n",
+        escape(&theme.name),
+        appearance
+    ));
+    match &theme.colors {
+        Some(colors) => {
+            let mut slots = vec![("fg", colors.fg), ("bg", colors.bg)];
+            slots.extend(colors.ansi());
+            html.push_str("<div class=\"swatches\">");
+            for (slot, color) in slots {
+                if let Some(rgb) = color {
+                    html.push_str(&format!(
+                        "<span style=\"background: {0}\" title=\"{1} {0}\"></span>",
+                        rgb.to_hex(),
+                        slot
+                    ));
+                }
+            }
+            html.push_str("</div>\n");
+            if let (Some(fg), Some(bg)) = (colors.fg, colors.bg) {
+                html.push_str(&sample(fg, bg));
+            }
+        }
+        None => html.push_str("<div class=\"missing\">No colors known</div>\n"),
+    }
+    if let Some(file) = &theme.file {
+        html.push_str(&format!("<div class=\"file\">{}</div>\n", escape(file)));
+    }
+    html.push_str("</div>\n");
+}
+
+fn sample(fg: Rgb, bg: Rgb) -> String {
+    format!(
+        "<div class=\"sample\" style=\"color: {}; background: {}\">$ echo sample text</div>\n",
+        fg.to_hex(),
+        bg.to_hex()
+    )
+}
+
+/// `text` with the characters HTML gives a meaning replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::ThemeColors;
+
+    /// A dark theme from the config, and one with a name and file that need
+    /// escaping whose colors are unknown.
+    fn fixtures() -> Vec<Theme> {
+        let colors = ThemeColors {
+            fg: Some(Rgb::new(216, 222, 233)),
+            bg: Some(Rgb::new(46, 52, 64)),
+            red: Some(Rgb::new(191, 97, 106)),
+            ..ThemeColors::default()
+        };
+        let mut nord = Theme::with_colors("nord", Some(colors));
+        nord.origin = Origin::Config;
+        nord.file = Some("config.kdl".to_string());
+        let mut awkward = Theme::new("<b>\"Tom & Jerry's\"</b>");
+        awkward.origin = Origin::Local;
+        awkward.file = Some("cartoons/<tom&jerry>.kdl".to_string());
+        let mut plain = Theme::new("default");
+        plain.origin = Origin::Upstream;
+        vec![plain, awkward, nord]
+    }
+
+    #[test]
+    fn the_page_matches_the_golden_file() {
+        let golden = include_str!("../tests/fixtures/gallery.html");
+        let page = render(&fixtures());
+        assert!(page == golden, "the gallery changed; the page is now:\n{}", page);
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        let page = render(&fixtures());
+        assert!(!page.contains("<b>") && !page.contains("<tom"));
+    }
+}
@@ -0,0 +1,83 @@
+//! A record of applied themes, so an earlier choice can be found again.
+
+use crate::config::write_atomically;
+use crate::lock::FileLock;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The history's name in the zellij config directory.
+pub const HISTORY_FILE: &str = ".theme_history.json";
+
+/// How many applications are remembered; older ones are dropped first.
+pub const HISTORY_LEN: usize = 200;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub theme: String,
+    /// Seconds since the Unix epoch.
+    pub applied_at: u64,
+    /// Hooks that failed after this application, as `hook: error`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hook_failures: Vec<String>,
+}
+
+impl HistoryEntry {
+    /// The time of application in local time, e.g. `2024-03-05 14:02`.
+    pub fn applied_at_label(&self) -> String {
+        Local
+            .timestamp_opt(self.applied_at as i64, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| self.applied_at.to_string())
+    }
+}
+
+/// Reads the history at `path`, oldest first. A missing file is an empty
+/// history.
+pub fn load(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Appends `theme` to the history at `path`. Applying the same theme twice
+/// in a row only moves the timestamp of the last entry forward.
+pub fn record(path: &Path, theme: &str) -> io::Result<()> {
+    let _lock = FileLock::acquire(path)?;
+    let mut entries = load(path)?;
+    let applied_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    match entries.last_mut() {
+        Some(last) if last.theme == theme => {
+            last.applied_at = applied_at;
+            last.hook_failures.clear();
+        }
+        _ => entries.push(HistoryEntry {
+            theme: theme.to_string(),
+            applied_at,
+            hook_failures: Vec::new(),
+        }),
+    }
+    let excess = entries.len().saturating_sub(HISTORY_LEN);
+    entries.drain(..excess);
+    write_atomically(path, serde_json::to_string(&entries)?.as_bytes())
+}
+
+/// Notes on the latest entry at `path` which hooks failed after it.
+pub fn record_hook_failures(path: &Path, failures: &[String]) -> io::Result<()> {
+    let _lock = FileLock::acquire(path)?;
+    let mut entries = load(path)?;
+    let Some(last) = entries.last_mut() else {
+        return Ok(());
+    };
+    last.hook_failures = failures.to_vec();
+    write_atomically(path, serde_json::to_string(&entries)?.as_bytes())
+}
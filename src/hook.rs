@@ -0,0 +1,150 @@
+//! The post-apply hook: a user command run after a theme was written to the
+//! config, e.g. to switch other programs to a matching theme. It comes from
+//! `post_apply_hook` in the settings file or `ZELLIJ_THEME_POST_HOOK`.
+//!
+//! Like the editor command it is a program followed by arguments, not a
+//! shell line; the theme name is passed as the last argument. Its output is
+//! captured so it cannot draw over the TUI, and a failure is only reported:
+//! the theme stays applied.
+//!
+//! After it, every executable in `hooks.d` next to the settings file runs
+//! in lexical order, with the theme name as its only argument and the
+//! theme's colors in `THEME_*` environment variables; see [`theme_env`].
+//! Each gets its own timeout, and one failing does not stop the next.
+
+use crate::theme::Theme;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// How long the hook may run before it is killed and reported as failed.
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs `command` with `theme` as its last argument and waits for it. An
+/// unsuccessful exit becomes an error carrying the status and the last
+/// line the hook wrote to stderr.
+pub async fn run(command: &str, theme: &str) -> io::Result<()> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the post-apply hook is empty")
+    })?;
+    let child = Command::new(program)
+        .args(words)
+        .arg(theme)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run {}: {}", program, e)))?;
+    wait(program, child).await
+}
+
+/// A hook that failed, as returned by
+/// [`ThemeData::run_hooks`](crate::data::ThemeData::run_hooks).
+#[derive(Clone, Debug)]
+pub struct HookFailure {
+    /// `post-apply hook`, or `hook` and the script's file name.
+    pub hook: String,
+    pub error: String,
+}
+
+/// The executables in `dir` in the order they run: sorted by file name,
+/// leaving out hidden files, directories and files without an execute bit.
+/// A missing directory has none.
+pub fn scripts(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut scripts: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| fs::metadata(path).is_ok_and(|meta| is_executable(&meta)))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    meta.is_file() && meta.permissions().mode() & 0o111 != 0
+}
+
+/// Without an execute bit to go by, every file counts.
+#[cfg(not(unix))]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    meta.is_file()
+}
+
+/// The environment a script in `hooks.d` gets for `theme`: `THEME_NAME`,
+/// `THEME_APPEARANCE` (`dark`, `light` or `unknown`), and a `#rrggbb`
+/// `THEME_FG`, `THEME_BG`, `THEME_BLACK` through `THEME_ORANGE`,
+/// `THEME_SELECTION_FG`, `THEME_SELECTION_BG`, `THEME_FRAME_SELECTED` and
+/// `THEME_FRAME_UNSELECTED` for each color the theme sets.
+pub fn theme_env(theme: &Theme) -> Vec<(String, String)> {
+    let mut env = vec![
+        ("THEME_NAME".to_string(), theme.name.clone()),
+        ("THEME_APPEARANCE".to_string(), theme.appearance.label().to_string()),
+    ];
+    let Some(colors) = &theme.colors else {
+        return env;
+    };
+    let mut slots = vec![("fg", colors.fg), ("bg", colors.bg)];
+    slots.extend(colors.ansi());
+    slots.extend([
+        ("selection_fg", colors.selection_fg),
+        ("selection_bg", colors.selection_bg),
+        ("frame_selected", colors.frame_selected),
+        ("frame_unselected", colors.frame_unselected),
+    ]);
+    for (slot, color) in slots {
+        if let Some(rgb) = color {
+            env.push((format!("THEME_{}", slot.to_uppercase()), rgb.to_hex()));
+        }
+    }
+    env
+}
+
+/// Runs the script at `path` with `theme` as its argument and `env` added
+/// to its environment, under the same timeout as the post-apply hook.
+pub async fn run_script(path: &Path, theme: &str, env: &[(String, String)]) -> io::Result<()> {
+    let program = path.file_name().unwrap_or_default().to_string_lossy();
+    let child = Command::new(path)
+        .arg(theme)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run {}: {}", program, e)))?;
+    wait(&program, child).await
+}
+
+/// Waits for a hook, killing it after [`HOOK_TIMEOUT`].
+async fn wait(program: &str, child: Child) -> io::Result<()> {
+    let output = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not finish within {}s", program, HOOK_TIMEOUT.as_secs()),
+            )
+        })??;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last_line = stderr.lines().map(str::trim).rfind(|line| !line.is_empty());
+    let message = match last_line {
+        Some(line) => format!("{} exited with {}: {}", program, output.status, line),
+        None => format!("{} exited with {}", program, output.status),
+    };
+    Err(io::Error::other(message))
+}
@@ -0,0 +1,215 @@
+//! The HTTP client theme downloads go through, and how it reaches a host.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy, Url};
+
+/// A client builder that gives up after `timeout`. Without `proxy` it
+/// follows `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` as reqwest
+/// does by default; with one, every request goes through it except for the
+/// hosts in `NO_PROXY`. The certificates in the PEM file `ca_bundle` are
+/// trusted on top of the system's.
+pub fn client_builder(
+    timeout: Duration,
+    proxy: Option<&str>,
+    ca_bundle: Option<&Path>,
+) -> io::Result<ClientBuilder> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| io::Error::other(format!("invalid proxy {}: {}", proxy, e)))?;
+        builder = builder.proxy(proxy.no_proxy(NoProxy::from_env()));
+    }
+    if let Some(path) = ca_bundle {
+        for certificate in read_ca_bundle(path)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+/// The certificates in the PEM file at `path`, failing if it holds none.
+fn read_ca_bundle(path: &Path) -> io::Result<Vec<Certificate>> {
+    let error = |kind, problem: String| {
+        let message = format!("CA bundle {}: {}", path.display(), problem);
+        io::Error::new(kind, message)
+    };
+    let pem = fs::read(path).map_err(|e| error(e.kind(), e.to_string()))?;
+    let certificates = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| error(io::ErrorKind::InvalidData, e.to_string()))?;
+    if certificates.is_empty() {
+        let problem = "it holds no PEM certificates".to_string();
+        return Err(error(io::ErrorKind::InvalidData, problem));
+    }
+    Ok(certificates)
+}
+
+/// How a request to `url` gets there, for error messages: e.g. "through
+/// proxy http://proxy:3128 from HTTPS_PROXY" or "without a proxy". It
+/// mirrors the choice [`client_builder`] leaves to reqwest, checking
+/// `NO_PROXY` by host name only.
+pub fn route(proxy: Option<&str>, url: &str) -> String {
+    route_with(proxy, url, |var| env::var(var).ok())
+}
+
+/// [`route`] with the environment looked up by `var`.
+fn route_with(proxy: Option<&str>, url: &str, var: impl Fn(&str) -> Option<String>) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return "without a proxy".to_string();
+    };
+    let proxy = match proxy {
+        Some(proxy) => Some(format!("{} from --proxy", proxy)),
+        None => {
+            let vars: &[&str] = if url.scheme() == "https" {
+                &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+            } else {
+                &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+            };
+            vars.iter().find_map(|name| {
+                let value = var(name).filter(|value| !value.is_empty())?;
+                Some(format!("{} from {}", value, name))
+            })
+        }
+    };
+    let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']);
+    let no_proxy = var("NO_PROXY").or_else(|| var("no_proxy")).unwrap_or_default();
+    match proxy {
+        Some(_) if bypasses_proxy(host, &no_proxy) => {
+            "without a proxy, as NO_PROXY lists it".to_string()
+        }
+        Some(proxy) => format!("through proxy {}", proxy),
+        None => "without a proxy".to_string(),
+    }
+}
+
+/// Whether the `NO_PROXY` list `no_proxy` exempts `host`: listed exactly,
+/// as a parent domain, or with `*`. Address ranges are not checked.
+fn bypasses_proxy(host: &str, no_proxy: &str) -> bool {
+    no_proxy.split(',').map(str::trim).filter(|entry| !entry.is_empty()).any(|entry| {
+        let domain = entry.trim_start_matches('.');
+        entry == "*"
+            || host == domain
+            || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A self-signed certificate, as a CA bundle holds them.
+    const CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBozCCAUmgAwIBAgIUUyObuxFhVRVN2fTIqDHoMBaMHzIwCgYIKoZIzj0EAwIw
+JjEkMCIGA1UEAwwbemVsbGlqLXRoZW1lLXBsdWdpbiB0ZXN0IENBMCAXDTI2MTAx
+NTE0MjYzNVoYDzIxMjYwOTIxMTQyNjM1WjAmMSQwIgYDVQQDDBt6ZWxsaWotdGhl
+bWUtcGx1Z2luIHRlc3QgQ0EwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAS/p/UV
+H9Lqdx9o35PD9nLD4mn+W8eVBKZ1K9ITQSsYvfb6wHFqFkyU53tVpjJPY58vvHZx
+mmU7oz7/uAtbsvD7o1MwUTAdBgNVHQ4EFgQU+M+ek3tk7TkxZaGtFhfg4M1Kvn0w
+HwYDVR0jBBgwFoAU+M+ek3tk7TkxZaGtFhfg4M1Kvn0wDwYDVR0TAQH/BAUwAwEB
+/zAKBggqhkjOPQQDAgNIADBFAiEA74Cq21pwrINjw7HEVgrtZsr1HGf+7sjmgu6/
+c5YvHk4CIGBU1JOJJK4fcT1gWHSZxHBZOOMlGVpjymlBvTxsRO4d
+-----END CERTIFICATE-----
+";
+
+    #[tokio::test]
+    async fn requests_go_through_the_proxy_given() {
+        // A proxy that answers every request itself, echoing the request line
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).into_owned();
+            let line = request.lines().next().unwrap_or_default().to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                line.len(),
+                line
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = client_builder(TIMEOUT, Some(&proxy), None).unwrap().build().unwrap();
+        let response = client.get("http://themes.invalid/nord.kdl").send().await.unwrap();
+
+        let body = response.text().await.unwrap();
+        assert_eq!(body, "GET http://themes.invalid/nord.kdl HTTP/1.1");
+    }
+
+    #[test]
+    fn an_invalid_proxy_is_an_error() {
+        let error = client_builder(TIMEOUT, Some("http://[::1"), None).unwrap_err();
+        assert!(error.to_string().starts_with("invalid proxy http://[::1: "), "{}", error);
+    }
+
+    #[test]
+    fn a_ca_bundle_has_to_hold_certificates() {
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        fs::write(&bundle, format!("{}{}", CERTIFICATE, CERTIFICATE)).unwrap();
+        assert_eq!(read_ca_bundle(&bundle).unwrap().len(), 2);
+        assert!(client_builder(TIMEOUT, None, Some(&bundle)).is_ok());
+
+        fs::write(&bundle, "not a certificate\n").unwrap();
+        let error = client_builder(TIMEOUT, None, Some(&bundle)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("it holds no PEM certificates"), "{}", error);
+
+        let missing = dir.path().join("missing.pem");
+        let error = client_builder(TIMEOUT, None, Some(&missing)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("CA bundle "), "{}", error);
+    }
+
+    /// [`route_with`] in an environment holding just `vars`.
+    fn route_in(vars: &[(&str, &str)], proxy: Option<&str>, url: &str) -> String {
+        route_with(proxy, url, |var| {
+            vars.iter().find(|(name, _)| *name == var).map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn the_route_names_the_proxy_and_where_it_came_from() {
+        let url = "https://api.github.com/repos";
+        let proxy = "http://proxy:3128";
+        assert_eq!(route_in(&[], None, url), "without a proxy");
+        assert_eq!(
+            route_in(&[("HTTPS_PROXY", proxy)], None, url),
+            "through proxy http://proxy:3128 from HTTPS_PROXY"
+        );
+        assert_eq!(
+            route_in(&[("HTTP_PROXY", proxy), ("all_proxy", "socks5://p")], None, url),
+            "through proxy socks5://p from all_proxy"
+        );
+        assert_eq!(route_in(&[("HTTPS_PROXY", "")], None, url), "without a proxy");
+        assert_eq!(
+            route_in(&[("HTTPS_PROXY", proxy)], Some("http://mine:8080"), url),
+            "through proxy http://mine:8080 from --proxy"
+        );
+    }
+
+    #[test]
+    fn no_proxy_exempts_hosts_and_their_subdomains() {
+        let bypassed = "without a proxy, as NO_PROXY lists it";
+        let route = |no_proxy: &str, url: &str| {
+            route_in(&[("HTTPS_PROXY", "http://p:1"), ("no_proxy", no_proxy)], None, url)
+        };
+        assert_eq!(route("github.com", "https://api.github.com/"), bypassed);
+        assert_eq!(route(" .github.com , x", "https://github.com/"), bypassed);
+        assert_eq!(route("*", "https://example.org/"), bypassed);
+        assert_eq!(route("::1", "https://[::1]:8443/"), bypassed);
+        let proxied = "through proxy http://p:1 from HTTPS_PROXY";
+        assert_eq!(route("hub.com", "https://github.com/"), proxied);
+    }
+}
@@ -0,0 +1,29 @@
+//! Just enough image decoding to pull a palette out of a wallpaper. Only
+//! the colors matter, so decoders are free to downsample.
+
+mod jpeg;
+mod png;
+
+use crate::color::Rgb;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Loads a PNG or JPEG file and returns its visible pixels. Fully
+/// transparent pixels are dropped and JPEGs come back at 1/8 scale.
+pub fn load_pixels(path: &Path) -> io::Result<Vec<Rgb>> {
+    let bytes = fs::read(path)?;
+    let pixels = if bytes.starts_with(png::SIGNATURE) {
+        png::decode(&bytes)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg::decode(&bytes)
+    } else {
+        Err(String::from("unrecognized image format, expected PNG or JPEG"))
+    };
+    pixels.map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
@@ -0,0 +1,411 @@
+//! A baseline JPEG decoder that only reconstructs the DC coefficient of
+//! each 8x8 block. The DC term is the block's average, so the result is
+//! the image at 1/8 scale without any inverse DCT work.
+
+use crate::color::Rgb;
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    prediction: i32,
+    /// Block averages, `blocks_wide` per row.
+    blocks: Vec<f32>,
+    blocks_wide: usize,
+}
+
+#[derive(Clone, Default)]
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u8>,
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    fn bit(&mut self) -> u32 {
+        if self.bits == 0 {
+            let byte = match self.data.get(self.pos) {
+                // A stuffed 0xFF00 is a literal 0xFF; any other marker ends the data
+                Some(0xFF) if self.data.get(self.pos + 1) == Some(&0x00) => {
+                    self.pos += 2;
+                    0xFF
+                }
+                Some(0xFF) | None => 0,
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                }
+            };
+            self.buffer = byte as u32;
+            self.bits = 8;
+        }
+        self.bits -= 1;
+        (self.buffer >> self.bits) & 1
+    }
+
+    fn bits(&mut self, count: u8) -> u32 {
+        (0..count).fold(0, |acc, _| (acc << 1) | self.bit())
+    }
+
+    /// Drops any partial byte and skips the next restart marker.
+    fn restart(&mut self) {
+        self.bits = 0;
+        while self.pos + 1 < self.data.len() {
+            if self.data[self.pos] == 0xFF && (0xD0..=0xD7).contains(&self.data[self.pos + 1]) {
+                self.pos += 2;
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0usize);
+        for &count in &table.counts {
+            code |= self.bit() as i32;
+            let count = count as i32;
+            if code - count < first {
+                return table
+                    .symbols
+                    .get(index + (code - first) as usize)
+                    .copied()
+                    .ok_or_else(|| String::from("invalid Huffman code"));
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid Huffman code"))
+    }
+
+    /// Reads a `size`-bit DC difference. Baseline DC sizes stop at 11.
+    fn receive_extend(&mut self, size: u8) -> Result<i32, String> {
+        if size == 0 {
+            return Ok(0);
+        }
+        if size > 11 {
+            return Err(format!("invalid JPEG DC coefficient size {}", size));
+        }
+        let value = self.bits(size) as i32;
+        Ok(if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        })
+    }
+}
+
+fn segment(bytes: &[u8], pos: usize) -> Result<&[u8], String> {
+    let length = u16::from_be_bytes([
+        *bytes.get(pos).ok_or("truncated JPEG")?,
+        *bytes.get(pos + 1).ok_or("truncated JPEG")?,
+    ]) as usize;
+    bytes
+        .get(pos + 2..pos + length)
+        .ok_or_else(|| String::from("truncated JPEG segment"))
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+    let mut quant_dc = [1u16; 4];
+    let mut dc_tables = vec![Huffman::default(); 4];
+    let mut ac_tables = vec![Huffman::default(); 4];
+    let mut components: Vec<Component> = Vec::new();
+    let (mut width, mut height) = (0usize, 0usize);
+    let mut restart_interval = 0usize;
+
+    let mut pos = 2;
+    loop {
+        // Find the next marker, skipping fill bytes
+        while bytes.get(pos) == Some(&0xFF) && bytes.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&0xFF) {
+            return Err(String::from("malformed JPEG marker"));
+        }
+        let marker = *bytes.get(pos + 1).ok_or("truncated JPEG")?;
+        pos += 2;
+        match marker {
+            0xD8 | 0x01 | 0xD0..=0xD7 => continue,
+            0xD9 => return Err(String::from("JPEG has no image data")),
+            _ => {}
+        }
+        let data = segment(bytes, pos)?;
+        match marker {
+            // DQT: only the DC entry (index 0) of each table is needed
+            0xDB => {
+                let mut i = 0;
+                while i < data.len() {
+                    let precision = data[i] >> 4;
+                    let id = (data[i] & 0x0F) as usize % 4;
+                    quant_dc[id] = if precision == 0 {
+                        *data.get(i + 1).ok_or("truncated DQT")? as u16
+                    } else {
+                        u16::from_be_bytes([
+                            *data.get(i + 1).ok_or("truncated DQT")?,
+                            *data.get(i + 2).ok_or("truncated DQT")?,
+                        ])
+                    };
+                    i += 1 + 64 * (precision as usize + 1);
+                }
+            }
+            // SOF0/SOF1: baseline and extended sequential Huffman frames
+            0xC0 | 0xC1 => {
+                if data.len() < 6 {
+                    return Err(String::from("truncated JPEG frame header"));
+                }
+                height = u16::from_be_bytes([data[1], data[2]]) as usize;
+                width = u16::from_be_bytes([data[3], data[4]]) as usize;
+                let count = data[5] as usize;
+                if count != 1 && count != 3 {
+                    return Err(String::from("only grayscale and YCbCr JPEGs are supported"));
+                }
+                for c in data[6..].chunks_exact(3).take(count) {
+                    components.push(Component {
+                        id: c[0],
+                        h: (c[1] >> 4).max(1) as usize,
+                        v: (c[1] & 0x0F).max(1) as usize,
+                        quant_table: (c[2] & 0x0F) as usize % 4,
+                        dc_table: 0,
+                        ac_table: 0,
+                        prediction: 0,
+                        blocks: Vec::new(),
+                        blocks_wide: 0,
+                    });
+                }
+            }
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(String::from(
+                    "progressive and lossless JPEGs are not supported, re-save as baseline or PNG",
+                ));
+            }
+            // DHT
+            0xC4 => {
+                let mut i = 0;
+                while i + 17 <= data.len() {
+                    let class = data[i] >> 4;
+                    let id = (data[i] & 0x0F) as usize % 4;
+                    let mut table = Huffman::default();
+                    for (n, count) in table.counts.iter_mut().enumerate() {
+                        *count = data[i + 1 + n] as u16;
+                    }
+                    let total: usize = table.counts.iter().map(|&c| c as usize).sum();
+                    table.symbols = data
+                        .get(i + 17..i + 17 + total)
+                        .ok_or("truncated DHT")?
+                        .to_vec();
+                    if class == 0 {
+                        dc_tables[id] = table;
+                    } else {
+                        ac_tables[id] = table;
+                    }
+                    i += 17 + total;
+                }
+            }
+            // DRI
+            0xDD if data.len() >= 2 => {
+                restart_interval = u16::from_be_bytes([data[0], data[1]]) as usize
+            }
+            // SOS
+            0xDA if !data.is_empty() => {
+                let count = data[0] as usize;
+                if count != components.len() {
+                    return Err(String::from("non-interleaved JPEG scans are not supported"));
+                }
+                for c in data[1..].chunks_exact(2).take(count) {
+                    let component = components
+                        .iter_mut()
+                        .find(|component| component.id == c[0])
+                        .ok_or("JPEG scan references an unknown component")?;
+                    component.dc_table = (c[1] >> 4) as usize % 4;
+                    component.ac_table = (c[1] & 0x0F) as usize % 4;
+                }
+                let scan = &bytes[pos + 2 + data.len()..];
+                decode_scan(
+                    scan,
+                    &mut components,
+                    &dc_tables,
+                    &ac_tables,
+                    &quant_dc,
+                    (width, height),
+                    restart_interval,
+                )?;
+                return Ok(to_rgb(&components));
+            }
+            _ => {}
+        }
+        pos += 2 + data.len();
+    }
+}
+
+fn decode_scan(
+    scan: &[u8],
+    components: &mut [Component],
+    dc_tables: &[Huffman],
+    ac_tables: &[Huffman],
+    quant_dc: &[u16; 4],
+    (width, height): (usize, usize),
+    restart_interval: usize,
+) -> Result<(), String> {
+    if components.is_empty() || width == 0 || height == 0 {
+        return Err(String::from("JPEG has no frame header"));
+    }
+    let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+    let mcus_wide = width.div_ceil(8 * h_max);
+    let mcus_high = height.div_ceil(8 * v_max);
+    // A lone component is not interleaved: one block per MCU, cropped to the image
+    let single = components.len() == 1;
+    let (mcus_wide, mcus_high) = if single {
+        (width.div_ceil(8), height.div_ceil(8))
+    } else {
+        (mcus_wide, mcus_high)
+    };
+    for component in components.iter_mut() {
+        let (h, v) = if single { (1, 1) } else { (component.h, component.v) };
+        component.blocks_wide = mcus_wide * h;
+        component.blocks = vec![0.0; mcus_wide * h * mcus_high * v];
+    }
+
+    let mut reader = BitReader::new(scan);
+    for mcu in 0..mcus_wide * mcus_high {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart();
+            for component in components.iter_mut() {
+                component.prediction = 0;
+            }
+        }
+        let (mcu_x, mcu_y) = (mcu % mcus_wide, mcu / mcus_wide);
+        for component in components.iter_mut() {
+            let (h, v) = if single { (1, 1) } else { (component.h, component.v) };
+            for by in 0..v {
+                for bx in 0..h {
+                    let size = reader.decode(&dc_tables[component.dc_table])?;
+                    component.prediction += reader.receive_extend(size)?;
+                    skip_ac(&mut reader, &ac_tables[component.ac_table])?;
+                    // The dequantized DC term is eight times the block's mean
+                    let dc = component.prediction * quant_dc[component.quant_table] as i32;
+                    let x = mcu_x * h + bx;
+                    let y = mcu_y * v + by;
+                    component.blocks[y * component.blocks_wide + x] = dc as f32 / 8.0 + 128.0;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_ac(reader: &mut BitReader, table: &Huffman) -> Result<(), String> {
+    let mut k = 1;
+    while k < 64 {
+        let symbol = reader.decode(table)?;
+        let (run, size) = (symbol >> 4, symbol & 0x0F);
+        if size == 0 {
+            if run != 15 {
+                // End of block
+                break;
+            }
+            k += 16;
+        } else {
+            k += run as usize;
+            reader.bits(size);
+            k += 1;
+        }
+    }
+    Ok(())
+}
+
+fn to_rgb(components: &[Component]) -> Vec<Rgb> {
+    let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    let luma = &components[0];
+    let rows = luma.blocks.len() / luma.blocks_wide.max(1);
+    let mut pixels = Vec::with_capacity(luma.blocks.len());
+    for y in 0..rows {
+        for x in 0..luma.blocks_wide {
+            let value = luma.blocks[y * luma.blocks_wide + x];
+            if components.len() < 3 {
+                let v = clamp(value);
+                pixels.push(Rgb::new(v, v, v));
+                continue;
+            }
+            // Chroma planes may be subsampled relative to luma
+            let chroma = |c: &Component| {
+                let cx = x * c.h / luma.h;
+                let cy = y * c.v / luma.v;
+                c.blocks
+                    .get(cy * c.blocks_wide + cx)
+                    .copied()
+                    .unwrap_or(128.0)
+                    - 128.0
+            };
+            let (cb, cr) = (chroma(&components[1]), chroma(&components[2]));
+            pixels.push(Rgb::new(
+                clamp(value + 1.402 * cr),
+                clamp(value - 0.344_136 * cb - 0.714_136 * cr),
+                clamp(value + 1.772 * cb),
+            ));
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x8 grayscale JPEG whose only DC code is the one-bit code `0`
+    /// for `dc_symbol`, followed by an immediate end of block.
+    fn jpeg(dc_symbol: u8) -> Vec<u8> {
+        let mut counts = [0u8; 16];
+        counts[0] = 1;
+        let mut bytes = vec![0xFF, 0xD8];
+        // SOF0: 8 bits, 8x8, one component with table 0
+        bytes.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8, 0, 8, 0, 8, 1, 1, 0x11, 0]);
+        // DHT: a DC table and an AC table holding only end of block
+        bytes.extend_from_slice(&[0xFF, 0xC4, 0, 38, 0x00]);
+        bytes.extend_from_slice(&counts);
+        bytes.extend_from_slice(&[dc_symbol, 0x10]);
+        bytes.extend_from_slice(&counts);
+        bytes.push(0x00);
+        // SOS, then the two zero bits of the only block and EOI
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0, 8, 1, 1, 0x00, 0, 63, 0]);
+        bytes.extend_from_slice(&[0x00, 0xFF, 0xD9]);
+        bytes
+    }
+
+    #[test]
+    fn a_block_with_no_dc_difference_is_mid_gray() {
+        assert_eq!(decode(&jpeg(0)).unwrap(), [Rgb::new(128, 128, 128)]);
+    }
+
+    #[test]
+    fn the_largest_baseline_dc_size_decodes() {
+        // Eleven zero bits are the most negative difference, clamped to black
+        assert_eq!(decode(&jpeg(11)).unwrap(), [Rgb::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn a_dc_size_past_eleven_is_an_error_not_an_overflow() {
+        for size in [12, 32, 255] {
+            let error = decode(&jpeg(size)).unwrap_err();
+            assert!(error.contains("DC coefficient size"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::color::Rgb;
+
+pub const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Whether the PNG spec allows this bit depth for this color type.
+    fn is_valid(&self) -> bool {
+        match self.color_type {
+            0 => matches!(self.bit_depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(self.bit_depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(self.bit_depth, 8 | 16),
+            _ => false,
+        }
+    }
+}
+
+/// Decodes a non-interlaced PNG of any color type and bit depth.
+pub fn decode(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut palette_alpha = Vec::new();
+    let mut idat = Vec::new();
+
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let data = bytes
+            .get(pos + 8..pos + 8 + length)
+            .ok_or("truncated PNG chunk")?;
+        match kind {
+            b"IHDR" => {
+                if data.len() < 13 {
+                    return Err(String::from("invalid PNG header"));
+                }
+                if data[12] != 0 {
+                    return Err(String::from("interlaced PNGs are not supported"));
+                }
+                header = Some(Header {
+                    width: u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize,
+                    height: u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize,
+                    bit_depth: data[8],
+                    color_type: data[9],
+                });
+            }
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|c| Rgb::new(c[0], c[1], c[2]))
+                    .collect();
+            }
+            // For indexed images tRNS holds one alpha value per palette entry
+            b"tRNS" => palette_alpha = data.to_vec(),
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        // Skip the data and the trailing CRC
+        pos += 12 + length;
+    }
+
+    let header = header.ok_or("missing PNG header")?;
+    if !header.is_valid() {
+        return Err(format!(
+            "unsupported PNG color type {} with bit depth {}",
+            header.color_type, header.bit_depth
+        ));
+    }
+
+    let bits_per_pixel = header.channels() * header.bit_depth as usize;
+    let pixel_bytes = bits_per_pixel.div_ceil(8);
+    let too_large = || String::from("PNG dimensions are too large");
+    let stride = header
+        .width
+        .checked_mul(bits_per_pixel)
+        .ok_or_else(too_large)?
+        .div_ceil(8);
+    let expected = (stride + 1)
+        .checked_mul(header.height)
+        .ok_or_else(too_large)?;
+    let capacity = header
+        .width
+        .checked_mul(header.height)
+        .ok_or_else(too_large)?;
+
+    let data = miniz_oxide::inflate::decompress_to_vec_zlib(&idat)
+        .map_err(|e| format!("corrupt PNG data: {:?}", e))?;
+    if data.len() < expected {
+        return Err(String::from("truncated PNG data"));
+    }
+
+    let mut pixels = Vec::with_capacity(capacity);
+    let mut previous = vec![0u8; stride];
+    let mut row = vec![0u8; stride];
+    for line in data.chunks_exact(stride + 1).take(header.height) {
+        row.copy_from_slice(&line[1..]);
+        unfilter(line[0], &mut row, &previous, pixel_bytes)?;
+        read_row(&header, &palette, &palette_alpha, &row, &mut pixels);
+        std::mem::swap(&mut row, &mut previous);
+    }
+    Ok(pixels)
+}
+
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], pixel_bytes: usize) -> Result<(), String> {
+    for i in 0..row.len() {
+        let left = if i >= pixel_bytes { row[i - pixel_bytes] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= pixel_bytes { previous[i - pixel_bytes] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(format!("invalid PNG filter type {}", filter)),
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn read_row(
+    header: &Header,
+    palette: &[Rgb],
+    palette_alpha: &[u8],
+    row: &[u8],
+    pixels: &mut Vec<Rgb>,
+) {
+    let depth = header.bit_depth as usize;
+    let channels = header.channels();
+    // Samples scaled to 8 bits; 16-bit samples keep their high byte
+    let sample = |index: usize| -> u8 {
+        match depth {
+            16 => row[index * 2],
+            8 => row[index],
+            _ => {
+                let bit = index * depth;
+                let raw = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                if header.color_type == 3 {
+                    raw
+                } else {
+                    (raw as u16 * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+    for x in 0..header.width {
+        let base = x * channels;
+        let (color, alpha) = match header.color_type {
+            0 => {
+                let v = sample(base);
+                (Rgb::new(v, v, v), 255)
+            }
+            2 => (Rgb::new(sample(base), sample(base + 1), sample(base + 2)), 255),
+            3 => {
+                let index = sample(base) as usize;
+                match palette.get(index) {
+                    Some(&color) => (color, palette_alpha.get(index).copied().unwrap_or(255)),
+                    None => continue,
+                }
+            }
+            4 => {
+                let v = sample(base);
+                (Rgb::new(v, v, v), sample(base + 1))
+            }
+            _ => (
+                Rgb::new(sample(base), sample(base + 1), sample(base + 2)),
+                sample(base + 3),
+            ),
+        };
+        if alpha > 0 {
+            pixels.push(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(data);
+        // The decoder never checks the CRC
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    fn png(width: u32, height: u32, bit_depth: u8, color_type: u8, raw: &[u8]) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend(chunk(b"IHDR", &header));
+        let idat = miniz_oxide::deflate::compress_to_vec_zlib(raw, 6);
+        bytes.extend(chunk(b"IDAT", &idat));
+        bytes.extend(chunk(b"IEND", &[]));
+        bytes
+    }
+
+    #[test]
+    fn an_rgb_image_decodes_row_by_row() {
+        let raw = [
+            0, 255, 0, 0, 0, 0, 255, // filter byte, red, blue
+            0, 0, 0, 0, 255, 255, 255, // filter byte, black, white
+        ];
+        assert_eq!(
+            decode(&png(2, 2, 8, 2, &raw)).unwrap(),
+            [
+                Rgb::new(255, 0, 0),
+                Rgb::new(0, 0, 255),
+                Rgb::new(0, 0, 0),
+                Rgb::new(255, 255, 255),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unknown_color_type_is_an_error_not_a_panic() {
+        let error = decode(&png(1, 1, 8, 7, &[0, 0])).unwrap_err();
+        assert!(error.contains("color type 7"), "{}", error);
+    }
+
+    #[test]
+    fn a_bit_depth_the_color_type_does_not_allow_is_an_error() {
+        // RGB needs 8 or 16 bits per sample, and indexed images stop at 8
+        assert!(decode(&png(1, 1, 4, 2, &[0, 0])).is_err());
+        assert!(decode(&png(1, 1, 16, 3, &[0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn dimensions_that_overflow_are_an_error_not_a_panic() {
+        let error = decode(&png(u32::MAX, u32::MAX, 16, 6, &[])).unwrap_err();
+        assert!(error.contains("too large"), "{}", error);
+    }
+
+    #[test]
+    fn data_shorter_than_the_header_promises_is_truncated() {
+        let error = decode(&png(4, 4, 8, 0, &[0, 1, 2, 3, 4])).unwrap_err();
+        assert!(error.contains("truncated"), "{}", error);
+    }
+}
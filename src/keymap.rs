@@ -0,0 +1,612 @@
+//! Key bindings for the selector's normal mode.
+//!
+//! Bindings map a sequence of key chords, written like `"ctrl-n"`,
+//! `"shift-enter"` or `"g g"`, to an [`Action`]. The defaults can be
+//! overridden per action from the `keys` block of the settings file:
+//!
+//! ```kdl
+//! keys {
+//!     move_down "ctrl-n" "down"
+//!     apply "l"
+//! }
+//! ```
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use kdl::{KdlNode, KdlValue};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Something the user can ask the selector to do from the theme list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveDown,
+    MoveUp,
+    Apply,
+    Quit,
+    Search,
+    ClearFilter,
+    ToggleContrastFilter,
+    CycleAppearance,
+    CopyName,
+    CopyDefinition,
+    CopyRestartCommand,
+    ToggleFamily,
+    ToggleGrouping,
+    ShowHistory,
+    ShowFailures,
+    Preview,
+    Mark,
+    Compare,
+    Refresh,
+    RefreshTheme,
+    Edit,
+    EditColors,
+    NewTheme,
+    Reset,
+    Undo,
+    Redo,
+    First,
+    Last,
+    DeleteFile,
+    Reveal,
+    Tag,
+    Help,
+}
+
+impl Action {
+    pub const ALL: [Action; 32] = [
+        Action::MoveDown,
+        Action::MoveUp,
+        Action::Apply,
+        Action::Quit,
+        Action::Search,
+        Action::ClearFilter,
+        Action::ToggleContrastFilter,
+        Action::CycleAppearance,
+        Action::CopyName,
+        Action::CopyDefinition,
+        Action::CopyRestartCommand,
+        Action::ToggleFamily,
+        Action::ToggleGrouping,
+        Action::ShowHistory,
+        Action::ShowFailures,
+        Action::Preview,
+        Action::Mark,
+        Action::Compare,
+        Action::Refresh,
+        Action::RefreshTheme,
+        Action::Edit,
+        Action::EditColors,
+        Action::NewTheme,
+        Action::Reset,
+        Action::Undo,
+        Action::Redo,
+        Action::First,
+        Action::Last,
+        Action::DeleteFile,
+        Action::Reveal,
+        Action::Tag,
+        Action::Help,
+    ];
+
+    /// The name used for the action in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveDown => "move_down",
+            Action::MoveUp => "move_up",
+            Action::Apply => "apply",
+            Action::Quit => "quit",
+            Action::Search => "search",
+            Action::ClearFilter => "clear_filter",
+            Action::ToggleContrastFilter => "toggle_contrast",
+            Action::CycleAppearance => "cycle_appearance",
+            Action::CopyName => "copy_name",
+            Action::CopyDefinition => "copy_definition",
+            Action::CopyRestartCommand => "copy_restart_command",
+            Action::ToggleFamily => "toggle_family",
+            Action::ToggleGrouping => "toggle_grouping",
+            Action::ShowHistory => "show_history",
+            Action::ShowFailures => "show_failures",
+            Action::Preview => "preview",
+            Action::Mark => "mark",
+            Action::Compare => "compare",
+            Action::Refresh => "refresh",
+            Action::RefreshTheme => "refresh_theme",
+            Action::Edit => "edit",
+            Action::EditColors => "edit_colors",
+            Action::NewTheme => "new_theme",
+            Action::Reset => "reset",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::DeleteFile => "delete_file",
+            Action::Reveal => "reveal",
+            Action::Tag => "tag",
+            Action::Help => "help",
+        }
+    }
+
+    /// What the action does, for the help overlay.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::MoveDown => "Move down",
+            Action::MoveUp => "Move up",
+            Action::Apply => "Apply the theme, or open/close a family",
+            Action::Quit => "Quit",
+            Action::Search => "Filter by name or #tag",
+            Action::ClearFilter => "Clear the filter, cancel a fetch or go back",
+            Action::ToggleContrastFilter => "Hide low-contrast themes",
+            Action::CycleAppearance => "Show all, dark or light themes",
+            Action::CopyName => "Copy the theme name",
+            Action::CopyDefinition => "Copy the theme definition",
+            Action::CopyRestartCommand => "Copy the command restarting Zellij",
+            Action::ToggleFamily => "Open/close the family",
+            Action::ToggleGrouping => "Group themes by family or not",
+            Action::ShowHistory => "Show recently applied themes",
+            Action::ShowFailures => "Show the theme files that failed to load",
+            Action::Preview => "Preview the theme for a few seconds",
+            Action::Mark => "Mark the theme for comparison",
+            Action::Compare => "Compare marked themes",
+            Action::Refresh => "Fetch the theme list again",
+            Action::RefreshTheme => "Fetch the theme's file again",
+            Action::Edit => "Edit the theme file",
+            Action::EditColors => "Change the local theme's colors",
+            Action::NewTheme => "Start a new theme from this one's colors",
+            Action::Reset => "Reset to Zellij's default theme",
+            Action::Undo => "Go back to the theme set before",
+            Action::Redo => "Redo the theme change just undone",
+            Action::First => "Go to the first theme",
+            Action::Last => "Go to the last theme",
+            Action::DeleteFile => "Delete the local theme file",
+            Action::Reveal => "Show the theme file in the file manager",
+            Action::Tag => "Edit the theme's tags",
+            Action::Help => "Show this help",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::MoveDown => &["down", "j"],
+            Action::MoveUp => &["up", "k"],
+            Action::Apply => &["enter"],
+            Action::Quit => &["q"],
+            Action::Search => &["/"],
+            Action::ClearFilter => &["esc"],
+            Action::ToggleContrastFilter => &["a"],
+            Action::CycleAppearance => &["d"],
+            Action::CopyName => &["Y"],
+            Action::CopyDefinition => &["y"],
+            Action::CopyRestartCommand => &["K"],
+            Action::ToggleFamily => &["space"],
+            Action::ToggleGrouping => &["f"],
+            Action::ShowHistory => &["h"],
+            Action::ShowFailures => &["L"],
+            Action::Preview => &["p"],
+            Action::Mark => &["m"],
+            Action::Compare => &["c"],
+            Action::Refresh => &["R"],
+            Action::RefreshTheme => &["r"],
+            Action::Edit => &["e"],
+            Action::EditColors => &["E"],
+            Action::NewTheme => &["N"],
+            Action::Reset => &["delete", "backspace"],
+            Action::Undo => &["u"],
+            Action::Redo => &["ctrl-r"],
+            Action::First => &["g g", "home"],
+            Action::Last => &["G", "end"],
+            Action::DeleteFile => &["x"],
+            Action::Reveal => &["o"],
+            Action::Tag => &["t"],
+            Action::Help => &["?"],
+        }
+    }
+}
+
+/// A single key press together with its modifiers. Shift is folded into
+/// the character for printable keys, so `"G"` and `"shift-g"` are the same
+/// chord.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            // Terminals report shift-tab as its own key
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::Tab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses a chord such as `j`, `ctrl-n`, `shift-enter` or `f5`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // A trailing "-" is the minus key itself, not a separator
+        while let Some((prefix, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", prefix, text)),
+            };
+            rest = key;
+        }
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            name => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                        Some(n @ 1..=24) => KeyCode::F(n),
+                        _ => return Err(format!("unknown key '{}'", text)),
+                    },
+                }
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => write!(f, "{}", format!("{:?}", code).to_ascii_lowercase()),
+        }
+    }
+}
+
+fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+    let chords = text
+        .split_whitespace()
+        .map(KeyChord::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if chords.is_empty() {
+        return Err(String::from("empty key binding"));
+    }
+    Ok(chords)
+}
+
+fn sequence_label(sequence: &[KeyChord]) -> String {
+    sequence
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How long a partly typed sequence waits for its next key.
+const SEQUENCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Translates key presses into actions, holding on to the first keys of a
+/// multi-key sequence until it is complete or times out.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyChord>, Action)>,
+    pending: Vec<KeyChord>,
+    last_key: Option<Instant>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .flat_map(|action| {
+                action
+                    .default_keys()
+                    .iter()
+                    .map(move |key| (parse_sequence(key).expect("valid default key"), action))
+            })
+            .collect();
+        Self {
+            bindings,
+            pending: Vec::new(),
+            last_key: None,
+        }
+    }
+}
+
+impl Keymap {
+    /// Builds a keymap from the `keys` block of the settings file. `source`
+    /// is the whole file, used to point warnings at the offending line.
+    /// Actions that are not mentioned keep their default keys, and any
+    /// binding that cannot be parsed or clashes with another is skipped.
+    pub fn from_node(node: &KdlNode, source: &str) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut warnings = Vec::new();
+        let Some(children) = node.children() else {
+            return (keymap, warnings);
+        };
+
+        let mut custom: Vec<(Vec<KeyChord>, Action, usize)> = Vec::new();
+        for child in children.nodes() {
+            let line = line_of(source, child.span().offset());
+            let Some(action) = Action::from_name(child.name().value()) else {
+                warnings.push(format!(
+                    "{}: unknown action '{}'",
+                    line_label(source, line),
+                    child.name().value()
+                ));
+                continue;
+            };
+            for entry in child.entries() {
+                let line = line_of(source, entry.span().offset());
+                let result = match entry.value() {
+                    KdlValue::String(keys) if entry.name().is_none() => parse_sequence(keys),
+                    _ => Err(String::from("key bindings must be strings like \"ctrl-n\"")),
+                };
+                match result {
+                    Ok(sequence) => custom.push((sequence, action, line)),
+                    Err(error) => {
+                        warnings.push(format!("{}: {}", line_label(source, line), error))
+                    }
+                }
+            }
+        }
+
+        // Configured actions replace their defaults entirely
+        keymap
+            .bindings
+            .retain(|(_, action)| !custom.iter().any(|(_, a, _)| a == action));
+        for (sequence, action, line) in custom {
+            let clash = keymap.bindings.iter().find(|(other, other_action)| {
+                let shorter = sequence.len().min(other.len());
+                sequence[..shorter] == other[..shorter]
+                    && !(other == &sequence && *other_action == action)
+            });
+            match clash {
+                Some((other, other_action)) => warnings.push(format!(
+                    "{}: '{}' conflicts with '{}' for {}, ignoring it",
+                    line_label(source, line),
+                    sequence_label(&sequence),
+                    sequence_label(other),
+                    other_action.name()
+                )),
+                None => keymap.bindings.push((sequence, action)),
+            }
+        }
+        (keymap, warnings)
+    }
+
+    /// Feeds one key press in. Returns the bound action once a complete
+    /// sequence has been typed, or `None` while a sequence is still being
+    /// entered or when the keys are not bound at all.
+    pub fn handle(&mut self, event: KeyEvent) -> Option<Action> {
+        let now = Instant::now();
+        if self
+            .last_key
+            .is_some_and(|last| now.duration_since(last) > SEQUENCE_TIMEOUT)
+        {
+            self.pending.clear();
+        }
+        self.last_key = Some(now);
+        self.pending.push(KeyChord::from(event));
+        loop {
+            if let Some((_, action)) = self.bindings.iter().find(|(seq, _)| *seq == self.pending) {
+                self.pending.clear();
+                return Some(*action);
+            }
+            if self
+                .bindings
+                .iter()
+                .any(|(seq, _)| seq.starts_with(&self.pending))
+            {
+                return None;
+            }
+            // A dead end; let the last key start a new sequence instead
+            if self.pending.len() > 1 {
+                self.pending.drain(..self.pending.len() - 1);
+            } else {
+                self.pending.clear();
+                return None;
+            }
+        }
+    }
+
+    /// Whether the first keys of a sequence have been typed and the next
+    /// one is awaited.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Forgets a partly typed sequence.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// The keys bound to `action`, for help text.
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(sequence, _)| sequence_label(sequence))
+            .collect()
+    }
+}
+
+/// 1-based line number of a byte offset into `source`.
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn line_label(source: &str, line: usize) -> String {
+    let text = source.lines().nth(line - 1).unwrap_or_default().trim();
+    format!("line {} `{}`", line, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kdl::KdlDocument;
+
+    /// The keymap and warnings for a settings file holding `keys`.
+    fn keymap(source: &str) -> (Keymap, Vec<String>) {
+        let doc: KdlDocument = source.parse().unwrap();
+        Keymap::from_node(doc.get("keys").unwrap(), source)
+    }
+
+    fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord::from(KeyEvent::new(code, modifiers))
+    }
+
+    fn press(keymap: &mut Keymap, c: char) -> Option<Action> {
+        keymap.handle(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn chords_parse_with_modifiers_and_named_keys() {
+        let ctrl_n = chord(KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(KeyChord::parse("ctrl-n"), Ok(ctrl_n));
+        assert_eq!(KeyChord::parse("C-n"), Ok(ctrl_n));
+        assert_eq!(KeyChord::parse("j"), Ok(chord(KeyCode::Char('j'), KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("Enter"), Ok(chord(KeyCode::Enter, KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("f5"), Ok(chord(KeyCode::F(5), KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("space"), Ok(chord(KeyCode::Char(' '), KeyModifiers::NONE)));
+        // A trailing minus is the key, not a separator
+        let ctrl_minus = chord(KeyCode::Char('-'), KeyModifiers::CONTROL);
+        assert_eq!(KeyChord::parse("ctrl--"), Ok(ctrl_minus));
+        assert_eq!(KeyChord::parse("ctrl-n").unwrap().to_string(), "ctrl-n");
+    }
+
+    #[test]
+    fn shift_is_folded_into_printable_keys() {
+        assert_eq!(KeyChord::parse("shift-g"), KeyChord::parse("G"));
+        assert_eq!(
+            KeyChord::parse("shift-g"),
+            Ok(chord(KeyCode::Char('g'), KeyModifiers::SHIFT))
+        );
+        // Terminals report shift-tab as its own key
+        assert_eq!(KeyChord::parse("shift-tab"), Ok(chord(KeyCode::BackTab, KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn unknown_keys_and_modifiers_are_errors() {
+        assert_eq!(
+            KeyChord::parse("hyper-x"),
+            Err(String::from("unknown modifier 'hyper' in 'hyper-x'"))
+        );
+        assert_eq!(KeyChord::parse("f25"), Err(String::from("unknown key 'f25'")));
+        assert_eq!(KeyChord::parse("enterr"), Err(String::from("unknown key 'enterr'")));
+        assert_eq!(parse_sequence("  "), Err(String::from("empty key binding")));
+    }
+
+    #[test]
+    fn configured_keys_replace_the_defaults_of_their_action() {
+        let (mut keys, warnings) = keymap("keys {\n    apply \"l\" \"ctrl-l\"\n}\n");
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(keys.keys_for(Action::Apply), ["l", "ctrl-l"]);
+        assert_eq!(keys.keys_for(Action::Quit), ["q"]);
+        assert_eq!(press(&mut keys, 'l'), Some(Action::Apply));
+    }
+
+    #[test]
+    fn two_actions_on_one_chord_conflict() {
+        let (keys, warnings) = keymap("keys {\n    apply \"x\"\n}\n");
+        assert_eq!(
+            warnings,
+            ["line 2 `apply \"x\"`: 'x' conflicts with 'x' for delete_file, ignoring it"]
+        );
+        assert!(keys.keys_for(Action::Apply).is_empty());
+        assert_eq!(keys.keys_for(Action::DeleteFile), ["x"]);
+
+        let (_, warnings) = keymap("keys {\n    apply \"l\"\n    quit \"l\"\n}\n");
+        assert_eq!(
+            warnings,
+            ["line 3 `quit \"l\"`: 'l' conflicts with 'l' for apply, ignoring it"]
+        );
+    }
+
+    #[test]
+    fn a_chord_that_starts_a_sequence_conflicts_with_it() {
+        // `g` alone could never fire while `g g` waits for its second key
+        let (_, warnings) = keymap("keys {\n    apply \"g\"\n}\n");
+        assert_eq!(
+            warnings,
+            ["line 2 `apply \"g\"`: 'g' conflicts with 'g g' for first, ignoring it"]
+        );
+        let (_, warnings) = keymap("keys {\n    refresh \"q r\"\n}\n");
+        assert_eq!(
+            warnings,
+            ["line 2 `refresh \"q r\"`: 'q r' conflicts with 'q' for quit, ignoring it"]
+        );
+    }
+
+    #[test]
+    fn unknown_actions_and_unparsable_keys_are_reported_by_line() {
+        let (_, warnings) = keymap("keys {\n    jump \"j\"\n    apply \"hyper-x\" 5\n}\n");
+        assert_eq!(
+            warnings,
+            [
+                "line 2 `jump \"j\"`: unknown action 'jump'",
+                "line 3 `apply \"hyper-x\" 5`: unknown modifier 'hyper' in 'hyper-x'",
+                "line 3 `apply \"hyper-x\" 5`: key bindings must be strings like \"ctrl-n\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_wait_for_their_next_key() {
+        let mut keys = Keymap::default();
+
+        assert_eq!(press(&mut keys, 'g'), None);
+        assert!(keys.is_pending());
+        assert_eq!(press(&mut keys, 'g'), Some(Action::First));
+        assert!(!keys.is_pending());
+
+        // A key that does not continue the sequence starts over on its own
+        assert_eq!(press(&mut keys, 'g'), None);
+        assert_eq!(press(&mut keys, 'j'), Some(Action::MoveDown));
+        assert!(!keys.is_pending());
+    }
+}
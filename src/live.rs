@@ -0,0 +1,347 @@
+//! Making an applied theme show up in Zellij sessions that are already
+//! running, instead of only on the next start.
+//!
+//! Zellij 0.41 and later watch `config.kdl` and reload it when it changes,
+//! so once the config is written the running sessions pick the theme up by
+//! themselves. Older versions read the config only at startup and offer no
+//! command to switch themes, so all we can do for them is say so.
+//!
+//! Every `zellij` invocation goes through [`CommandRunner`] so the logic can
+//! be exercised without a real Zellij installed.
+
+use std::cell::OnceCell;
+use std::env;
+use std::fmt;
+use std::io;
+use std::process::Command;
+
+/// The first release that reloads `config.kdl` while running.
+const RELOADS_CONFIG: Version = Version {
+    major: 0,
+    minor: 41,
+    patch: 0,
+};
+
+/// What users of Zellij versions without config reloading run to restart
+/// every session at once.
+pub const RESTART_COMMAND: &str = "zellij kill-all-sessions";
+
+/// What a finished command printed and whether it succeeded.
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+}
+
+/// Runs external programs. Swapped out for a fake to test without Zellij.
+pub trait CommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput>;
+}
+
+/// Runs programs for real.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+        let output = Command::new(program).args(args).output()?;
+        Ok(CommandOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    /// Parses the output of `zellij --version`, e.g. `zellij 0.40.1`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let number = text.split_whitespace().last()?;
+        let mut parts = number
+            .split(|c: char| !c.is_ascii_digit())
+            .map(|part| part.parse::<u32>().ok());
+        Some(Self {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next().flatten().unwrap_or(0),
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// What happened to the running sessions after the config was written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LiveApply {
+    /// Running sessions reload the config and switch right away. Holds the
+    /// name of the session we are running in, if any.
+    Switched(Option<String>),
+    /// No session is running; the theme is used when Zellij next starts.
+    NoSession,
+    /// The installed Zellij only reads the config at startup.
+    Unsupported(Version),
+    /// There is no usable `zellij` binary to ask.
+    NoBinary,
+}
+
+impl LiveApply {
+    /// A short note for the status bar, e.g. `live in session 'main'`.
+    pub fn label(&self) -> String {
+        match self {
+            LiveApply::Switched(Some(session)) => format!("live in session '{}'", session),
+            LiveApply::Switched(None) => String::from("live in the running sessions"),
+            LiveApply::NoSession => String::from("will apply to new sessions"),
+            LiveApply::Unsupported(_) => format!(
+                "restart your Zellij session (or run `{}`) to see it",
+                RESTART_COMMAND
+            ),
+            LiveApply::NoBinary => String::from("config only; zellij was not found"),
+        }
+    }
+
+    /// The message for having applied `theme`, with `configs` (e.g. ` to 2
+    /// configs`) after its name and `note` (e.g. where the theme was
+    /// installed) next to the label. Versions that only read the config at
+    /// startup get the restart advice instead of a success that would not
+    /// show, without the name to leave room for it in the status bar.
+    pub fn report(&self, theme: &str, configs: &str, note: Option<&str>) -> String {
+        match (self, note) {
+            (LiveApply::Unsupported(_), Some(note)) => {
+                format!("Theme written{} ({}) — {}", configs, note, self.label())
+            }
+            (LiveApply::Unsupported(_), None) => {
+                format!("Theme written{} — {}", configs, self.label())
+            }
+            (_, Some(note)) => format!(
+                "Successfully applied theme: {}{} ({}; {})",
+                theme,
+                configs,
+                note,
+                self.label()
+            ),
+            (_, None) => {
+                format!("Successfully applied theme: {}{} ({})", theme, configs, self.label())
+            }
+        }
+    }
+}
+
+/// Works out whether an applied theme reaches running sessions. The Zellij
+/// version is asked for once and remembered.
+pub struct LiveSwitcher<R: CommandRunner = SystemRunner> {
+    runner: R,
+    version: OnceCell<Option<Version>>,
+    /// The session we run in, as [`current_session`] found it on creation.
+    session: Option<Option<String>>,
+}
+
+impl Default for LiveSwitcher {
+    fn default() -> Self {
+        Self::new(SystemRunner)
+    }
+}
+
+impl<R: CommandRunner> LiveSwitcher<R> {
+    pub fn new(runner: R) -> Self {
+        Self {
+            runner,
+            version: OnceCell::new(),
+            session: current_session(),
+        }
+    }
+
+    /// The installed Zellij version, or `None` if it cannot be run.
+    pub fn version(&self) -> Option<Version> {
+        *self.version.get_or_init(|| {
+            let output = self.runner.run("zellij", &["--version"]).ok()?;
+            if !output.success {
+                return None;
+            }
+            Version::parse(output.stdout.trim())
+        })
+    }
+
+    /// Whether we are inside a session, or `zellij list-sessions` shows one
+    /// that has not exited.
+    fn session_running(&self) -> bool {
+        if self.session.is_some() {
+            return true;
+        }
+        match self.runner.run("zellij", &["list-sessions"]) {
+            Ok(output) if output.success => output
+                .stdout
+                .lines()
+                .any(|line| !line.trim().is_empty() && !line.contains("EXITED")),
+            _ => false,
+        }
+    }
+
+    /// Called after the config has been written with the new theme.
+    pub fn apply(&self) -> LiveApply {
+        let Some(version) = self.version() else {
+            return LiveApply::NoBinary;
+        };
+        if !self.session_running() {
+            LiveApply::NoSession
+        } else if version >= RELOADS_CONFIG {
+            LiveApply::Switched(self.session.clone().flatten())
+        } else {
+            LiveApply::Unsupported(version)
+        }
+    }
+}
+
+/// `Some` when running inside a Zellij session, with the session's name if
+/// Zellij exported it.
+fn current_session() -> Option<Option<String>> {
+    env::var_os("ZELLIJ")?;
+    Some(env::var("ZELLIJ_SESSION_NAME").ok().filter(|name| !name.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A Zellij that reports `version` and lists `sessions`; `None` for a
+    /// version means there is no binary. Remembers what it was asked.
+    struct FakeZellij {
+        version: Option<&'static str>,
+        sessions: &'static str,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl CommandRunner for FakeZellij {
+        fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+            self.calls.borrow_mut().push(format!("{} {}", program, args.join(" ")));
+            let version = self.version.ok_or(io::ErrorKind::NotFound)?;
+            let stdout = match args {
+                ["--version"] => format!("zellij {}\n", version),
+                ["list-sessions"] => self.sessions.to_string(),
+                _ => return Err(io::ErrorKind::Unsupported.into()),
+            };
+            Ok(CommandOutput {
+                success: true,
+                stdout,
+            })
+        }
+    }
+
+    /// A switcher for `zellij` at `version` listing `sessions`, running in
+    /// `session`.
+    fn switcher(
+        version: Option<&'static str>,
+        sessions: &'static str,
+        session: Option<Option<&str>>,
+    ) -> LiveSwitcher<FakeZellij> {
+        let runner = FakeZellij {
+            version,
+            sessions,
+            calls: RefCell::default(),
+        };
+        LiveSwitcher {
+            session: session.map(|name| name.map(str::to_string)),
+            ..LiveSwitcher::new(runner)
+        }
+    }
+
+    fn version(major: u32, minor: u32, patch: u32) -> Option<Version> {
+        Some(Version {
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    #[test]
+    fn versions_parse_from_zellij_output() {
+        assert_eq!(Version::parse("zellij 0.40.1"), version(0, 40, 1));
+        assert_eq!(Version::parse("zellij 0.41.0-rc.1"), version(0, 41, 0));
+        assert_eq!(Version::parse("0.39"), version(0, 39, 0));
+        assert_eq!(Version::parse("zellij"), None);
+        assert_eq!(Version::parse(""), None);
+    }
+
+    #[test]
+    fn versions_order_numerically() {
+        assert!(Version::parse("0.41.0") >= Some(RELOADS_CONFIG));
+        assert!(Version::parse("0.40.99") < Some(RELOADS_CONFIG));
+        assert!(Version::parse("0.100.0") > Some(RELOADS_CONFIG));
+    }
+
+    #[test]
+    fn apply_decides_by_version_and_sessions() {
+        let running = "main [Created 1h ago]\n";
+        let exited = "old [Created 2d ago] (EXITED - attach to resurrect)\n";
+        let cases = [
+            (None, running, Some(None), LiveApply::NoBinary),
+            (Some("0.41.0"), "", None, LiveApply::NoSession),
+            (Some("0.41.0"), exited, None, LiveApply::NoSession),
+            (Some("0.41.0"), running, None, LiveApply::Switched(None)),
+            (Some("0.41.0"), "", Some(None), LiveApply::Switched(None)),
+            (Some("0.42.1"), "", Some(Some("main")), LiveApply::Switched(Some("main".into()))),
+            (Some("0.40.1"), running, None, LiveApply::Unsupported(version(0, 40, 1).unwrap())),
+            (Some("0.40.1"), "", None, LiveApply::NoSession),
+        ];
+        for (zellij, sessions, session, expected) in cases {
+            let applied = switcher(zellij, sessions, session).apply();
+            assert_eq!(applied, expected, "{:?} {:?} {:?}", zellij, sessions, session);
+        }
+    }
+
+    #[test]
+    fn old_versions_are_told_to_restart_and_others_outside_a_session_to_wait() {
+        let unsupported = LiveApply::Unsupported(version(0, 40, 1).unwrap());
+        assert_eq!(
+            unsupported.report("nord", "", None),
+            "Theme written — restart your Zellij session (or run `zellij kill-all-sessions`) \
+             to see it"
+        );
+        assert_eq!(
+            LiveApply::NoSession.report("nord", "", None),
+            "Successfully applied theme: nord (will apply to new sessions)"
+        );
+    }
+
+    #[test]
+    fn the_version_is_asked_for_once() {
+        let live = switcher(Some("0.41.0"), "main [Created 1h ago]\n", None);
+        live.apply();
+        live.apply();
+        let calls = live.runner.calls.borrow();
+        let asked = calls.iter().filter(|call| *call == "zellij --version").count();
+        assert_eq!(asked, 1, "{:?}", calls);
+    }
+
+    #[test]
+    fn a_missing_binary_is_asked_for_once_and_not_for_sessions() {
+        let live = switcher(None, "", None);
+        assert_eq!(live.version(), None);
+        assert_eq!(live.apply(), LiveApply::NoBinary);
+        assert_eq!(*live.runner.calls.borrow(), ["zellij --version"]);
+    }
+
+    #[test]
+    fn inside_a_session_the_sessions_are_not_listed() {
+        let live = switcher(Some("0.41.0"), "", Some(Some("main")));
+        assert_eq!(live.apply(), LiveApply::Switched(Some("main".to_string())));
+        assert_eq!(*live.runner.calls.borrow(), ["zellij --version"]);
+        assert_eq!(live.apply().label(), "live in session 'main'");
+    }
+
+    #[test]
+    fn only_sessions_that_have_not_exited_count() {
+        let listed = "\nold [Created 2d ago] (EXITED - attach to resurrect)\n  \n";
+        assert!(!switcher(Some("0.41.0"), listed, None).session_running());
+        let listed = "old [Created 2d ago] (EXITED - attach to resurrect)\nwork [Created 5m ago]\n";
+        assert!(switcher(Some("0.41.0"), listed, None).session_running());
+    }
+}
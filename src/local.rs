@@ -0,0 +1,111 @@
+//! Theme files in the theme directory: finding them and naming the themes
+//! read from them. Shared by the selector and the zellij plugin, so it only
+//! uses `std::fs`.
+
+use crate::theme::{self, Origin, Theme};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The `*.kdl` files in `dir` and the directories below it, sorted by path
+/// so name clashes resolve the same way every time. Hidden files and
+/// directories are skipped, and symlinked directories are not followed, as
+/// nothing would stop a loop.
+pub fn kdl_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_kdl_files(dir, &mut files);
+    files.sort();
+    files
+}
+
+fn collect_kdl_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => collect_kdl_files(&path, files),
+            Ok(_) if path.extension().is_some_and(|ext| ext == "kdl") => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// How the theme file at `path` is named in the list: its path below `dir`
+/// with `/` separators, e.g. `catppuccin/latte.kdl`, or just its file name
+/// when it is elsewhere.
+pub fn file_name(dir: &Path, path: &Path) -> String {
+    match path.strip_prefix(dir) {
+        Ok(relative) => relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+    }
+}
+
+/// Marks `themes` as read from the local file named `file`.
+pub fn mark_local(themes: Vec<Theme>, file: &str) -> Vec<Theme> {
+    themes
+        .into_iter()
+        .map(|mut theme| {
+            theme.origin = Origin::Local;
+            theme.file = Some(file.to_string());
+            theme
+        })
+        .collect()
+}
+
+/// The themes in [`kdl_files`] of `dir`. Files that cannot be read or do
+/// not parse are left out; the selector reports them.
+pub fn themes(dir: &Path) -> Vec<Theme> {
+    let mut themes = Vec::new();
+    for path in kdl_files(dir) {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let found = theme::extract_themes(&content, &stem).unwrap_or_default();
+        themes.extend(mark_local(found, &file_name(dir, &path)));
+    }
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn kdl_files_are_found_below_the_dir_in_order_skipping_hidden_ones() {
+        let dir = TempDir::new().unwrap();
+        for file in ["b.kdl", "a/z.kdl", "a/notes.txt", ".hidden/x.kdl", "a/.y.kdl"] {
+            write(&dir.path().join(file), "");
+        }
+        let files: Vec<String> =
+            kdl_files(dir.path()).iter().map(|path| file_name(dir.path(), path)).collect();
+        assert_eq!(files, ["a/z.kdl", "b.kdl"]);
+    }
+
+    #[test]
+    fn themes_are_marked_local_with_their_file() {
+        let dir = TempDir::new().unwrap();
+        let nord = "themes {\n    nord {\n        fg 1 2 3\n    }\n}\n";
+        write(&dir.path().join("nord/nord.kdl"), nord);
+        write(&dir.path().join("broken.kdl"), "themes {\n");
+        let themes = themes(dir.path());
+        assert_eq!(themes.len(), 1);
+        assert_eq!(themes[0].name, "nord");
+        assert_eq!(themes[0].origin, Origin::Local);
+        assert_eq!(themes[0].file.as_deref(), Some("nord/nord.kdl"));
+    }
+}
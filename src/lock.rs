@@ -0,0 +1,236 @@
+//! Keeping two instances from editing the same file at once, e.g. with the
+//! selector open in two panes. Each read-modify-write of the zellij config
+//! or the theme cache holds a lock file next to it, `.config.kdl.lock` and
+//! the like, holding the owner's PID.
+//!
+//! The lock is advisory: only this tool looks at it. A lock whose owner is
+//! no longer running, because it crashed or was killed, is removed by the
+//! next instance that wants it.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long to wait for another instance to finish before giving up.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Held while a file is being updated; the lock file is removed on drop.
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Takes the lock for `target`, waiting up to [`TIMEOUT`] for another
+    /// instance to release it. A symlink is resolved first, so every path
+    /// to the same file shares one lock.
+    pub fn acquire(target: &Path) -> io::Result<Self> {
+        Self::acquire_within(target, TIMEOUT)
+    }
+
+    fn acquire_within(target: &Path, timeout: Duration) -> io::Result<Self> {
+        let target = fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf());
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| io::Error::other(format!("{} is not a file", target.display())))?;
+        let path = target.with_file_name(format!(".{}.lock", file_name.to_string_lossy()));
+        let deadline = Instant::now() + timeout;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = Self { path };
+                    if let Some(id) = process_id() {
+                        write!(file, "{}", id)?;
+                    }
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!("could not create {}: {}", path.display(), e),
+                    ))
+                }
+            }
+            if is_stale(&path) && break_stale(&path) {
+                log::warn!("removed {}, left behind by an instance that is gone", path.display());
+                continue;
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "another instance is updating {}; if none is running, remove {}",
+                        target.display(),
+                        path.display()
+                    ),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!("could not remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// The ID of this process, or `None` in the zellij plugin: WASI has no
+/// process IDs, and asking for one panics. Its locks are left without one,
+/// and only count as stale by their age.
+pub(crate) fn process_id() -> Option<u32> {
+    if cfg!(target_os = "wasi") {
+        None
+    } else {
+        Some(std::process::id())
+    }
+}
+
+/// Whether the lock file at `path` belongs to a process that has exited.
+/// One without a PID may be in the middle of being written, so it only
+/// counts as stale once it is older than [`TIMEOUT`].
+fn is_stale(path: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(path) else {
+        // Gone already, or unreadable and best left alone
+        return false;
+    };
+    match content.trim().parse::<u32>() {
+        Ok(pid) => !is_running(pid),
+        Err(_) => fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > TIMEOUT),
+    }
+}
+
+/// Removes the stale lock file at `path`, returning whether it did.
+/// Another instance may have broken it and taken the lock in the meantime,
+/// so the file is first renamed to a name of its own and checked again
+/// there; a live lock caught that way is put back.
+fn break_stale(path: &Path) -> bool {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let mut claimed = path.as_os_str().to_owned();
+    claimed.push(format!(".{}-{}.stale", process_id().unwrap_or(0), nanos));
+    let claimed = PathBuf::from(claimed);
+    if fs::rename(path, &claimed).is_err() {
+        // Already broken by someone else
+        return false;
+    }
+    if is_stale(&claimed) {
+        let _ = fs::remove_file(&claimed);
+        return true;
+    }
+    // A hard link does not replace a lock taken since
+    if let Err(e) = fs::hard_link(&claimed, path) {
+        log::warn!("could not put back the lock {}: {}", path.display(), e);
+    }
+    let _ = fs::remove_file(&claimed);
+    false
+}
+
+/// Whether a process with `pid` exists, as `kill -0` tells. Only "No such
+/// process" counts as gone: a process of another user, which `kill` may
+/// not signal, is running, and so is any process when `kill` cannot be
+/// run, so a lock is never broken on a guess.
+fn is_running(pid: u32) -> bool {
+    let output = Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .env("LC_ALL", "C")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output();
+    match output {
+        Ok(output) if !output.status.success() => {
+            !String::from_utf8_lossy(&output.stderr).contains("No such process")
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// The PID of a process that has already exited.
+    fn exited_pid() -> u32 {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    fn lock_file(dir: &TempDir) -> PathBuf {
+        dir.path().join(".config.kdl.lock")
+    }
+
+    #[test]
+    fn the_lock_file_holds_the_pid_and_goes_away_on_drop() {
+        let dir = TempDir::new().unwrap();
+        let lock = FileLock::acquire(&dir.path().join("config.kdl")).unwrap();
+        let content = fs::read_to_string(lock_file(&dir)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+        drop(lock);
+        assert!(!lock_file(&dir).exists());
+    }
+
+    #[test]
+    fn a_lock_left_by_an_exited_process_is_taken_over() {
+        let dir = TempDir::new().unwrap();
+        fs::write(lock_file(&dir), exited_pid().to_string()).unwrap();
+
+        let started = Instant::now();
+        let _lock = FileLock::acquire(&dir.path().join("config.kdl")).unwrap();
+        assert!(started.elapsed() < TIMEOUT);
+        let content = fs::read_to_string(lock_file(&dir)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+        // The stale file was renamed aside and removed, not left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_lock_held_by_a_running_process_times_out() {
+        let dir = TempDir::new().unwrap();
+        fs::write(lock_file(&dir), std::process::id().to_string()).unwrap();
+
+        let target = dir.path().join("config.kdl");
+        let timeout = Duration::from_millis(200);
+        let error = FileLock::acquire_within(&target, timeout).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(error.to_string().contains("another instance"), "{}", error);
+        let content = fs::read_to_string(lock_file(&dir)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+    }
+
+    #[test]
+    fn a_live_lock_caught_while_breaking_a_stale_one_is_put_back() {
+        let dir = TempDir::new().unwrap();
+        // Taken by a running instance after the stale one was seen
+        fs::write(lock_file(&dir), std::process::id().to_string()).unwrap();
+
+        assert!(!break_stale(&lock_file(&dir)));
+        let content = fs::read_to_string(lock_file(&dir)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_process_that_cannot_be_signalled_counts_as_running() {
+        // init belongs to root: kill -0 fails with EPERM for anyone else
+        assert!(is_running(1));
+        assert!(is_running(std::process::id()));
+        assert!(!is_running(exited_pid()));
+    }
+}
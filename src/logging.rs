@@ -0,0 +1,99 @@
+//! The log file. Code logs through the `log` macros as usual; this module
+//! decides where the lines go, which is never the terminal: the selector
+//! owns it while running, so anything printed there would garble the UI.
+//!
+//! Lines are appended to `$XDG_STATE_HOME/zellij-theme-plugin/log`, or the
+//! same under `~/.local/state`, unless `--log-file` names another file.
+//! Info and above are written by default, debug with `-v` and trace with
+//! `-vv`. Only this crate's own messages are kept; the HTTP stack's are
+//! far too chatty to help.
+
+use crate::state;
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// The prefix of every log target in this crate, library and binary alike.
+const CRATE_TARGET: &str = "zellij_theme_selector";
+
+static ACTIVE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// The level for the number of times `-v` was given.
+pub fn level(verbose: u64) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Where the log goes without `--log-file`, or `None` when neither
+/// `XDG_STATE_HOME` nor `HOME` is set.
+pub fn default_path() -> Option<PathBuf> {
+    Some(state::dir()?.join("log"))
+}
+
+/// Starts appending log lines at `level` and above to `path`, creating its
+/// directory if needed. Only the first call in a process takes effect.
+pub fn init(path: &Path, level: LevelFilter) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let logger = FileLogger {
+        file: Mutex::new(file),
+        level,
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(io::Error::other)?;
+    log::set_max_level(level);
+    let _ = ACTIVE_PATH.set(path.to_path_buf());
+    Ok(())
+}
+
+/// The file being logged to, once [`init`] has succeeded.
+pub fn active_path() -> Option<&'static Path> {
+    ACTIVE_PATH.get().map(PathBuf::as_path)
+}
+
+struct FileLogger {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with(CRATE_TARGET)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record
+            .target()
+            .strip_prefix(CRATE_TARGET)
+            .map(|rest| rest.trim_start_matches("::"))
+            .filter(|rest| !rest.is_empty())
+            .unwrap_or("main");
+        let line = format!(
+            "{} {:<5} {}: {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            module,
+            record.args()
+        );
+        // A log that cannot be written is not worth failing anything over
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
@@ -1,188 +1,1071 @@
-mod data;
-
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use data::ThemeData;
-use std::{env, io};
-use ratatui::{
-    prelude::*,
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+use chrono::Local;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::runtime::{Builder, Runtime};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use zellij_theme_selector::config::theme_key_problem;
+use zellij_theme_selector::data::{FetchOutcome, Install, LayoutUpdate, ThemeData};
+use zellij_theme_selector::live::LiveSwitcher;
+use zellij_theme_selector::logging;
+use zellij_theme_selector::schedule::Schedule;
+use zellij_theme_selector::settings::Settings;
+use zellij_theme_selector::swatch::{self, ColorDepth};
+use zellij_theme_selector::tags;
+use zellij_theme_selector::theme::{Appearance, Theme, ThemeColors};
+use zellij_theme_selector::variant::Adjustment;
+use zellij_theme_selector::plain::run_plain_selector;
+use zellij_theme_selector::{
+    appearance, completions, doctor, gallery, hook, image, matcher, palette, progress,
+    run_theme_selector, Options,
 };
 
-struct App {
-    themes: Vec<String>,
-    state: ListState,
-    status_message: String,
+/// The plugin's crate in the source this binary was built from, for
+/// `install-plugin --build`.
+const PLUGIN_MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/plugin/Cargo.toml");
+/// What zellij loads plugins as, formerly called `wasm32-wasi`.
+const PLUGIN_TARGET: &str = "wasm32-wasip1";
+const PLUGIN_FILE: &str = "zellij-theme-plugin.wasm";
+
+/// Set by `--quiet`.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--force-refresh`.
+static FORCE_REFRESH: AtomicBool = AtomicBool::new(false);
+
+/// Reports what a command did on stdout, and in the log. Left out with
+/// `--quiet`, unlike what the command was asked to print.
+macro_rules! say {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        log::info!("{}", message);
+        if !QUIET.load(Ordering::Relaxed) {
+            println!("{}", message);
+        }
+    }};
 }
 
-impl App {
-    fn new(themes: Vec<String>) -> App {
-        let mut state = ListState::default();
-        state.select(Some(0));
-        App {
-            themes,
-            state,
-            status_message: String::from("Press Enter to apply theme, q to quit"),
+/// A `warning:` line on stderr, and in the log. Left out with `--quiet`.
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        log::warn!("{}", message);
+        if !QUIET.load(Ordering::Relaxed) {
+            eprintln!("warning: {}", message);
         }
+    }};
+}
+
+/// Preview and switch between Zellij themes.
+#[derive(Parser)]
+#[clap(name = "zellij-theme-selector", version)]
+struct Cli {
+    /// Force refresh the theme list from GitHub, showing the progress on
+    /// stderr outside the selector
+    #[clap(long, global = true)]
+    force_refresh: bool,
+
+    /// Threshold for the low-contrast filter [default: 4.5, or min_contrast
+    /// from settings.kdl]
+    #[clap(long, value_name = "RATIO")]
+    min_contrast: Option<f64>,
+
+    /// Print a numbered menu and read the choice from stdin instead of
+    /// running the full-screen interface
+    #[clap(long)]
+    plain: bool,
+
+    /// Overwrite installed theme files even if they were edited locally,
+    /// and apply themes zellij would not find
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Edit this config.kdl instead of the one in the zellij config
+    /// directory. Repeat it or give a comma-separated list to write the
+    /// theme to several configs; the first one is read from
+    #[clap(long = "config", value_name = "PATH", use_value_delimiter = true)]
+    configs: Vec<PathBuf>,
+
+    /// Also update the theme in layout files under layouts/ that set one
+    /// [default: update_layouts from settings.kdl]
+    #[clap(long)]
+    layouts: bool,
+
+    /// List zellij's themes at this branch, tag or commit instead of the
+    /// release tag of the installed zellij [default: git_ref from config.toml]
+    #[clap(long = "ref", value_name = "REF", global = true)]
+    git_ref: Option<String>,
+
+    /// Read and write the theme name in this config node instead of
+    /// `theme`, e.g. `ui_theme`. Layout files always use `theme`
+    #[clap(long, value_name = "KEY", global = true, parse(try_from_str = parse_key))]
+    key: Option<String>,
+
+    /// Send requests through this proxy instead of the one in HTTPS_PROXY,
+    /// HTTP_PROXY or ALL_PROXY; hosts in NO_PROXY are still reached directly
+    /// [default: proxy from config.toml]
+    #[clap(long, value_name = "URL", global = true, parse(try_from_str = parse_proxy))]
+    proxy: Option<String>,
+
+    /// Do not list a theme that no file defines, such as zellij's built-in
+    /// `default` [default: default_theme from settings.kdl]
+    #[clap(long, global = true)]
+    no_default_theme: bool,
+
+    /// Do not run the post-apply hook or the scripts in hooks.d after
+    /// applying a theme
+    #[clap(long, global = true)]
+    no_hooks: bool,
+
+    /// Print the path of each config.kdl that would be edited and exit
+    #[clap(long)]
+    print_config_path: bool,
+
+    /// Print the undo stack, most recent first, and exit. `>` marks the
+    /// theme the config is at; `u` and ctrl-r in the selector move it
+    #[clap(long)]
+    history: bool,
+
+    /// Log debug details; give it twice to log everything. Also prints how
+    /// long the selector took to draw its first frame
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: u64,
+
+    /// Write the log here instead of
+    /// $XDG_STATE_HOME/zellij-theme-plugin/log
+    #[clap(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Only print errors and what the command was asked for, e.g. the names
+    /// from `list`, not what it did along the way
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a theme from the colors of an image
+    Generate {
+        /// PNG or JPEG image to take the palette from
+        #[clap(long, value_name = "PATH")]
+        from_image: PathBuf,
+        /// Name of the new theme
+        #[clap(long)]
+        name: String,
+        /// Use the lightest color as the background
+        #[clap(long)]
+        light: bool,
+    },
+    /// Create a variant of an existing theme with adjusted colors
+    Derive {
+        /// Theme to start from
+        #[clap(value_name = completions::THEME_VALUE_NAME)]
+        base: String,
+        /// Name of the new theme
+        #[clap(long)]
+        name: String,
+        /// Darken by a percentage, e.g. 10%
+        #[clap(long, value_name = "PERCENT", parse(try_from_str = parse_percent))]
+        darken: Option<f64>,
+        /// Lighten by a percentage, e.g. 10%
+        #[clap(long, value_name = "PERCENT", parse(try_from_str = parse_percent))]
+        lighten: Option<f64>,
+        /// Change saturation by a percentage; negative values desaturate
+        #[clap(
+            long,
+            value_name = "PERCENT",
+            allow_hyphen_values = true,
+            parse(try_from_str = parse_percent)
+        )]
+        saturate: Option<f64>,
+        /// Rotate every hue by this many degrees
+        #[clap(long, value_name = "DEGREES", allow_hyphen_values = true)]
+        hue_shift: Option<f64>,
+        /// Keep the base theme's background color
+        #[clap(long)]
+        keep_bg: bool,
+        /// Keep the base theme's foreground color
+        #[clap(long)]
+        keep_fg: bool,
+    },
+    /// Check the environment and explain anything that looks wrong
+    Doctor,
+    /// Apply a theme by name without opening the selector
+    Apply {
+        /// Name of the theme, or `-` to read it from stdin, e.g. from
+        /// `list | fzf`
+        #[clap(value_name = completions::THEME_VALUE_NAME)]
+        name: String,
+    },
+    /// Install a theme from a KDL snippet in a file, or `-` for stdin,
+    /// into the themes directory and apply it
+    ApplyFile {
+        /// File holding the theme definition
+        #[clap(value_name = "PATH")]
+        path: PathBuf,
+        /// Name to install the theme under instead of the one in the snippet
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Remove the theme setting from the config so Zellij uses its
+    /// built-in default
+    Reset,
+    /// Print a theme's colors as swatches and sample text, without the
+    /// full-screen interface
+    Preview {
+        /// Name of the theme
+        #[clap(value_name = completions::THEME_VALUE_NAME)]
+        name: String,
+    },
+    /// Print the names of all known themes, one per line
+    List {
+        /// Only read the cache and the local theme files; never use the
+        /// network, even if the cache is stale
+        #[clap(long)]
+        cached: bool,
+    },
+    /// Print the themes matching a query, best first, ranked like the
+    /// selector's `/` filter. Only the cache and the local theme files are
+    /// read, so it works offline. Exits with 1 when nothing matches
+    Search {
+        /// Fuzzy name query, with `#tag` and `bg:#1e1e2e`-style words like
+        /// the filter; several words are joined with spaces
+        #[clap(required = true)]
+        query: Vec<String>,
+        /// Print at most this many matches
+        #[clap(long, value_name = "N")]
+        limit: Option<usize>,
+        /// Print the matches as JSON, with their scores
+        #[clap(long)]
+        json: bool,
+    },
+    /// Write a static HTML page showing every known theme as a card,
+    /// grouped by source
+    ExportGallery {
+        /// File to write the page to, or `-` for stdout
+        #[clap(long, short, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Check the theme files installed from a source against the versions
+    /// it lists now
+    Outdated,
+    /// Download every theme file the source lists into the theme directory,
+    /// skipping the ones already there and current
+    DownloadAll,
+    /// List the installed theme files the source no longer lists, leaving
+    /// out files added by hand or edited locally
+    Prune {
+        /// Delete the listed files
+        #[clap(long)]
+        yes: bool,
+    },
+    /// Print a shell completion script, completing theme names too
+    Completions {
+        #[clap(arg_enum, value_name = "SHELL")]
+        shell: Shell,
+    },
+    /// List recently applied themes, newest first
+    History {
+        /// Print the entries as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// List the tags given to themes with `t` in the selector, marking
+    /// themes that no longer exist as orphaned
+    Tags {
+        /// Drop the tags of orphaned themes
+        #[clap(long)]
+        prune: bool,
+    },
+    /// Keep running and apply a light or a dark theme whenever the desktop
+    /// switches between light and dark mode
+    WatchAppearance {
+        /// Theme for light mode [default: light_theme from settings.kdl]
+        #[clap(long, value_name = "NAME")]
+        light: Option<String>,
+        /// Theme for dark mode [default: dark_theme from settings.kdl]
+        #[clap(long, value_name = "NAME")]
+        dark: Option<String>,
+    },
+    /// Apply day_theme or night_theme from settings.kdl, whichever is due
+    /// now, unless it is already set; meant to run from cron or a timer
+    SwitchScheduled,
+    /// List the scripts in hooks.d that run after applying a theme, in the
+    /// order they run
+    Hooks {
+        /// Print the environment the scripts would get for this theme
+        /// instead
+        #[clap(long, value_name = "NAME")]
+        print_env: Option<String>,
+    },
+    /// Copy the zellij plugin into the plugins directory next to the zellij
+    /// config and print a keybinding that opens it
+    InstallPlugin {
+        /// The plugin's .wasm file [default: the one --build makes]
+        #[clap(value_name = "PATH")]
+        wasm: Option<PathBuf>,
+        /// Build the plugin first, from the source this binary was built
+        /// from; needs the wasm32-wasip1 target
+        #[clap(long)]
+        build: bool,
+    },
+}
+
+/// Parses `10%`, `10` or `-25%` into a fraction.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    value
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map(|percent| percent / 100.0)
+        .map_err(|_| format!("'{}' is not a percentage", value))
+}
+
+/// Accepts a `--key` that passes `theme_key_problem`.
+fn parse_key(value: &str) -> Result<String, String> {
+    match theme_key_problem(value) {
+        Some(problem) => Err(format!("'{}' cannot be used: {}", value, problem)),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Accepts a `--proxy` reqwest can use, e.g. `http://proxy:3128`.
+fn parse_proxy(value: &str) -> Result<String, String> {
+    match reqwest::Proxy::all(value) {
+        Ok(_) => Ok(value.to_string()),
+        Err(e) => Err(format!("'{}' is not a proxy URL: {}", value, e)),
+    }
+}
+
+/// The theme list, fetched afresh with a progress counter if
+/// `--force-refresh` was given.
+async fn fetch_themes(theme_data: &ThemeData) -> io::Result<FetchOutcome> {
+    let force_refresh = FORCE_REFRESH.load(Ordering::Relaxed);
+    progress::fetch_themes(theme_data, force_refresh, QUIET.load(Ordering::Relaxed)).await
+}
+
+/// Runs `future` to completion on a single-threaded runtime. Only the
+/// commands that wait on the network or a hook build one, so answering from
+/// the cache, e.g. `list --cached` for shell completion, starts without it.
+fn block_on<F: Future>(future: F) -> io::Result<F::Output> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    Ok(runtime.block_on(future))
+}
+
+/// The selector fetches in a background task while the main thread waits
+/// for keys, so it needs a worker thread; one is plenty.
+fn selector_runtime() -> io::Result<Runtime> {
+    Builder::new_multi_thread().worker_threads(1).enable_all().build()
+}
+
+fn main() -> Result<(), io::Error> {
+    let started = Instant::now();
+    let cli = Cli::parse();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    FORCE_REFRESH.store(cli.force_refresh, Ordering::Relaxed);
+    // Before anything can take over the terminal, so a problem shows up
+    // once the selector exits at the latest
+    if let Some(path) = cli.log_file.clone().or_else(logging::default_path) {
+        if let Err(e) = logging::init(&path, logging::level(cli.verbose)) {
+            warning!("could not open log file {}: {}", path.display(), e);
+        }
+    }
+    let (mut settings, warnings) = Settings::load();
+    if let Some(ratio) = cli.min_contrast {
+        settings.min_contrast = ratio;
+    }
+    if cli.layouts {
+        settings.update_layouts = true;
+    }
+    settings.use_config_files(cli.configs);
+    if cli.git_ref.is_some() {
+        settings.git_ref = cli.git_ref;
+    }
+    if cli.proxy.is_some() {
+        settings.proxy = cli.proxy;
+    }
+    if let Some(key) = cli.key {
+        settings.theme_key = key;
+    }
+    if cli.no_default_theme {
+        settings.list_default_theme = false;
+    }
+    if cli.no_hooks {
+        settings.post_apply_hook = None;
+        settings.hooks_dir = None;
     }
 
-    fn next(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.themes.len() - 1 {
-                    0
-                } else {
-                    i + 1
+    if cli.print_config_path {
+        let theme_data = ThemeData::new(&settings)?;
+        for path in theme_data.config_paths() {
+            println!("{}", path.display());
+        }
+        if !cli.quiet {
+            eprintln!("(from {})", settings.zellij_config_dir_origin.label());
+        }
+        return Ok(());
+    }
+
+    if cli.history {
+        return print_rollback(&settings);
+    }
+
+    if cli.command.is_some() {
+        for warning in &warnings {
+            warning!("{}", warning);
+        }
+    }
+
+    match cli.command {
+        Some(Command::Generate {
+            from_image,
+            name,
+            light,
+        }) => generate(&settings, &from_image, &name, light),
+        Some(Command::Derive {
+            base,
+            name,
+            darken,
+            lighten,
+            saturate,
+            hue_shift,
+            keep_bg,
+            keep_fg,
+        }) => {
+            let adjustment = Adjustment {
+                lighten: lighten.unwrap_or(0.0) - darken.unwrap_or(0.0),
+                saturate: saturate.unwrap_or(0.0),
+                hue_shift: hue_shift.unwrap_or(0.0),
+                keep_bg,
+                keep_fg,
+            };
+            block_on(derive(&settings, &base, &name, &adjustment))?
+        }
+        Some(Command::History { json }) => history(&settings, json),
+        Some(Command::Tags { prune }) => block_on(tags(&settings, prune))?,
+        Some(Command::WatchAppearance { light, dark }) => {
+            block_on(watch_appearance(&settings, light, dark, cli.force))?
+        }
+        Some(Command::SwitchScheduled) => block_on(switch_scheduled(&settings, cli.force))?,
+        Some(Command::Hooks { print_env }) => block_on(hooks(&settings, print_env.as_deref()))?,
+        Some(Command::InstallPlugin { wasm, build }) => {
+            install_plugin(&settings, wasm.as_deref(), build)
+        }
+        Some(Command::Apply { name }) => block_on(apply(&settings, &name, cli.force))?,
+        Some(Command::ApplyFile { path, name }) => {
+            block_on(apply_file(&settings, &path, name.as_deref(), cli.force))?
+        }
+        Some(Command::Reset) => reset(&settings),
+        Some(Command::Preview { name }) => block_on(preview(&settings, &name))?,
+        Some(Command::List { cached: true }) => list_cached(&settings),
+        Some(Command::List { cached: false }) => block_on(list(&settings))?,
+        Some(Command::Search { query, limit, json }) => {
+            search(&settings, &query.join(" "), limit, json)
+        }
+        Some(Command::ExportGallery { output }) => block_on(export_gallery(&settings, &output))?,
+        Some(Command::Outdated) => block_on(outdated(&settings))?,
+        Some(Command::DownloadAll) => block_on(download_all(&settings, cli.force))?,
+        Some(Command::Prune { yes }) => block_on(prune(&settings, yes))?,
+        Some(Command::Completions { shell }) => {
+            completions::write(shell, &mut Cli::command(), &mut io::stdout())
+        }
+        Some(Command::Doctor) => {
+            let checks = block_on(doctor::run_checks(&settings))?;
+            for check in &checks {
+                println!("[{}] {}: {}", check.status.label(), check.name, check.detail);
+                if let Some(hint) = &check.hint {
+                    println!("       {}", hint);
                 }
             }
-            None => 0,
-        };
-        self.state.select(Some(i));
+            let worst = checks.iter().map(|check| check.status).max();
+            std::process::exit(worst.map_or(0, doctor::Status::exit_code));
+        }
+        None => {
+            let options = Options {
+                force_refresh: cli.force_refresh,
+                force: cli.force,
+                started: (cli.verbose > 0).then_some(started),
+            };
+            if cli.plain {
+                block_on(run_plain_selector(settings, warnings, options))?
+            } else {
+                selector_runtime()?.block_on(run_theme_selector(settings, warnings, options))
+            }
+        }
     }
+}
 
-    fn previous(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.themes.len() - 1
-                } else {
-                    i - 1
-                }
+fn generate(settings: &Settings, from_image: &Path, name: &str, light: bool) -> io::Result<()> {
+    let pixels = image::load_pixels(from_image)?;
+    let swatches = palette::extract_palette(&pixels, 16);
+    if swatches.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no visible pixels", from_image.display()),
+        ));
+    }
+    let colors = palette::theme_from_palette(&swatches, light);
+    let path = ThemeData::new(settings)?.save_theme(name, &colors)?;
+    say!("Wrote theme '{}' to {}", name, path.display());
+    Ok(())
+}
+
+async fn derive(
+    settings: &Settings,
+    base: &str,
+    name: &str,
+    adjustment: &Adjustment,
+) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    let outcome = fetch_themes(&theme_data).await?;
+    let colors = theme_colors(find_theme(&outcome.themes, base)?)?;
+    let path = theme_data.save_theme(name, &adjustment.apply_to(colors))?;
+    say!("Wrote theme '{}' to {}", name, path.display());
+    Ok(())
+}
+
+async fn apply(settings: &Settings, name: &str, force: bool) -> io::Result<()> {
+    let input;
+    let name = if name == "-" {
+        input = io::read_to_string(io::stdin())?;
+        name_from_stdin(&input)?
+    } else {
+        name
+    };
+    let theme_data = ThemeData::new(settings)?;
+    theme_data.ensure_theme_dir()?;
+    let outcome = fetch_themes(&theme_data).await?;
+    let theme = find_theme(&outcome.themes, name)?;
+    apply_theme(&theme_data, theme, force).await
+}
+
+async fn apply_file(
+    settings: &Settings,
+    path: &Path,
+    name: Option<&str>,
+    force: bool,
+) -> io::Result<()> {
+    let snippet = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?
+    };
+    let theme_data = ThemeData::new(settings)?;
+    let theme = theme_data
+        .import_theme(&snippet, name, force)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => io::Error::new(
+                e.kind(),
+                format!("{}; rerun with --force to replace it", e),
+            ),
+            io::ErrorKind::InvalidInput if name.is_none() => {
+                io::Error::new(e.kind(), format!("{}; use --name", e))
             }
-            None => 0,
+            _ => e,
+        })?;
+    if let Some(file) = &theme.file {
+        let path = theme_data.theme_dir().join(file);
+        say!("Installed theme '{}' to {}", theme.name, path.display());
+    }
+    apply_theme(&theme_data, &theme, force).await
+}
+
+/// Applies `light` or `dark` to match the desktop, at startup and after
+/// every change that lasts, until SIGTERM or Ctrl-C. Nothing is undone on
+/// exit: the last theme applied stays.
+async fn watch_appearance(
+    settings: &Settings,
+    light: Option<String>,
+    dark: Option<String>,
+    force: bool,
+) -> io::Result<()> {
+    let missing = |mode: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no {0} theme; pass --{0} or set {0}_theme in settings.kdl", mode),
+        )
+    };
+    let light = light.or_else(|| settings.light_theme.clone()).ok_or_else(|| missing("light"))?;
+    let dark = dark.or_else(|| settings.dark_theme.clone()).ok_or_else(|| missing("dark"))?;
+    let theme_data = ThemeData::new(settings)?;
+    theme_data.ensure_theme_dir()?;
+    // A misspelled name should fail now, not at the next switch
+    let outcome = fetch_themes(&theme_data).await?;
+    find_theme(&outcome.themes, &light)?;
+    find_theme(&outcome.themes, &dark)?;
+
+    let mut changes = appearance::watch().await?;
+    let mut terminate = terminate_signal()?;
+    let mut applied = None;
+    loop {
+        let appearance = tokio::select! {
+            appearance = appearance::next_settled(&mut changes) => appearance.ok_or_else(|| {
+                io::Error::other("stopped receiving the desktop appearance")
+            })?,
+            _ = terminated(&mut terminate) => break,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        if applied == Some(appearance) {
+            continue;
+        }
+        let name = if appearance == Appearance::Dark { &dark } else { &light };
+        say!("The desktop is in {} mode; applying {}", appearance.label(), name);
+        let result = async {
+            // The cache may have expired while waiting
+            let outcome = theme_data.fetch_themes(false).await?;
+            apply_theme(&theme_data, find_theme(&outcome.themes, name)?, force).await
         };
-        self.state.select(Some(i));
+        match result.await {
+            Ok(()) => applied = Some(appearance),
+            // Keep watching; the next switch may work
+            Err(e) => warning!("could not apply {}: {}", name, e),
+        }
     }
+    log::info!("stopped watching the desktop appearance");
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), io::Error> {
-    // Check for force refresh flag
-    let force_refresh = env::args().any(|arg| arg == "--force-refresh");
+/// SIGTERM, which a service manager sends to stop the watcher.
+#[cfg(unix)]
+fn terminate_signal() -> io::Result<Signal> {
+    signal(SignalKind::terminate())
+}
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+#[cfg(unix)]
+async fn terminated(signal: &mut Signal) {
+    signal.recv().await;
+}
 
-    // Initialize theme data
-    let theme_data = match ThemeData::new() {
-        Ok(td) => td,
-        Err(e) => {
-            disable_raw_mode()?;
-            println!("Error initializing theme data: {}", e);
-            return Ok(());
-        }
+/// There is no SIGTERM here, so only Ctrl-C stops the watcher.
+#[cfg(not(unix))]
+fn terminate_signal() -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn terminated(_: &mut ()) {
+    std::future::pending().await
+}
+
+async fn switch_scheduled(settings: &Settings, force: bool) -> io::Result<()> {
+    let missing = |key: &str| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("no {} set in settings.kdl", key))
+    };
+    let schedule = Schedule::from_settings(settings)?;
+    let (period, name) = if schedule.is_day(Local::now()) {
+        ("day", settings.day_theme.as_ref().ok_or_else(|| missing("day_theme"))?)
+    } else {
+        ("night", settings.night_theme.as_ref().ok_or_else(|| missing("night_theme"))?)
     };
+    let theme_data = ThemeData::new(settings)?;
+    // Checked before anything is fetched, so running every few minutes
+    // costs nothing while the theme is already right
+    let current = match theme_data.current_theme() {
+        Ok(current) => current,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    if current.as_deref() == Some(name.as_str()) {
+        log::debug!("{} is already set for the {}", name, period);
+        return Ok(());
+    }
+    log::info!("switching to {} for the {}", name, period);
+    theme_data.ensure_theme_dir()?;
+    let outcome = fetch_themes(&theme_data).await?;
+    apply_theme(&theme_data, find_theme(&outcome.themes, name)?, force).await
+}
 
-    // Ensure theme directory exists
-    if let Err(e) = theme_data.ensure_theme_dir() {
-        disable_raw_mode()?;
-        println!("Error checking theme directory: {}", e);
+async fn hooks(settings: &Settings, print_env: Option<&str>) -> io::Result<()> {
+    if let Some(name) = print_env {
+        let outcome = fetch_themes(&ThemeData::new(settings)?).await?;
+        for (key, value) in hook::theme_env(find_theme(&outcome.themes, name)?) {
+            println!("{}={}", key, value);
+        }
         return Ok(());
     }
+    let Some(dir) = &settings.hooks_dir else {
+        say!("Hooks are turned off");
+        return Ok(());
+    };
+    let scripts = hook::scripts(dir);
+    if scripts.is_empty() {
+        say!("No executable scripts in {}", dir.display());
+    }
+    for script in scripts {
+        println!("{}", script.display());
+    }
+    Ok(())
+}
+
+/// Copies the plugin to `plugins/` in the zellij config directory, after
+/// building it with `build`, and prints how to open it.
+fn install_plugin(settings: &Settings, wasm: Option<&Path>, build: bool) -> io::Result<()> {
+    let manifest = Path::new(PLUGIN_MANIFEST);
+    if build {
+        if !manifest.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "--build needs the source this binary was built from, but {} is gone; \
+                     build the plugin there and pass its .wasm file instead",
+                    manifest.display()
+                ),
+            ));
+        }
+        let status = std::process::Command::new("cargo")
+            .args(["build", "--release", "--target", PLUGIN_TARGET, "--manifest-path"])
+            .arg(manifest)
+            .status()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not run cargo: {}", e)))?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "building the plugin failed ({}); if the target is missing, run \
+                 `rustup target add {}`",
+                status, PLUGIN_TARGET
+            )));
+        }
+    }
+    let built = manifest
+        .with_file_name("target")
+        .join(PLUGIN_TARGET)
+        .join("release")
+        .join(PLUGIN_FILE);
+    let source = wasm.unwrap_or(&built);
+    if !source.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist; pass --build to build it", source.display()),
+        ));
+    }
+    let config_dir = settings.zellij_config_dir.as_deref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find the zellij config directory; set ZELLIJ_CONFIG_DIR to it",
+        )
+    })?;
+    let plugins = config_dir.join("plugins");
+    fs::create_dir_all(&plugins)?;
+    let target = plugins.join(PLUGIN_FILE);
+    fs::copy(source, &target)?;
+    say!("Installed the plugin to {}", target.display());
+    say!("To open it with Alt t, add to the keybinds in config.kdl:");
+    say!("");
+    say!("    shared {{");
+    say!("        bind \"Alt t\" {{");
+    say!("            LaunchOrFocusPlugin \"file:{}\" {{", target.display());
+    say!("                floating true");
+    say!("            }}");
+    say!("        }}");
+    say!("    }}");
+    Ok(())
+}
 
-    // Fetch available themes
-    let themes = match ThemeData::fetch_themes(force_refresh).await {
-        Ok(themes) => themes,
-        Err(e) => {
-            disable_raw_mode()?;
-            println!("Error fetching themes: {}", e);
+/// Writes `theme` to the config and reports the outcome like `apply`.
+async fn apply_theme(theme_data: &ThemeData, theme: &Theme, force: bool) -> io::Result<()> {
+    let name = theme.name.as_str();
+    match theme_data.update_config(theme, force).await? {
+        Install::Modified(path) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} has local changes; rerun with --force to overwrite it", path.display()),
+            ))
+        }
+        Install::Unresolved => return Err(unresolved_error(theme_data, name)),
+        Install::AlreadyApplied => {
+            say!("{} is already active", name);
             return Ok(());
         }
-    };
+        _ if force && !theme_data.resolves(theme) => {
+            // Shown even with --quiet: the theme will not show up
+            eprintln!("warning: {}", unresolved(theme_data, name));
+        }
+        _ => {}
+    }
+    say!("{}", LiveSwitcher::default().apply().report(name, "", None));
+    print_layout_updates(&theme_data.update_layouts(Some(name)));
+    for failure in theme_data.run_hooks(theme).await {
+        // Shown even with --quiet, like the layouts: part of the job failed
+        eprintln!("warning: {} failed: {}", failure.hook, failure.error);
+    }
+    Ok(())
+}
 
-    let mut app = App::new(themes);
-    let res = run_app(&mut terminal, &mut app, theme_data);
+/// Why Zellij would not find the theme called `name`.
+fn unresolved(theme_data: &ThemeData, name: &str) -> String {
+    format!(
+        "zellij does not know theme '{}': it is not built in, and neither {} nor {} defines \
+         it, so zellij would use its default",
+        name,
+        theme_data.theme_dir().display(),
+        theme_data.config_path().display()
+    )
+}
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+fn unresolved_error(theme_data: &ThemeData, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}; rerun with --force to apply it anyway", unresolved(theme_data, name)),
+    )
+}
 
-    if let Err(err) = res {
-        println!("Error: {}", err);
+fn reset(settings: &Settings) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    match theme_data.reset_theme()? {
+        Some(previous) => say!(
+            "Removed theme {} from the config; Zellij now uses its default ({})",
+            previous,
+            LiveSwitcher::default().apply().label()
+        ),
+        None => say!("No theme is set in the config; nothing to reset"),
     }
+    print_layout_updates(&theme_data.update_layouts(None));
+    Ok(())
+}
 
+/// One line per layout file whose `theme` node was changed, or could not be.
+fn print_layout_updates(updates: &[LayoutUpdate]) {
+    for update in updates {
+        match &update.result {
+            Ok(()) => say!("  updated layout {}", update.file),
+            Err(e) => eprintln!("warning: could not update layout {}: {}", update.file, e),
+        }
+    }
+}
+
+async fn preview(settings: &Settings, name: &str) -> io::Result<()> {
+    let outcome = fetch_themes(&ThemeData::new(settings)?).await?;
+    let colors = theme_colors(find_theme(&outcome.themes, name)?)?;
+    let depth = ColorDepth::detect();
+    print!("{}", swatch::render(name, colors, depth, settings.color_names));
     Ok(())
 }
 
-fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    app: &mut App,
-    theme_data: ThemeData,
-) -> io::Result<()> {
-    loop {
-        terminal.draw(|frame| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([
-                    Constraint::Length(3),  // Status
-                    Constraint::Min(1),     // List
-                ])
-                .split(frame.size());
-
-            // Status message
-            let status = Paragraph::new(app.status_message.clone())
-                .block(Block::default().borders(Borders::ALL).title("Status"));
-            frame.render_widget(status, chunks[0]);
-
-            // Theme list
-            let items: Vec<ListItem> = app
-                .themes
-                .iter()
-                .map(|theme| {
-                    ListItem::new(Line::from(vec![Span::styled(
-                        theme,
-                        Style::default().add_modifier(Modifier::BOLD),
-                    )]))
+/// The one theme name piped to `apply -`, trimmed. Nothing at all is what
+/// fzf leaves when it is cancelled.
+fn name_from_stdin(input: &str) -> io::Result<&str> {
+    let mut names = input.lines().map(str::trim).filter(|line| !line.is_empty());
+    match (names.next(), names.next()) {
+        (Some(name), None) => Ok(name),
+        (None, _) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no theme name on stdin",
+        )),
+        (Some(_), Some(_)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected one theme name on stdin, got several",
+        )),
+    }
+}
+
+fn find_theme<'a>(themes: &'a [Theme], name: &str) -> io::Result<&'a Theme> {
+    themes.iter().find(|theme| theme.name == name).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("unknown theme '{}'", name))
+    })
+}
+
+fn theme_colors(theme: &Theme) -> io::Result<&ThemeColors> {
+    theme.colors.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no colors are known for '{}'", theme.name),
+        )
+    })
+}
+
+async fn list(settings: &Settings) -> io::Result<()> {
+    let outcome = fetch_themes(&ThemeData::new(settings)?).await?;
+    print_names(&outcome.themes);
+    Ok(())
+}
+
+fn list_cached(settings: &Settings) -> io::Result<()> {
+    print_names(&ThemeData::new(settings)?.cached_themes().themes);
+    Ok(())
+}
+
+fn search(settings: &Settings, query: &str, limit: Option<usize>, json: bool) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    let themes = theme_data.cached_themes().themes;
+    let mut ranked = matcher::rank(query, themes.iter().enumerate(), &theme_data.tags()?);
+    let found = !ranked.is_empty();
+    ranked.truncate(limit.unwrap_or(usize::MAX));
+    if json {
+        let matches: Vec<serde_json::Value> = ranked
+            .iter()
+            .map(|ranked| {
+                serde_json::json!({
+                    "name": themes[ranked.index].name,
+                    "score": ranked.score,
+                    "color_distance": ranked.distance,
                 })
-                .collect();
-
-            let themes = List::new(items)
-                .block(Block::default().borders(Borders::ALL).title("Themes"))
-                .highlight_style(
-                    Style::default()
-                        .bg(Color::Green)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .highlight_symbol("> ");
-
-            frame.render_stateful_widget(themes, chunks[1], &mut app.state);
-        })?;
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+    } else {
+        for ranked in &ranked {
+            println!("{}", themes[ranked.index].name);
+        }
+    }
+    if !found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Down | KeyCode::Char('j') => app.next(),
-                    KeyCode::Up | KeyCode::Char('k') => app.previous(),
-                    KeyCode::Enter => {
-                        if let Some(selected) = app.state.selected() {
-                            let theme = &app.themes[selected];
-                            match theme_data.update_config(theme) {
-                                Ok(_) => {
-                                    app.status_message = format!("Successfully applied theme: {}", theme);
-                                }
-                                Err(e) => {
-                                    app.status_message = format!("Error updating config: {}", e);
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
-                }
+fn print_names(themes: &[Theme]) {
+    for theme in themes {
+        println!("{}", theme.name);
+    }
+}
+
+async fn export_gallery(settings: &Settings, output: &Path) -> io::Result<()> {
+    let outcome = fetch_themes(&ThemeData::new(settings)?).await?;
+    let html = gallery::render(&outcome.themes);
+    if output == Path::new("-") {
+        io::stdout().write_all(html.as_bytes())?;
+    } else {
+        fs::write(output, html)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", output.display(), e)))?;
+        say!("Wrote {} themes to {}", outcome.themes.len(), output.display());
+    }
+    Ok(())
+}
+
+async fn outdated(settings: &Settings) -> io::Result<()> {
+    let statuses = ThemeData::new(settings)?.check_installed().await?;
+    if statuses.is_empty() {
+        say!("No theme files were installed from a source");
+        return Ok(());
+    }
+    let width = statuses.iter().map(|status| status.file.len()).max().unwrap_or(0);
+    for status in &statuses {
+        let modified = if status.modified { " (edited locally)" } else { "" };
+        println!("{:<width$}  {}{}", status.file, status.upstream.label(), modified);
+    }
+    Ok(())
+}
+
+async fn download_all(settings: &Settings, force: bool) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    let report = theme_data.download_all(force).await?;
+    for failure in &report.failed {
+        warning!("could not download {}: {}", failure.file, failure.error);
+    }
+    say!(
+        "{} downloaded, {} skipped, {} failed, in {}",
+        report.downloaded.len(),
+        report.skipped.len(),
+        report.failed.len(),
+        theme_data.theme_dir().display()
+    );
+    if report.failed.is_empty() {
+        Ok(())
+    } else {
+        let failed = report.failed.len();
+        Err(io::Error::other(format!("{} theme files could not be downloaded", failed)))
+    }
+}
+
+async fn prune(settings: &Settings, yes: bool) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    let stale = theme_data.stale_files().await?;
+    if stale.is_empty() {
+        say!("No installed theme files are stale");
+        return Ok(());
+    }
+    if !yes {
+        for path in &stale {
+            println!("{}", path.display());
+        }
+        say!("The source no longer lists these files; rerun with --yes to delete them");
+        return Ok(());
+    }
+    for path in &stale {
+        theme_data.delete_theme_file(path)?;
+        say!("Deleted {}", path.display());
+    }
+    Ok(())
+}
+
+fn history(settings: &Settings, json: bool) -> io::Result<()> {
+    let mut entries = ThemeData::new(settings)?.history()?;
+    entries.reverse();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for entry in &entries {
+            println!("{}  {}", entry.applied_at_label(), entry.theme);
+            for failure in &entry.hook_failures {
+                println!("                  failed {}", failure);
             }
         }
     }
-} 
\ No newline at end of file
+    Ok(())
+}
+
+/// The undo stack for `--history`, newest first.
+fn print_rollback(settings: &Settings) -> io::Result<()> {
+    let stack = ThemeData::new(settings)?.rollback()?;
+    if stack.entries.is_empty() {
+        say!("Nothing to undo yet; applying a theme starts the stack");
+        return Ok(());
+    }
+    for (i, entry) in stack.entries.iter().enumerate().rev() {
+        let marker = if i == stack.position { ">" } else { " " };
+        println!("{} {}", marker, entry.as_deref().unwrap_or("(no theme)"));
+    }
+    Ok(())
+}
+
+async fn tags(settings: &Settings, prune: bool) -> io::Result<()> {
+    let theme_data = ThemeData::new(settings)?;
+    let mut tags = theme_data.tags()?;
+    if tags.is_empty() {
+        say!("No themes are tagged");
+        return Ok(());
+    }
+    let outcome = fetch_themes(&theme_data).await?;
+    let is_known = |name: &str| outcome.themes.iter().any(|theme| theme.name == name);
+    if !outcome.failures.is_empty() {
+        // Their themes would look orphaned
+        let message = format!("{} theme files failed to load", outcome.failures.len());
+        if prune {
+            return Err(io::Error::other(format!("{}; not pruning", message)));
+        }
+        warning!("{}; their themes are shown as orphaned", message);
+    }
+    if prune {
+        let orphaned: Vec<String> = tags.orphaned(is_known).into_iter().map(String::from).collect();
+        if orphaned.is_empty() {
+            say!("Every tagged theme still exists; nothing to prune");
+            return Ok(());
+        }
+        for name in &orphaned {
+            tags.set(name, BTreeSet::new());
+        }
+        theme_data.save_tags(&tags)?;
+        say!(
+            "Removed the tags of {} themes that no longer exist: {}",
+            orphaned.len(),
+            orphaned.join(", ")
+        );
+        return Ok(());
+    }
+    let width = tags.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, labels) in tags.iter() {
+        let orphaned = if is_known(name) { "" } else { "  (orphaned)" };
+        let labels = tags::label(labels.iter().map(String::as_str));
+        println!("{:<width$}  {}{}", name, labels, orphaned);
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Fuzzy matching of theme names against the filter query.
+//!
+//! The query has to appear in the name as a subsequence. Matching is
+//! smart-case: it ignores case unless the query contains an uppercase
+//! letter. Among matching names, those where the query characters start
+//! words (after `-`, `_` or a space) or run together score higher, so
+//! `dark` ranks `dark-plus` above `dracula-park`.
+//!
+//! [`rank`] applies a whole filter query, tags and colors included, so the
+//! selector's list and the `search` command order themes the same way.
+
+use crate::search;
+use crate::tags::{self, Tags};
+use crate::theme::Theme;
+
+const MATCH: i64 = 1;
+const WORD_START: i64 = 8;
+const CONSECUTIVE: i64 = 5;
+const GAP: i64 = 1;
+
+/// A name that matched, with how well and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    /// Char indices into the name of the matched characters, ascending.
+    pub positions: Vec<usize>,
+}
+
+/// A theme a filter query matched, as ranked by [`rank`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ranked {
+    /// Index of the theme in the list given to [`rank`].
+    pub index: usize,
+    /// How far its colors are from the query's `bg:`-style words; `0.0`
+    /// without any.
+    pub distance: f64,
+    /// The fuzzy score of its name against the rest of the query.
+    pub score: i64,
+}
+
+/// The themes among `themes` that `query` matches, best first: closest in
+/// color, then best fuzzy score, then in list order. `#tag` words keep only
+/// themes with all those tags, in `tags` or from their source.
+pub fn rank<'a>(
+    query: &str,
+    themes: impl IntoIterator<Item = (usize, &'a Theme)>,
+    tags: &Tags,
+) -> Vec<Ranked> {
+    let (wanted, query) = tags::split_query(query);
+    let (colors, query) = search::split_query(&query);
+    let mut ranked: Vec<Ranked> = themes
+        .into_iter()
+        .filter(|(_, theme)| tags.has_all(theme, &wanted))
+        .filter_map(|(index, theme)| {
+            Some(Ranked {
+                index,
+                distance: search::distance(&colors, theme)?,
+                score: fuzzy_match(&query, &theme.name)?.score,
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(b.score.cmp(&a.score)));
+    ranked
+}
+
+/// Matches `query` against `name`, or returns `None` if it does not occur
+/// as a subsequence. An empty query matches everything with score 0.
+pub fn fuzzy_match(query: &str, name: &str) -> Option<Match> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let query: Vec<char> = query.chars().map(fold).collect();
+    let name: Vec<char> = name.chars().collect();
+    let Some(&first) = query.first() else {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    };
+
+    // Greedy from every place the first character occurs, keeping the best
+    let mut best: Option<Match> = None;
+    for start in (0..name.len()).filter(|&i| fold(name[i]) == first) {
+        let mut positions = vec![start];
+        let mut next = start + 1;
+        for &wanted in &query[1..] {
+            match (next..name.len()).find(|&i| fold(name[i]) == wanted) {
+                Some(i) => {
+                    positions.push(i);
+                    next = i + 1;
+                }
+                None => break,
+            }
+        }
+        if positions.len() < query.len() {
+            // Later starts only leave fewer characters to match
+            break;
+        }
+        let score = score(&name, &positions);
+        if best.as_ref().is_none_or(|best| score > best.score) {
+            best = Some(Match { score, positions });
+        }
+    }
+    best
+}
+
+fn score(name: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    for (n, &i) in positions.iter().enumerate() {
+        score += MATCH;
+        if i == 0 || matches!(name[i - 1], '-' | '_' | ' ') {
+            score += WORD_START;
+        }
+        if n > 0 {
+            let gap = (i - positions[n - 1] - 1) as i64;
+            score += if gap == 0 { CONSECUTIVE } else { -GAP * gap };
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names among `names` that `query` matches, best first.
+    fn ranked<'a>(query: &str, names: &[&'a str]) -> Vec<&'a str> {
+        let themes: Vec<Theme> = names.iter().map(|name| Theme::new(*name)).collect();
+        rank(query, themes.iter().enumerate(), &Tags::default())
+            .into_iter()
+            .map(|ranked| names[ranked.index])
+            .collect()
+    }
+
+    #[test]
+    fn word_starts_and_runs_rank_first() {
+        assert_eq!(ranked("dark", &["dracula-park", "dark-plus"]), ["dark-plus", "dracula-park"]);
+        assert_eq!(ranked("gl", &["gruvbox-light", "gl"]), ["gl", "gruvbox-light"]);
+        assert_eq!(
+            ranked("tn", &["catppuccin", "tokyo-night", "one-dark"]),
+            ["tokyo-night", "catppuccin"]
+        );
+    }
+
+    #[test]
+    fn equal_scores_keep_the_list_order_and_non_matches_are_dropped() {
+        assert_eq!(ranked("nord", &["nord", "dracula", "nord"]), ["nord", "nord"]);
+        assert_eq!(ranked("", &["b", "a"]), ["b", "a"]);
+        assert_eq!(ranked("xyz", &["nord", "dracula"]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn a_lowercase_query_ignores_case() {
+        assert_eq!(ranked("solar", &["Solarized", "solarized"]), ["Solarized", "solarized"]);
+        assert!(fuzzy_match("ayu", "AYU-Dark").is_some());
+    }
+
+    #[test]
+    fn an_uppercase_query_matches_case_sensitively() {
+        assert_eq!(ranked("Solar", &["solarized", "Solarized"]), ["Solarized"]);
+        assert!(fuzzy_match("AYU", "ayu-dark").is_none());
+    }
+
+    #[test]
+    fn matched_positions_are_the_best_scoring_ones() {
+        let found = fuzzy_match("dark", "dracula-dark").unwrap();
+        assert_eq!(found.positions, [8, 9, 10, 11]);
+    }
+}
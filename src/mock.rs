@@ -0,0 +1,289 @@
+//! A miniature Zellij session drawn in a theme's colors: tab bar, pane
+//! frames with some shell output, and the status bar.
+//!
+//! The colors go where Zellij's default plugins put them. Semantic themes
+//! name the tab and frame colors directly; for legacy themes they are
+//! derived from the palette the way Zellij does: the active tab and the
+//! focused frame are green, inactive tabs use the foreground color as
+//! background with black text, and keys in the hints are orange.
+
+use crate::color::Rgb;
+use crate::theme::{Component, ThemeColors};
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+/// The palette roles the mock session is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockColors {
+    pub text: Component,
+    pub tab_active: Component,
+    pub tab_inactive: Component,
+    pub frame_focused: Rgb,
+    pub frame_unfocused: Rgb,
+    pub key: Rgb,
+    pub success: Rgb,
+    pub error: Rgb,
+    pub accent: Rgb,
+}
+
+impl MockColors {
+    /// Maps a theme onto the mock's roles, or `None` without both a
+    /// foreground and a background.
+    pub fn from_theme(colors: &ThemeColors) -> Option<Self> {
+        let fg = colors.fg?;
+        let bg = colors.bg?;
+        let black = colors.black.unwrap_or(bg);
+        let green = colors.green.unwrap_or(fg);
+        Some(Self {
+            text: Component {
+                base: fg,
+                background: bg,
+            },
+            tab_active: colors.ribbon_selected.unwrap_or(Component {
+                base: black,
+                background: green,
+            }),
+            tab_inactive: colors.ribbon_unselected.unwrap_or(Component {
+                base: black,
+                background: fg,
+            }),
+            frame_focused: colors.frame_selected.unwrap_or(green),
+            frame_unfocused: colors.frame_unselected.unwrap_or(fg),
+            key: colors.orange.unwrap_or(fg),
+            success: green,
+            error: colors.red.unwrap_or(fg),
+            accent: colors.blue.unwrap_or(fg),
+        })
+    }
+}
+
+/// The widget. Drawn at any size; below 20×5 only the tab bar and the
+/// status bar are left.
+pub struct MockSession {
+    colors: MockColors,
+}
+
+impl MockSession {
+    pub fn new(colors: MockColors) -> Self {
+        Self { colors }
+    }
+
+    fn style(&self, component: Component) -> Style {
+        Style::default()
+            .fg(rgb(component.base))
+            .bg(rgb(component.background))
+    }
+
+    /// The active tab, and the current mode in the status bar.
+    fn active(&self) -> Style {
+        self.style(self.colors.tab_active).add_modifier(Modifier::BOLD)
+    }
+
+    fn tab_bar(&self) -> Line<'static> {
+        let text = self.style(self.colors.text);
+        Line::from(vec![
+            Span::styled(" Zellij (demo) ", text),
+            Span::styled(" Tab #1 ", self.active()),
+            Span::styled(" ", text),
+            Span::styled(" Tab #2 ", self.style(self.colors.tab_inactive)),
+        ])
+    }
+
+    fn status_bar(&self) -> Line<'static> {
+        let text = self.style(self.colors.text);
+        let key = text.fg(rgb(self.colors.key)).add_modifier(Modifier::BOLD);
+        Line::from(vec![
+            Span::styled(" Ctrl + ", text),
+            Span::styled(" <p> PANE ", self.active()),
+            Span::styled(" ", text),
+            Span::styled(" <t> TAB ", self.style(self.colors.tab_inactive)),
+            Span::styled("  Tip: ", text),
+            Span::styled("Alt + n", key),
+            Span::styled(" new pane", text),
+        ])
+    }
+
+    fn pane(
+        &self,
+        focused: bool,
+        title: &'static str,
+        body: Vec<Line<'static>>,
+    ) -> Paragraph<'static> {
+        let frame = if focused {
+            self.colors.frame_focused
+        } else {
+            self.colors.frame_unfocused
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(rgb(frame)))
+            .title(Span::styled(title, Style::default().fg(rgb(frame))));
+        Paragraph::new(body)
+            .style(self.style(self.colors.text))
+            .block(block)
+    }
+
+    fn shell_output(&self) -> Vec<Line<'static>> {
+        let text = self.style(self.colors.text);
+        let color = |rgb: Rgb| text.fg(self::rgb(rgb));
+        vec![
+            Line::from(vec![
+                Span::styled("~/src ", color(self.colors.accent)),
+                Span::styled("$ cargo build", text),
+            ]),
+            Line::from(Span::styled("   Compiling demo v0.1.0", color(self.colors.success))),
+            Line::from(Span::styled("error: expected `;`", color(self.colors.error))),
+            Line::from(Span::styled("Some body text in the foreground", text)),
+        ]
+    }
+}
+
+impl Widget for MockSession {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height == 0 || area.width == 0 {
+            return;
+        }
+        buf.set_style(area, self.style(self.colors.text));
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(area);
+        Paragraph::new(self.tab_bar()).render(rows[0], buf);
+        Paragraph::new(self.status_bar()).render(rows[2], buf);
+
+        let body = rows[1];
+        if body.height < 3 || body.width < 20 {
+            return;
+        }
+        // Two panes side by side when there is room, the left one focused
+        let panes = if body.width >= 48 {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .split(body)
+                .to_vec()
+        } else {
+            vec![body]
+        };
+        self.pane(true, " ~/src ", self.shell_output()).render(panes[0], buf);
+        if let Some(&area) = panes.get(1) {
+            let text = self.style(self.colors.text);
+            let idle = vec![Line::from(Span::styled("$ ", text))];
+            self.pane(false, " Pane #2 ", idle).render(area, buf);
+        }
+    }
+}
+
+fn rgb(color: Rgb) -> Color {
+    Color::Rgb(color.r, color.g, color.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FG: Rgb = Rgb::new(216, 222, 233);
+    const BG: Rgb = Rgb::new(46, 52, 64);
+    const BLACK: Rgb = Rgb::new(59, 66, 82);
+    const GREEN: Rgb = Rgb::new(163, 190, 140);
+
+    fn legacy() -> ThemeColors {
+        ThemeColors {
+            fg: Some(FG),
+            bg: Some(BG),
+            black: Some(BLACK),
+            green: Some(GREEN),
+            ..ThemeColors::default()
+        }
+    }
+
+    /// The mock drawn `width` by `height` in `colors`.
+    fn render(colors: &ThemeColors, width: u16, height: u16) -> Buffer {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, width, height));
+        MockSession::new(MockColors::from_theme(colors).unwrap()).render(buffer.area, &mut buffer);
+        buffer
+    }
+
+    /// The characters of each row of `buffer`.
+    fn text(buffer: &Buffer) -> Vec<String> {
+        let area = buffer.area;
+        (0..area.height)
+            .map(|y| (0..area.width).map(|x| buffer.get(x, y).symbol.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn two_panes_side_by_side_when_wide() {
+        let buffer = render(&legacy(), 60, 8);
+        assert_eq!(
+            text(&buffer),
+            [
+                " Zellij (demo)  Tab #1   Tab #2                             ",
+                "┌ ~/src ───────────────────────────┐┌ Pane #2 ─────────────┐",
+                "│~/src $ cargo build               ││$                     │",
+                "│   Compiling demo v0.1.0          ││                      │",
+                "│error: expected `;`               ││                      │",
+                "│Some body text in the foreground  ││                      │",
+                "└──────────────────────────────────┘└──────────────────────┘",
+                " Ctrl +  <p> PANE   <t> TAB   Tip: Alt + n new pane         ",
+            ]
+        );
+        // The active tab in green, the inactive one in the foreground color
+        assert_eq!(buffer.get(16, 0).bg, rgb(GREEN));
+        assert_eq!(buffer.get(16, 0).fg, rgb(BLACK));
+        assert_eq!(buffer.get(25, 0).bg, rgb(FG));
+        // The focused frame in green, the other in the foreground color
+        assert_eq!(buffer.get(0, 1).fg, rgb(GREEN));
+        assert_eq!(buffer.get(36, 1).fg, rgb(FG));
+        assert_eq!(buffer.get(40, 4).bg, rgb(BG));
+    }
+
+    #[test]
+    fn one_pane_when_narrow() {
+        let buffer = render(&legacy(), 30, 6);
+        assert_eq!(
+            text(&buffer),
+            [
+                " Zellij (demo)  Tab #1   Tab #",
+                "┌ ~/src ─────────────────────┐",
+                "│~/src $ cargo build         │",
+                "│   Compiling demo v0.1.0    │",
+                "└────────────────────────────┘",
+                " Ctrl +  <p> PANE   <t> TAB   ",
+            ]
+        );
+    }
+
+    #[test]
+    fn semantic_themes_name_the_tab_and_frame_colors() {
+        let (red, blue) = (Rgb::new(191, 97, 106), Rgb::new(129, 161, 193));
+        let colors = ThemeColors {
+            ribbon_selected: Some(Component {
+                base: BG,
+                background: red,
+            }),
+            frame_selected: Some(blue),
+            ..legacy()
+        };
+        let buffer = render(&colors, 60, 8);
+        assert_eq!(buffer.get(16, 0).bg, rgb(red));
+        assert_eq!(buffer.get(16, 0).fg, rgb(BG));
+        assert_eq!(buffer.get(0, 1).fg, rgb(blue));
+    }
+
+    #[test]
+    fn a_theme_without_both_fg_and_bg_cannot_be_drawn() {
+        let colors = ThemeColors {
+            bg: None,
+            ..legacy()
+        };
+        assert_eq!(MockColors::from_theme(&colors), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Palette extraction (median cut) and the heuristics that turn a palette
+//! into zellij theme slots.
+
+use crate::color::{contrast_ratio, hue_distance, Hsl, Rgb};
+use crate::theme::{ThemeColors, ThemeFormat};
+
+/// Upper bound on the pixels fed to median cut; larger images are sampled.
+const MAX_SAMPLES: usize = 16_384;
+
+// Target hues for the accent slots, in degrees
+const RED: f64 = 0.0;
+const ORANGE: f64 = 30.0;
+const YELLOW: f64 = 55.0;
+const GREEN: f64 = 120.0;
+const CYAN: f64 = 185.0;
+const BLUE: f64 = 225.0;
+const MAGENTA: f64 = 300.0;
+const ACCENT_HUES: [f64; 7] = [RED, ORANGE, YELLOW, GREEN, CYAN, BLUE, MAGENTA];
+
+/// A representative color and the number of sampled pixels it stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swatch {
+    pub color: Rgb,
+    pub population: usize,
+}
+
+/// Reduces `pixels` to at most `max_colors` swatches using median cut,
+/// most common first.
+pub fn extract_palette(pixels: &[Rgb], max_colors: usize) -> Vec<Swatch> {
+    if pixels.is_empty() || max_colors == 0 {
+        return Vec::new();
+    }
+    let step = pixels.len().div_ceil(MAX_SAMPLES);
+    let samples: Vec<Rgb> = pixels.iter().step_by(step).copied().collect();
+
+    let mut boxes = vec![samples];
+    while boxes.len() < max_colors {
+        // Split the box with the widest spread, weighted by how many pixels
+        // it holds so big mixed boxes go before small outliers
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (i, channel, range as usize * colors.len())
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|color| channel_value(*color, channel));
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut swatches: Vec<Swatch> = boxes
+        .iter()
+        .map(|colors| Swatch {
+            color: average(colors),
+            population: colors.len(),
+        })
+        .collect();
+    swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.population));
+    swatches
+}
+
+fn channel_value(color: Rgb, channel: usize) -> u8 {
+    match channel {
+        0 => color.r,
+        1 => color.g,
+        _ => color.b,
+    }
+}
+
+fn widest_channel(colors: &[Rgb]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|&c| channel_value(c, channel));
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn average(colors: &[Rgb]) -> Rgb {
+    let n = colors.len().max(1) as u64;
+    let sum = colors.iter().fold((0u64, 0u64, 0u64), |(r, g, b), c| {
+        (r + c.r as u64, g + c.g as u64, b + c.b as u64)
+    });
+    Rgb::new((sum.0 / n) as u8, (sum.1 / n) as u8, (sum.2 / n) as u8)
+}
+
+/// Nudges the lightness of `color` away from `bg` until the pair reaches
+/// `min_ratio`, or as far as lightness allows.
+fn ensure_contrast(color: Rgb, bg: Rgb, min_ratio: f64) -> Rgb {
+    let mut hsl = Hsl::from(color);
+    let step = if bg.is_light() { -0.02 } else { 0.02 };
+    let mut adjusted = color;
+    while contrast_ratio(adjusted, bg) < min_ratio && (0.0..=1.0).contains(&(hsl.l + step)) {
+        hsl.l += step;
+        adjusted = Rgb::from(hsl);
+    }
+    adjusted
+}
+
+/// Maps a palette onto theme slots: the darkest swatch becomes the
+/// background (the lightest with `light`), the swatch contrasting most
+/// with it the foreground, and saturated swatches fill the ANSI hues they
+/// are closest to. Hues the palette lacks are synthesized so every slot
+/// is filled.
+pub fn theme_from_palette(swatches: &[Swatch], light: bool) -> ThemeColors {
+    let mut by_lightness: Vec<Rgb> = swatches.iter().map(|s| s.color).collect();
+    by_lightness.sort_by(|a, b| a.relative_luminance().total_cmp(&b.relative_luminance()));
+    let darkest = by_lightness.first().copied().unwrap_or(Rgb::new(0, 0, 0));
+    let lightest = by_lightness.last().copied().unwrap_or(Rgb::new(255, 255, 255));
+
+    // Keep the background properly dark (or light) even for washed out images
+    let clamp_lightness = |color: Rgb, min: f64, max: f64| {
+        let mut hsl = Hsl::from(color);
+        hsl.l = hsl.l.clamp(min, max);
+        Rgb::from(hsl)
+    };
+    let bg = if light {
+        clamp_lightness(lightest, 0.9, 1.0)
+    } else {
+        clamp_lightness(darkest, 0.0, 0.12)
+    };
+    let fg = by_lightness
+        .iter()
+        .copied()
+        .max_by(|a, b| contrast_ratio(*a, bg).total_cmp(&contrast_ratio(*b, bg)))
+        .unwrap_or(lightest);
+    let fg = ensure_contrast(fg, bg, 7.0);
+
+    let saturated: Vec<(Hsl, &Swatch)> = swatches
+        .iter()
+        .map(|s| (Hsl::from(s.color), s))
+        .filter(|(hsl, _)| hsl.s >= 0.25 && (0.15..=0.85).contains(&hsl.l))
+        .collect();
+    let mean_saturation = if saturated.is_empty() {
+        0.6
+    } else {
+        (saturated.iter().map(|(hsl, _)| hsl.s).sum::<f64>() / saturated.len() as f64).max(0.45)
+    };
+    // Each swatch only competes for the slot whose hue it is closest to
+    let nearest_target = |h: f64| {
+        ACCENT_HUES
+            .iter()
+            .copied()
+            .min_by(|a, b| hue_distance(h, *a).total_cmp(&hue_distance(h, *b)))
+    };
+    let accent = |hue: f64| {
+        let color = saturated
+            .iter()
+            .filter(|(hsl, _)| {
+                hue_distance(hsl.h, hue) <= 30.0 && nearest_target(hsl.h) == Some(hue)
+            })
+            .max_by_key(|(_, swatch)| swatch.population)
+            .map(|(_, swatch)| swatch.color)
+            .unwrap_or_else(|| {
+                Rgb::from(Hsl {
+                    h: hue,
+                    s: mean_saturation,
+                    l: if light { 0.4 } else { 0.6 },
+                })
+            });
+        Some(ensure_contrast(color, bg, 3.0))
+    };
+
+    ThemeColors {
+        format: ThemeFormat::Legacy,
+        fg: Some(fg),
+        bg: Some(bg),
+        black: Some(if light {
+            clamp_lightness(darkest, 0.0, 0.2)
+        } else {
+            clamp_lightness(darkest, 0.15, 0.25)
+        }),
+        red: accent(RED),
+        green: accent(GREEN),
+        yellow: accent(YELLOW),
+        blue: accent(BLUE),
+        magenta: accent(MAGENTA),
+        cyan: accent(CYAN),
+        white: Some(if light {
+            clamp_lightness(lightest, 0.75, 0.85)
+        } else {
+            clamp_lightness(lightest, 0.8, 1.0)
+        }),
+        orange: accent(ORANGE),
+        selection_fg: None,
+        selection_bg: None,
+        ribbon_selected: None,
+        ribbon_unselected: None,
+        frame_selected: None,
+        frame_unselected: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` pixels of each color, in blocks.
+    fn pixels(colors: &[(Rgb, usize)]) -> Vec<Rgb> {
+        colors.iter().flat_map(|&(color, count)| vec![color; count]).collect()
+    }
+
+    #[test]
+    fn distinct_colors_come_back_most_common_first() {
+        let navy = Rgb::new(10, 20, 60);
+        let red = Rgb::new(220, 40, 40);
+        let white = Rgb::new(250, 250, 250);
+        // Populations that median cut splits exactly at color boundaries
+        let image = pixels(&[(red, 200), (navy, 400), (white, 200)]);
+        let palette = extract_palette(&image, 4);
+        let mut found: Vec<(Rgb, usize)> =
+            palette.iter().map(|swatch| (swatch.color, swatch.population)).collect();
+        assert_eq!(found.remove(0), (navy, 400));
+        found.sort_by_key(|(color, _)| color.r);
+        assert_eq!(found, [(red, 200), (white, 200)]);
+    }
+
+    #[test]
+    fn a_single_color_is_not_split_and_nothing_gives_nothing() {
+        let palette = extract_palette(&pixels(&[(Rgb::new(1, 2, 3), 50)]), 8);
+        assert_eq!(palette, [Swatch { color: Rgb::new(1, 2, 3), population: 50 }]);
+        assert!(extract_palette(&[], 8).is_empty());
+        assert!(extract_palette(&pixels(&[(Rgb::new(1, 2, 3), 50)]), 0).is_empty());
+    }
+
+    #[test]
+    fn large_images_are_sampled() {
+        let image = pixels(&[(Rgb::new(0, 0, 0), 50_000), (Rgb::new(255, 255, 255), 50_000)]);
+        let palette = extract_palette(&image, 2);
+        assert_eq!(palette.len(), 2);
+        assert!(palette.iter().map(|swatch| swatch.population).sum::<usize>() <= MAX_SAMPLES);
+    }
+
+    #[test]
+    fn the_darkest_swatch_is_the_background_and_the_lightest_with_light() {
+        let image = pixels(&[
+            (Rgb::new(20, 24, 30), 500),
+            (Rgb::new(230, 230, 220), 300),
+            (Rgb::new(200, 50, 50), 100),
+            (Rgb::new(60, 160, 80), 100),
+        ]);
+        let palette = extract_palette(&image, 8);
+
+        let dark = theme_from_palette(&palette, false);
+        let (fg, bg) = (dark.fg.unwrap(), dark.bg.unwrap());
+        assert_eq!(bg, Rgb::new(20, 24, 30));
+        assert!(contrast_ratio(fg, bg) >= 7.0);
+        assert_eq!(dark.red, Some(Rgb::new(200, 50, 50)));
+        assert_eq!(dark.green, Some(Rgb::new(60, 160, 80)));
+
+        let light = theme_from_palette(&palette, true);
+        assert!(light.bg.unwrap().is_light());
+        assert!(contrast_ratio(light.fg.unwrap(), light.bg.unwrap()) >= 7.0);
+    }
+
+    #[test]
+    fn every_accent_is_filled_and_readable_even_without_saturated_colors() {
+        let grays = pixels(&[(Rgb::new(30, 30, 30), 10), (Rgb::new(200, 200, 200), 10)]);
+        let theme = theme_from_palette(&extract_palette(&grays, 4), false);
+        let bg = theme.bg.unwrap();
+        let accents = [theme.red, theme.green, theme.yellow, theme.blue, theme.magenta];
+        for accent in accents.into_iter().chain([theme.cyan, theme.orange]) {
+            assert!(contrast_ratio(accent.unwrap(), bg) >= 3.0, "{:?}", accent);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! A line-based fallback for terminals where raw mode or the alternate
+//! screen misbehave: the themes are printed as a numbered menu and the
+//! choice is read as a line from stdin.
+
+use crate::color::ContrastLevel;
+use crate::data::{Install, ThemeData};
+use crate::live::LiveSwitcher;
+use crate::progress;
+use crate::settings::Settings;
+use crate::theme::{Appearance, Theme};
+use crate::selector::{configs_note, Options};
+use std::io::{self, BufRead, Write};
+
+pub async fn run_plain_selector(
+    settings: Settings,
+    warnings: Vec<String>,
+    options: Options,
+) -> io::Result<()> {
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let theme_data = ThemeData::new(&settings)?;
+    theme_data.ensure_theme_dir()?;
+    let outcome = progress::fetch_themes(&theme_data, options.force_refresh, false).await?;
+    for failure in &outcome.failures {
+        eprintln!("warning: {} failed to load: {}", failure.file, failure.error);
+    }
+    if outcome.themes.is_empty() {
+        println!("No themes found.");
+        return Ok(());
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let width = outcome.themes.len().to_string().len();
+    for (i, theme) in outcome.themes.iter().enumerate() {
+        writeln!(out, "{:>width$}) {}{}", i + 1, theme.name, describe(theme), width = width)?;
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        write!(out, "Select a theme by number or name (empty to quit): ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(out)?;
+            return Ok(());
+        };
+        let choice = line.trim();
+        if choice.is_empty() {
+            return Ok(());
+        }
+        match pick(&outcome.themes, choice) {
+            Some(theme) => {
+                match theme_data.update_config(theme, options.force).await? {
+                    Install::Modified(path) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!(
+                                "{} has local changes; rerun with --force to overwrite it",
+                                path.display()
+                            ),
+                        ))
+                    }
+                    Install::Unresolved => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "zellij does not know theme '{}': it is not built in, and \
+                                 neither {} nor config.kdl defines it; rerun with --force to \
+                                 apply it anyway",
+                                theme.name,
+                                theme_data.theme_dir().display()
+                            ),
+                        ))
+                    }
+                    Install::AlreadyApplied => {
+                        writeln!(out, "{} is already active", theme.name)?;
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                let live = LiveSwitcher::default().apply();
+                let report = live.report(&theme.name, &configs_note(&theme_data), None);
+                writeln!(out, "{}", report)?;
+                for update in theme_data.update_layouts(Some(&theme.name)) {
+                    match update.result {
+                        Ok(()) => writeln!(out, "  updated layout {}", update.file)?,
+                        Err(e) => {
+                            eprintln!("warning: could not update layout {}: {}", update.file, e)
+                        }
+                    }
+                }
+                for failure in theme_data.run_hooks(theme).await {
+                    eprintln!("warning: {} failed: {}", failure.hook, failure.error);
+                }
+                return Ok(());
+            }
+            None => writeln!(out, "No theme matches '{}'", choice)?,
+        }
+    }
+}
+
+/// Resolves a 1-based menu number or an exact theme name.
+fn pick<'a>(themes: &'a [Theme], choice: &str) -> Option<&'a Theme> {
+    match choice.parse::<usize>() {
+        Ok(number) => number.checked_sub(1).and_then(|i| themes.get(i)),
+        Err(_) => themes.iter().find(|theme| theme.name == choice),
+    }
+}
+
+/// The appearance and contrast grade, when known, e.g. ` (dark, AAA)`.
+fn describe(theme: &Theme) -> String {
+    let mut facts = Vec::new();
+    if theme.appearance != Appearance::Unknown {
+        facts.push(theme.appearance.label().to_string());
+    }
+    if let Some(ratio) = theme.fg_bg_contrast() {
+        facts.push(ContrastLevel::from_ratio(ratio).label().to_string());
+    }
+    if facts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", facts.join(", "))
+    }
+}
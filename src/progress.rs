@@ -0,0 +1,73 @@
+//! Progress of a forced refresh outside the selector, for `list
+//! --force-refresh` and the like, which would otherwise sit silent for as
+//! long as the downloads take.
+//!
+//! The counter is fed by [`ThemeData::stream_themes`], the same events the
+//! selector's loading bar follows, and goes to stderr so it never mixes
+//! with what a command prints. It is only drawn on a terminal; the summary
+//! line after it is printed either way, unless the caller is quiet.
+
+use crate::data::{FetchEvent, FetchOutcome, ThemeData};
+use std::io::{self, IsTerminal, Write};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Erases the line the cursor is on and returns to its start.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Lists the themes like [`ThemeData::fetch_themes`]. A forced refresh
+/// shows its progress on stderr unless `quiet`; reading the cache is quick
+/// and says nothing.
+pub async fn fetch_themes(
+    theme_data: &ThemeData,
+    force_refresh: bool,
+    quiet: bool,
+) -> io::Result<FetchOutcome> {
+    if !force_refresh {
+        return theme_data.fetch_themes(false).await;
+    }
+    let started = Instant::now();
+    let draw = !quiet && io::stderr().is_terminal();
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let counter = async {
+        if draw {
+            show("Listing theme files…");
+        }
+        let mut themes = 0;
+        while let Some(event) = events.recv().await {
+            let (done, total) = match event {
+                FetchEvent::Themes(batch) => {
+                    themes += batch.len();
+                    continue;
+                }
+                FetchEvent::Progress { done, total } => (done, total),
+            };
+            if draw {
+                show(&format!("{}/{} theme files, {} themes", done, total, themes));
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(theme_data.stream_themes(true, sender), counter);
+    if draw {
+        show("");
+    }
+    let outcome = result?;
+    let summary = format!(
+        "Found {} themes in {:.1}s, files skipped: {}",
+        outcome.themes.len(),
+        started.elapsed().as_secs_f64(),
+        outcome.failures.len()
+    );
+    log::info!("{}", summary);
+    if !quiet {
+        eprintln!("{}", summary);
+    }
+    Ok(outcome)
+}
+
+/// Replaces the progress line with `text`.
+fn show(text: &str) {
+    let mut stderr = io::stderr();
+    let _ = write!(stderr, "{}{}", CLEAR_LINE, text);
+    let _ = stderr.flush();
+}
@@ -0,0 +1,50 @@
+//! Showing a theme file in the desktop's file manager.
+
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The file manager command for this session, or `None` when there is no
+/// desktop to show a file on: over SSH, or outside macOS and Windows
+/// without `DISPLAY` or `WAYLAND_DISPLAY`. Finder and Explorer select the
+/// file; `xdg-open` can only open the directory holding it.
+fn opener(path: &Path) -> Option<(&'static str, Vec<OsString>)> {
+    if env::var_os("SSH_CONNECTION").is_some() || env::var_os("SSH_TTY").is_some() {
+        return None;
+    }
+    if cfg!(target_os = "macos") {
+        return Some(("open", vec!["-R".into(), path.into()]));
+    }
+    if cfg!(windows) {
+        let mut select = OsString::from("/select,");
+        select.push(path);
+        return Some(("explorer", vec![select]));
+    }
+    if env::var_os("WAYLAND_DISPLAY").is_some() || env::var_os("DISPLAY").is_some() {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        return Some(("xdg-open", vec![dir.unwrap_or(Path::new(".")).into()]));
+    }
+    None
+}
+
+/// Shows `path` in the file manager and returns the command used, or
+/// `None` without a desktop, so the caller can show the path instead.
+pub fn reveal(path: &Path) -> io::Result<Option<&'static str>> {
+    let Some((program, args)) = opener(path) else {
+        return Ok(None);
+    };
+    let status = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    // Explorer exits with 1 even when it opened the window
+    if status.success() || cfg!(windows) {
+        Ok(Some(program))
+    } else {
+        Err(io::Error::other(format!("{} failed", program)))
+    }
+}
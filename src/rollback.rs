@@ -0,0 +1,90 @@
+//! The undo stack: the themes the config went through most recently, so
+//! the selector can step back through them and forward again, the way an
+//! editor undoes and redoes.
+//!
+//! Unlike the history, which keeps every theme ever applied, the stack
+//! holds at most [`CAPACITY`] entries and has a position, the entry the
+//! config is at. Applying a theme after stepping back drops the entries
+//! ahead of the position, as redo would otherwise jump somewhere
+//! unexpected. It is kept in `.theme_rollback.json` next to the history.
+
+use crate::config::write_atomically;
+use crate::lock::FileLock;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The stack's name, next to the history.
+pub const ROLLBACK_FILE: &str = ".theme_rollback.json";
+
+/// How many entries the stack keeps; older ones are dropped.
+pub const CAPACITY: usize = 10;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rollback {
+    /// Oldest first. `None` stands for no theme set, as after a reset.
+    pub entries: Vec<Option<String>>,
+    /// The index in `entries` of the theme the config is at.
+    pub position: usize,
+}
+
+impl Rollback {
+    /// Notes that the config went from `previous` to `current`, dropping
+    /// whatever could have been redone. An entry the same as the one before
+    /// it is not added again.
+    pub fn push(&mut self, previous: Option<&str>, current: Option<&str>) {
+        self.entries.truncate(self.position + 1);
+        for theme in [previous, current] {
+            if self.entries.last().map(Option::as_deref) != Some(theme) {
+                self.entries.push(theme.map(str::to_string));
+            }
+        }
+        let excess = self.entries.len().saturating_sub(CAPACITY);
+        self.entries.drain(..excess);
+        self.position = self.entries.len().saturating_sub(1);
+    }
+
+    /// Steps back, returning the entry to go back to, or `None` at the
+    /// oldest entry.
+    pub fn back(&mut self) -> Option<Option<String>> {
+        self.position = self.position.checked_sub(1)?;
+        self.entries.get(self.position).cloned()
+    }
+
+    /// Steps forward again after [`back`](Self::back), returning the entry
+    /// to go to, or `None` when nothing was undone.
+    pub fn forward(&mut self) -> Option<Option<String>> {
+        let entry = self.entries.get(self.position + 1)?.clone();
+        self.position += 1;
+        Some(entry)
+    }
+}
+
+/// Reads the stack at `path`. A missing file is an empty stack, and so is
+/// one that does not parse, which is logged: losing the undo stack is not
+/// worth failing an apply over.
+pub fn load(path: &Path) -> io::Result<Rollback> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Rollback::default()),
+        Err(e) => return Err(e),
+    };
+    let mut stack: Rollback = serde_json::from_str(&content).unwrap_or_else(|e| {
+        log::warn!("starting a new undo stack, {} is invalid: {}", path.display(), e);
+        Rollback::default()
+    });
+    stack.position = stack.position.min(stack.entries.len().saturating_sub(1));
+    Ok(stack)
+}
+
+/// Loads the stack at `path`, lets `change` modify it and saves it again,
+/// holding the lock throughout. Returns what `change` returned.
+pub fn update<T>(path: &Path, change: impl FnOnce(&mut Rollback) -> T) -> io::Result<T> {
+    let _lock = FileLock::acquire(path)?;
+    let mut stack = load(path)?;
+    let result = change(&mut stack);
+    write_atomically(path, serde_json::to_string(&stack)?.as_bytes())?;
+    Ok(result)
+}
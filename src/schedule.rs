@@ -0,0 +1,188 @@
+//! `switch-scheduled`: a day theme and a night theme, with the switch at
+//! fixed times of day or at sunrise and sunset.
+//!
+//! Sunrise and sunset come from the sunrise equation as NOAA approximates
+//! it, which is good to a minute or two away from the poles: plenty for
+//! picking a theme, and no data files or network needed.
+
+use crate::settings::Settings;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::io;
+
+/// When day begins and ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schedule {
+    /// The same times every day, in local time. Night may begin before day
+    /// does, e.g. at 1:00 for a day that starts at 9:00.
+    Fixed {
+        day_start: NaiveTime,
+        night_start: NaiveTime,
+    },
+    /// Sunrise and sunset at a place, in degrees north and east.
+    Sun { latitude: f64, longitude: f64 },
+}
+
+/// The sun on one day at one place, as computed by [`daylight`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Daylight {
+    Between {
+        sunrise: DateTime<Utc>,
+        sunset: DateTime<Utc>,
+    },
+    /// The sun does not set, as in a polar summer.
+    AlwaysUp,
+    /// The sun does not rise, as in a polar winter.
+    AlwaysDown,
+}
+
+impl Schedule {
+    /// The schedule the settings describe: sunrise and sunset when both
+    /// coordinates are set, otherwise `day_start` and `night_start`.
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        match (settings.latitude, settings.longitude) {
+            (Some(latitude), Some(longitude)) => Ok(Self::Sun {
+                latitude,
+                longitude,
+            }),
+            (None, None) => Ok(Self::Fixed {
+                day_start: settings.day_start,
+                night_start: settings.night_start,
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "set both latitude and longitude in settings.kdl, or neither",
+            )),
+        }
+    }
+
+    /// Whether it is day at `now`.
+    pub fn is_day(&self, now: DateTime<Local>) -> bool {
+        match *self {
+            Self::Fixed {
+                day_start,
+                night_start,
+            } => {
+                let time = now.time();
+                if day_start <= night_start {
+                    day_start <= time && time < night_start
+                } else {
+                    time >= day_start || time < night_start
+                }
+            }
+            Self::Sun {
+                latitude,
+                longitude,
+            } => match daylight(now.date_naive(), latitude, longitude) {
+                Daylight::Between { sunrise, sunset } => sunrise <= now && now < sunset,
+                Daylight::AlwaysUp => true,
+                Daylight::AlwaysDown => false,
+            },
+        }
+    }
+}
+
+/// Sunrise and sunset on `date` at `latitude` degrees north and `longitude`
+/// degrees east. `date` is the calendar day where the observer is; the
+/// times are in UTC.
+pub fn daylight(date: NaiveDate, latitude: f64, longitude: f64) -> Daylight {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).expect("valid date");
+    let days = (date - epoch).num_days() as f64;
+    // Mean solar noon, in days since noon UTC on 1 January 2000
+    let noon = days - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * noon).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * anomaly.sin()
+        + 0.0200 * (2.0 * anomaly).sin()
+        + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude =
+        (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+    let transit =
+        noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+    // The sun's center 0.833° below the horizon: its radius plus refraction
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if cos_hour_angle < -1.0 {
+        return Daylight::AlwaysUp;
+    }
+    if cos_hour_angle > 1.0 {
+        return Daylight::AlwaysDown;
+    }
+    let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+    Daylight::Between {
+        sunrise: from_j2000(transit - half_day),
+        sunset: from_j2000(transit + half_day),
+    }
+}
+
+/// The instant `days` after noon UTC on 1 January 2000.
+fn from_j2000(days: f64) -> DateTime<Utc> {
+    const J2000_UNIX: i64 = 946_728_000;
+    let seconds = J2000_UNIX + (days * 86_400.0).round() as i64;
+    Utc.timestamp_opt(seconds, 0).single().expect("in range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(date: NaiveDate, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date.and_hms_opt(hour, minute, 0).unwrap())
+    }
+
+    /// Whether `actual` is within two minutes of `expected`.
+    fn close(actual: DateTime<Utc>, expected: DateTime<Utc>) -> bool {
+        (actual - expected).num_seconds().abs() <= 120
+    }
+
+    #[test]
+    fn london_at_midsummer() {
+        // Published times: sunrise 04:43 and sunset 21:21 BST
+        let day = date(2024, 6, 21);
+        let Daylight::Between { sunrise, sunset } = daylight(day, 51.5074, -0.1278) else {
+            panic!("the sun rises and sets in London");
+        };
+        assert!(close(sunrise, utc(day, 3, 43)), "{}", sunrise);
+        assert!(close(sunset, utc(day, 20, 21)), "{}", sunset);
+    }
+
+    #[test]
+    fn sydney_in_midwinter() {
+        // Published times: sunrise 07:00 and sunset 16:54 AEST, UTC+10, so
+        // sunrise falls on the previous day in UTC
+        let day = date(2024, 6, 21);
+        let Daylight::Between { sunrise, sunset } = daylight(day, -33.8688, 151.2093) else {
+            panic!("the sun rises and sets in Sydney");
+        };
+        assert!(close(sunrise, utc(date(2024, 6, 20), 21, 0)), "{}", sunrise);
+        assert!(close(sunset, utc(day, 6, 54)), "{}", sunset);
+    }
+
+    #[test]
+    fn the_polar_summer_has_no_night_and_the_polar_winter_no_day() {
+        let (latitude, longitude) = (69.6492, 18.9553);
+        assert_eq!(daylight(date(2024, 6, 21), latitude, longitude), Daylight::AlwaysUp);
+        assert_eq!(daylight(date(2024, 12, 21), latitude, longitude), Daylight::AlwaysDown);
+        // The other way round at the same latitude south
+        assert_eq!(daylight(date(2024, 6, 21), -latitude, longitude), Daylight::AlwaysDown);
+        assert_eq!(daylight(date(2024, 12, 21), -latitude, longitude), Daylight::AlwaysUp);
+    }
+
+    #[test]
+    fn a_fixed_night_may_start_after_midnight() {
+        let at = |hour| Local.with_ymd_and_hms(2024, 6, 21, hour, 30, 0).unwrap();
+        let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        let schedule = Schedule::Fixed {
+            day_start: time(9),
+            night_start: time(1),
+        };
+        assert!(schedule.is_day(at(0)));
+        assert!(!schedule.is_day(at(1)));
+        assert!(!schedule.is_day(at(8)));
+        assert!(schedule.is_day(at(9)));
+    }
+}
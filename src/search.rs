@@ -0,0 +1,208 @@
+//! Finding themes by color: the `slot:color` words of the filter query,
+//! such as `bg:#1e1e2e` or `bg:near:purple`.
+//!
+//! The slot is `bg`, `fg` or `accent`, the last meaning whichever of the
+//! red through orange colors is closest. A hex color matches colors that
+//! look almost the same, within [`EXACT_TOLERANCE`] in OKLab. `near:` asks
+//! for the same kind of color instead: one of roughly the same hue, or for
+//! black, white and gray, a neutral one of roughly the same lightness.
+//! A color can also be any CSS color name known to [`color_names`], such
+//! as `cornflowerblue`, including aliases like `grey`.
+
+use crate::color::{delta_e, hue_distance, Oklch, Rgb};
+use crate::color_names;
+use crate::theme::{Theme, ThemeColors};
+
+/// How far, in [`delta_e`], a color may be from a hex query.
+pub const EXACT_TOLERANCE: f64 = 10.0;
+
+/// How far, in degrees, a hue may be from a `near:` query's.
+const HUE_TOLERANCE: f64 = 30.0;
+
+/// Below this OKLCH chroma a color counts as black, white or gray.
+const NEUTRAL_CHROMA: f64 = 0.03;
+
+/// How far, in OKLCH lightness, a neutral color may be from a neutral
+/// `near:` query's.
+const LIGHTNESS_TOLERANCE: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Bg,
+    Fg,
+    Accent,
+}
+
+/// One `slot:color` word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorQuery {
+    pub slot: Slot,
+    pub target: Rgb,
+    /// Written as `near:`, matching the kind of color rather than the color.
+    pub near: bool,
+}
+
+impl ColorQuery {
+    /// Parses `bg:#1e1e2e`, `fg:near:white` and the like. Anything else,
+    /// including an unknown color, is not a color query.
+    pub fn parse(word: &str) -> Option<Self> {
+        let (slot, color) = word.split_once(':')?;
+        let slot = match slot.to_lowercase().as_str() {
+            "bg" => Slot::Bg,
+            "fg" => Slot::Fg,
+            "accent" => Slot::Accent,
+            _ => return None,
+        };
+        let (near, color) = match color.strip_prefix("near:") {
+            Some(color) => (true, color),
+            None => (false, color),
+        };
+        let target = match color.strip_prefix('#') {
+            Some(_) => Rgb::from_hex(color)?,
+            None => color_names::lookup(color)?,
+        };
+        Some(Self { slot, target, near })
+    }
+
+    /// How far the closest color of `colors` in this slot is from the
+    /// target, or `None` when none is within the tolerance. Smaller is
+    /// closer; the scale is only meant for ranking.
+    pub fn distance(&self, colors: &ThemeColors) -> Option<f64> {
+        let candidates = match self.slot {
+            Slot::Bg => vec![colors.bg],
+            Slot::Fg => vec![colors.fg],
+            Slot::Accent => colors
+                .ansi()
+                .into_iter()
+                .filter(|(slot, _)| !matches!(*slot, "black" | "white"))
+                .map(|(_, color)| color)
+                .collect(),
+        };
+        candidates
+            .into_iter()
+            .flatten()
+            .filter_map(|color| self.distance_to(color))
+            .min_by(f64::total_cmp)
+    }
+
+    fn distance_to(&self, color: Rgb) -> Option<f64> {
+        if !self.near {
+            return Some(delta_e(color, self.target)).filter(|&d| d <= EXACT_TOLERANCE);
+        }
+        let (target, color) = (Oklch::from(self.target), Oklch::from(color));
+        if target.c < NEUTRAL_CHROMA {
+            let lightness = (color.l - target.l).abs();
+            (color.c < NEUTRAL_CHROMA * 2.0 && lightness <= LIGHTNESS_TOLERANCE)
+                .then_some(lightness * 100.0)
+        } else {
+            let hue = hue_distance(color.h, target.h);
+            (color.c >= NEUTRAL_CHROMA / 2.0 && hue <= HUE_TOLERANCE).then_some(hue / 3.0)
+        }
+    }
+}
+
+/// Splits the color words off a filter query, returning them and the
+/// rest, which is matched against theme names.
+pub fn split_query(query: &str) -> (Vec<ColorQuery>, String) {
+    if !query.contains(':') {
+        return (Vec::new(), query.to_string());
+    }
+    let mut colors = Vec::new();
+    let mut rest = Vec::new();
+    for word in query.split_whitespace() {
+        match ColorQuery::parse(word) {
+            Some(color) => colors.push(color),
+            None => rest.push(word),
+        }
+    }
+    (colors, rest.join(" "))
+}
+
+/// How far `theme` is from all of `queries` together, `0.0` without any,
+/// or `None` when it misses one of them or its colors are unknown.
+pub fn distance(queries: &[ColorQuery], theme: &Theme) -> Option<f64> {
+    if queries.is_empty() {
+        return Some(0.0);
+    }
+    let colors = theme.colors.as_ref()?;
+    queries.iter().map(|query| query.distance(colors)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags;
+
+    fn theme(bg: Rgb, red: Rgb) -> Theme {
+        let mut theme = Theme::new("test");
+        theme.colors = Some(ThemeColors {
+            bg: Some(bg),
+            fg: Some(Rgb::new(0xcd, 0xd6, 0xf4)),
+            red: Some(red),
+            ..ThemeColors::default()
+        });
+        theme
+    }
+
+    fn queries(query: &str) -> Vec<ColorQuery> {
+        split_query(query).0
+    }
+
+    #[test]
+    fn slot_color_words_parse_hex_and_near() {
+        let query = ColorQuery::parse("BG:#1e1e2e").unwrap();
+        assert_eq!(query.slot, Slot::Bg);
+        assert_eq!(query.target, Rgb::new(0x1e, 0x1e, 0x2e));
+        assert!(!query.near);
+        let query = ColorQuery::parse("accent:near:purple").unwrap();
+        assert_eq!(query.slot, Slot::Accent);
+        assert!(query.near);
+        assert_eq!(ColorQuery::parse("border:#1e1e2e"), None);
+        assert_eq!(ColorQuery::parse("bg:#12"), None);
+    }
+
+    #[test]
+    fn color_names_are_the_css_ones_aliases_included() {
+        let target = |word: &str| ColorQuery::parse(word).map(|query| query.target);
+        assert_eq!(target("bg:grey"), Some(Rgb::new(0x80, 0x80, 0x80)));
+        assert_eq!(target("fg:near:Aqua"), Some(Rgb::new(0x00, 0xff, 0xff)));
+        assert_eq!(target("accent:fuchsia"), Some(Rgb::new(0xff, 0x00, 0xff)));
+        assert_eq!(target("bg:cornflowerblue"), Some(Rgb::new(0x64, 0x95, 0xed)));
+        assert_eq!(target("bg:notacolor"), None);
+    }
+
+    #[test]
+    fn words_that_are_not_color_queries_are_left_for_the_name_filter() {
+        let (colors, rest) = split_query("bg:notacolor nord bg:#000000 dark");
+        assert_eq!(colors.len(), 1);
+        assert_eq!(rest, "bg:notacolor nord dark");
+        assert_eq!(split_query("tokyo night"), (Vec::new(), String::from("tokyo night")));
+    }
+
+    #[test]
+    fn tag_words_are_left_for_the_tag_filter() {
+        // The tag filter takes its words first, as the matcher does
+        let (tags, rest) = tags::split_query("#work bg:#1e1e2e nord");
+        assert_eq!(tags, ["work"]);
+        let (colors, rest) = split_query(&rest);
+        assert_eq!(colors.len(), 1);
+        assert_eq!(rest, "nord");
+        // And a `#tag` is never taken for a hex color
+        let (colors, rest) = split_query("#1e1e2e bg:black");
+        assert_eq!(colors.len(), 1);
+        assert_eq!(rest, "#1e1e2e");
+    }
+
+    #[test]
+    fn themes_match_when_every_query_finds_its_color() {
+        let mocha = theme(Rgb::new(0x1e, 0x1e, 0x2e), Rgb::new(0xf3, 0x8b, 0xa8));
+
+        assert_eq!(distance(&queries("bg:#1e1e2e"), &mocha), Some(0.0));
+        assert!(distance(&queries("bg:#1f1f2f"), &mocha).is_some());
+        assert_eq!(distance(&queries("bg:white"), &mocha), None);
+        assert!(distance(&queries("bg:near:navy accent:near:pink"), &mocha).is_some());
+        assert_eq!(distance(&queries("bg:#1e1e2e accent:near:green"), &mocha), None);
+        assert_eq!(distance(&[], &Theme::new("unknown")), Some(0.0));
+        assert_eq!(distance(&queries("bg:black"), &Theme::new("unknown")), None);
+    }
+}
@@ -0,0 +1,2999 @@
+//! The selector: the full-screen theme list `zellij-theme-selector` opens
+//! by default, with its previews, filter and key bindings.
+
+use crate::{
+    cache, clipboard, color, color_names, editor, live, matcher, mock, reveal, search, state, tags,
+    theme,
+};
+use crate::color::ContrastLevel;
+use crate::data::{
+    FetchEvent, FetchFailure, FetchOutcome, Install, LayoutUpdate, ListChanges, RateLimit,
+    ThemeData,
+};
+use crate::history::HistoryEntry;
+use crate::hook::HookFailure;
+use crate::keymap::{Action, Keymap};
+use crate::live::LiveSwitcher;
+use crate::matcher::fuzzy_match;
+use crate::settings::{AppearanceFilter, Settings};
+use crate::state::UiState;
+use crate::tags::Tags;
+use crate::theme::{ContrastPair, Theme};
+use crossterm::{
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers,
+    },
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    prelude::*,
+    widgets::{
+        block::{Position, Title},
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, Wrap,
+    },
+};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+/// Startup options for the selector, usually taken from the command line.
+#[derive(Default)]
+pub struct Options {
+    pub force_refresh: bool,
+    /// Replace installed theme files even if they were edited locally.
+    pub force: bool,
+    /// When the process started. If set, the time until the first frame
+    /// was drawn is printed to stderr on exit.
+    pub started: Option<Instant>,
+}
+
+/// The startup hint, naming whichever keys are bound to the main actions.
+fn help_message(keymap: &Keymap) -> String {
+    let hints = [
+        (Action::Apply, "apply theme"),
+        (Action::Search, "filter"),
+        (Action::ToggleContrastFilter, "hide low contrast"),
+        (Action::Quit, "quit"),
+        (Action::Help, "list all keys"),
+    ];
+    let hints: Vec<String> = hints
+        .iter()
+        .filter_map(|(action, label)| {
+            let keys = keymap.keys_for(*action);
+            keys.first().map(|key| format!("{} to {}", key, label))
+        })
+        .collect();
+    format!("Press {}", hints.join(", "))
+}
+
+/// How many past filter queries are remembered for recall.
+const SEARCH_HISTORY_LEN: usize = 20;
+
+/// One line of the theme list.
+#[derive(Clone, PartialEq, Eq)]
+enum ListRow {
+    /// A family of related themes, e.g. every `catppuccin-*` theme.
+    Header { family: String, members: usize },
+    /// Index into `themes`. `grouped` themes sit under a header.
+    Theme { index: usize, grouped: bool },
+}
+
+/// The family a theme belongs to: its name up to the first `-`.
+fn family(name: &str) -> &str {
+    name.split_once('-').map_or(name, |(family, _)| family)
+}
+
+/// What the left-hand pane lists.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Themes,
+    /// Recently applied themes, newest first.
+    History,
+    /// Two themes side by side; see `App::comparison`.
+    Compare,
+    /// The active key bindings, over the theme list.
+    Help,
+    /// Why theme files failed to load in the last fetch, over the list.
+    Failures,
+}
+
+/// What keystrokes currently drive.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    /// Typing into the `/` filter prompt.
+    Filter,
+    /// Asking whether to overwrite a locally edited theme file.
+    ConfirmOverwrite,
+    /// Asking whether to install a remote theme so it can be edited.
+    ConfirmInstall,
+    /// Asking what to do about a theme zellij would not find; see
+    /// `ThemeData::resolves`.
+    ConfirmUnresolved,
+    /// Asking whether to delete a theme file; see `App::pending_delete`.
+    ConfirmDelete,
+    /// Asking whether to reset the config after its theme was deleted.
+    ConfirmReset,
+    /// Editing the tags of a theme; see `App::tagging`.
+    Tag,
+    /// A theme is applied on trial; see `App::preview`.
+    Preview,
+    /// Changing colors in the theme editor; see `App::color_editor`.
+    EditColors,
+}
+
+/// How long a previewed theme stays before it is reverted on its own.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A theme applied on trial, and the setting to go back to.
+struct Preview {
+    theme: String,
+    /// The theme `config.kdl` selected before; `None` if it selected none.
+    previous: Option<String>,
+    /// Whether running sessions show the preview, for the prompt.
+    live: String,
+    started: Instant,
+}
+
+/// How long the status banner shows an applied theme's colors.
+const SPLASH_DURATION: Duration = Duration::from_secs(1);
+
+/// The status banner drawn in the colors of the theme just applied, as a
+/// sign that it took.
+struct Splash {
+    colors: mock::MockColors,
+    started: Instant,
+}
+
+/// A theme's colors in the editor form, one hex field per role in the
+/// order `ThemeColors::to_kdl` writes them.
+struct ColorEditor {
+    /// The local theme saved back in place, or `None` for a new theme.
+    editing: Option<String>,
+    /// The theme the colors were taken from.
+    from: String,
+    fields: Vec<(&'static str, String)>,
+    /// The fields as opened, to tell whether anything changed.
+    original: Vec<String>,
+    selected: usize,
+    /// The name typed at the save prompt while it is open.
+    name: Option<String>,
+    /// Whether Esc is waiting for an answer about discarding changes.
+    confirm_discard: bool,
+}
+
+impl ColorEditor {
+    fn new(theme: &Theme, editing: bool) -> Self {
+        let colors = theme.colors.clone().unwrap_or_default();
+        let fields: Vec<(&'static str, String)> = [("fg", colors.fg), ("bg", colors.bg)]
+            .into_iter()
+            .chain(colors.ansi())
+            .map(|(role, rgb)| (role, rgb.map(color::Rgb::to_hex).unwrap_or_default()))
+            .collect();
+        Self {
+            editing: editing.then(|| theme.name.clone()),
+            from: theme.name.clone(),
+            original: fields.iter().map(|(_, value)| value.clone()).collect(),
+            fields,
+            selected: 0,
+            name: None,
+            confirm_discard: false,
+        }
+    }
+
+    /// Whether field `index` holds a color, or is empty for a role the
+    /// theme may leave out; only `fg` and `bg` are required.
+    fn is_valid(&self, index: usize) -> bool {
+        let (role, value) = &self.fields[index];
+        match value.trim() {
+            "" => !matches!(*role, "fg" | "bg"),
+            value => color::Rgb::from_hex(value).is_some(),
+        }
+    }
+
+    fn invalid_roles(&self) -> Vec<&'static str> {
+        (0..self.fields.len())
+            .filter(|&index| !self.is_valid(index))
+            .map(|index| self.fields[index].0)
+            .collect()
+    }
+
+    fn is_modified(&self) -> bool {
+        self.fields.iter().map(|(_, value)| value).ne(self.original.iter())
+    }
+
+    /// The colors of the valid fields; the others are left unset.
+    fn colors(&self) -> theme::ThemeColors {
+        let mut colors = theme::ThemeColors::default();
+        for (role, value) in &self.fields {
+            let rgb = color::Rgb::from_hex(value);
+            match *role {
+                "fg" => colors.fg = rgb,
+                "bg" => colors.bg = rgb,
+                "black" => colors.black = rgb,
+                "red" => colors.red = rgb,
+                "green" => colors.green = rgb,
+                "yellow" => colors.yellow = rgb,
+                "blue" => colors.blue = rgb,
+                "magenta" => colors.magenta = rgb,
+                "cyan" => colors.cyan = rgb,
+                "white" => colors.white = rgb,
+                "orange" => colors.orange = rgb,
+                _ => {}
+            }
+        }
+        colors
+    }
+
+    /// The theme as it stands, for the details pane.
+    fn preview(&self) -> Theme {
+        let name = self.editing.clone().unwrap_or_else(|| format!("new theme from {}", self.from));
+        let mut theme = Theme::with_colors(name, Some(self.colors()));
+        theme.origin = theme::Origin::Local;
+        theme
+    }
+
+    fn move_by(&mut self, step: isize) {
+        let len = self.fields.len() as isize;
+        self.selected = (self.selected as isize + step).rem_euclid(len) as usize;
+    }
+
+    /// Types `c` into the selected field if it can be part of a hex color.
+    fn type_char(&mut self, c: char) {
+        let value = &mut self.fields[self.selected].1;
+        if (c.is_ascii_hexdigit() || (c == '#' && value.is_empty())) && value.len() < 7 {
+            value.push(c.to_ascii_lowercase());
+        }
+    }
+}
+
+struct App {
+    themes: Vec<Theme>,
+    /// Indices into `themes` that pass the active filters, in display order.
+    visible: Vec<usize>,
+    /// What the list shows: `visible` with family headers mixed in.
+    rows: Vec<ListRow>,
+    /// Selection index into `rows`.
+    state: ListState,
+    view: View,
+    /// Applied themes, newest first.
+    history: Vec<HistoryEntry>,
+    history_state: ListState,
+    status_message: String,
+    min_contrast: f64,
+    hide_low_contrast: bool,
+    appearance_filter: AppearanceFilter,
+    /// Whether themes are grouped under family headers or listed flat.
+    group_families: bool,
+    /// Families whose headers have been opened.
+    expanded: HashSet<String>,
+    mode: Mode,
+    /// The filter query, applied by [`matcher::rank`]: `#tag` words keep
+    /// themes with those tags, `slot:color` words such as `bg:#1e1e2e`
+    /// match colors, and the rest fuzzy-matches names, smart-case.
+    query: String,
+    /// Recent filter queries, most recent first and without duplicates.
+    search_history: Vec<String>,
+    /// Position in `search_history` while recalling with Up/Down.
+    history_index: Option<usize>,
+    /// The query being typed before history recall replaced it.
+    draft_query: String,
+    /// Saved whenever a filter toggle changes so it sticks across runs.
+    settings: Settings,
+    /// Overwrite edited theme files without asking (`--force`).
+    force: bool,
+    /// The theme waiting on an answer in `Mode::ConfirmOverwrite` or
+    /// `Mode::ConfirmUnresolved`.
+    pending_apply: Option<String>,
+    /// The theme waiting on an answer in `Mode::ConfirmInstall`.
+    pending_edit: Option<String>,
+    /// The file waiting on an answer in `Mode::ConfirmDelete`.
+    pending_delete: Option<PathBuf>,
+    /// Remote files processed and in total while themes are still being
+    /// fetched; the total is 0 until the file list has arrived.
+    progress: Option<(usize, usize)>,
+    /// Tells whether an applied theme reached the running sessions.
+    live: LiveSwitcher,
+    /// The theme being tried in `Mode::Preview`.
+    preview: Option<Preview>,
+    /// Set for a moment after a theme was applied.
+    splash: Option<Splash>,
+    /// Hooks still running for the themes applied.
+    hooks: Hooks,
+    /// The theme open in `Mode::EditColors`.
+    color_editor: Option<ColorEditor>,
+    /// Up to two theme names marked with `m`, oldest first.
+    marked: Vec<String>,
+    /// Themes the last fetch found that the cache before it did not have.
+    new_themes: HashSet<String>,
+    /// The theme files the last fetch could not load, shown with `L`.
+    failures: Vec<FetchFailure>,
+    /// The user's tags, by theme name.
+    tags: Tags,
+    /// The theme whose tags are edited in `Mode::Tag`, and the text typed.
+    tagging: Option<String>,
+    tag_input: String,
+    /// The themes shown by `View::Compare`.
+    comparison: Option<(Theme, Theme)>,
+    /// The row highlighted when the last run ended, selected once it is
+    /// listed. Dropped when a key is pressed first, or when the fetch
+    /// finishes without it.
+    restore_selection: Option<String>,
+    /// From `Options::started`, and how long after it the first frame was
+    /// drawn.
+    started: Option<Instant>,
+    first_frame: Option<Duration>,
+}
+
+impl App {
+    fn new(themes: Vec<Theme>, settings: &Settings) -> App {
+        let mut app = App {
+            themes,
+            visible: Vec::new(),
+            rows: Vec::new(),
+            state: ListState::default(),
+            view: View::Themes,
+            history: Vec::new(),
+            history_state: ListState::default(),
+            status_message: help_message(&settings.keymap),
+            min_contrast: settings.min_contrast,
+            hide_low_contrast: settings.hide_low_contrast,
+            appearance_filter: settings.appearance_filter,
+            group_families: settings.group_families,
+            expanded: HashSet::new(),
+            mode: Mode::Normal,
+            query: String::new(),
+            search_history: Vec::new(),
+            history_index: None,
+            draft_query: String::new(),
+            settings: settings.clone(),
+            force: false,
+            pending_apply: None,
+            pending_edit: None,
+            pending_delete: None,
+            progress: None,
+            live: LiveSwitcher::default(),
+            preview: None,
+            splash: None,
+            hooks: Hooks::new(),
+            color_editor: None,
+            marked: Vec::new(),
+            new_themes: HashSet::new(),
+            failures: Vec::new(),
+            tags: Tags::default(),
+            tagging: None,
+            tag_input: String::new(),
+            comparison: None,
+            restore_selection: None,
+            started: None,
+            first_frame: None,
+        };
+        app.apply_filters();
+        app
+    }
+
+    fn selected_row(&self) -> Option<&ListRow> {
+        self.state.selected().and_then(|i| self.rows.get(i))
+    }
+
+    fn selected_theme(&self) -> Option<&Theme> {
+        match self.selected_row() {
+            Some(ListRow::Theme { index, .. }) => Some(&self.themes[*index]),
+            _ => None,
+        }
+    }
+
+    fn is_visible(&self, theme: &Theme) -> bool {
+        // Themes without a parsed palette have nothing to judge, so they stay.
+        let readable = !self.hide_low_contrast
+            || theme
+                .fg_bg_contrast()
+                .is_none_or(|ratio| ratio >= self.min_contrast);
+        readable && self.appearance_filter.allows(theme.appearance)
+    }
+
+    /// Rebuilds `visible` and the list rows, keeping the highlighted theme
+    /// selected if it survives the filters.
+    fn apply_filters(&mut self) {
+        let selected = self.selected_name();
+        self.refilter(selected);
+    }
+
+    /// Swaps in a new theme list, keeping the highlighted theme selected by
+    /// name since its index may have moved.
+    fn replace_themes(&mut self, themes: Vec<Theme>) {
+        let mut selected = self.selected_name();
+        self.themes = themes;
+        let listed = |name: &String| {
+            self.themes
+                .iter()
+                .any(|theme| theme.name == *name || family(&theme.name) == name)
+        };
+        if let Some(name) = self.restore_selection.take_if(|name| listed(name)) {
+            selected = Some(name);
+        }
+        self.refilter(selected);
+    }
+
+    /// Picks up where the last run ended.
+    fn restore(&mut self, state: UiState) {
+        self.search_history = state.search_history;
+        self.search_history.truncate(SEARCH_HISTORY_LEN);
+        self.expanded = state.expanded.into_iter().collect();
+        self.restore_selection = state.selected;
+        self.rebuild_rows();
+    }
+
+    /// What the next run picks up.
+    fn ui_state(&self) -> UiState {
+        let mut expanded: Vec<String> = self.expanded.iter().cloned().collect();
+        expanded.sort();
+        UiState {
+            selected: self.selected_name(),
+            search_history: self.search_history.clone(),
+            expanded,
+        }
+    }
+
+    /// Merges themes that arrived while fetching into the sorted list.
+    fn add_themes(&mut self, themes: Vec<Theme>) {
+        let mut all = self.themes.clone();
+        all.extend(themes);
+        self.replace_themes(theme::merge_themes(all));
+    }
+
+    /// Swaps in the themes read again from `file` after it was edited or
+    /// fetched again. Themes the file no longer defines are dropped, and
+    /// those it shadows stay noted on the new versions.
+    fn replace_file_themes(&mut self, origin: theme::Origin, file: &str, themes: Vec<Theme>) {
+        let (old, mut all): (Vec<Theme>, Vec<Theme>) =
+            self.themes.iter().cloned().partition(|theme| {
+                theme.origin == origin && theme.file.as_deref() == Some(file)
+            });
+        for mut theme in themes {
+            if let Some(previous) = old.iter().find(|previous| previous.name == theme.name) {
+                theme.also_defined_in = previous.also_defined_in.clone();
+            }
+            all.push(theme);
+        }
+        self.replace_themes(theme::merge_themes(all));
+    }
+
+    /// Called when the fetch has finished. The final list replaces the one
+    /// built up from streamed batches, and failures are reported. A failed
+    /// `refresh` leaves the list as it was.
+    fn finish_loading(&mut self, result: io::Result<FetchOutcome>, refresh: bool) {
+        self.progress = None;
+        let notice = match result {
+            Ok(outcome) => {
+                self.replace_themes(outcome.themes);
+                let mut notices = Vec::new();
+                if refresh {
+                    notices.push(format!("Refreshed the theme list ({} themes)", self.themes.len()));
+                }
+                if let Some(changes) = outcome.changes.filter(|changes| !changes.is_empty()) {
+                    notices.push(changes_notice(&changes));
+                    self.new_themes = changes.added.into_iter().collect();
+                }
+                if !outcome.failures.is_empty() {
+                    let kept = if outcome.kept_cache {
+                        ", so the cached list was kept"
+                    } else {
+                        ""
+                    };
+                    let keys = self.settings.keymap.keys_for(Action::ShowFailures);
+                    let details = match keys.first() {
+                        Some(key) => format!(" (press {} for details)", key),
+                        None => String::new(),
+                    };
+                    notices.push(format!(
+                        "{} theme files failed to load{}{}",
+                        outcome.failures.len(),
+                        kept,
+                        details
+                    ));
+                }
+                // Themes in files that failed to load are not gone
+                let themes = &self.themes;
+                let orphaned = self.tags.orphaned(|name| themes.iter().any(|t| t.name == name));
+                if !orphaned.is_empty() && outcome.failures.is_empty() {
+                    notices.push(format!(
+                        "Kept the tags of {} missing themes: {} (tags --prune drops them)",
+                        orphaned.len(),
+                        orphaned.join(", ")
+                    ));
+                }
+                self.failures = outcome.failures;
+                if notices.is_empty() {
+                    return;
+                }
+                notices.join(" | ")
+            }
+            Err(e) if refresh => format!("Refresh failed, keeping the current list: {}", e),
+            Err(e) => format!("Error fetching themes: {}", e),
+        };
+        if refresh || self.status_message == help_message(&self.settings.keymap) {
+            self.status_message = notice;
+        } else {
+            self.status_message = format!("{} | {}", self.status_message, notice);
+        }
+    }
+
+    /// The listed theme called `name`, or a bare reference to it if it is
+    /// no longer listed.
+    fn theme_named(&self, name: &str) -> Theme {
+        self.themes
+            .iter()
+            .find(|theme| theme.name == name)
+            .cloned()
+            .unwrap_or_else(|| Theme::new(name))
+    }
+
+    /// The name of the highlighted theme or family.
+    fn selected_name(&self) -> Option<String> {
+        self.selected_row().map(|row| match row {
+            ListRow::Header { family, .. } => family.clone(),
+            ListRow::Theme { index, .. } => self.themes[*index].name.clone(),
+        })
+    }
+
+    /// Recomputes `visible`. While a query is typed only matching themes
+    /// are kept, best match first; ties keep the alphabetical order. Its
+    /// `#tag` words keep only themes with those tags, and its color words
+    /// only themes with such a color, closest first.
+    fn refilter(&mut self, selected: Option<String>) {
+        let visible = self.themes.iter().enumerate().filter(|(_, theme)| self.is_visible(theme));
+        let ranked = matcher::rank(&self.query, visible, &self.tags);
+        self.visible = ranked.into_iter().map(|ranked| ranked.index).collect();
+        self.layout_rows(selected);
+    }
+
+    /// Highlights the best match for the query just typed.
+    fn select_best_match(&mut self) {
+        let best = self
+            .rows
+            .iter()
+            .position(|row| matches!(row, ListRow::Theme { .. }));
+        if best.is_some() {
+            self.state.select(best);
+        }
+    }
+
+    fn rebuild_rows(&mut self) {
+        let selected = self.selected_name();
+        self.layout_rows(selected);
+    }
+
+    /// Lays `visible` out as rows, selecting `selected` again if it is still
+    /// listed. Families with more than one theme get a header, and their
+    /// themes are only listed once it is expanded or a query is narrowing
+    /// the list.
+    fn layout_rows(&mut self, selected: Option<String>) {
+        self.rows.clear();
+        if self.group_families {
+            let mut families: Vec<(&str, Vec<usize>)> = Vec::new();
+            for &i in &self.visible {
+                let name = family(&self.themes[i].name);
+                match families.iter_mut().find(|(family, _)| *family == name) {
+                    Some((_, members)) => members.push(i),
+                    None => families.push((name, vec![i])),
+                }
+            }
+            for (name, members) in families {
+                if members.len() == 1 {
+                    self.rows.push(ListRow::Theme { index: members[0], grouped: false });
+                    continue;
+                }
+                self.rows.push(ListRow::Header {
+                    family: name.to_string(),
+                    members: members.len(),
+                });
+                if self.expanded.contains(name) || !self.query.is_empty() {
+                    self.rows.extend(
+                        members
+                            .into_iter()
+                            .map(|index| ListRow::Theme { index, grouped: true }),
+                    );
+                }
+            }
+        } else {
+            self.rows.extend(
+                self.visible
+                    .iter()
+                    .map(|&index| ListRow::Theme { index, grouped: false }),
+            );
+        }
+
+        // Fall back to the family header when the theme was folded away
+        let position = |name: &str| {
+            self.rows.iter().position(|row| match row {
+                ListRow::Header { family, .. } => family == name,
+                ListRow::Theme { index, .. } => self.themes[*index].name == name,
+            })
+        };
+        let index = selected.and_then(|name| position(&name).or_else(|| position(family(&name))));
+        self.state.select(match index {
+            Some(i) => Some(i),
+            None if self.rows.is_empty() => None,
+            None => Some(0),
+        });
+    }
+
+    /// Opens or closes the family under the cursor. On a theme inside a
+    /// family this closes the family and moves to its header.
+    fn toggle_family(&mut self) {
+        let name = match self.selected_row() {
+            Some(ListRow::Header { family, .. }) => family.clone(),
+            Some(ListRow::Theme { index, grouped: true }) => family(&self.themes[*index].name).to_string(),
+            _ => return,
+        };
+        if !self.query.is_empty() {
+            self.status_message = String::from("Families stay expanded while filtering");
+            return;
+        }
+        if !self.expanded.remove(&name) {
+            self.expanded.insert(name.clone());
+        }
+        let header = self.rows.iter().position(|row| {
+            matches!(row, ListRow::Header { family, .. } if *family == name)
+        });
+        self.state.select(header);
+        self.rebuild_rows();
+    }
+
+    fn toggle_grouping(&mut self) {
+        self.group_families = !self.group_families;
+        self.rebuild_rows();
+        self.status_message = if self.group_families {
+            String::from("Grouping themes by family")
+        } else {
+            String::from("Showing a flat list")
+        };
+        self.save_settings();
+    }
+
+    fn toggle_contrast_filter(&mut self) {
+        self.hide_low_contrast = !self.hide_low_contrast;
+        self.apply_filters();
+        self.status_message = if self.hide_low_contrast {
+            format!(
+                "Hiding themes with fg/bg contrast below {:.1}:1 ({} shown)",
+                self.min_contrast,
+                self.visible.len()
+            )
+        } else {
+            String::from("Showing all themes")
+        };
+        self.save_settings();
+    }
+
+    fn cycle_appearance_filter(&mut self) {
+        self.appearance_filter = self.appearance_filter.cycle();
+        self.apply_filters();
+        self.status_message = match self.appearance_filter {
+            AppearanceFilter::All => String::from("Showing dark and light themes"),
+            AppearanceFilter::Dark => format!(
+                "Showing dark themes and unclassified ones ({} shown)",
+                self.visible.len()
+            ),
+            AppearanceFilter::Light => format!(
+                "Showing light themes and unclassified ones ({} shown)",
+                self.visible.len()
+            ),
+        };
+        self.save_settings();
+    }
+
+    /// Persists the current toggles, noting a failure in the status bar
+    /// rather than interrupting the session.
+    fn save_settings(&mut self) {
+        self.settings.hide_low_contrast = self.hide_low_contrast;
+        self.settings.appearance_filter = self.appearance_filter;
+        self.settings.group_families = self.group_families;
+        if let Err(e) = self.settings.save() {
+            self.status_message = format!("{} (settings not saved: {})", self.status_message, e);
+        }
+    }
+
+    fn start_filter(&mut self) {
+        self.mode = Mode::Filter;
+        self.history_index = None;
+        self.draft_query = self.query.clone();
+    }
+
+    fn edit_query(&mut self, edit: impl FnOnce(&mut String)) {
+        edit(&mut self.query);
+        self.history_index = None;
+        self.draft_query = self.query.clone();
+        self.apply_filters();
+        self.select_best_match();
+    }
+
+    /// Leaves the prompt, remembering the query, and clears the filter.
+    /// Returns the highlighted theme, which the caller applies.
+    fn confirm_filter(&mut self) -> Option<String> {
+        let chosen = self.selected_theme().map(|theme| theme.name.clone());
+        if !self.query.is_empty() {
+            self.search_history.retain(|q| *q != self.query);
+            self.search_history.insert(0, self.query.clone());
+            self.search_history.truncate(SEARCH_HISTORY_LEN);
+        }
+        self.cancel_filter();
+        chosen
+    }
+
+    fn cancel_filter(&mut self) {
+        self.mode = Mode::Normal;
+        self.history_index = None;
+        self.query.clear();
+        self.apply_filters();
+    }
+
+    /// Steps through `search_history` with Up (`older`) and Down, returning
+    /// to the draft query past the newest entry.
+    fn recall_query(&mut self, older: bool) {
+        let index = match (self.history_index, older) {
+            (None, true) if !self.search_history.is_empty() => Some(0),
+            (None, _) => return,
+            (Some(i), true) => Some((i + 1).min(self.search_history.len() - 1)),
+            (Some(0), false) => None,
+            (Some(i), false) => Some(i - 1),
+        };
+        self.history_index = index;
+        self.query = match index {
+            Some(i) => self.search_history[i].clone(),
+            None => self.draft_query.clone(),
+        };
+        self.apply_filters();
+        self.select_best_match();
+    }
+
+    fn copy_selected_name(&mut self) {
+        let Some(name) = self.selected_theme().map(|theme| theme.name.clone()) else {
+            return;
+        };
+        self.status_message = match clipboard::copy(&name) {
+            Ok(clipboard::Mechanism::Osc52) => format!(
+                "Sent '{}' to the clipboard via OSC 52 (needs terminal support)",
+                name
+            ),
+            Ok(mechanism) => format!("Copied '{}' to the clipboard via {}", name, mechanism.label()),
+            Err(e) => format!("No clipboard available: {}", e),
+        };
+    }
+
+    /// Copies the selected theme's `themes { name { ... } }` block, or the
+    /// `theme "name"` config line when its colors are not known.
+    fn copy_selected_definition(&mut self) {
+        let Some(theme) = self.selected_theme() else {
+            return;
+        };
+        let (text, what) = match &theme.colors {
+            Some(colors) => (
+                colors.to_kdl(&theme.name),
+                format!("Copied the definition of '{}'", theme.name),
+            ),
+            None => (
+                format!("theme \"{}\"\n", theme.name),
+                format!(
+                    "No colors are known for '{}'; copied its config line instead",
+                    theme.name
+                ),
+            ),
+        };
+        self.status_message = match clipboard::copy(&text) {
+            Ok(clipboard::Mechanism::Osc52) => format!(
+                "{} ({} bytes) via OSC 52 (needs terminal support)",
+                what,
+                text.len()
+            ),
+            Ok(mechanism) => format!("{} ({} bytes) via {}", what, text.len(), mechanism.label()),
+            Err(e) => format!("No clipboard available: {}", e),
+        };
+    }
+
+    /// Copies the command that restarts every session, for Zellij versions
+    /// that only read the config at startup.
+    fn copy_restart_command(&mut self) {
+        self.status_message = match clipboard::copy(live::RESTART_COMMAND) {
+            Ok(clipboard::Mechanism::Osc52) => format!(
+                "Sent '{}' to the clipboard via OSC 52 (needs terminal support)",
+                live::RESTART_COMMAND
+            ),
+            Ok(mechanism) => format!(
+                "Copied '{}' to the clipboard via {}",
+                live::RESTART_COMMAND,
+                mechanism.label()
+            ),
+            Err(e) => format!("No clipboard available: {}", e),
+        };
+    }
+
+    /// Replaces the history shown by the `h` view; `entries` are oldest
+    /// first, as stored.
+    fn set_history(&mut self, mut entries: Vec<HistoryEntry>) {
+        entries.reverse();
+        self.history = entries;
+        self.history_state
+            .select(if self.history.is_empty() { None } else { Some(0) });
+    }
+
+    fn toggle_history_view(&mut self) {
+        self.view = match self.view {
+            View::History => View::Themes,
+            View::Themes | View::Compare | View::Help | View::Failures => View::History,
+        };
+        if self.view == View::History && self.history.is_empty() {
+            self.status_message = String::from("No themes have been applied yet");
+        }
+    }
+
+    /// Opens the list of files the last fetch failed to load, if any did.
+    fn show_failures(&mut self) {
+        if self.failures.is_empty() {
+            self.status_message = String::from("No theme files failed to load");
+        } else {
+            self.view = View::Failures;
+        }
+    }
+
+    fn selected_history_entry(&self) -> Option<&HistoryEntry> {
+        self.history_state
+            .selected()
+            .and_then(|i| self.history.get(i))
+    }
+
+    /// The theme whose details are shown: the highlighted theme, or the
+    /// theme of the highlighted history entry.
+    fn detail_theme(&self) -> Option<&Theme> {
+        match self.view {
+            View::Themes | View::Help | View::Failures => self.selected_theme(),
+            View::History => {
+                let entry = self.selected_history_entry()?;
+                self.themes.iter().find(|theme| theme.name == entry.theme)
+            }
+            View::Compare => None,
+        }
+    }
+
+    /// Marks the highlighted theme for comparison, or unmarks it. Marking a
+    /// third theme drops the one marked first.
+    fn toggle_mark(&mut self) {
+        let Some(name) = self.selected_theme().map(|theme| theme.name.clone()) else {
+            return;
+        };
+        if let Some(i) = self.marked.iter().position(|marked| *marked == name) {
+            self.marked.remove(i);
+            self.status_message = format!("Unmarked {}", name);
+            return;
+        }
+        if self.marked.len() == 2 {
+            self.marked.remove(0);
+        }
+        self.marked.push(name);
+        self.status_message = match self.marked.as_slice() {
+            [first, second] => format!("Marked {} and {} for comparison", first, second),
+            _ => format!(
+                "Marked {}; highlight another theme to compare it with",
+                self.marked[0]
+            ),
+        };
+    }
+
+    /// Opens the tag prompt for the highlighted theme, filled with the tags
+    /// it has so they can be edited.
+    fn start_tagging(&mut self) {
+        let Some(name) = self.selected_theme().map(|theme| theme.name.clone()) else {
+            return;
+        };
+        self.tag_input = self.tags.of(&name).collect::<Vec<_>>().join(" ");
+        if !self.tag_input.is_empty() {
+            self.tag_input.push(' ');
+        }
+        self.tagging = Some(name);
+        self.mode = Mode::Tag;
+    }
+
+    fn cancel_tagging(&mut self) {
+        self.mode = Mode::Normal;
+        self.tagging = None;
+        self.tag_input.clear();
+    }
+
+    /// Opens the comparison of the two marked themes, or of the one marked
+    /// theme and the highlighted one. With the marked theme itself
+    /// highlighted, it is compared with `current`, the theme the config
+    /// selects now.
+    fn open_comparison(&mut self, current: Option<String>) {
+        let highlighted = self.selected_theme().map(|theme| theme.name.clone());
+        let pair = match self.marked.as_slice() {
+            [first, second] => (self.theme_named(first), self.theme_named(second)),
+            [only] => match highlighted.filter(|name| name != only).or(current) {
+                Some(other) => (self.theme_named(only), self.theme_named(&other)),
+                None => {
+                    self.status_message = String::from(
+                        "No theme is set in config.kdl; highlight another theme to compare",
+                    );
+                    return;
+                }
+            },
+            _ => {
+                self.status_message = String::from("Mark one or two themes to compare first");
+                return;
+            }
+        };
+        self.comparison = Some(pair);
+        self.view = View::Compare;
+    }
+
+    /// The list the cursor keys move through in the current view.
+    fn cursor(&mut self) -> (&mut ListState, usize) {
+        match self.view {
+            View::Themes => (&mut self.state, self.rows.len()),
+            View::History => (&mut self.history_state, self.history.len()),
+            View::Compare | View::Help | View::Failures => (&mut self.state, 0),
+        }
+    }
+
+    /// Moves to the next row after the cursor whose name starts with
+    /// `letter`, ignoring case and wrapping around, so pressing the letter
+    /// again cycles through them. A folded family counts by its name.
+    fn jump_to_letter(&mut self, letter: char) {
+        let len = self.rows.len();
+        let current = self.state.selected().unwrap_or(len.saturating_sub(1));
+        let starts_with = |row: &ListRow| {
+            let name = match row {
+                ListRow::Header { family, .. } => family.as_str(),
+                ListRow::Theme { index, .. } => self.themes[*index].name.as_str(),
+            };
+            name.chars()
+                .next()
+                .is_some_and(|first| first.to_lowercase().eq(letter.to_lowercase()))
+        };
+        match (1..=len)
+            .map(|step| (current + step) % len)
+            .find(|&i| starts_with(&self.rows[i]))
+        {
+            Some(i) => self.state.select(Some(i)),
+            None => self.status_message = format!("No theme starts with '{}'", letter),
+        }
+    }
+
+    fn next(&mut self) {
+        let (state, len) = self.cursor();
+        if len == 0 {
+            return;
+        }
+        let i = match state.selected() {
+            Some(i) => {
+                if i >= len - 1 {
+                    0
+                } else {
+                    i + 1
+                }
+            }
+            None => 0,
+        };
+        state.select(Some(i));
+    }
+
+    fn first(&mut self) {
+        let (state, len) = self.cursor();
+        if len > 0 {
+            state.select(Some(0));
+        }
+    }
+
+    fn last(&mut self) {
+        let (state, len) = self.cursor();
+        if len > 0 {
+            state.select(Some(len - 1));
+        }
+    }
+
+    fn previous(&mut self) {
+        let (state, len) = self.cursor();
+        if len == 0 {
+            return;
+        }
+        let i = match state.selected() {
+            Some(i) => {
+                if i == 0 {
+                    len - 1
+                } else {
+                    i - 1
+                }
+            }
+            None => 0,
+        };
+        state.select(Some(i));
+    }
+}
+
+/// Makes the theme called `name` active and refreshes the history view.
+/// If that would overwrite a theme file with local edits, or name a theme
+/// zellij would not find, the user is asked first unless `force` or
+/// `--force` says not to.
+async fn apply_theme(app: &mut App, theme_data: &ThemeData, name: &str, force: bool) {
+    let theme = app.theme_named(name);
+    match theme_data.update_config(&theme, force || app.force).await {
+        Ok(Install::Modified(path)) => {
+            app.mode = Mode::ConfirmOverwrite;
+            app.pending_apply = Some(name.to_string());
+            app.status_message = format!(
+                "{} has local changes and a newer version is available. Overwrite it? (y/n)",
+                path.display()
+            );
+        }
+        Ok(Install::Unresolved) => {
+            app.mode = Mode::ConfirmUnresolved;
+            app.pending_apply = Some(name.to_string());
+            // Zellij would fall back to its default theme
+            app.status_message = if theme.colors.is_some() {
+                format!(
+                    "Zellij would not find {}. Install it to themes/? \
+                     (y: install, a: apply anyway, n: cancel)",
+                    name
+                )
+            } else {
+                format!(
+                    "Zellij would not find {} and its colors are unknown. Apply anyway? (y/n)",
+                    name
+                )
+            };
+        }
+        Ok(Install::AlreadyApplied) => {
+            app.status_message = format!("{} is already the active theme", name);
+        }
+        Ok(install) => {
+            app.splash = theme.colors.as_ref().and_then(mock::MockColors::from_theme).map(
+                |colors| Splash {
+                    colors,
+                    started: Instant::now(),
+                },
+            );
+            let live = app.live.apply();
+            let layouts = layouts_note(&theme_data.update_layouts(Some(name)));
+            let installed = match install {
+                Install::Installed(path) => Some(format!("installed {}", path.display())),
+                _ => None,
+            };
+            app.status_message =
+                live.report(name, &configs_note(theme_data), installed.as_deref());
+            app.status_message.push_str(&layouts);
+            app.hooks.spawn(theme_data, theme, layouts);
+            if let Ok(entries) = theme_data.history() {
+                app.set_history(entries);
+            }
+        }
+        Err(e) => {
+            app.status_message = format!("Error updating config: {}", e);
+        }
+    }
+}
+
+/// Removes the `theme` setting so zellij falls back to its built-in
+/// default, which applying the `default` entry does not do when the user
+/// defines a theme of that name.
+fn reset_theme(app: &mut App, theme_data: &ThemeData) {
+    app.status_message = match theme_data.reset_theme() {
+        Ok(Some(previous)) => format!(
+            "Removed theme {} from the config; Zellij now uses its default ({})",
+            previous,
+            app.live.apply().label()
+        ),
+        Ok(None) => String::from("No theme is set in the config; nothing to reset"),
+        Err(e) => {
+            app.status_message = format!("Error updating config: {}", e);
+            return;
+        }
+    };
+    let layouts = theme_data.update_layouts(None);
+    app.status_message.push_str(&layouts_note(&layouts));
+}
+
+/// Undoes the last theme change when `back`, otherwise redoes the last one
+/// undone, then switches running sessions, layouts and hooks over as
+/// applying does.
+fn step_rollback(app: &mut App, theme_data: &ThemeData, back: bool) {
+    let (result, verb) = if back {
+        (theme_data.undo(), "Undid")
+    } else {
+        (theme_data.redo(), "Redid")
+    };
+    let target = match result {
+        Ok(Some(target)) => target,
+        Ok(None) if back => {
+            app.status_message = String::from("Nothing to undo");
+            return;
+        }
+        Ok(None) => {
+            app.status_message = String::from("Nothing to redo");
+            return;
+        }
+        Err(e) => {
+            app.status_message = format!("Error updating config: {}", e);
+            return;
+        }
+    };
+    let live = app.live.apply().label();
+    let layouts = layouts_note(&theme_data.update_layouts(target.as_deref()));
+    app.status_message = match &target {
+        Some(name) => format!("{} the theme change; now using {} ({})", verb, name, live),
+        None => format!(
+            "{} the theme change; no theme is set, so Zellij uses its default ({})",
+            verb, live
+        ),
+    };
+    app.status_message.push_str(&layouts);
+    if let Some(name) = &target {
+        let theme = app.theme_named(name);
+        app.hooks.spawn(theme_data, theme, layouts);
+    }
+    if let Ok(entries) = theme_data.history() {
+        app.set_history(entries);
+    }
+}
+
+/// ` | layouts updated: a.kdl, b.kdl` plus a note for each layout file
+/// whose `theme` node could not be changed along with the config.
+pub(crate) fn layouts_note(updates: &[LayoutUpdate]) -> String {
+    let updated: Vec<&str> = updates
+        .iter()
+        .filter(|update| update.result.is_ok())
+        .map(|update| update.file.as_str())
+        .collect();
+    let mut note = String::new();
+    if !updated.is_empty() {
+        note.push_str(&format!(" | layouts updated: {}", updated.join(", ")));
+    }
+    for update in updates {
+        if let Err(e) = &update.result {
+            note.push_str(&format!(" | layout {} failed: {}", update.file, e));
+        }
+    }
+    note
+}
+
+/// What a fetch changed about the list, e.g. `2 new themes since the last
+/// fetch (marked new); removed: foo`.
+fn changes_notice(changes: &ListChanges) -> String {
+    let mut parts = Vec::new();
+    match changes.added.len() {
+        0 => {}
+        1 => parts.push(String::from("1 new theme since the last fetch (marked new)")),
+        count => parts.push(format!("{} new themes since the last fetch (marked new)", count)),
+    }
+    if !changes.removed.is_empty() {
+        parts.push(format!("removed: {}", changes.removed.join(", ")));
+    }
+    parts.join("; ")
+}
+
+/// ` to N configs` when applying writes more than one config file.
+pub(crate) fn configs_note(theme_data: &ThemeData) -> String {
+    match theme_data.config_paths().count() {
+        1 => String::new(),
+        count => format!(" to {} configs", count),
+    }
+}
+
+/// The status message to show instead of the usual one when hooks for
+/// `name` failed. The theme stays applied.
+fn hook_failure(name: &str, failures: &[HookFailure]) -> Option<String> {
+    let first = failures.first()?;
+    let count = match failures.len() {
+        1 => String::new(),
+        count => format!(" (and {} more)", count - 1),
+    };
+    Some(format!(
+        "Applied theme {}, but the {} failed{}: {}",
+        name, first.hook, count, first.error
+    ))
+}
+
+/// Applies `name` on trial and enters `Mode::Preview`, remembering the
+/// current theme so it can be put back.
+async fn start_preview(app: &mut App, theme_data: &ThemeData, name: &str) {
+    let previous = match theme_data.current_theme() {
+        Ok(previous) => previous,
+        Err(e) => {
+            app.status_message = format!("Error reading config: {}", e);
+            return;
+        }
+    };
+    match theme_data.preview_theme(&app.theme_named(name), app.force).await {
+        Ok(Install::Modified(path)) => {
+            app.status_message = format!(
+                "{} has local changes; apply {} to decide whether to overwrite it",
+                path.display(),
+                name
+            );
+        }
+        Ok(Install::Unresolved) => {
+            app.status_message =
+                format!("Zellij does not know {}; apply it to decide what to do", name);
+        }
+        Ok(_) => {
+            app.mode = Mode::Preview;
+            app.preview = Some(Preview {
+                theme: name.to_string(),
+                previous,
+                live: app.live.apply().label(),
+                started: Instant::now(),
+            });
+        }
+        Err(e) => {
+            app.status_message = format!("Error updating config: {}", e);
+        }
+    }
+}
+
+/// Ends a preview, keeping the theme as if it had been applied normally or
+/// restoring the one that was set before.
+fn finish_preview(app: &mut App, theme_data: &ThemeData, keep: bool) {
+    app.mode = Mode::Normal;
+    let Some(preview) = app.preview.take() else {
+        return;
+    };
+    let result = if keep {
+        theme_data.keep_preview(&preview.theme, preview.previous.as_deref())
+    } else {
+        theme_data.revert_preview(preview.previous.as_deref())
+    };
+    let applied = keep && result.is_ok();
+    app.status_message = match (result, keep) {
+        (Ok(()), true) => format!("Successfully applied theme: {}", preview.theme),
+        (Ok(()), false) => format!(
+            "Reverted to {}",
+            preview.previous.as_deref().unwrap_or("no theme")
+        ),
+        (Err(e), _) => format!("Error updating config: {}", e),
+    };
+    if applied {
+        let layouts = layouts_note(&theme_data.update_layouts(Some(&preview.theme)));
+        let theme = app.theme_named(&preview.theme);
+        app.status_message.push_str(&layouts);
+        app.hooks.spawn(theme_data, theme, layouts);
+    }
+    if let Ok(entries) = theme_data.history() {
+        app.set_history(entries);
+    }
+}
+
+/// Opens the selected theme's file in the editor, or offers to install a
+/// copy first when it has none in the theme directory.
+fn start_edit<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    theme_data: &ThemeData,
+) -> io::Result<()> {
+    let Some(theme) = app.selected_theme().cloned() else {
+        return Ok(());
+    };
+    if theme.origin == theme::Origin::Config {
+        let path = theme_data.config_path().to_path_buf();
+        return edit_theme_file(terminal, app, theme_data, &path);
+    }
+    match theme_data.theme_file(&theme) {
+        Some(path) => edit_theme_file(terminal, app, theme_data, &path),
+        None => {
+            app.status_message = format!(
+                "{} is not installed locally. Install a copy in {} and edit it? (y/n)",
+                theme.name,
+                theme_data.theme_dir().display()
+            );
+            app.pending_edit = Some(theme.name);
+            app.mode = Mode::ConfirmInstall;
+            Ok(())
+        }
+    }
+}
+
+/// Shows the selected theme's file in the file manager, downloading a
+/// theme from a custom source first as applying it would. Without a
+/// desktop, as over SSH, the path is shown in the status line instead.
+async fn reveal_theme_file(app: &mut App, theme_data: &ThemeData) {
+    let Some(theme) = app.selected_theme().cloned() else {
+        return;
+    };
+    let path = if theme.origin == theme::Origin::Config {
+        Some(theme_data.config_path().to_path_buf())
+    } else if theme.origin == theme::Origin::Custom && theme_data.theme_file(&theme).is_none() {
+        if let Err(e) = theme_data.install_theme(&theme, false).await {
+            app.status_message = format!("Could not download {}: {}", theme.name, e);
+            return;
+        }
+        theme_data.theme_file(&theme)
+    } else {
+        theme_data.theme_file(&theme)
+    };
+    let Some(path) = path else {
+        let keys = app.settings.keymap.keys_for(Action::Edit);
+        app.status_message = match keys.first() {
+            Some(key) => format!(
+                "{} is built into Zellij and has no file; {} installs a copy to edit",
+                theme.name, key
+            ),
+            None => format!("{} is built into Zellij and has no file", theme.name),
+        };
+        return;
+    };
+    let (name, shown) = (&theme.name, path.display());
+    app.status_message = match reveal::reveal(&path) {
+        Ok(Some(program)) => format!("Opened {} with {}", shown, program),
+        Ok(None) => format!("No desktop to show it on; {} is in {}", name, shown),
+        Err(e) => format!("Could not open a file manager ({}); {} is in {}", e, name, shown),
+    };
+}
+
+/// The character a key types if it can start a theme name: a letter or a
+/// digit, without Ctrl or Alt.
+fn jump_letter(key: KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c)
+            if c.is_alphanumeric()
+                && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            Some(c)
+        }
+        _ => None,
+    }
+}
+
+/// Asks before deleting the selected theme's file from the theme
+/// directory, naming every theme that goes with it.
+fn start_delete(app: &mut App, theme_data: &ThemeData) {
+    let Some(theme) = app.selected_theme().cloned() else {
+        return;
+    };
+    let Some(path) = theme_data.theme_file(&theme) else {
+        app.status_message = format!(
+            "{} has no file in {} to delete",
+            theme.name,
+            theme_data.theme_dir().display()
+        );
+        return;
+    };
+    let file = theme_data.local_file_name(&path);
+    let defined: Vec<&str> = app
+        .themes
+        .iter()
+        .filter(|other| theme_data.theme_file(other).as_ref() == Some(&path))
+        .map(|other| other.name.as_str())
+        .collect();
+    app.status_message = if defined.len() > 1 {
+        let names = defined.join(", ");
+        format!("Delete {}? It defines {} themes: {} (y/n)", file, defined.len(), names)
+    } else {
+        format!("Delete {} ({})? (y/n)", file, theme.name)
+    };
+    app.pending_delete = Some(path);
+    app.mode = Mode::ConfirmDelete;
+}
+
+/// Deletes `path` and lists the themes again from the cache and the theme
+/// directory, so a theme that is also available upstream shows up as that
+/// one. Offers a reset if the applied theme went with the file.
+fn delete_theme_file(app: &mut App, theme_data: &ThemeData, path: &Path) {
+    let file = theme_data.local_file_name(path);
+    if let Err(e) = theme_data.delete_theme_file(path) {
+        app.status_message = format!("Could not delete {}: {}", file, e);
+        return;
+    }
+    let row = app.state.selected();
+    let selected = app.selected_name();
+    app.replace_themes(theme_data.cached_themes().themes);
+    // The row below takes the place of a theme that is gone
+    if app.selected_name() != selected {
+        let last = app.rows.len().checked_sub(1);
+        app.state.select(row.zip(last).map(|(row, last)| row.min(last)));
+    }
+    let themes = &app.themes;
+    app.marked.retain(|name| themes.iter().any(|theme| &theme.name == name));
+    app.status_message = format!("Deleted {}", file);
+
+    let current = theme_data.current_theme().ok().flatten();
+    if let Some(current) = current.filter(|name| !app.themes.iter().any(|t| &t.name == name)) {
+        app.status_message = format!(
+            "Deleted {}. It defined {}, the applied theme; reset to Zellij's default? (y/n)",
+            file, current
+        );
+        app.mode = Mode::ConfirmReset;
+    }
+}
+
+/// Stores the tags typed for `App::tagging` and filters again, since the
+/// query may ask for tags.
+fn save_tags(app: &mut App, theme_data: &ThemeData) {
+    let input = std::mem::take(&mut app.tag_input);
+    app.mode = Mode::Normal;
+    let Some(name) = app.tagging.take() else {
+        return;
+    };
+    let new = tags::parse(&input);
+    let label = tags::label(new.iter().map(String::as_str));
+    match theme_data.set_tags(&name, new) {
+        Ok(all) => {
+            app.tags = all;
+            app.apply_filters();
+            app.status_message = if label.is_empty() {
+                format!("Removed the tags of {}", name)
+            } else {
+                format!("Tagged {}: {}", name, label)
+            };
+        }
+        Err(e) => app.status_message = format!("Could not save the tags of {}: {}", name, e),
+    }
+}
+
+/// The status line while the theme editor is open.
+const EDITOR_HINT: &str = "↑/↓: choose a color, type a hex value, Delete: clear it, \
+                           Enter: save, Esc: close";
+
+/// Opens the theme editor on the highlighted theme's colors: to change a
+/// local theme in place, or with `new` to start another theme from them.
+fn start_color_editor(app: &mut App, new: bool) {
+    let Some(theme) = app.selected_theme() else {
+        return;
+    };
+    if !new && theme.origin != theme::Origin::Local {
+        app.status_message = format!(
+            "{} is not a local theme; start a new theme from its colors instead",
+            theme.name
+        );
+        return;
+    }
+    app.color_editor = Some(ColorEditor::new(theme, !new));
+    app.mode = Mode::EditColors;
+    app.status_message = String::from(EDITOR_HINT);
+}
+
+/// Feeds a key press to the theme editor: moving between fields and
+/// typing into them, the save prompt and the question about discarding
+/// unsaved changes.
+fn edit_colors_key(app: &mut App, theme_data: &ThemeData, key: KeyEvent) {
+    let Some(editor) = app.color_editor.as_mut() else {
+        app.mode = Mode::Normal;
+        return;
+    };
+    if editor.confirm_discard {
+        editor.confirm_discard = false;
+        if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            app.color_editor = None;
+            app.mode = Mode::Normal;
+            app.status_message = String::from("Discarded the changes");
+        }
+        return;
+    }
+    if let Some(name) = editor.name.as_mut() {
+        match key.code {
+            KeyCode::Enter => save_edited_theme(app, theme_data),
+            KeyCode::Esc => {
+                editor.name = None;
+                app.status_message = String::from(EDITOR_HINT);
+            }
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) => name.push(c),
+            _ => {}
+        }
+        return;
+    }
+    match key.code {
+        KeyCode::Up | KeyCode::BackTab => editor.move_by(-1),
+        KeyCode::Down | KeyCode::Tab => editor.move_by(1),
+        KeyCode::Backspace => {
+            editor.fields[editor.selected].1.pop();
+        }
+        KeyCode::Delete => editor.fields[editor.selected].1.clear(),
+        KeyCode::Char(c) => editor.type_char(c),
+        KeyCode::Enter => {
+            let invalid = editor.invalid_roles();
+            if invalid.is_empty() {
+                editor.name = Some(editor.editing.clone().unwrap_or_default());
+                app.status_message = String::from("Enter: save, Esc: back to the colors");
+            } else {
+                app.status_message = format!("Fix {} before saving", invalid.join(", "));
+            }
+        }
+        KeyCode::Esc if editor.is_modified() => editor.confirm_discard = true,
+        KeyCode::Esc => {
+            app.color_editor = None;
+            app.mode = Mode::Normal;
+            app.status_message = String::from("Closed the theme editor");
+        }
+        _ => {}
+    }
+}
+
+/// Writes the edited colors as `<name>.kdl` in the theme directory, with
+/// the name typed at the save prompt. The name may not belong to another
+/// theme or file; a local theme may keep its own, but only when saving
+/// would rewrite just its file.
+fn save_edited_theme(app: &mut App, theme_data: &ThemeData) {
+    let Some(editor) = app.color_editor.as_ref() else {
+        return;
+    };
+    let name = editor.name.as_deref().unwrap_or_default().trim().to_string();
+    if name.is_empty() {
+        app.status_message = String::from("Type a name for the theme");
+        return;
+    }
+    let path = theme_data.theme_dir().join(format!("{}.kdl", name));
+    let in_file = |theme: &Theme| theme_data.theme_file(theme).as_ref() == Some(&path);
+    if editor.editing.as_deref() == Some(name.as_str()) {
+        let own_file = in_file(&app.theme_named(&name))
+            && app.themes.iter().filter(|theme| in_file(theme)).count() == 1;
+        if !own_file {
+            app.status_message = format!(
+                "{} is not alone in {}; save it under a new name",
+                name,
+                theme_data.local_file_name(&path)
+            );
+            return;
+        }
+    } else if app.themes.iter().any(|theme| theme.name == name) {
+        app.status_message = format!("A theme named {} already exists; choose another name", name);
+        return;
+    } else if path.exists() {
+        app.status_message = format!("{} already exists; choose another name", path.display());
+        return;
+    }
+    let colors = editor.colors();
+    let path = match theme_data.save_theme(&name, &colors) {
+        Ok(path) => path,
+        Err(e) => {
+            app.status_message = format!("Could not save {}: {}", name, e);
+            return;
+        }
+    };
+    app.color_editor = None;
+    app.mode = Mode::Normal;
+    let file = theme_data.local_file_name(&path);
+    app.status_message = match theme_data.read_theme_file(&path) {
+        Ok(themes) => {
+            app.replace_file_themes(theme::Origin::Local, &file, themes);
+            app.refilter(Some(name.clone()));
+            format!("Saved {} to {}", name, path.display())
+        }
+        Err(e) => format!("Saved {} to {}, but it does not read back: {}", name, path.display(), e),
+    };
+}
+
+/// Fetches the highlighted theme's file again on its own, for when just
+/// that one came through broken.
+async fn refetch_selected(app: &mut App, theme_data: &ThemeData) {
+    let Some(theme) = app.selected_theme().cloned() else {
+        return;
+    };
+    let file = theme.file.clone().unwrap_or_default();
+    app.status_message = match theme_data.refetch_theme(&theme).await {
+        Ok(themes) => {
+            let count = themes.len();
+            app.replace_file_themes(theme.origin, &file, themes);
+            match app.themes.iter().find(|found| found.name == theme.name) {
+                Some(found) if found.colors.is_some() => {
+                    format!("Reloaded {} from {} ({} themes)", theme.name, file, count)
+                }
+                Some(_) => format!("Reloaded {}, but it still has no colors", file),
+                None => format!("Reloaded {}; it no longer defines {}", file, theme.name),
+            }
+        }
+        Err(e) => format!("Could not reload {}: {}", theme.name, e),
+    };
+}
+
+/// Hands the terminal to the editor for `path`, then reloads the themes
+/// the file defines. If it no longer parses, the old colors are kept and
+/// the parse error is shown.
+fn edit_theme_file<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    theme_data: &ThemeData,
+    path: &Path,
+) -> io::Result<()> {
+    restore_terminal(terminal)?;
+    let status = editor::edit(path);
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+
+    let editor = editor::editor_command();
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            app.status_message = format!("Could not run {}: {}", editor, e);
+            return Ok(());
+        }
+    };
+    // The config is edited for the themes defined in it
+    let (origin, file, themes) = if path == theme_data.config_path() {
+        let themes = theme_data.config_themes();
+        (theme::Origin::Config, theme_data.config_file_name(), themes)
+    } else {
+        let themes = theme_data.read_theme_file(path);
+        (theme::Origin::Local, theme_data.local_file_name(path), themes)
+    };
+    let notice = match themes {
+        Ok(themes) => {
+            let count = themes.len();
+            app.replace_file_themes(origin, &file, themes);
+            format!("Reloaded {} ({} themes)", file, count)
+        }
+        Err(e) => format!("{} does not parse, keeping the old colors: {}", file, e),
+    };
+    app.status_message = if status.success() {
+        notice
+    } else {
+        format!("{} exited with {} | {}", editor, status, notice)
+    };
+    Ok(())
+}
+
+/// Runs the interactive selector. `warnings` are problems found while
+/// loading `settings`, shown in the status bar once the list is up.
+pub async fn run_theme_selector(
+    settings: Settings,
+    warnings: Vec<String>,
+    options: Options,
+) -> io::Result<()> {
+    // Checked before the terminal is set up, so that e.g. a missing HOME
+    // ends in an error message and exit status rather than a broken screen
+    let theme_data = ThemeData::new(&settings)?;
+    theme_data.ensure_theme_dir()?;
+
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // Without a cache this is most likely the first run: say what is about
+    // to happen before going to the network
+    let theme_data = match theme_data.inspect_cache(cache::unix_now()) {
+        Ok(None) => match welcome(&mut terminal, &theme_data).await {
+            Ok(Welcome::Fetch(Some(token))) => theme_data.with_github_token(token),
+            Ok(Welcome::Fetch(None)) => theme_data,
+            Ok(Welcome::Quit) => return restore_terminal(&mut terminal),
+            Err(e) => {
+                restore_terminal(&mut terminal)?;
+                return Err(e);
+            }
+        },
+        _ => theme_data,
+    };
+
+    // Fetch in the background; themes show up in the list as they arrive
+    let loading = Loading::start(&theme_data, options.force_refresh, false);
+
+    let mut app = App::new(Vec::new(), &settings);
+    app.force = options.force;
+    app.started = options.started;
+    app.progress = Some((0, 0));
+    let mut notices = Vec::new();
+    match theme_data.history() {
+        Ok(entries) => app.set_history(entries),
+        Err(e) => notices.push(format!("History unavailable: {}", e)),
+    }
+    match theme_data.tags() {
+        Ok(tags) => app.tags = tags,
+        Err(e) => notices.push(format!("Tags unavailable: {}", e)),
+    }
+    let state_path = state::default_path();
+    if let Some(path) = &state_path {
+        app.restore(state::load(path));
+    }
+    if !warnings.is_empty() {
+        notices.push(format!("Settings: {}", warnings.join("; ")));
+    }
+    if !notices.is_empty() {
+        app.status_message = notices.join(" | ");
+    }
+    let res = run_app(&mut terminal, &mut app, &theme_data, Some(loading)).await;
+
+    restore_terminal(&mut terminal)?;
+    app.hooks.finish().await;
+    if let Some(path) = &state_path {
+        if let Err(e) = state::save(path, &app.ui_state()) {
+            log::warn!("could not save the state to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(elapsed) = app.first_frame {
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        let message = format!("first frame drawn {:.1}ms after start", millis);
+        log::debug!("{}", message);
+        eprintln!("{}", message);
+    }
+
+    // Quitting, or failing, during a preview must not leave it applied
+    if let Some(preview) = app.preview.take() {
+        if let Err(e) = theme_data.revert_preview(preview.previous.as_deref()) {
+            println!("Could not revert the preview of {}: {}", preview.theme, e);
+        }
+    }
+
+    if let Err(err) = res {
+        println!("Error: {}", err);
+    }
+
+    Ok(())
+}
+
+fn restore_terminal<B: Backend + io::Write>(terminal: &mut Terminal<B>) -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()
+}
+
+/// What was chosen on the welcome screen.
+enum Welcome {
+    /// Fetch the themes, with a token typed in for this run, if any.
+    Fetch(Option<String>),
+    Quit,
+}
+
+/// The screen shown when there is no cache yet: where the themes are about
+/// to be fetched from and how much of GitHub's rate limit is left, with the
+/// chance to enter a token first when it is used up.
+async fn welcome<B: Backend>(
+    terminal: &mut Terminal<B>,
+    theme_data: &ThemeData,
+) -> io::Result<Welcome> {
+    let data = theme_data.clone();
+    let mut check = Some(tokio::spawn(async move { data.rate_limit().await }));
+    let mut quota = None;
+    // The token being typed, once `t` was pressed
+    let mut token: Option<String> = None;
+    let choice = loop {
+        if let Some(done) = check.take_if(|check| check.is_finished()) {
+            quota = Some(done.await.unwrap_or_else(|e| Err(io::Error::other(e))));
+        }
+        terminal.draw(|frame| {
+            render_welcome(frame, theme_data, check.is_some(), quota.as_ref(), token.as_deref())
+        })?;
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match (&mut token, key.code) {
+            (Some(input), KeyCode::Enter) if !input.trim().is_empty() => {
+                break Welcome::Fetch(Some(input.trim().to_string()));
+            }
+            (Some(_), KeyCode::Enter | KeyCode::Esc) => token = None,
+            (Some(input), KeyCode::Backspace) => {
+                input.pop();
+            }
+            (Some(input), KeyCode::Char(c)) => input.push(c),
+            (None, KeyCode::Enter) => break Welcome::Fetch(None),
+            (None, KeyCode::Char('t')) => token = Some(String::new()),
+            (None, KeyCode::Char('q') | KeyCode::Esc) => break Welcome::Quit,
+            _ => {}
+        }
+    };
+    if let Some(check) = check {
+        check.abort();
+    }
+    Ok(choice)
+}
+
+fn render_welcome(
+    frame: &mut Frame,
+    theme_data: &ThemeData,
+    checking: bool,
+    quota: Option<&io::Result<Option<RateLimit>>>,
+    token: Option<&str>,
+) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines = vec![
+        Line::from("There is no theme cache yet, so this first start downloads the list from"),
+        Line::from(Span::styled(format!("  {}", theme_data.api_url()), dim)),
+        Line::from("and keeps it in"),
+        Line::from(Span::styled(format!("  {}", theme_data.cache_path().display()), dim)),
+        Line::from("so later starts are quick. Theme files in"),
+        Line::from(Span::styled(format!("  {}", theme_data.theme_dir().display()), dim)),
+        Line::from("are listed as well."),
+        Line::from(""),
+    ];
+    let exhausted = matches!(quota, Some(Ok(Some(limit))) if limit.remaining == 0);
+    if theme_data.has_github_token() {
+        lines.push(Line::from("GITHUB_TOKEN is set and is sent with GitHub API requests."));
+    } else {
+        lines.push(Line::from(
+            "Listing the themes takes one GitHub API request; without a token GitHub allows \
+             60 an hour from your address.",
+        ));
+    }
+    let note = match quota {
+        _ if checking => Some(String::from("Checking the rate limit…")),
+        Some(Ok(Some(limit))) if limit.remaining == 0 => Some(match limit.reset {
+            Some(reset) => format!(
+                "The rate limit is used up until {}. Enter a token to fetch now.",
+                reset.format("%H:%M")
+            ),
+            None => String::from("The rate limit is used up. Enter a token to fetch now."),
+        }),
+        Some(Ok(Some(limit))) => {
+            Some(format!("{} API requests are left this hour.", limit.remaining))
+        }
+        Some(Err(e)) => Some(format!("Could not check the rate limit: {}", e)),
+        _ => None,
+    };
+    if let Some(note) = note {
+        let style = if exhausted {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(Span::styled(note, style)));
+    }
+    lines.push(Line::from(""));
+    match token {
+        Some(input) => {
+            lines.push(Line::from(format!("Token: {}", "•".repeat(input.chars().count()))));
+            lines.push(Line::from(Span::styled(
+                "Enter: use it for this run (set GITHUB_TOKEN to keep one), Esc: cancel",
+                dim,
+            )));
+        }
+        None => lines.push(Line::from(Span::styled(
+            "Enter: fetch the themes, t: enter a GitHub token, q: quit",
+            dim,
+        ))),
+    }
+
+    let area = frame.size();
+    let width = area.width.min(90);
+    let height = (lines.len() as u16 + 4).min(area.height);
+    let area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Welcome to the Zellij theme selector ");
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }).block(block), area);
+}
+
+/// The editor form: each color role with its hex value and a swatch of it.
+/// Values that are not colors are shown in red.
+fn render_color_editor(frame: &mut Frame, area: Rect, editor: &ColorEditor) {
+    let items: Vec<ListItem> = editor
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(index, (role, value))| {
+            let value_style = if editor.is_valid(index) {
+                Style::default()
+            } else {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            };
+            let mut spans = vec![
+                Span::raw(format!("{:<9}", role)),
+                Span::styled(format!("{:<8}", value), value_style),
+            ];
+            if let Some(rgb) = color::Rgb::from_hex(value) {
+                spans.push(Span::styled("████", Style::default().fg(to_color(rgb))));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let title = match &editor.editing {
+        Some(name) => format!("Editing {}", name),
+        None => format!("New theme from {}", editor.from),
+    };
+    let title = if editor.is_modified() { format!("{} *", title) } else { title };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(Some(editor.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Every action with the keys bound to it, in a box over the middle of the
+/// screen. Actions left without keys by the `keys` block are shown as such.
+fn render_help(frame: &mut Frame, keymap: &Keymap) {
+    let rows: Vec<(String, &str)> = Action::ALL
+        .into_iter()
+        .map(|action| (keymap.keys_for(action).join(", "), action.description()))
+        .collect();
+    let key_width = rows.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0);
+    let lines: Vec<Line> = rows
+        .into_iter()
+        .map(|(keys, description)| {
+            let keys = if keys.is_empty() { String::from("(unbound)") } else { keys };
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<width$}", keys, width = key_width.max(9)),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw("  "),
+                Span::raw(description),
+            ])
+        })
+        .collect();
+
+    let area = frame.size();
+    let width = area.width.min(70);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default().borders(Borders::ALL).title(" Keys ").title(
+        Title::from(Span::styled(" Esc to close ", Style::default().fg(Color::DarkGray)))
+            .position(Position::Bottom)
+            .alignment(Alignment::Right),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Each file the last fetch failed to load with the reason, in a box over
+/// the middle of the screen like the help.
+fn render_failures(frame: &mut Frame, failures: &[FetchFailure]) {
+    let lines: Vec<Line> = failures
+        .iter()
+        .map(|failure| {
+            Line::from(vec![
+                Span::styled(failure.file.as_str(), Style::default().fg(Color::Cyan)),
+                Span::raw("  "),
+                Span::raw(failure.error.as_str()),
+            ])
+        })
+        .collect();
+
+    let area = frame.size();
+    let width = area.width.min(90);
+    // Long errors wrap, so leave room for a second line each
+    let height = (lines.len() as u16 * 2 + 2).min(area.height);
+    let area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" {} theme files failed to load ", failures.len()))
+        .title(
+            Title::from(Span::styled(" Esc to close ", Style::default().fg(Color::DarkGray)))
+                .position(Position::Bottom)
+                .alignment(Alignment::Right),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }).block(block), area);
+}
+
+/// Hooks running in the background for applied themes, so a slow one
+/// does not freeze the list. A run that fails sends the status message
+/// to show in its place.
+struct Hooks {
+    running: JoinSet<()>,
+    failures_tx: UnboundedSender<String>,
+    failures: UnboundedReceiver<String>,
+}
+
+impl Hooks {
+    fn new() -> Self {
+        let (failures_tx, failures) = mpsc::unbounded_channel();
+        Self {
+            running: JoinSet::new(),
+            failures_tx,
+            failures,
+        }
+    }
+
+    /// Runs the hooks for `theme`; `note` is appended to the failure
+    /// message as it was to the status line.
+    fn spawn(&mut self, theme_data: &ThemeData, theme: Theme, note: String) {
+        let task_data = theme_data.clone();
+        let failures_tx = self.failures_tx.clone();
+        self.running.spawn(async move {
+            let failures = task_data.run_hooks(&theme).await;
+            if let Some(failure) = hook_failure(&theme.name, &failures) {
+                let _ = failures_tx.send(failure + &note);
+            }
+        });
+    }
+
+    fn is_running(&self) -> bool {
+        !self.running.is_empty()
+    }
+
+    /// Reaps the finished runs and returns the newest failure message.
+    fn poll(&mut self) -> Option<String> {
+        while self.running.try_join_next().is_some() {}
+        let mut latest = None;
+        while let Ok(failure) = self.failures.try_recv() {
+            latest = Some(failure);
+        }
+        latest
+    }
+
+    /// Waits for the runs still going, so quitting right after applying
+    /// does not kill the hooks.
+    async fn finish(&mut self) {
+        while self.running.join_next().await.is_some() {}
+    }
+}
+
+/// A fetch still running in the background, and the batches it has sent.
+struct Loading {
+    fetch: JoinHandle<io::Result<FetchOutcome>>,
+    events: UnboundedReceiver<FetchEvent>,
+    /// Stops the fetch and every download it has in flight.
+    cancel: CancellationToken,
+    /// Whether this replaces a list already shown (`R`) rather than
+    /// filling an empty one.
+    refresh: bool,
+}
+
+impl Loading {
+    fn start(theme_data: &ThemeData, force_refresh: bool, refresh: bool) -> Self {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let task_data = theme_data.clone();
+        let cancel = CancellationToken::new();
+        let cancelled = cancel.clone();
+        // Dropping the fetch drops its downloads, aborting their requests
+        let fetch = tokio::spawn(async move {
+            tokio::select! {
+                biased;
+                _ = cancelled.cancelled() => {
+                    Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+                }
+                result = task_data.stream_themes(force_refresh, events_tx) => result,
+            }
+        });
+        Self {
+            fetch,
+            events,
+            cancel,
+            refresh,
+        }
+    }
+
+    /// Moves whatever has arrived into `app`. Returns `true` once the fetch
+    /// has finished.
+    async fn poll(&mut self, app: &mut App) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                // A refresh keeps the list as it is until the end
+                FetchEvent::Themes(_) if self.refresh => {}
+                FetchEvent::Themes(themes) => app.add_themes(themes),
+                FetchEvent::Progress { done, total } if self.refresh => {
+                    app.status_message =
+                        format!("Refreshing themes… downloaded {}/{} files", done, total);
+                }
+                FetchEvent::Progress { done, total } => app.progress = Some((done, total)),
+            }
+        }
+        if !self.fetch.is_finished() {
+            return false;
+        }
+        let result = match (&mut self.fetch).await {
+            Ok(result) => result,
+            Err(e) => Err(io::Error::other(e)),
+        };
+        app.finish_loading(result, self.refresh);
+        // Not listed by now, so the theme highlighted last time is gone
+        app.restore_selection = None;
+        true
+    }
+
+    /// Stops the fetch and waits for it to wind down, leaving the list as
+    /// it is: the current one for a refresh, whatever has arrived so far
+    /// otherwise.
+    async fn cancel(&mut self, app: &mut App) {
+        self.cancel.cancel();
+        let _ = (&mut self.fetch).await;
+        app.progress = None;
+        app.restore_selection = None;
+        app.status_message = if self.refresh {
+            String::from("Refresh cancelled, keeping the current list")
+        } else if app.themes.is_empty() {
+            String::from("Fetch cancelled before any themes arrived")
+        } else {
+            format!("Fetch cancelled; showing the {} themes loaded so far", app.themes.len())
+        };
+    }
+}
+
+// Quitting while the fetch is still running cancels it
+impl Drop for Loading {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        self.fetch.abort();
+    }
+}
+
+/// The theme name in bold, with the characters matching `query`
+/// underlined in yellow.
+fn highlight_matches<'a>(name: &'a str, query: &str) -> Vec<Span<'a>> {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let Some(found) = fuzzy_match(query, name).filter(|found| !found.positions.is_empty()) else {
+        return vec![Span::styled(name, bold)];
+    };
+    let matched = bold.fg(Color::Yellow).add_modifier(Modifier::UNDERLINED);
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let style = if found.positions.contains(&i) { matched } else { bold };
+            Span::styled(c.to_string(), style)
+        })
+        .collect()
+}
+
+fn contrast_style(level: ContrastLevel) -> Style {
+    match level {
+        ContrastLevel::Aaa => Style::default().fg(Color::Green),
+        ContrastLevel::Aa => Style::default().fg(Color::Yellow),
+        ContrastLevel::Fail => Style::default().fg(Color::Red),
+    }
+}
+
+fn to_color(rgb: color::Rgb) -> Color {
+    Color::Rgb(rgb.r, rgb.g, rgb.b)
+}
+
+/// `rgb` as hex, followed by the nearest named color with `names`.
+fn color_label(rgb: color::Rgb, names: bool) -> String {
+    if names {
+        format!("{} {}", rgb.to_hex(), color_names::nearest(rgb))
+    } else {
+        rgb.to_hex()
+    }
+}
+
+/// Both themes' palettes slot by slot with how far apart each color is,
+/// followed by both contrast reports.
+fn render_comparison(frame: &mut Frame, area: Rect, left: &Theme, right: &Theme) {
+    let title = format!("Compare: {} vs {} (c or Esc to go back)", left.name, right.name);
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(13), Constraint::Min(1)])
+        .split(inner);
+
+    let swatch = |color: Option<color::Rgb>| match color {
+        Some(rgb) => Cell::from(Line::from(vec![
+            Span::styled("██ ", Style::default().fg(to_color(rgb))),
+            Span::raw(rgb.to_hex()),
+        ])),
+        None => Cell::from(Span::styled("-", Style::default().fg(Color::DarkGray))),
+    };
+    let slots = |theme: &Theme| {
+        let colors = theme.colors.clone().unwrap_or_default();
+        let mut slots = vec![("fg", colors.fg), ("bg", colors.bg)];
+        slots.extend(colors.ansi());
+        slots
+    };
+    let rows: Vec<Row> = slots(left)
+        .into_iter()
+        .zip(slots(right))
+        .map(|((slot, a), (_, b))| {
+            let delta = match (a, b) {
+                (Some(a), Some(b)) => {
+                    let delta = color::delta_e(a, b);
+                    let style = match delta {
+                        d if d < 2.0 => Style::default().fg(Color::DarkGray),
+                        d if d < 10.0 => Style::default().fg(Color::Yellow),
+                        _ => Style::default().fg(Color::Red),
+                    };
+                    Cell::from(Span::styled(format!("ΔE {:>5.1}", delta), style))
+                }
+                _ => Cell::from(""),
+            };
+            Row::new(vec![Cell::from(slot), swatch(a), swatch(b), delta])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(9),
+        Constraint::Length(16),
+        Constraint::Length(16),
+        Constraint::Length(9),
+    ];
+    let header = Row::new(vec!["Slot", left.name.as_str(), right.name.as_str(), "Diff"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    frame.render_widget(Table::new(rows).header(header).widths(&widths), chunks[0]);
+
+    let report = |theme: &Theme| {
+        theme
+            .colors
+            .as_ref()
+            .map(|colors| colors.contrast_report())
+            .unwrap_or_default()
+    };
+    let (left_report, right_report) = (report(left), report(right));
+    let ratio = |pair: Option<&ContrastPair>| match pair {
+        Some(pair) => Cell::from(Span::styled(
+            format!("{:>5.2}:1 {}", pair.ratio, pair.level().label()),
+            contrast_style(pair.level()),
+        )),
+        None => Cell::from(Span::styled("-", Style::default().fg(Color::DarkGray))),
+    };
+    let mut labels: Vec<&str> = left_report.iter().map(|pair| pair.label).collect();
+    for pair in &right_report {
+        if !labels.contains(&pair.label) {
+            labels.push(pair.label);
+        }
+    }
+    let rows: Vec<Row> = labels
+        .into_iter()
+        .map(|label| {
+            Row::new(vec![
+                Cell::from(label),
+                ratio(left_report.iter().find(|pair| pair.label == label)),
+                ratio(right_report.iter().find(|pair| pair.label == label)),
+            ])
+        })
+        .collect();
+    let header = Row::new(vec!["Contrast", left.name.as_str(), right.name.as_str()])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let widths = [Constraint::Length(9), Constraint::Length(16), Constraint::Length(16)];
+    frame.render_widget(Table::new(rows).header(header).widths(&widths), chunks[1]);
+}
+
+/// Rows taken by the mock session in the details pane, with a blank line
+/// below it.
+const MOCK_HEIGHT: u16 = 9;
+
+/// `min_contrast` is the text contrast below which the theme is flagged;
+/// `names` follows each hex value with the nearest named color.
+fn render_details(
+    frame: &mut Frame,
+    area: Rect,
+    theme: Option<&Theme>,
+    min_contrast: f64,
+    names: bool,
+) {
+    let block = Block::default().borders(Borders::ALL).title("Details");
+    let Some(theme) = theme else {
+        frame.render_widget(block, area);
+        return;
+    };
+    // Where the theme comes from, and other definitions it shadows
+    let mut provenance = vec![Line::from(Span::styled(
+        format!("From {}", theme.location()),
+        Style::default().fg(Color::DarkGray),
+    ))];
+    if !theme.also_defined_in.is_empty() {
+        provenance.push(Line::from(Span::styled(
+            format!("Also defined in {}", theme.also_defined_in.join(", ")),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+
+    let Some(colors) = &theme.colors else {
+        let mut lines = vec![Line::from(format!(
+            "{} ({})",
+            theme.name,
+            theme.appearance.label()
+        ))];
+        lines.extend(provenance);
+        lines.push(Line::from(""));
+        lines.push(Line::from("No color information available"));
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+        return;
+    };
+
+    let ratio = colors.fg_bg_contrast();
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{} ({}, {:?} format{})",
+            theme.name,
+            theme.appearance.label(),
+            colors.format,
+            ratio.map(|ratio| format!(", text {:.2}:1", ratio)).unwrap_or_default()
+        ),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    if ratio.is_some_and(|ratio| ratio < min_contrast) {
+        lines.push(Line::from(Span::styled(
+            format!("⚠ Text contrast is below {:.1}:1", min_contrast),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    lines.extend(provenance);
+    if let Some(bg) = colors.bg {
+        lines.push(Line::from(Span::styled(
+            format!("Background {}", color_label(bg, names)),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let mock_colors = mock::MockColors::from_theme(colors);
+    let mock_height = if mock_colors.is_some() { MOCK_HEIGHT } else { 0 };
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(lines.len() as u16 + 1), // Name, warning and origin
+            Constraint::Length(mock_height),            // Mock session
+            Constraint::Min(1),                         // Contrast table
+        ])
+        .split(inner);
+
+    let header = Paragraph::new(lines);
+    frame.render_widget(header, chunks[0]);
+    if let Some(mock_colors) = mock_colors {
+        let mock_area = Rect {
+            height: chunks[1].height.saturating_sub(1),
+            ..chunks[1]
+        };
+        frame.render_widget(mock::MockSession::new(mock_colors), mock_area);
+    }
+
+    let rows: Vec<Row> = colors
+        .contrast_report()
+        .into_iter()
+        .map(|pair| {
+            let level = pair.level();
+            Row::new(vec![
+                Cell::from(pair.label),
+                Cell::from(" Sample ")
+                    .style(Style::default().fg(to_color(pair.fg)).bg(to_color(pair.bg))),
+                Cell::from(format!("{:>5.2}:1", pair.ratio)),
+                Cell::from(level.label()).style(contrast_style(level)),
+                Cell::from(color_label(pair.fg, names)),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Length(8),
+        Constraint::Length(9),
+        Constraint::Length(4),
+        Constraint::Length(38),
+    ];
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec!["Pair", "Sample", "Ratio", "WCAG", "Color"])
+                .style(Style::default().add_modifier(Modifier::UNDERLINED)),
+        )
+        .widths(&widths)
+        .column_spacing(1);
+    frame.render_widget(table, chunks[2]);
+}
+
+async fn run_app<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    theme_data: &ThemeData,
+    mut loading: Option<Loading>,
+) -> io::Result<()> {
+    loop {
+        if let Some(pending) = loading.as_mut() {
+            if pending.poll(app).await {
+                loading = None;
+            }
+        }
+        if app.preview.as_ref().is_some_and(|p| p.started.elapsed() >= PREVIEW_TIMEOUT) {
+            finish_preview(app, theme_data, false);
+        }
+        app.splash.take_if(|splash| splash.started.elapsed() >= SPLASH_DURATION);
+        if let Some(failure) = app.hooks.poll() {
+            app.status_message = failure;
+        }
+
+        terminal.draw(|frame| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints([
+                    Constraint::Length(3), // Status
+                    Constraint::Min(1),    // List and details
+                ])
+                .split(frame.size());
+
+            // Status message, replaced by the prompt while filtering
+            let status = match app.mode {
+                Mode::Filter => Paragraph::new(format!("/{}_", app.query)).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Filter (Enter: apply, Esc: clear, Up/Down: history, #tag)"),
+                ),
+                Mode::Tag => Paragraph::new(format!("{}_", app.tag_input)).block(
+                    Block::default().borders(Borders::ALL).title(format!(
+                        "Tags for {} (separated by spaces; Enter: save, Esc: cancel)",
+                        app.tagging.as_deref().unwrap_or_default()
+                    )),
+                ),
+                Mode::Normal => {
+                    // Just applied: the banner in the theme's own colors
+                    let (text, border) = match &app.splash {
+                        Some(splash) => (
+                            Style::default()
+                                .fg(to_color(splash.colors.text.base))
+                                .bg(to_color(splash.colors.text.background)),
+                            Style::default().fg(to_color(splash.colors.frame_focused)),
+                        ),
+                        None => (Style::default(), Style::default()),
+                    };
+                    Paragraph::new(app.status_message.clone()).style(text).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(border)
+                            .title("Status")
+                            .title(
+                                // Which config.kdl Enter will edit
+                                Title::from(Span::styled(
+                                    format!(
+                                        " {}{} ({}) ",
+                                        theme_data.config_path().display(),
+                                        match theme_data.config_paths().count() - 1 {
+                                            0 => String::new(),
+                                            more => format!(" +{} more", more),
+                                        },
+                                        app.settings.zellij_config_dir_origin.label()
+                                    ),
+                                    Style::default().fg(Color::DarkGray),
+                                ))
+                                .position(Position::Bottom)
+                                .alignment(Alignment::Right),
+                            ),
+                    )
+                }
+                Mode::ConfirmOverwrite
+                | Mode::ConfirmUnresolved
+                | Mode::ConfirmInstall
+                | Mode::ConfirmDelete
+                | Mode::ConfirmReset => Paragraph::new(app.status_message.clone())
+                    .style(Style::default().fg(Color::Yellow))
+                    .block(Block::default().borders(Borders::ALL).title("Confirm")),
+                Mode::EditColors => match &app.color_editor {
+                    Some(editor) if editor.confirm_discard => {
+                        Paragraph::new("Discard the changes? (y/n)")
+                            .style(Style::default().fg(Color::Yellow))
+                            .block(Block::default().borders(Borders::ALL).title("Confirm"))
+                    }
+                    // What went wrong with the last name tried, or the keys
+                    Some(ColorEditor { name: Some(name), .. }) => {
+                        Paragraph::new(format!("{}_", name)).block(
+                            Block::default().borders(Borders::ALL).title("Save as").title(
+                                Title::from(Span::styled(
+                                    format!(" {} ", app.status_message),
+                                    Style::default().fg(Color::Yellow),
+                                ))
+                                .position(Position::Bottom),
+                            ),
+                        )
+                    }
+                    _ => Paragraph::new(app.status_message.clone())
+                        .block(Block::default().borders(Borders::ALL).title("Theme editor")),
+                },
+                Mode::Preview => {
+                    let text = match &app.preview {
+                        Some(preview) => format!(
+                            "Previewing {} ({}). y to keep, any other key to revert ({}s)",
+                            preview.theme,
+                            preview.live,
+                            PREVIEW_TIMEOUT.saturating_sub(preview.started.elapsed()).as_secs() + 1
+                        ),
+                        None => String::new(),
+                    };
+                    Paragraph::new(text)
+                        .style(Style::default().fg(Color::Yellow))
+                        .block(Block::default().borders(Borders::ALL).title("Preview"))
+                }
+            };
+            frame.render_widget(status, chunks[0]);
+
+            let body = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .split(chunks[1]);
+
+            // Theme list
+            let (_, name_query) = tags::split_query(&app.query);
+            let (_, name_query) = search::split_query(&name_query);
+            let items: Vec<ListItem> = app
+                .rows
+                .iter()
+                .map(|row| {
+                    let (index, grouped) = match row {
+                        ListRow::Header { family, members } => {
+                            let marker = if app.expanded.contains(family) || !app.query.is_empty() {
+                                "▾"
+                            } else {
+                                "▸"
+                            };
+                            return ListItem::new(Line::from(Span::styled(
+                                format!("{} {} ({})", marker, family, members),
+                                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                            )));
+                        }
+                        ListRow::Theme { index, grouped } => (*index, *grouped),
+                    };
+                    let theme = &app.themes[index];
+                    // A dot in the theme's own background color; hollow when unknown
+                    let badge = match theme.colors.as_ref().and_then(|colors| colors.bg) {
+                        Some(bg) => Span::styled("● ", Style::default().fg(to_color(bg))),
+                        None => Span::styled("○ ", Style::default().fg(Color::DarkGray)),
+                    };
+                    let indent = Span::raw(if grouped { "  " } else { "" });
+                    let mut spans = vec![indent, badge];
+                    spans.extend(highlight_matches(&theme.name, &name_query));
+                    if app.new_themes.contains(&theme.name) {
+                        spans.push(Span::styled(
+                            " new",
+                            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if theme.origin == theme::Origin::Config {
+                        spans.push(Span::styled(" [config]", Style::default().fg(Color::Blue)));
+                    } else if theme_data.is_installed(theme) {
+                        spans.push(Span::styled(" ✓", Style::default().fg(Color::Green)));
+                    }
+                    let tags = tags::label(app.tags.with_source_tags(theme).into_iter());
+                    if !tags.is_empty() {
+                        spans.push(Span::styled(
+                            format!(" {}", tags),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    if app.marked.contains(&theme.name) {
+                        spans.push(Span::styled(" ◆", Style::default().fg(Color::Magenta)));
+                    }
+                    if let Some(ratio) = theme.fg_bg_contrast() {
+                        let level = ContrastLevel::from_ratio(ratio);
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            format!("[{}]", level.label()),
+                            contrast_style(level),
+                        ));
+                        if ratio < app.min_contrast {
+                            spans.push(Span::styled(" ⚠", Style::default().fg(Color::Red)));
+                        }
+                    }
+                    ListItem::new(Line::from(spans))
+                })
+                .collect();
+
+            let mut filters = Vec::new();
+            if !app.query.is_empty() {
+                filters.push(format!("/{}", app.query));
+            }
+            if app.hide_low_contrast {
+                filters.push(format!("contrast >= {:.1}", app.min_contrast));
+            }
+            match app.appearance_filter {
+                AppearanceFilter::All => {}
+                AppearanceFilter::Dark => filters.push(String::from("dark")),
+                AppearanceFilter::Light => filters.push(String::from("light")),
+            }
+            match app.progress {
+                Some((_, 0)) => filters.push(String::from("fetching…")),
+                Some((done, total)) => {
+                    filters.push(format!("downloaded {}/{} files", done, total))
+                }
+                None => {}
+            }
+            let title = if filters.is_empty() {
+                String::from("Themes")
+            } else {
+                format!("Themes ({})", filters.join(", "))
+            };
+            let themes = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .highlight_style(
+                    Style::default()
+                        .bg(app.settings.highlight_color)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol("> ");
+
+            match app.view {
+                View::Themes | View::Help | View::Failures => {
+                    frame.render_stateful_widget(themes, body[0], &mut app.state)
+                }
+                View::History => {
+                    let items: Vec<ListItem> = app
+                        .history
+                        .iter()
+                        .map(|entry| {
+                            ListItem::new(Line::from(vec![
+                                Span::styled(
+                                    entry.applied_at_label(),
+                                    Style::default().fg(Color::DarkGray),
+                                ),
+                                Span::raw("  "),
+                                Span::styled(
+                                    entry.theme.as_str(),
+                                    Style::default().add_modifier(Modifier::BOLD),
+                                ),
+                            ]))
+                        })
+                        .collect();
+                    let history = List::new(items)
+                        .block(Block::default().borders(Borders::ALL).title("Recently applied"))
+                        .highlight_style(
+                            Style::default()
+                                .bg(app.settings.highlight_color)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                        .highlight_symbol("> ");
+                    frame.render_stateful_widget(history, body[0], &mut app.history_state);
+                }
+                View::Compare => {}
+            }
+
+            match (&app.view, &app.comparison) {
+                (View::Compare, Some((left, right))) => {
+                    render_comparison(frame, chunks[1], left, right)
+                }
+                _ => render_details(
+                    frame,
+                    body[1],
+                    app.detail_theme(),
+                    app.min_contrast,
+                    app.settings.color_names,
+                ),
+            }
+            let editing = app.color_editor.as_ref().filter(|_| app.mode == Mode::EditColors);
+            if let Some(editor) = editing {
+                frame.render_widget(Clear, chunks[1]);
+                render_color_editor(frame, body[0], editor);
+                let preview = editor.preview();
+                let names = app.settings.color_names;
+                render_details(frame, body[1], Some(&preview), app.min_contrast, names);
+            }
+            if app.view == View::Help {
+                render_help(frame, &app.settings.keymap);
+            }
+            if app.view == View::Failures {
+                render_failures(frame, &app.failures);
+            }
+        })?;
+        if app.first_frame.is_none() {
+            app.first_frame = app.started.map(|started| started.elapsed());
+        }
+
+        // Keep redrawing while themes are still arriving, a preview counts
+        // down, the splash is up or hooks may still report a failure
+        let ticking = loading.is_some()
+            || app.preview.is_some()
+            || app.splash.is_some()
+            || app.hooks.is_running();
+        if ticking && !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            // The user has taken over from the restored selection
+            app.restore_selection = None;
+            if key.kind == KeyEventKind::Press && app.mode == Mode::Filter {
+                match key.code {
+                    KeyCode::Enter => {
+                        if let Some(name) = app.confirm_filter() {
+                            apply_theme(app, theme_data, &name, false).await;
+                        }
+                    }
+                    KeyCode::Esc => app.cancel_filter(),
+                    KeyCode::Backspace => app.edit_query(|query| {
+                        query.pop();
+                    }),
+                    KeyCode::Up => app.recall_query(true),
+                    KeyCode::Down => app.recall_query(false),
+                    KeyCode::Char(c) => app.edit_query(|query| query.push(c)),
+                    _ => {}
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::Tag {
+                match key.code {
+                    KeyCode::Enter => save_tags(app, theme_data),
+                    KeyCode::Esc => app.cancel_tagging(),
+                    KeyCode::Backspace => {
+                        app.tag_input.pop();
+                    }
+                    KeyCode::Char(c) => app.tag_input.push(c),
+                    _ => {}
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::EditColors {
+                edit_colors_key(app, theme_data, key);
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::Preview {
+                if app.settings.keymap.handle(key) == Some(Action::Quit) {
+                    return Ok(());
+                }
+                app.settings.keymap.reset();
+                let keep = matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y'));
+                finish_preview(app, theme_data, keep);
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::ConfirmInstall {
+                app.mode = Mode::Normal;
+                let Some(name) = app.pending_edit.take() else {
+                    continue;
+                };
+                if !matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    app.status_message = format!("{} was not installed", name);
+                    continue;
+                }
+                match theme_data.install_for_editing(&app.theme_named(&name)).await {
+                    Ok(path) => edit_theme_file(terminal, app, theme_data, &path)?,
+                    Err(e) => app.status_message = format!("Could not install {}: {}", name, e),
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::ConfirmDelete {
+                app.mode = Mode::Normal;
+                let Some(path) = app.pending_delete.take() else {
+                    continue;
+                };
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    delete_theme_file(app, theme_data, &path);
+                } else {
+                    let file = theme_data.local_file_name(&path);
+                    app.status_message = format!("Kept {}", file);
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::ConfirmReset {
+                app.mode = Mode::Normal;
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    reset_theme(app, theme_data);
+                } else {
+                    app.status_message = String::from(
+                        "Left the config as it is; Zellij falls back to its default theme",
+                    );
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::ConfirmUnresolved {
+                app.mode = Mode::Normal;
+                let Some(name) = app.pending_apply.take() else {
+                    continue;
+                };
+                let theme = app.theme_named(&name);
+                match (key.code, &theme.colors) {
+                    (KeyCode::Char('y') | KeyCode::Char('Y'), Some(colors)) => {
+                        match theme_data.save_theme(&name, colors) {
+                            Ok(path) => {
+                                // List it as the local theme it now is
+                                let file = theme_data.local_file_name(&path);
+                                if let Ok(themes) = theme_data.read_theme_file(&path) {
+                                    app.replace_file_themes(theme::Origin::Local, &file, themes);
+                                }
+                                apply_theme(app, theme_data, &name, false).await
+                            }
+                            Err(e) => {
+                                app.status_message = format!("Could not install {}: {}", name, e)
+                            }
+                        }
+                    }
+                    (KeyCode::Char('y') | KeyCode::Char('Y'), None)
+                    | (KeyCode::Char('a') | KeyCode::Char('A'), Some(_)) => {
+                        apply_theme(app, theme_data, &name, true).await
+                    }
+                    _ => app.status_message = format!("{} was not applied", name),
+                }
+            } else if key.kind == KeyEventKind::Press && app.mode == Mode::ConfirmOverwrite {
+                app.mode = Mode::Normal;
+                let Some(name) = app.pending_apply.take() else {
+                    continue;
+                };
+                if let KeyCode::Char('y') | KeyCode::Char('Y') = key.code {
+                    apply_theme(app, theme_data, &name, true).await;
+                } else {
+                    app.status_message = format!("Kept the local file; {} was not applied", name);
+                }
+            } else if key.kind == KeyEventKind::Press {
+                let Some(action) = app.settings.keymap.handle(key) else {
+                    // Letters and digits no binding uses jump to themes
+                    // starting with them
+                    if app.view == View::Themes && !app.settings.keymap.is_pending() {
+                        if let Some(letter) = jump_letter(key) {
+                            app.jump_to_letter(letter);
+                        }
+                    }
+                    continue;
+                };
+                if app.view == View::Help {
+                    match action {
+                        Action::Quit => return Ok(()),
+                        Action::Help | Action::ClearFilter => app.view = View::Themes,
+                        _ => {}
+                    }
+                    continue;
+                }
+                if app.view == View::Failures {
+                    match action {
+                        Action::Quit => return Ok(()),
+                        Action::ShowFailures | Action::ClearFilter => app.view = View::Themes,
+                        _ => {}
+                    }
+                    continue;
+                }
+                if app.view == View::Compare {
+                    match action {
+                        Action::Quit => return Ok(()),
+                        Action::Compare | Action::ClearFilter => app.view = View::Themes,
+                        _ => {}
+                    }
+                    continue;
+                }
+                if app.view == View::History {
+                    match action {
+                        Action::Quit => return Ok(()),
+                        Action::MoveDown => app.next(),
+                        Action::MoveUp => app.previous(),
+                        Action::First => app.first(),
+                        Action::Last => app.last(),
+                        Action::ShowHistory | Action::ClearFilter => app.toggle_history_view(),
+                        Action::Apply => {
+                            if let Some(entry) = app.selected_history_entry() {
+                                let name = entry.theme.clone();
+                                apply_theme(app, theme_data, &name, false).await;
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                match action {
+                    Action::Quit => return Ok(()),
+                    Action::MoveDown => app.next(),
+                    Action::MoveUp => app.previous(),
+                    Action::First => app.first(),
+                    Action::Last => app.last(),
+                    Action::ToggleContrastFilter => app.toggle_contrast_filter(),
+                    Action::CycleAppearance => app.cycle_appearance_filter(),
+                    Action::CopyName => app.copy_selected_name(),
+                    Action::CopyDefinition => app.copy_selected_definition(),
+                    Action::CopyRestartCommand => app.copy_restart_command(),
+                    Action::Search => app.start_filter(),
+                    Action::ClearFilter => match loading.take() {
+                        Some(mut pending) => pending.cancel(app).await,
+                        None => app.cancel_filter(),
+                    },
+                    Action::ToggleFamily => app.toggle_family(),
+                    Action::ToggleGrouping => app.toggle_grouping(),
+                    Action::ShowHistory => app.toggle_history_view(),
+                    Action::ShowFailures => app.show_failures(),
+                    Action::Help => app.view = View::Help,
+                    Action::Mark => app.toggle_mark(),
+                    Action::Tag => app.start_tagging(),
+                    Action::DeleteFile | Action::Refresh if loading.is_some() => {
+                        app.status_message = String::from("Still fetching themes; try again once it finishes");
+                    }
+                    Action::DeleteFile => start_delete(app, theme_data),
+                    Action::Refresh => {
+                        app.status_message = String::from("Refreshing themes…");
+                        loading = Some(Loading::start(theme_data, true, true));
+                    }
+                    Action::Edit => start_edit(terminal, app, theme_data)?,
+                    Action::EditColors => start_color_editor(app, false),
+                    Action::NewTheme => start_color_editor(app, true),
+                    Action::Reset => reset_theme(app, theme_data),
+                    Action::Reveal => reveal_theme_file(app, theme_data).await,
+                    Action::Undo => step_rollback(app, theme_data, true),
+                    Action::Redo => step_rollback(app, theme_data, false),
+                    Action::RefreshTheme => refetch_selected(app, theme_data).await,
+                    Action::Compare => {
+                        let current = theme_data.current_theme().ok().flatten();
+                        app.open_comparison(current);
+                    }
+                    Action::Preview => {
+                        if let Some(theme) = app.selected_theme() {
+                            let name = theme.name.clone();
+                            start_preview(app, theme_data, &name).await;
+                        }
+                    }
+                    Action::Apply => {
+                        if let Some(ListRow::Header { .. }) = app.selected_row() {
+                            app.toggle_family();
+                        } else if let Some(theme) = app.selected_theme() {
+                            let name = theme.name.clone();
+                            apply_theme(app, theme_data, &name, false).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn cancelling_a_refresh_stuck_on_the_network_returns_promptly() {
+        // Accepts connections and never answers them
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (accepted_tx, mut accepted) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+                let _ = accepted_tx.send(());
+            }
+        });
+        let dir = TempDir::new().unwrap();
+        let data = ThemeData::builder()
+            .config_path(dir.path().join("config.kdl"))
+            .api_url(format!("{}/repos/o/r/contents/themes", base))
+            .api_base(&base)
+            .request_timeout(Duration::from_secs(600))
+            .build()
+            .unwrap();
+        let mut app = App::new(vec![Theme::new("nord")], &Settings::default());
+
+        let mut loading = Loading::start(&data, true, true);
+        accepted.recv().await.unwrap();
+        assert!(!loading.poll(&mut app).await);
+        let cancelled = tokio::time::timeout(Duration::from_secs(5), loading.cancel(&mut app));
+        cancelled.await.expect("cancelling does not wait for the request to time out");
+
+        assert!(loading.fetch.is_finished());
+        assert_eq!(app.status_message, "Refresh cancelled, keeping the current list");
+        let names: Vec<&str> = app.themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["nord"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hooks_run_in_the_background_and_report_a_failure_to_the_status_line() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        // Blocks until the test lets it go, then fails
+        let dir = TempDir::new().unwrap();
+        let hook = dir.path().join("hook.sh");
+        let release = dir.path().join("release");
+        let script = format!(
+            "#!/bin/sh\nuntil [ -e {} ]; do sleep 0.05; done\necho broken >&2\nexit 3\n",
+            release.display()
+        );
+        fs::write(&hook, script).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        let data = ThemeData::builder()
+            .config_path(dir.path().join("config.kdl"))
+            .post_apply_hook(hook.display().to_string())
+            .build()
+            .unwrap();
+        let mut hooks = Hooks::new();
+
+        hooks.spawn(&data, Theme::new("nord"), String::from(" | layouts updated: a.kdl"));
+        assert!(hooks.is_running());
+        assert_eq!(hooks.poll(), None);
+
+        fs::write(&release, "").unwrap();
+        let failure = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(failure) = hooks.poll() {
+                    return failure;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        let failure = failure.await.expect("the hook reports back once it exits");
+        assert!(
+            failure.starts_with("Applied theme nord, but the post-apply hook failed: "),
+            "{}",
+            failure
+        );
+        assert!(failure.ends_with(" | layouts updated: a.kdl"), "{}", failure);
+        hooks.finish().await;
+        assert!(!hooks.is_running());
+    }
+}
@@ -0,0 +1,893 @@
+//! The selector's own preferences, kept in
+//! `$XDG_CONFIG_HOME/zellij-theme-plugin/settings.kdl`, on top of the
+//! tool's configuration in `config.toml` next to it.
+//!
+//! Every key is optional and falls back to a default, and keys this version
+//! does not know are reported as warnings rather than errors so a settings
+//! file written by a newer version still loads. Saving edits the parsed
+//! document in place, so comments and unknown keys survive. `config.toml`
+//! is only ever read.
+
+use crate::color::AA_RATIO;
+use crate::config::{write_atomically, DEFAULT_THEME_KEY};
+use crate::data::{DEFAULT_CACHE_TTL, DEFAULT_REQUEST_TIMEOUT, GITHUB_API_BASE, GITHUB_API_URL};
+use crate::keymap::Keymap;
+use crate::lock::FileLock;
+use crate::theme::Appearance;
+use chrono::NaiveTime;
+use kdl::{KdlDocument, KdlNode, KdlValue};
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const KNOWN_KEYS: &[&str] = &[
+    "zellij_config_dir",
+    "api_url",
+    "api_base",
+    "default_theme",
+    "cache_ttl",
+    "request_timeout",
+    "min_contrast",
+    "hide_low_contrast",
+    "appearance",
+    "group_families",
+    "post_apply_hook",
+    "update_layouts",
+    "highlight_color",
+    "color_names",
+    "light_theme",
+    "dark_theme",
+    "day_theme",
+    "night_theme",
+    "day_start",
+    "night_start",
+    "latitude",
+    "longitude",
+    "keys",
+];
+
+/// Environment variables that override a setting for one run, e.g.
+/// `ZELLIJ_THEME_CACHE_TTL=0` to skip the cache without editing the file.
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("ZELLIJ_THEME_API_URL", "api_url"),
+    ("ZELLIJ_THEME_API_BASE", "api_base"),
+    ("ZELLIJ_THEME_CACHE_TTL", "cache_ttl"),
+    ("ZELLIJ_THEME_REQUEST_TIMEOUT", "request_timeout"),
+    ("ZELLIJ_THEME_POST_HOOK", "post_apply_hook"),
+    ("ZELLIJ_THEME_HIGHLIGHT_COLOR", "highlight_color"),
+];
+
+/// The file next to `settings.kdl` that [`TomlSettings`] are read from.
+const TOML_FILE: &str = "config.toml";
+
+/// The tool's configuration in `config.toml`: where themes come from and
+/// how they are fetched. It gives the defaults that `settings.kdl`, the
+/// environment and command line flags each override in turn.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TomlSettings {
+    pub zellij_config_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub api_url: Option<String>,
+    pub api_base: Option<String>,
+    pub default_theme: Option<String>,
+    /// Seconds, like `request_timeout`.
+    pub cache_ttl: Option<u64>,
+    pub request_timeout: Option<u64>,
+    pub github_token: Option<String>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub git_ref: Option<String>,
+    pub post_apply_hook: Option<String>,
+    /// Keys this version does not know, reported as warnings.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl TomlSettings {
+    /// Reads the file at `path`. A missing file gives no settings; one that
+    /// cannot be read or parsed gives none and a warning.
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(e) => (
+                Self::default(),
+                vec![format!("could not read {}: {}", path.display(), e)],
+            ),
+        }
+    }
+
+    /// Parses TOML text. Any value of the wrong type makes the whole file
+    /// unusable, as serde reports only the first problem.
+    pub fn parse(content: &str) -> (Self, Vec<String>) {
+        match toml::from_str::<Self>(content) {
+            Ok(file) => {
+                let warnings = file
+                    .unknown
+                    .keys()
+                    .map(|key| format!("unknown setting '{}' in {} ignored", key, TOML_FILE))
+                    .collect();
+                (file, warnings)
+            }
+            Err(e) => (
+                Self::default(),
+                vec![format!("{} is not valid, ignoring it: {}", TOML_FILE, e.message())],
+            ),
+        }
+    }
+
+    /// Sets what the file gives on `settings`, checking each value as
+    /// `settings.kdl` and the command line do.
+    fn apply(&self, settings: &mut Settings, warnings: &mut Vec<String>) {
+        let mut invalid = |key: &str| {
+            warnings.push(format!(
+                "invalid value for '{}' in {}, using the default",
+                key, TOML_FILE
+            ));
+        };
+        let text = |value: &Option<String>| {
+            value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(String::from)
+        };
+        if let Some(dir) = &self.zellij_config_dir {
+            settings.zellij_config_dir = Some(dir.clone());
+        }
+        if let Some(dir) = &self.cache_dir {
+            settings.cache_dir = Some(dir.clone());
+        }
+        if let Some(url) = text(&self.api_url) {
+            settings.api_url = url;
+        }
+        if let Some(base) = &self.api_base {
+            match parse_api_base(base) {
+                Some(base) => settings.api_base = base,
+                None => invalid("api_base"),
+            }
+        }
+        if let Some(name) = text(&self.default_theme) {
+            settings.default_theme = Some(name);
+        }
+        if let Some(secs) = self.cache_ttl {
+            settings.cache_ttl = Duration::from_secs(secs);
+        }
+        match self.request_timeout {
+            Some(0) => invalid("request_timeout"),
+            Some(secs) => settings.request_timeout = Duration::from_secs(secs),
+            None => {}
+        }
+        settings.github_token = text(&self.github_token);
+        if let Some(proxy) = text(&self.proxy) {
+            match reqwest::Proxy::all(&proxy) {
+                Ok(_) => settings.proxy = Some(proxy),
+                Err(_) => invalid("proxy"),
+            }
+        }
+        settings.ca_bundle = self.ca_bundle.clone();
+        settings.git_ref = text(&self.git_ref);
+        settings.post_apply_hook = text(&self.post_apply_hook);
+    }
+}
+
+/// Which themes the light/dark filter lets through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AppearanceFilter {
+    #[default]
+    All,
+    Dark,
+    Light,
+}
+
+impl AppearanceFilter {
+    pub fn cycle(self) -> Self {
+        match self {
+            AppearanceFilter::All => AppearanceFilter::Dark,
+            AppearanceFilter::Dark => AppearanceFilter::Light,
+            AppearanceFilter::Light => AppearanceFilter::All,
+        }
+    }
+
+    /// Themes whose background could not be classified pass either way,
+    /// since hiding them would make them unreachable.
+    pub fn allows(self, appearance: Appearance) -> bool {
+        match (self, appearance) {
+            (AppearanceFilter::All, _) | (_, Appearance::Unknown) => true,
+            (AppearanceFilter::Dark, appearance) => appearance == Appearance::Dark,
+            (AppearanceFilter::Light, appearance) => appearance == Appearance::Light,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AppearanceFilter::All => "all",
+            AppearanceFilter::Dark => "dark",
+            AppearanceFilter::Light => "light",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "all" => Some(AppearanceFilter::All),
+            "dark" => Some(AppearanceFilter::Dark),
+            "light" => Some(AppearanceFilter::Light),
+            _ => None,
+        }
+    }
+}
+
+/// How the zellij config directory was chosen, in order of precedence.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigDirOrigin {
+    /// The `ZELLIJ_CONFIG_DIR` environment variable.
+    Env,
+    /// `zellij_config_dir` in the settings file.
+    Settings,
+    /// `zellij_config_dir` in `config.toml`.
+    Toml,
+    /// `$XDG_CONFIG_HOME/zellij`.
+    Xdg,
+    /// `~/.config/zellij`.
+    #[default]
+    Home,
+    /// The directory of the first `--config` file.
+    Cli,
+}
+
+impl ConfigDirOrigin {
+    pub fn label(self) -> &'static str {
+        match self {
+            ConfigDirOrigin::Env => "ZELLIJ_CONFIG_DIR",
+            ConfigDirOrigin::Settings => "settings.kdl",
+            ConfigDirOrigin::Toml => TOML_FILE,
+            ConfigDirOrigin::Xdg => "XDG_CONFIG_HOME",
+            ConfigDirOrigin::Home => "HOME default",
+            ConfigDirOrigin::Cli => "--config",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Directory holding zellij's `config.kdl`, `themes/` and the theme
+    /// cache. `ZELLIJ_CONFIG_DIR` overrides the file; `None` when neither is
+    /// set and there is no home directory to fall back to.
+    pub zellij_config_dir: Option<PathBuf>,
+    pub zellij_config_dir_origin: ConfigDirOrigin,
+    /// Directory for the theme cache: `ZELLIJ_THEME_CACHE_DIR`, else
+    /// `cache_dir` in `config.toml`, else `zellij-theme-plugin` under
+    /// `$XDG_CACHE_HOME` or `~/.cache`. `None` keeps the cache in
+    /// `zellij_config_dir` as older versions did. Never read from or saved
+    /// to the settings file.
+    pub cache_dir: Option<PathBuf>,
+    /// Config files given with `--config`, all of which applying a theme
+    /// writes; empty for `config.kdl` in `zellij_config_dir`. Never read
+    /// from or saved to the settings file.
+    pub config_files: Vec<PathBuf>,
+    /// Sent with GitHub API requests to raise the hourly rate limit, from
+    /// `GITHUB_TOKEN` or `config.toml`. Never read from or saved to the
+    /// settings file.
+    pub github_token: Option<String>,
+    /// The proxy every request goes through instead of the ones in
+    /// `HTTPS_PROXY` and friends, from `--proxy` or `config.toml`. Never
+    /// read from or saved to the settings file.
+    pub proxy: Option<String>,
+    /// A PEM file of certificates to trust besides the system's, from
+    /// `ZELLIJ_THEME_CA_BUNDLE` or `config.toml`. Never read from or saved
+    /// to the settings file.
+    pub ca_bundle: Option<PathBuf>,
+    /// The branch, tag or commit to list themes at, from `--ref` or
+    /// `config.toml`; `None` uses the installed zellij's release tag. Never
+    /// read from or saved to the settings file.
+    pub git_ref: Option<String>,
+    /// The config node that names the active theme, from `--key`; `theme`
+    /// unless the config uses another one. Never read from or saved to the
+    /// settings file.
+    pub theme_key: String,
+    /// GitHub contents API listing the upstream theme files.
+    pub api_url: String,
+    /// The GitHub API host, for a GitHub Enterprise instance such as
+    /// `https://ghe.example.com/api/v3`. It takes the place of
+    /// `https://api.github.com` in `api_url`, and `GITHUB_TOKEN` is sent
+    /// to it.
+    pub api_base: String,
+    /// The theme listed even when no file defines it; `None` for zellij's
+    /// built-in `default`, which a custom `api_url` leaves out.
+    pub default_theme: Option<String>,
+    /// Whether to list `default_theme` at all; off with `default_theme
+    /// false` or `--no-default-theme`.
+    pub list_default_theme: bool,
+    /// How long the downloaded theme list is reused before refetching.
+    pub cache_ttl: Duration,
+    /// How long a single download may take before it is abandoned.
+    pub request_timeout: Duration,
+    /// Minimum fg/bg contrast ratio used by the low-contrast filter.
+    pub min_contrast: f64,
+    pub hide_low_contrast: bool,
+    pub appearance_filter: AppearanceFilter,
+    /// Group themes under collapsible family headers instead of a flat list.
+    pub group_families: bool,
+    /// Command run with the theme name after a theme is applied.
+    pub post_apply_hook: Option<String>,
+    /// `hooks.d` next to the settings file, whose executables run after the
+    /// post-apply hook; `None` with `--no-hooks`. Never read from or saved
+    /// to the settings file.
+    pub hooks_dir: Option<PathBuf>,
+    /// The themes `watch-appearance` applies when the desktop turns light
+    /// or dark.
+    pub light_theme: Option<String>,
+    pub dark_theme: Option<String>,
+    /// The themes `switch-scheduled` applies by day and by night.
+    pub day_theme: Option<String>,
+    pub night_theme: Option<String>,
+    /// When day and night begin for `switch-scheduled`, unless `latitude`
+    /// and `longitude` are both set to follow sunrise and sunset instead.
+    pub day_start: NaiveTime,
+    pub night_start: NaiveTime,
+    /// Degrees north and east; south and west are negative.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Also rewrite `theme` nodes in the layout files under
+    /// `layouts/`, which would otherwise override the config.
+    pub update_layouts: bool,
+    /// Background of the selected row in the selector's own lists; not
+    /// related to the theme being applied.
+    pub highlight_color: Color,
+    /// Follow hex values in the details pane and `preview` with the nearest
+    /// CSS color name.
+    pub color_names: bool,
+    /// Key bindings, from the defaults plus the `keys` block.
+    pub keymap: Keymap,
+    path: Option<PathBuf>,
+    doc: KdlDocument,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            zellij_config_dir: None,
+            zellij_config_dir_origin: ConfigDirOrigin::Home,
+            cache_dir: None,
+            config_files: Vec::new(),
+            github_token: None,
+            proxy: None,
+            ca_bundle: None,
+            git_ref: None,
+            theme_key: DEFAULT_THEME_KEY.to_string(),
+            api_url: GITHUB_API_URL.to_string(),
+            api_base: GITHUB_API_BASE.to_string(),
+            default_theme: None,
+            list_default_theme: true,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            min_contrast: AA_RATIO,
+            hide_low_contrast: false,
+            appearance_filter: AppearanceFilter::All,
+            group_families: true,
+            post_apply_hook: None,
+            hooks_dir: None,
+            light_theme: None,
+            dark_theme: None,
+            day_theme: None,
+            night_theme: None,
+            day_start: NaiveTime::from_hms_opt(7, 0, 0).expect("valid time"),
+            night_start: NaiveTime::from_hms_opt(19, 0, 0).expect("valid time"),
+            latitude: None,
+            longitude: None,
+            update_layouts: false,
+            highlight_color: Color::Green,
+            color_names: true,
+            keymap: Keymap::default(),
+            path: None,
+            doc: KdlDocument::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads `config.toml`, then the settings file over it, and applies
+    /// environment overrides, which win over both files just as command
+    /// line flags win over everything. Missing files give the defaults;
+    /// anything that could not be used is returned as a warning alongside
+    /// the settings.
+    pub fn load() -> (Self, Vec<String>) {
+        let path = Self::default_path();
+        let dir = path.as_deref().and_then(Path::parent);
+        let (toml, mut warnings) = match dir {
+            Some(dir) => TomlSettings::load(&dir.join(TOML_FILE)),
+            None => (TomlSettings::default(), Vec::new()),
+        };
+        let mut settings = Self::default();
+        toml.apply(&mut settings, &mut warnings);
+        if let Some(path) = &path {
+            match fs::read_to_string(path) {
+                Ok(content) => {
+                    let (parsed, parse_warnings) = Self::parse_over(settings, &content);
+                    settings = parsed;
+                    warnings.extend(parse_warnings);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warnings.push(format!("could not read {}: {}", path.display(), e)),
+            }
+        }
+        settings.hooks_dir = dir.map(|dir| dir.join("hooks.d"));
+        settings.path = path;
+
+        if let Some(dir) = env::var_os("ZELLIJ_CONFIG_DIR") {
+            settings.zellij_config_dir = Some(PathBuf::from(dir));
+            settings.zellij_config_dir_origin = ConfigDirOrigin::Env;
+        } else if settings.zellij_config_dir.is_some() {
+            let from_toml = settings.zellij_config_dir == toml.zellij_config_dir;
+            settings.zellij_config_dir_origin = if from_toml {
+                ConfigDirOrigin::Toml
+            } else {
+                ConfigDirOrigin::Settings
+            };
+        } else if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            settings.zellij_config_dir = Some(PathBuf::from(dir).join("zellij"));
+            settings.zellij_config_dir_origin = ConfigDirOrigin::Xdg;
+        } else {
+            settings.zellij_config_dir =
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/zellij"));
+            settings.zellij_config_dir_origin = ConfigDirOrigin::Home;
+        }
+        settings.cache_dir = env::var_os("ZELLIJ_THEME_CACHE_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| settings.cache_dir.take())
+            .or_else(|| {
+                let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+                    Some(dir) => PathBuf::from(dir),
+                    None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+                };
+                Some(cache_home.join("zellij-theme-plugin"))
+            });
+        settings.apply_env_overrides(&mut warnings);
+        if let Some(path) = settings.api_url.strip_prefix(GITHUB_API_BASE) {
+            settings.api_url = format!("{}{}", settings.api_base, path);
+        }
+        settings.github_token = env::var("GITHUB_TOKEN")
+            .ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty())
+            .or_else(|| settings.github_token.take());
+        settings.ca_bundle = env::var_os("ZELLIJ_THEME_CA_BUNDLE")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| settings.ca_bundle.take());
+        (settings, warnings)
+    }
+
+    /// Points the selector at the `config.kdl` files named on the command
+    /// line. The first one takes the place of the configured directory, so
+    /// its `themes/` and history are used; the others only get the
+    /// `theme` setting written alongside it.
+    pub fn use_config_files(&mut self, files: Vec<PathBuf>) {
+        let Some(first) = files.first() else {
+            return;
+        };
+        let dir = first.parent().filter(|dir| !dir.as_os_str().is_empty());
+        self.zellij_config_dir = Some(dir.map_or_else(|| PathBuf::from("."), Path::to_path_buf));
+        self.zellij_config_dir_origin = ConfigDirOrigin::Cli;
+        self.config_files = files;
+    }
+
+    /// Replaces file settings with the values of any `ENV_OVERRIDES` that
+    /// are set. Overridden values are never written back by `save`.
+    fn apply_env_overrides(&mut self, warnings: &mut Vec<String>) {
+        for (var, key) in ENV_OVERRIDES {
+            let Ok(value) = env::var(var) else {
+                continue;
+            };
+            let seconds = value.trim().parse::<u64>().ok().map(Duration::from_secs);
+            let applied = match (*key, seconds) {
+                ("api_url", _) if !value.is_empty() => {
+                    self.api_url = value.clone();
+                    true
+                }
+                ("api_base", _) => match parse_api_base(&value) {
+                    Some(base) => {
+                        self.api_base = base;
+                        true
+                    }
+                    None => false,
+                },
+                ("cache_ttl", Some(ttl)) => {
+                    self.cache_ttl = ttl;
+                    true
+                }
+                ("request_timeout", Some(timeout)) if !timeout.is_zero() => {
+                    self.request_timeout = timeout;
+                    true
+                }
+                ("highlight_color", _) => match value.trim().parse::<Color>() {
+                    Ok(color) => {
+                        self.highlight_color = color;
+                        true
+                    }
+                    Err(_) => false,
+                },
+                // Set but empty turns off a hook from the file for this run
+                ("post_apply_hook", _) => {
+                    let hook = value.trim();
+                    self.post_apply_hook = (!hook.is_empty()).then(|| hook.to_string());
+                    true
+                }
+                _ => false,
+            };
+            if !applied {
+                warnings.push(format!("invalid value '{}' in {}, ignoring it", value, var));
+            }
+        }
+    }
+
+    /// Parses settings from KDL text, falling back to the default for any
+    /// key that is missing or has a value of the wrong type.
+    pub fn parse(content: &str) -> (Self, Vec<String>) {
+        Self::parse_over(Self::default(), content)
+    }
+
+    /// Like [`Settings::parse`], but keys the text leaves out keep their
+    /// value in `settings`.
+    fn parse_over(mut settings: Self, content: &str) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let doc = match content.parse::<KdlDocument>() {
+            Ok(doc) => doc,
+            Err(e) => {
+                warnings.push(format!("settings file is not valid KDL, using defaults: {}", e));
+                return (settings, warnings);
+            }
+        };
+
+        for node in doc.nodes() {
+            let key = node.name().value();
+            if !KNOWN_KEYS.contains(&key) {
+                warnings.push(format!("unknown setting '{}' ignored", key));
+                continue;
+            }
+            if key == "keys" {
+                let (keymap, keymap_warnings) = Keymap::from_node(node, content);
+                settings.keymap = keymap;
+                warnings.extend(keymap_warnings);
+                continue;
+            }
+            let value = node.get(0).map(|entry| entry.value());
+            let applied = match (key, value) {
+                ("zellij_config_dir", Some(KdlValue::String(dir))) => {
+                    settings.zellij_config_dir = Some(PathBuf::from(dir));
+                    true
+                }
+                ("api_url", Some(KdlValue::String(url))) => {
+                    settings.api_url = url.clone();
+                    true
+                }
+                ("api_base", Some(KdlValue::String(url))) => match parse_api_base(url) {
+                    Some(base) => {
+                        settings.api_base = base;
+                        true
+                    }
+                    None => false,
+                },
+                ("default_theme", Some(KdlValue::String(name))) if !name.is_empty() => {
+                    settings.default_theme = Some(name.clone());
+                    true
+                }
+                ("default_theme", Some(KdlValue::Bool(false))) => {
+                    settings.list_default_theme = false;
+                    true
+                }
+                ("cache_ttl", Some(KdlValue::Base10(secs))) if *secs >= 0 => {
+                    settings.cache_ttl = Duration::from_secs(*secs as u64);
+                    true
+                }
+                ("request_timeout", Some(KdlValue::Base10(secs))) if *secs > 0 => {
+                    settings.request_timeout = Duration::from_secs(*secs as u64);
+                    true
+                }
+                ("min_contrast", Some(value)) => match number(value) {
+                    Some(ratio) if ratio >= 1.0 => {
+                        settings.min_contrast = ratio;
+                        true
+                    }
+                    _ => false,
+                },
+                ("hide_low_contrast", Some(KdlValue::Bool(hide))) => {
+                    settings.hide_low_contrast = *hide;
+                    true
+                }
+                ("group_families", Some(KdlValue::Bool(group))) => {
+                    settings.group_families = *group;
+                    true
+                }
+                ("highlight_color", Some(KdlValue::String(color))) => {
+                    match color.trim().parse::<Color>() {
+                        Ok(color) => {
+                            settings.highlight_color = color;
+                            true
+                        }
+                        Err(_) => false,
+                    }
+                }
+                ("highlight_color", Some(KdlValue::Base10(index))) => match u8::try_from(*index) {
+                    Ok(index) => {
+                        settings.highlight_color = Color::Indexed(index);
+                        true
+                    }
+                    Err(_) => false,
+                },
+                ("color_names", Some(KdlValue::Bool(show))) => {
+                    settings.color_names = *show;
+                    true
+                }
+                ("update_layouts", Some(KdlValue::Bool(update))) => {
+                    settings.update_layouts = *update;
+                    true
+                }
+                ("post_apply_hook", Some(KdlValue::String(hook))) if !hook.trim().is_empty() => {
+                    settings.post_apply_hook = Some(hook.trim().to_string());
+                    true
+                }
+                ("light_theme", Some(KdlValue::String(name))) if !name.is_empty() => {
+                    settings.light_theme = Some(name.clone());
+                    true
+                }
+                ("dark_theme", Some(KdlValue::String(name))) if !name.is_empty() => {
+                    settings.dark_theme = Some(name.clone());
+                    true
+                }
+                ("day_theme", Some(KdlValue::String(name))) if !name.is_empty() => {
+                    settings.day_theme = Some(name.clone());
+                    true
+                }
+                ("night_theme", Some(KdlValue::String(name))) if !name.is_empty() => {
+                    settings.night_theme = Some(name.clone());
+                    true
+                }
+                ("day_start", Some(KdlValue::String(time))) => match parse_time(time) {
+                    Some(time) => {
+                        settings.day_start = time;
+                        true
+                    }
+                    None => false,
+                },
+                ("night_start", Some(KdlValue::String(time))) => match parse_time(time) {
+                    Some(time) => {
+                        settings.night_start = time;
+                        true
+                    }
+                    None => false,
+                },
+                ("latitude", Some(value)) => match number(value) {
+                    Some(degrees) if (-90.0..=90.0).contains(&degrees) => {
+                        settings.latitude = Some(degrees);
+                        true
+                    }
+                    _ => false,
+                },
+                ("longitude", Some(value)) => match number(value) {
+                    Some(degrees) if (-180.0..=180.0).contains(&degrees) => {
+                        settings.longitude = Some(degrees);
+                        true
+                    }
+                    _ => false,
+                },
+                ("appearance", Some(KdlValue::String(filter))) => {
+                    match AppearanceFilter::parse(filter) {
+                        Some(filter) => {
+                            settings.appearance_filter = filter;
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                _ => false,
+            };
+            if !applied {
+                warnings.push(format!("invalid value for setting '{}', using the default", key));
+            }
+        }
+        settings.doc = doc;
+        (settings, warnings)
+    }
+
+    /// `$XDG_CONFIG_HOME/zellij-theme-plugin/settings.kdl`, or the same
+    /// under `~/.config` when `XDG_CONFIG_HOME` is unset.
+    fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("zellij-theme-plugin").join("settings.kdl"))
+    }
+
+    /// Where the settings are saved, if a location could be determined.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the toggles that can be changed from the TUI back to the
+    /// settings file, leaving every other line as it was. The file is read
+    /// again under its lock, so what another instance saved meanwhile is
+    /// kept, and a file that is no longer valid KDL is left alone.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self.path.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no location for the settings file")
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let _lock = FileLock::acquire(&path)?;
+        let mut doc = match fs::read_to_string(&path) {
+            Ok(content) => content.parse::<KdlDocument>().map_err(|e| {
+                let message = format!("{} is not valid KDL: {}", path.display(), e);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => KdlDocument::new(),
+            Err(e) => return Err(e),
+        };
+        set_value(&mut doc, "hide_low_contrast", self.hide_low_contrast.into());
+        set_value(&mut doc, "appearance", self.appearance_filter.as_str().into());
+        set_value(&mut doc, "group_families", self.group_families.into());
+        write_atomically(&path, doc.to_string().as_bytes())?;
+        self.doc = doc;
+        Ok(())
+    }
+}
+
+/// Checks an `api_base` URL, dropping a trailing `/`. It has to use
+/// https, since the token is sent to it; plain http is only accepted for
+/// this machine, e.g. a test server.
+fn parse_api_base(value: &str) -> Option<String> {
+    let base = value.trim().trim_end_matches('/');
+    let url = reqwest::Url::parse(base).ok()?;
+    let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    let secure = url.scheme() == "https" || (url.scheme() == "http" && local);
+    (secure && url.has_host() && url.query().is_none()).then(|| base.to_string())
+}
+
+/// A time of day like `7:30` or `19:00`.
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M").ok()
+}
+
+fn number(value: &KdlValue) -> Option<f64> {
+    match value {
+        KdlValue::Base10Float(n) => Some(*n),
+        KdlValue::Base10(n) => Some(*n as f64),
+        _ => None,
+    }
+}
+
+/// Replaces the argument of the top-level `key` node, appending the node
+/// on its own line if the document does not have one yet.
+fn set_value(doc: &mut KdlDocument, key: &str, value: KdlValue) {
+    if let Some(node) = doc.get_mut(key) {
+        node.clear_entries();
+        node.push(value);
+        return;
+    }
+    if let Some(last) = doc.nodes_mut().last_mut() {
+        let trailing = last.trailing().unwrap_or_default();
+        if !trailing.ends_with('\n') {
+            last.set_trailing(format!("{}\n", trailing));
+        }
+    }
+    let mut node = KdlNode::new(key);
+    node.push(value);
+    node.set_trailing("\n");
+    doc.nodes_mut().push(node);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Settings loaded from `content`, saved to `settings.kdl` in `dir`.
+    fn loaded(dir: &TempDir, content: &str) -> Settings {
+        let path = dir.path().join("settings.kdl");
+        fs::write(&path, content).unwrap();
+        let (mut settings, _) = Settings::parse(content);
+        settings.path = Some(path);
+        settings
+    }
+
+    #[test]
+    fn save_keeps_what_another_instance_wrote_meanwhile() {
+        let dir = TempDir::new().unwrap();
+        let mut settings = loaded(&dir, "// mine\nhide_low_contrast false\n");
+        let path = dir.path().join("settings.kdl");
+        fs::write(&path, "// mine\nhide_low_contrast false\nmin_contrast 7.0\n").unwrap();
+
+        settings.hide_low_contrast = true;
+        settings.save().unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("// mine\nhide_low_contrast true\nmin_contrast 7.0\n"));
+        assert!(saved.contains("group_families"));
+    }
+
+    #[test]
+    fn save_leaves_a_file_that_no_longer_parses_alone() {
+        let dir = TempDir::new().unwrap();
+        let mut settings = loaded(&dir, "hide_low_contrast false\n");
+        let path = dir.path().join("settings.kdl");
+        fs::write(&path, "hide_low_contrast {\n").unwrap();
+
+        let error = settings.save().unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hide_low_contrast {\n");
+    }
+
+    #[test]
+    fn config_toml_is_applied_and_unknown_keys_are_warned_about() {
+        let (toml, warnings) = TomlSettings::parse(concat!(
+            "api_url = \"https://example.com/themes\"\n",
+            "cache_ttl = 60\n",
+            "github_token = \"  secret \"\n",
+            "git_ref = \"main\"\n",
+            "offline = true\n",
+        ));
+
+        assert_eq!(toml.api_url.as_deref(), Some("https://example.com/themes"));
+        assert_eq!(toml.cache_ttl, Some(60));
+        assert_eq!(warnings, ["unknown setting 'offline' in config.toml ignored"]);
+        let mut settings = Settings::default();
+        let mut warnings = Vec::new();
+        toml.apply(&mut settings, &mut warnings);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(settings.cache_ttl, Duration::from_secs(60));
+        assert_eq!(settings.github_token.as_deref(), Some("secret"));
+        assert_eq!(settings.git_ref.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn config_toml_with_a_value_of_the_wrong_type_is_ignored() {
+        let (toml, warnings) = TomlSettings::parse("cache_ttl = \"an hour\"\n");
+
+        assert_eq!(toml, TomlSettings::default());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("config.toml is not valid"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn invalid_values_in_config_toml_keep_the_defaults() {
+        let (toml, _) = TomlSettings::parse(concat!(
+            "api_base = \"http://ghe.example.com\"\n",
+            "request_timeout = 0\n",
+            "proxy = \"not a proxy\"\n",
+        ));
+        let mut settings = Settings::default();
+        let mut warnings = Vec::new();
+        toml.apply(&mut settings, &mut warnings);
+
+        assert_eq!(settings.api_base, GITHUB_API_BASE);
+        assert_eq!(settings.request_timeout, DEFAULT_REQUEST_TIMEOUT);
+        assert_eq!(settings.proxy, None);
+        assert_eq!(
+            warnings,
+            [
+                "invalid value for 'api_base' in config.toml, using the default",
+                "invalid value for 'request_timeout' in config.toml, using the default",
+                "invalid value for 'proxy' in config.toml, using the default",
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_kdl_overrides_config_toml_key_by_key() {
+        let (toml, _) = TomlSettings::parse("cache_ttl = 60\nrequest_timeout = 5\n");
+        let mut settings = Settings::default();
+        toml.apply(&mut settings, &mut Vec::new());
+
+        let (settings, warnings) = Settings::parse_over(settings, "cache_ttl 120\n");
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(settings.cache_ttl, Duration::from_secs(120));
+        assert_eq!(settings.request_timeout, Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,69 @@
+//! What the selector looked like when it was last closed, so the next run
+//! can pick up there: the highlighted theme, recent filter queries and the
+//! opened families.
+//!
+//! This is machine-local ephemera rather than preferences, so it lives in
+//! `$XDG_STATE_HOME/zellij-theme-plugin/state.json` instead of the settings
+//! file. A missing or unreadable state file just means a fresh start.
+
+use crate::config::write_atomically;
+use crate::lock::FileLock;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    /// The highlighted theme, or family header, by name.
+    pub selected: Option<String>,
+    /// Recent filter queries, most recent first.
+    pub search_history: Vec<String>,
+    /// Families whose headers were open.
+    pub expanded: Vec<String>,
+}
+
+/// `$XDG_STATE_HOME/zellij-theme-plugin`, or the same under
+/// `~/.local/state`; `None` when neither `XDG_STATE_HOME` nor `HOME` is
+/// set.
+pub fn dir() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state_home.join("zellij-theme-plugin"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(dir()?.join("state.json"))
+}
+
+/// Reads the state at `path`, falling back to a fresh start when it is
+/// missing or does not parse.
+pub fn load(path: &Path) -> UiState {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::debug!("ignoring the state in {}: {}", path.display(), e);
+            }
+            return UiState::default();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        log::debug!("ignoring the state in {}: {}", path.display(), e);
+        UiState::default()
+    })
+}
+
+/// Replaces the state file with `state`. Two selectors closing at once each
+/// write a whole file, so the last one wins instead of the two mixing.
+pub fn save(path: &Path, state: &UiState) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _lock = FileLock::acquire(path)?;
+    write_atomically(path, serde_json::to_string_pretty(state)?.as_bytes())
+}
@@ -0,0 +1,87 @@
+//! `preview`: prints a theme's colors straight to the terminal, as
+//! labelled swatches and a line of sample text, without the TUI.
+
+use crate::color::Rgb;
+use crate::color_names;
+use crate::theme::ThemeColors;
+use std::env;
+use std::io::{self, IsTerminal};
+
+const SAMPLE: &str = " sample text ";
+
+/// How colors can be shown on standard output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit escapes, when `COLORTERM` says the terminal handles them.
+    TrueColor,
+    /// The nearest xterm 256-color palette entries.
+    Ansi256,
+    /// Not a terminal: no escapes at all, only hex values.
+    Plain,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        if !io::stdout().is_terminal() {
+            return ColorDepth::Plain;
+        }
+        match env::var("COLORTERM") {
+            Ok(value) if value == "truecolor" || value == "24bit" => ColorDepth::TrueColor,
+            _ => ColorDepth::Ansi256,
+        }
+    }
+
+    /// The SGR parameters selecting `rgb` as foreground (`38`) or
+    /// background (`48`).
+    fn sgr(self, layer: u8, rgb: Rgb) -> String {
+        match self {
+            ColorDepth::TrueColor => format!("{};2;{};{};{}", layer, rgb.r, rgb.g, rgb.b),
+            ColorDepth::Ansi256 | ColorDepth::Plain => {
+                format!("{};5;{}", layer, rgb.to_ansi256())
+            }
+        }
+    }
+}
+
+/// Renders `colors` as one line per color role followed by the sample
+/// text in the theme's foreground on its background. With `names`, each
+/// hex value is followed by the nearest named color.
+pub fn render(name: &str, colors: &ThemeColors, depth: ColorDepth, names: bool) -> String {
+    let mut roles = vec![("fg", colors.fg), ("bg", colors.bg)];
+    roles.extend(colors.ansi());
+    roles.push(("selection_fg", colors.selection_fg));
+    roles.push(("selection_bg", colors.selection_bg));
+
+    let mut out = format!("{}\n", name);
+    for (role, color) in roles {
+        let Some(rgb) = color else {
+            continue;
+        };
+        let cell = match depth {
+            ColorDepth::Plain => String::new(),
+            _ => format!("\x1b[{}m      \x1b[0m ", depth.sgr(48, rgb)),
+        };
+        let nearest = if names {
+            format!("  {}", color_names::nearest(rgb))
+        } else {
+            String::new()
+        };
+        out.push_str(&format!("  {:<13}{}{}{}\n", role, cell, rgb.to_hex(), nearest));
+    }
+
+    match (colors.fg, colors.bg, depth) {
+        (Some(fg), Some(bg), ColorDepth::Plain) => out.push_str(&format!(
+            "\n  sample text: {} on {}\n",
+            fg.to_hex(),
+            bg.to_hex()
+        )),
+        (Some(fg), Some(bg), _) => out.push_str(&format!(
+            "\n  \x1b[{};{}m{}\x1b[0m\n",
+            depth.sgr(38, fg),
+            depth.sgr(48, bg),
+            SAMPLE
+        )),
+        _ => {}
+    }
+    out
+}
@@ -0,0 +1,161 @@
+//! The user's own labels on themes, like `work` or `high-contrast`.
+//!
+//! Tags are keyed by theme name, so they survive refreshes of the list. A
+//! theme that disappears keeps its tags, as it may be back with the next
+//! fetch; the `tags` command shows such orphaned entries and drops them
+//! with `--prune`.
+//!
+//! A theme source can tag its themes too, with a tag manifest next to the
+//! theme files. Those tags come with the themes, in
+//! [`Theme::source_tags`], and the filter matches both kinds.
+
+use crate::theme::Theme;
+use kdl::{KdlDocument, KdlValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The tags' name, next to the history in the zellij config directory.
+pub const TAGS_FILE: &str = ".theme_tags.json";
+/// The names a source's tag manifest may have in the listing of its theme
+/// files, in the order they are looked for.
+pub const TAG_MANIFESTS: &[&str] = &["tags.json", "tags.kdl"];
+
+/// Every tagged theme and its tags, both sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tags(BTreeMap<String, BTreeSet<String>>);
+
+impl Tags {
+    /// The tags of `theme`, which may have none.
+    pub fn of(&self, theme: &str) -> impl Iterator<Item = &str> {
+        self.0.get(theme).into_iter().flatten().map(String::as_str)
+    }
+
+    /// The user's tags of `theme` together with those of its source,
+    /// sorted and without duplicates.
+    pub fn with_source_tags<'a>(&'a self, theme: &'a Theme) -> BTreeSet<&'a str> {
+        self.of(&theme.name).chain(theme.source_tags.iter().map(String::as_str)).collect()
+    }
+
+    /// Whether `theme` carries every tag in `wanted`, given by the user or
+    /// by its source.
+    pub fn has_all(&self, theme: &Theme, wanted: &[String]) -> bool {
+        let own = self.0.get(&theme.name);
+        let has = |tag: &String| {
+            own.is_some_and(|tags| tags.contains(tag)) || theme.source_tags.contains(tag)
+        };
+        wanted.iter().all(has)
+    }
+
+    /// Replaces the tags of `theme`; an empty set removes its entry.
+    pub fn set(&mut self, theme: &str, tags: BTreeSet<String>) {
+        if tags.is_empty() {
+            self.0.remove(theme);
+        } else {
+            self.0.insert(theme.to_string(), tags);
+        }
+    }
+
+    /// Tagged themes and their tags, by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BTreeSet<String>)> {
+        self.0.iter().map(|(theme, tags)| (theme.as_str(), tags))
+    }
+
+    /// The tagged themes `is_known` does not recognize.
+    pub fn orphaned(&self, is_known: impl Fn(&str) -> bool) -> Vec<&str> {
+        self.0.keys().map(String::as_str).filter(|theme| !is_known(theme)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Reads the tags at `path`. A missing file means nothing is tagged.
+pub fn load(path: &Path) -> io::Result<Tags> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Tags::default()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn save(path: &Path, tags: &Tags) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(tags)?)
+}
+
+/// The tags typed into the tag prompt: words separated by spaces or
+/// commas, lowercased, with a leading `#` allowed.
+pub fn parse(input: &str) -> BTreeSet<String> {
+    input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_start_matches('#').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Parses a source's tag manifest, `file` being one of [`TAG_MANIFESTS`]:
+/// a JSON object from theme names to lists of tags, or a KDL document with
+/// a node per theme whose arguments are its tags:
+///
+/// ```kdl
+/// nord "pastel" "cool"
+/// "gruvbox-dark" "warm"
+/// ```
+///
+/// Tags are lowercased like typed ones, and returned sorted.
+pub fn parse_manifest(file: &str, content: &str) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let manifest: BTreeMap<String, Vec<String>> = if file.ends_with(".json") {
+        serde_json::from_str(content).map_err(|e| e.to_string())?
+    } else {
+        let doc: KdlDocument = content.parse().map_err(|e: kdl::KdlError| e.to_string())?;
+        doc.nodes()
+            .iter()
+            .map(|node| {
+                let tags = node
+                    .entries()
+                    .iter()
+                    .filter(|entry| entry.name().is_none())
+                    .filter_map(|entry| match entry.value() {
+                        KdlValue::String(tag) => Some(tag.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                (node.name().value().to_string(), tags)
+            })
+            .collect()
+    };
+    Ok(manifest
+        .into_iter()
+        .map(|(theme, tags)| {
+            let tags: BTreeSet<String> = tags.iter().flat_map(|tag| parse(tag)).collect();
+            (theme, tags.into_iter().collect())
+        })
+        .collect())
+}
+
+/// Splits a filter query into the tags its `#tag` words ask for and the
+/// rest, which is matched against theme names. A lone `#` is ignored.
+pub fn split_query(query: &str) -> (Vec<String>, String) {
+    if !query.split_whitespace().any(|word| word.starts_with('#')) {
+        return (Vec::new(), query.to_string());
+    }
+    let mut tags = Vec::new();
+    let mut rest = Vec::new();
+    for word in query.split_whitespace() {
+        match word.strip_prefix('#') {
+            Some(tag) if !tag.is_empty() => tags.push(tag.to_lowercase()),
+            Some(_) => {}
+            None => rest.push(word),
+        }
+    }
+    (tags, rest.join(" "))
+}
+
+/// `#work #high-contrast`, for showing next to a theme name.
+pub fn label<'a>(tags: impl Iterator<Item = &'a str>) -> String {
+    tags.map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" ")
+}
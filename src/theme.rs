@@ -0,0 +1,614 @@
+use crate::color::{contrast_ratio, ContrastLevel, Rgb};
+use kdl::{KdlDocument, KdlIdentifier, KdlNode, KdlValue};
+use serde::{Deserialize, Serialize};
+
+/// A theme as listed in the selector. `colors` is `None` when no palette
+/// could be parsed, e.g. for zellij's built-in `default` theme.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    #[serde(default)]
+    pub colors: Option<ThemeColors>,
+    #[serde(default)]
+    pub appearance: Appearance,
+    /// The remote file the theme was downloaded from; `None` for local and
+    /// built-in themes.
+    #[serde(default)]
+    pub source: Option<ThemeSource>,
+    #[serde(default)]
+    pub origin: Origin,
+    /// Name of the file the theme was read from, if any.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Other definitions of the same name with different colors that lost
+    /// to this one, e.g. `upstream nord.kdl`.
+    #[serde(default)]
+    pub also_defined_in: Vec<String>,
+    /// Tags the source's tag manifest gives the theme, e.g. `pastel`,
+    /// sorted. The user's own tags are kept apart, in [`Tags`].
+    ///
+    /// [`Tags`]: crate::tags::Tags
+    #[serde(default)]
+    pub source_tags: Vec<String>,
+}
+
+/// Where a theme definition was found. When several define the same name,
+/// the earliest variant wins: the config's own `themes` block, then local
+/// files, then a custom source, then zellij's own themes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Origin {
+    /// The `themes` block of `config.kdl` itself.
+    Config,
+    /// A file in the local theme directory.
+    Local,
+    /// A repository configured with `api_url`.
+    Custom,
+    /// zellij's own repository, including the built-in `default` theme.
+    #[default]
+    Upstream,
+}
+
+impl Origin {
+    pub fn label(self) -> &'static str {
+        match self {
+            Origin::Config => "config",
+            Origin::Local => "local",
+            Origin::Custom => "custom",
+            Origin::Upstream => "upstream",
+        }
+    }
+}
+
+/// A theme file in a GitHub contents listing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThemeSource {
+    /// File name, e.g. `nord.kdl`.
+    pub file: String,
+    pub download_url: String,
+    /// Git blob SHA of the file as listed, empty when the listing has none.
+    #[serde(default)]
+    pub sha: String,
+}
+
+impl Theme {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            colors: None,
+            appearance: Appearance::Unknown,
+            source: None,
+            origin: Origin::Upstream,
+            file: None,
+            also_defined_in: Vec::new(),
+            source_tags: Vec::new(),
+        }
+    }
+
+    /// Builds a theme from its parsed palette, classifying it once so the
+    /// result can be cached alongside the colors.
+    pub fn with_colors(name: impl Into<String>, colors: Option<ThemeColors>) -> Self {
+        let appearance = colors
+            .as_ref()
+            .and_then(|colors| colors.bg)
+            .map_or(Appearance::Unknown, Appearance::of);
+        Self {
+            name: name.into(),
+            colors,
+            appearance,
+            source: None,
+            origin: Origin::Upstream,
+            file: None,
+            also_defined_in: Vec::new(),
+            source_tags: Vec::new(),
+        }
+    }
+
+    /// Where this definition lives, e.g. `local nord.kdl`.
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{} {}", self.origin.label(), file),
+            None => self.origin.label().to_string(),
+        }
+    }
+
+    /// Contrast of the main text color against the background, if known.
+    pub fn fg_bg_contrast(&self) -> Option<f64> {
+        self.colors.as_ref().and_then(ThemeColors::fg_bg_contrast)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Appearance {
+    Dark,
+    Light,
+    /// No background color was available to judge by.
+    #[default]
+    Unknown,
+}
+
+impl Appearance {
+    pub fn of(bg: Rgb) -> Self {
+        if bg.is_light() {
+            Appearance::Light
+        } else {
+            Appearance::Dark
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Appearance::Dark => "dark",
+            Appearance::Light => "light",
+            Appearance::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeFormat {
+    /// The original flat palette: `fg`, `bg` and the ANSI color names.
+    #[default]
+    Legacy,
+    /// The component based format (`text_unselected`, `ribbon_selected`, ...).
+    Semantic,
+}
+
+/// The colors of a theme, normalized so both zellij theme formats can be
+/// previewed the same way. For the semantic format the ANSI slots are
+/// recovered from the components zellij derives them from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThemeColors {
+    pub format: ThemeFormat,
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub black: Option<Rgb>,
+    pub red: Option<Rgb>,
+    pub green: Option<Rgb>,
+    pub yellow: Option<Rgb>,
+    pub blue: Option<Rgb>,
+    pub magenta: Option<Rgb>,
+    pub cyan: Option<Rgb>,
+    pub white: Option<Rgb>,
+    pub orange: Option<Rgb>,
+    pub selection_fg: Option<Rgb>,
+    pub selection_bg: Option<Rgb>,
+    /// The active and inactive tabs of the semantic format. Legacy themes
+    /// leave these to zellij, which derives them from the palette.
+    pub ribbon_selected: Option<Component>,
+    pub ribbon_unselected: Option<Component>,
+    /// Focused and unfocused pane frames of the semantic format.
+    pub frame_selected: Option<Rgb>,
+    pub frame_unselected: Option<Rgb>,
+}
+
+/// The text and background colors of one semantic component.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Component {
+    pub base: Rgb,
+    pub background: Rgb,
+}
+
+/// One row of a theme's contrast report.
+pub struct ContrastPair {
+    pub label: &'static str,
+    pub fg: Rgb,
+    pub bg: Rgb,
+    pub ratio: f64,
+}
+
+impl ContrastPair {
+    pub fn level(&self) -> ContrastLevel {
+        ContrastLevel::from_ratio(self.ratio)
+    }
+}
+
+impl ThemeColors {
+    /// Reads a single theme definition, i.e. a child of a `themes` block.
+    /// Returns `None` if the node holds no recognizable colors.
+    pub fn from_node(node: &KdlNode) -> Option<Self> {
+        Self::from_document(node.children()?)
+    }
+
+    /// Reads the colors defined in the body of a theme. Semantic components
+    /// may sit directly in the body or be nested in a `styling` group.
+    pub fn from_document(doc: &KdlDocument) -> Option<Self> {
+        let children = doc.get("styling").and_then(KdlNode::children).unwrap_or(doc);
+        let colors = if children.get("text_unselected").is_some() {
+            Self::from_semantic(children)
+        } else {
+            Self::from_legacy(children)
+        };
+        if colors.fg.is_none() && colors.bg.is_none() {
+            return None;
+        }
+        Some(colors)
+    }
+
+    fn from_legacy(doc: &KdlDocument) -> Self {
+        let color = |name: &str| doc.get(name).and_then(parse_color);
+        Self {
+            format: ThemeFormat::Legacy,
+            fg: color("fg"),
+            bg: color("bg"),
+            black: color("black"),
+            red: color("red"),
+            green: color("green"),
+            yellow: color("yellow"),
+            blue: color("blue"),
+            magenta: color("magenta"),
+            cyan: color("cyan"),
+            white: color("white"),
+            orange: color("orange"),
+            selection_fg: None,
+            selection_bg: None,
+            ribbon_selected: None,
+            ribbon_unselected: None,
+            frame_selected: None,
+            frame_unselected: None,
+        }
+    }
+
+    fn from_semantic(doc: &KdlDocument) -> Self {
+        let color = |component: &str, slot: &str| {
+            doc.get(component)
+                .and_then(KdlNode::children)
+                .and_then(|c| c.get(slot))
+                .and_then(parse_color)
+        };
+        let component = |component: &str| {
+            Some(Component {
+                base: color(component, "base")?,
+                background: color(component, "background")?,
+            })
+        };
+        Self {
+            format: ThemeFormat::Semantic,
+            fg: color("text_unselected", "base"),
+            bg: color("text_unselected", "background"),
+            black: color("ribbon_unselected", "base"),
+            red: color("exit_code_error", "base"),
+            green: color("exit_code_success", "base"),
+            yellow: color("frame_highlight", "emphasis_1"),
+            blue: color("ribbon_selected", "emphasis_3"),
+            magenta: color("text_unselected", "emphasis_3"),
+            cyan: color("text_unselected", "emphasis_1"),
+            white: color("ribbon_unselected", "emphasis_1"),
+            orange: color("text_unselected", "emphasis_0"),
+            selection_fg: color("text_selected", "base"),
+            selection_bg: color("text_selected", "background"),
+            ribbon_selected: component("ribbon_selected"),
+            ribbon_unselected: component("ribbon_unselected"),
+            frame_selected: color("frame_selected", "base"),
+            frame_unselected: color("frame_unselected", "base"),
+        }
+    }
+
+    /// The ANSI palette slots in display order.
+    pub fn ansi(&self) -> [(&'static str, Option<Rgb>); 9] {
+        [
+            ("black", self.black),
+            ("red", self.red),
+            ("green", self.green),
+            ("yellow", self.yellow),
+            ("blue", self.blue),
+            ("magenta", self.magenta),
+            ("cyan", self.cyan),
+            ("white", self.white),
+            ("orange", self.orange),
+        ]
+    }
+
+    pub fn fg_bg_contrast(&self) -> Option<f64> {
+        Some(contrast_ratio(self.fg?, self.bg?))
+    }
+
+    /// Renders a theme file defining `name` with these colors, in the
+    /// legacy format zellij accepts everywhere.
+    pub fn to_kdl(&self, name: &str) -> String {
+        let mut theme = KdlNode::new(name);
+        let slots = [("fg", self.fg), ("bg", self.bg)].into_iter().chain(self.ansi());
+        for (slot, color) in slots {
+            if let Some(color) = color {
+                let mut node = KdlNode::new(slot);
+                node.push(color.r as i64);
+                node.push(color.g as i64);
+                node.push(color.b as i64);
+                theme.ensure_children().nodes_mut().push(node);
+            }
+        }
+        let mut themes = KdlNode::new("themes");
+        themes.ensure_children().nodes_mut().push(theme);
+        let mut doc = KdlDocument::new();
+        doc.nodes_mut().push(themes);
+        doc.fmt();
+        // Named only now, as formatting drops the quotes
+        doc.nodes_mut()[0].ensure_children().nodes_mut()[0].set_name(node_name(name));
+        doc.to_string()
+    }
+
+    /// Contrast ratios for the pairs that matter for readability: text on
+    /// the background, every ANSI color on the background and, for the
+    /// semantic format, selected text on the selection background.
+    pub fn contrast_report(&self) -> Vec<ContrastPair> {
+        let mut pairs = Vec::new();
+        let mut push = |label, fg: Option<Rgb>, bg: Option<Rgb>| {
+            if let (Some(fg), Some(bg)) = (fg, bg) {
+                pairs.push(ContrastPair {
+                    label,
+                    fg,
+                    bg,
+                    ratio: contrast_ratio(fg, bg),
+                });
+            }
+        };
+        push("fg", self.fg, self.bg);
+        for (label, color) in self.ansi() {
+            push(label, color, self.bg);
+        }
+        if self.format == ThemeFormat::Semantic {
+            push("selection", self.selection_fg, self.selection_bg);
+        }
+        pairs
+    }
+}
+
+/// Parses a color node's arguments: `r g b`, a `"#rrggbb"` string or a
+/// single 256-color palette index.
+fn parse_color(node: &KdlNode) -> Option<Rgb> {
+    let values: Vec<_> = node
+        .entries()
+        .iter()
+        .filter(|entry| entry.name().is_none())
+        .map(|entry| entry.value())
+        .collect();
+    match values.as_slice() {
+        [r, g, b] => Some(Rgb::new(
+            u8::try_from(r.as_i64()?).ok()?,
+            u8::try_from(g.as_i64()?).ok()?,
+            u8::try_from(b.as_i64()?).ok()?,
+        )),
+        [value] => match value.as_string() {
+            Some(hex) => Rgb::from_hex(hex),
+            None => u8::try_from(value.as_i64()?).ok().map(Rgb::from_ansi256),
+        },
+        _ => None,
+    }
+}
+
+/// `name` as a node name that reads back unchanged: bare when it is a
+/// simple identifier like `catppuccin-mocha`, quoted otherwise. The kdl
+/// crate only quotes names with characters a bare identifier cannot hold,
+/// so it would leave `1984`, `-1` or `null` bare, which read back as a
+/// number or a keyword or do not parse at all.
+pub fn node_name(name: &str) -> KdlIdentifier {
+    let mut identifier = KdlIdentifier::from(name);
+    let simple = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !matches!(name, "true" | "false" | "null");
+    if !simple {
+        identifier.set_repr(KdlValue::String(name.to_string()).to_string());
+    }
+    identifier
+}
+
+/// Finds the theme definitions in a theme file. Besides the usual
+/// `themes { name { ... } }` block this accepts theme nodes written at
+/// the top level without the wrapper, the `theme "name" { ... }` form
+/// in either place, and colors written directly at the top level, which
+/// are named after the file (`file_stem`). A `themes` block and top-level
+/// definitions in the same file are all listed.
+pub fn extract_themes(content: &str, file_stem: &str) -> Result<Vec<Theme>, String> {
+    let doc = content.parse::<KdlDocument>().map_err(|e| e.to_string())?;
+    let mut themes = Vec::new();
+    for node in doc.nodes() {
+        match node.name().value() {
+            "themes" => {
+                // Every child of the block is a theme, with colors or not
+                let children = node.children().map(KdlDocument::nodes).unwrap_or_default();
+                themes.extend(children.iter().map(|child| {
+                    Theme::with_colors(definition_name(child), ThemeColors::from_node(child))
+                }));
+            }
+            // The body of a theme written at the top level, read below
+            "styling" => {}
+            _ => {
+                if let Some(colors) = ThemeColors::from_node(node) {
+                    themes.push(Theme::with_colors(definition_name(node), Some(colors)));
+                }
+            }
+        }
+    }
+    if themes.is_empty() {
+        if let Some(colors) = ThemeColors::from_document(&doc) {
+            themes.push(Theme::with_colors(file_stem, Some(colors)));
+        }
+    }
+    if themes.is_empty() {
+        log::debug!("no theme definitions found in {}", file_stem);
+    }
+    Ok(themes)
+}
+
+/// The name a theme definition node gives its theme: its own name, or the
+/// argument of a `theme "name" { ... }` node.
+pub fn definition_name(node: &KdlNode) -> &str {
+    match node.get(0).and_then(|entry| entry.value().as_string()) {
+        Some(name) if node.name().value() == "theme" => name,
+        _ => node.name().value(),
+    }
+}
+
+/// Keeps one theme per name, sorted by name. The winner is the one with
+/// the highest precedence `Origin`, or the first listed among equals; the
+/// locations of losing definitions with different colors are noted on it.
+pub fn merge_themes(mut themes: Vec<Theme>) -> Vec<Theme> {
+    // Stable, so equal origins keep their listing order
+    themes.sort_by(|a, b| a.name.cmp(&b.name).then(a.origin.cmp(&b.origin)));
+    let mut merged: Vec<Theme> = Vec::with_capacity(themes.len());
+    for theme in themes {
+        match merged.last_mut() {
+            Some(winner) if winner.name == theme.name => {
+                if winner.colors != theme.colors {
+                    winner.also_defined_in.push(theme.location());
+                }
+                winner.also_defined_in.extend(theme.also_defined_in);
+                // Tags go by name, so a local copy keeps those of the source
+                if winner.source_tags.is_empty() {
+                    winner.source_tags = theme.source_tags;
+                }
+            }
+            _ => merged.push(theme),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn awkward_theme_names_read_back_unchanged() {
+        let colors = ThemeColors {
+            fg: Some(Rgb { r: 1, g: 2, b: 3 }),
+            ..ThemeColors::default()
+        };
+        let names = ["my theme", "say \"hi\"", "thème ✨", "1984", "null", "-1", "plain-name"];
+        for name in names {
+            let written = colors.to_kdl(name);
+            let themes = extract_themes(&written, "file").unwrap();
+            assert_eq!(themes.len(), 1, "{}", written);
+            assert_eq!(themes[0].name, name, "{}", written);
+            assert_eq!(themes[0].colors.as_ref().and_then(|colors| colors.fg), colors.fg);
+        }
+    }
+
+    /// The names and fg/bg colors `extract_themes` finds in `content`.
+    fn extracted(content: &str, file_stem: &str) -> Vec<(String, Option<Rgb>, Option<Rgb>)> {
+        extract_themes(content, file_stem)
+            .unwrap()
+            .into_iter()
+            .map(|theme| {
+                let colors = theme.colors.unwrap_or_default();
+                (theme.name, colors.fg, colors.bg)
+            })
+            .collect()
+    }
+
+    fn nord() -> (String, Option<Rgb>, Option<Rgb>) {
+        let (fg, bg) = (Rgb::new(216, 222, 233), Rgb::new(46, 52, 64));
+        ("nord".to_string(), Some(fg), Some(bg))
+    }
+
+    #[test]
+    fn a_themes_block_lists_each_child() {
+        let content = "\
+themes {
+    nord {
+        fg 216 222 233
+        bg 46 52 64
+    }
+    plain
+}
+";
+        let plain = ("plain".to_string(), None, None);
+        assert_eq!(extracted(content, "file"), [nord(), plain]);
+    }
+
+    #[test]
+    fn a_theme_node_without_the_themes_block_is_a_theme() {
+        let content = "nord {\n    fg 216 222 233\n    bg 46 52 64\n}\n";
+        assert_eq!(extracted(content, "file"), [nord()]);
+    }
+
+    #[test]
+    fn semantic_definitions_are_read_with_or_without_styling() {
+        let body = "\
+text_unselected {
+    base 216 222 233
+    background 46 52 64
+}
+text_selected {
+    base 46 52 64
+    background 136 192 208
+}
+";
+        let nested = format!("themes {{\nnord {{\nstyling {{\n{}}}\n}}\n}}\n", body);
+        let flat = format!("themes {{\nnord {{\n{}}}\n}}\n", body);
+        for content in [nested, flat] {
+            assert_eq!(extracted(&content, "file"), [nord()], "{}", content);
+            let colors = extract_themes(&content, "file").unwrap()[0].colors.clone().unwrap();
+            assert_eq!(colors.format, ThemeFormat::Semantic);
+            assert_eq!(colors.selection_bg, Some(Rgb::new(136, 192, 208)));
+        }
+    }
+
+    #[test]
+    fn a_file_without_theme_data_has_no_themes_and_no_error() {
+        assert_eq!(extracted("// nothing here\nkeybinds {\n}\n", "file"), []);
+        assert_eq!(extracted("", "file"), []);
+        assert!(extract_themes("themes {\n", "file").is_err());
+    }
+
+    #[test]
+    fn upstream_files_with_hex_colors_and_several_themes_are_read() {
+        // As in zellij's own theme directory, e.g. catppuccin.kdl
+        let content = "\
+themes {
+    catppuccin-latte {
+        fg \"#acb0be\"
+        bg \"#eff1f5\"
+        red \"#d20f39\"
+    }
+    catppuccin-mocha {
+        fg 205 214 244
+        bg 30 30 46
+        red 1
+    }
+}
+";
+        let themes = extract_themes(content, "catppuccin").unwrap();
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["catppuccin-latte", "catppuccin-mocha"]);
+        let latte = themes[0].colors.as_ref().unwrap();
+        assert_eq!(latte.bg, Some(Rgb::new(0xef, 0xf1, 0xf5)));
+        assert_eq!(latte.red, Some(Rgb::new(0xd2, 0x0f, 0x39)));
+        let mocha = themes[1].colors.as_ref().unwrap();
+        assert_eq!(mocha.red, Some(Rgb::from_ansi256(1)));
+    }
+
+    #[test]
+    fn the_theme_name_form_is_read_at_the_top_level_and_in_a_block() {
+        let body = "{\n    fg 216 222 233\n    bg 46 52 64\n}\n";
+        let top_level = format!("theme \"nord\" {}", body);
+        let in_block = format!("themes {{\n    theme \"nord\" {}}}\n", body);
+        for content in [top_level, in_block] {
+            assert_eq!(extracted(&content, "file"), [nord()], "{}", content);
+        }
+    }
+
+    #[test]
+    fn colors_at_the_top_level_are_named_after_the_file() {
+        let content = "fg 216 222 233\nbg 46 52 64\n";
+        assert_eq!(extracted(content, "nord"), [nord()]);
+    }
+
+    #[test]
+    fn a_themes_block_and_top_level_themes_are_all_listed() {
+        let content = "\
+themes {
+    nord {
+        fg 216 222 233
+        bg 46 52 64
+    }
+}
+theme \"dracula\" {
+    fg 248 248 242
+    bg 40 42 54
+}
+";
+        let themes = extract_themes(content, "file").unwrap();
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["nord", "dracula"]);
+    }
+}
@@ -0,0 +1,128 @@
+//! Derived themes: an existing palette with its lightness, chroma or hue
+//! adjusted in OKLCH.
+
+use crate::color::{Oklch, Rgb};
+use crate::theme::{ThemeColors, ThemeFormat};
+
+/// Adjustments applied to every color of a theme. Fractions are relative,
+/// so `lighten: -0.1` darkens by 10%.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Adjustment {
+    /// Positive values move lightness toward white, negative toward black.
+    pub lighten: f64,
+    /// Relative change in chroma; negative values desaturate.
+    pub saturate: f64,
+    /// Hue rotation in degrees.
+    pub hue_shift: f64,
+    /// Leave the background untouched.
+    pub keep_bg: bool,
+    /// Leave the foreground untouched.
+    pub keep_fg: bool,
+}
+
+impl Adjustment {
+    pub fn apply(&self, color: Rgb) -> Rgb {
+        let mut lch = Oklch::from(color);
+        if self.lighten >= 0.0 {
+            lch.l += (1.0 - lch.l) * self.lighten;
+        } else {
+            lch.l *= 1.0 + self.lighten;
+        }
+        lch.c *= (1.0 + self.saturate).max(0.0);
+        lch.h = (lch.h + self.hue_shift).rem_euclid(360.0);
+        lch.to_rgb()
+    }
+
+    pub fn apply_to(&self, colors: &ThemeColors) -> ThemeColors {
+        let adjust = |color: Option<Rgb>| color.map(|c| self.apply(c));
+        ThemeColors {
+            format: ThemeFormat::Legacy,
+            fg: if self.keep_fg { colors.fg } else { adjust(colors.fg) },
+            bg: if self.keep_bg { colors.bg } else { adjust(colors.bg) },
+            black: adjust(colors.black),
+            red: adjust(colors.red),
+            green: adjust(colors.green),
+            yellow: adjust(colors.yellow),
+            blue: adjust(colors.blue),
+            magenta: adjust(colors.magenta),
+            cyan: adjust(colors.cyan),
+            white: adjust(colors.white),
+            orange: adjust(colors.orange),
+            selection_fg: adjust(colors.selection_fg),
+            selection_bg: adjust(colors.selection_bg),
+            // Written out in the legacy format, which has no components
+            ribbon_selected: None,
+            ribbon_unselected: None,
+            frame_selected: None,
+            frame_unselected: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_survives_the_trip_through_oklch() {
+        let named = ["#000000", "#ffffff", "#2e3440", "#88c0d0", "#ff0000", "#00ff00", "#0000ff"];
+        for hex in named {
+            let color = Rgb::from_hex(hex).unwrap();
+            assert_eq!(Oklch::from(color).to_rgb().to_hex(), hex);
+        }
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let color = Rgb::new(r, g, b);
+                    assert_eq!(Oklch::from(color).to_rgb(), color, "{}", color.to_hex());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_adjustment_and_a_full_turn_change_nothing() {
+        let colors = [Rgb::new(0x2e, 0x34, 0x40), Rgb::new(0xbf, 0x61, 0x6a)];
+        let turn = Adjustment {
+            hue_shift: 360.0,
+            ..Adjustment::default()
+        };
+        for color in colors {
+            assert_eq!(Adjustment::default().apply(color), color);
+            assert_eq!(turn.apply(color), color);
+        }
+    }
+
+    #[test]
+    fn darkening_lowers_lightness_and_keeps_hue() {
+        let color = Rgb::new(0x88, 0xc0, 0xd0);
+        let darker = Adjustment {
+            lighten: -0.2,
+            ..Adjustment::default()
+        }
+        .apply(color);
+        let (before, after) = (Oklch::from(color), Oklch::from(darker));
+        assert!((after.l - before.l * 0.8).abs() < 0.01, "{} vs {}", after.l, before.l);
+        assert!((after.h - before.h).abs() < 2.0, "{} vs {}", after.h, before.h);
+    }
+
+    #[test]
+    fn kept_fg_and_bg_are_left_alone() {
+        let colors = ThemeColors {
+            fg: Some(Rgb::new(0xd8, 0xde, 0xe9)),
+            bg: Some(Rgb::new(0x2e, 0x34, 0x40)),
+            red: Some(Rgb::new(0xbf, 0x61, 0x6a)),
+            ..ThemeColors::default()
+        };
+        let adjustment = Adjustment {
+            hue_shift: 90.0,
+            keep_bg: true,
+            keep_fg: true,
+            ..Adjustment::default()
+        };
+        let shifted = adjustment.apply_to(&colors);
+        assert_eq!((shifted.fg, shifted.bg), (colors.fg, colors.bg));
+        assert_ne!(shifted.red, colors.red);
+        assert_eq!(shifted.green, None);
+    }
+}